@@ -14,14 +14,28 @@ fn main() -> Result<(), anyhow::Error> {
     let m2 = [0x44, 0x44, 0x44, 0x44, 0x44, 0x44];
     let m3 = [0x55, 0x55, 0x55, 0x55, 0x55, 0x55];
 
-    init_tables(&mut pipeline, m1, m2);
+    init_tables(&mut pipeline, m1, m2)?;
     run_test(pipeline, m2, m3)
 }
 
-fn init_tables(pipeline: &mut main_pipeline, m1: [u8; 6], m2: [u8; 6]) {
+fn init_tables(
+    pipeline: &mut main_pipeline,
+    m1: [u8; 6],
+    m2: [u8; 6],
+) -> Result<(), anyhow::Error> {
     // add static forwarding entries
-    pipeline.add_ingress_fwd_fib_entry("forward", &m1, &0u16.to_le_bytes(), 0);
-    pipeline.add_ingress_fwd_fib_entry("forward", &m2, &1u16.to_le_bytes(), 0);
+    pipeline.add_ingress_fwd_fib_entry(
+        "forward",
+        &m1,
+        &0u16.to_le_bytes(),
+        0,
+    )?;
+    pipeline.add_ingress_fwd_fib_entry(
+        "forward",
+        &m2,
+        &1u16.to_le_bytes(),
+        0,
+    )?;
 
     // port 0 vlan 47
     pipeline.add_ingress_vlan_port_vlan_entry(
@@ -29,7 +43,7 @@ fn init_tables(pipeline: &mut main_pipeline, m1: [u8; 6], m2: [u8; 6]) {
         0u16.to_le_bytes().as_ref(),
         47u16.to_le_bytes().as_ref(),
         0,
-    );
+    )?;
 
     // sanity check the table
     let x = pipeline.get_ingress_vlan_port_vlan_entries();
@@ -41,7 +55,9 @@ fn init_tables(pipeline: &mut main_pipeline, m1: [u8; 6], m2: [u8; 6]) {
         1u16.to_le_bytes().as_ref(),
         47u16.to_le_bytes().as_ref(),
         0,
-    );
+    )?;
+
+    Ok(())
 }
 
 fn run_test(
@@ -2,18 +2,30 @@
 
 use crate::{
     expression::ExpressionGenerator,
-    qualified_table_function_name, rust_type,
+    extern_type, is_builtin_extern, qualified_table_function_name, rust_type,
     statement::{StatementContext, StatementGenerator},
     try_extract_prefix_len, Context,
 };
 use p4::ast::{
-    Action, Control, ControlParameter, Direction, ExpressionKind,
-    KeySetElementValue, MatchKind, Table, Type, AST,
+    Action, BinOp, Control, ControlParameter, Direction, Expression,
+    ExpressionKind, KeySetElementValue, Lvalue, MatchKind, StatementBlock,
+    Table, Type, VisitorMut, AST,
 };
 use p4::hlir::Hlir;
 use p4::util::resolve_lvalue;
 use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
+use std::collections::HashSet;
+
+/// The roots of the lvalues an AST node refers to.
+#[derive(Default)]
+struct Referenced(HashSet<String>);
+
+impl VisitorMut for Referenced {
+    fn lvalue(&mut self, lval: &Lvalue) {
+        self.0.insert(lval.root().to_owned());
+    }
+}
 
 pub(crate) struct ControlGenerator<'a> {
     ast: &'a AST,
@@ -42,6 +54,100 @@ impl<'a> ControlGenerator<'a> {
         if let Some(egress) = self.ast.get_control("egress") {
             self.generate_top_level_control(egress);
         };
+
+        for name in self.ctx.standalone_controls.clone() {
+            match self.ast.get_control(&name) {
+                Some(control) => self.generate_standalone_control(control),
+                None => panic!("standalone control {} not found", name),
+            }
+        }
+    }
+
+    /// Generate a `<control>_control` struct that owns the tables of
+    /// `control`, initialized with their const entries, and applies the
+    /// control to the arguments it is given.
+    fn generate_standalone_control(&mut self, control: &Control) {
+        self.generate_top_level_control(control);
+
+        let (params, _) = self.control_parameters(control);
+        let mut args = Vec::new();
+        for arg in &control.parameters {
+            if let Type::UserDefined(typename) = &arg.ty {
+                if self.ast.get_user_defined_type(typename).is_none()
+                    && control.is_type_parameter(typename)
+                {
+                    continue;
+                }
+            }
+            let name = format_ident!("{}", arg.name);
+            args.push(quote! { #name });
+        }
+
+        let mut members = Vec::new();
+        let mut initializers = Vec::new();
+        let mut table_args = Vec::new();
+        for (cs, t) in control.tables(self.ast) {
+            let c = cs.last().unwrap().1;
+            let name = format_ident!(
+                "{}",
+                qualified_table_function_name(None, &cs, t)
+            );
+            let ctor = format_ident!(
+                "{}",
+                qualified_table_function_name(Some(control), &cs, t)
+            );
+            let (_, mut param_types) = self.control_parameters(c);
+            for var in &c.variables {
+                if let Type::UserDefined(typename) = &var.ty {
+                    if self.ast.get_extern(typename).is_some() {
+                        let extern_type = extern_type(typename);
+                        param_types.push(quote! {
+                            &#extern_type
+                        })
+                    }
+                }
+            }
+            let n = t.key.len();
+            members.push(quote! {
+                pub #name: p4rs::table::Table::<
+                    #n,
+                    std::sync::Arc<dyn Fn(#(#param_types),*)>
+                >
+            });
+            initializers.push(quote! { #name: #ctor() });
+            table_args.push(quote! { &self.#name });
+        }
+
+        let struct_name = format_ident!("{}_control", control.name);
+        let apply = format_ident!("{}_apply", control.name);
+        self.ctx.structs.insert(
+            struct_name.to_string(),
+            quote! {
+                pub struct #struct_name {
+                    #(#members,)*
+                    pub externs: p4rs::externs::ExternRegistry,
+                }
+
+                impl #struct_name {
+                    pub fn new() -> Self {
+                        Self {
+                            #(#initializers,)*
+                            externs: p4rs::externs::ExternRegistry::default(),
+                        }
+                    }
+
+                    pub fn apply(&self, #(#params),*) {
+                        #apply(#(#args,)* #(#table_args,)* &self.externs)
+                    }
+                }
+
+                impl Default for #struct_name {
+                    fn default() -> Self {
+                        Self::new()
+                    }
+                }
+            },
+        );
     }
 
     fn generate_top_level_control(&mut self, control: &Control) {
@@ -54,9 +160,9 @@ impl<'a> ControlGenerator<'a> {
             for var in &control.variables {
                 if let Type::UserDefined(typename) = &var.ty {
                     if self.ast.get_extern(typename).is_some() {
-                        let extern_type = format_ident!("{}", typename);
+                        let extern_type = extern_type(typename);
                         param_types.push(quote! {
-                            &p4rs::externs::#extern_type
+                            &#extern_type
                         })
                     }
                 }
@@ -97,9 +203,9 @@ impl<'a> ControlGenerator<'a> {
             for var in &c.variables {
                 if let Type::UserDefined(typename) = &var.ty {
                     if self.ast.get_extern(typename).is_some() {
-                        let extern_type = format_ident!("{}", typename);
+                        let extern_type = extern_type(typename);
                         param_types.push(quote! {
-                            &p4rs::externs::#extern_type
+                            &#extern_type
                         })
                     }
                 }
@@ -116,6 +222,7 @@ impl<'a> ControlGenerator<'a> {
                 #qtn: &#table_type
             });
         }
+        params.push(quote! { externs: &p4rs::externs::ExternRegistry });
 
         let name = format_ident!("{}_apply", control.name);
         let apply_body = self.generate_control_apply_body(control);
@@ -143,6 +250,12 @@ impl<'a> ControlGenerator<'a> {
 
         for arg in &control.parameters {
             match arg.ty {
+                // A deparser emits headers into the packet it is given.
+                Type::UserDefined(ref typename) if typename == "packet_out" => {
+                    let name = format_ident!("{}", arg.name);
+                    params.push(quote! { #name: &mut packet_out });
+                    types.push(quote! { &mut packet_out });
+                }
                 Type::UserDefined(ref typename) => {
                     match self.ast.get_user_defined_type(typename) {
                         Some(_udt) => {
@@ -205,9 +318,9 @@ impl<'a> ControlGenerator<'a> {
             if let Type::UserDefined(typename) = &var.ty {
                 if self.ast.get_extern(typename).is_some() {
                     let name = format_ident!("{}", var.name);
-                    let extern_type = format_ident!("{}", typename);
+                    let extern_type = extern_type(typename);
                     params.push(quote! {
-                        #name: &p4rs::externs::#extern_type
+                        #name: &#extern_type
                     })
                 }
             }
@@ -222,8 +335,18 @@ impl<'a> ControlGenerator<'a> {
         let dump_args: Vec<TokenStream> = action
             .parameters
             .iter()
-            .map(|x| format_ident!("{}", x.name.clone()))
-            .map(|x| quote! { #x })
+            .map(|x| {
+                let name = format_ident!("{}", x.name);
+                // structs have no Display impl
+                match &x.ty {
+                    Type::UserDefined(t)
+                        if self.ast.get_struct(t).is_some() =>
+                    {
+                        quote! { #name.dump() }
+                    }
+                    _ => quote! { #name },
+                }
+            })
             .collect();
 
         let dump = quote! {
@@ -236,31 +359,36 @@ impl<'a> ControlGenerator<'a> {
         for arg in &action.parameters {
             // if the type is user defined, check to ensure it's defined
             if let Type::UserDefined(ref typename) = arg.ty {
-                match self.ast.get_user_defined_type(typename) {
-                    Some(_) => {
-                        let name = format_ident!("{}", arg.name);
-                        let ty = rust_type(&arg.ty);
-                        params.push(quote! { #name: #ty });
-                    }
-                    None => {
-                        panic!(
-                            "codegen: undefined type {} for arg {:#?}",
-                            typename, arg,
-                        );
-                    }
+                if self.ast.get_user_defined_type(typename).is_none() {
+                    panic!(
+                        "codegen: undefined type {} for arg {:#?}",
+                        typename, arg,
+                    );
+                }
+            }
+            let name = format_ident!("{}", arg.name);
+            let ty = rust_type(&arg.ty);
+            // out and inout parameters are written back to the caller
+            match arg.direction {
+                Direction::Out | Direction::InOut => {
+                    params.push(quote! { #name: &mut #ty });
                 }
-            } else {
-                let name = format_ident!("{}", arg.name);
-                let ty = rust_type(&arg.ty);
-                params.push(quote! { #name: #ty });
+                _ => params.push(quote! { #name: #ty }),
             }
         }
 
+        let shadowed: Vec<&str> =
+            action.parameters.iter().map(|p| p.name.as_str()).collect();
+        let constants =
+            self.control_constants(control, &action.statement_block, &shadowed);
+
         let mut names = control.names();
+        names.extend(action.names());
         let sg = StatementGenerator::new(
             self.ast,
             self.hlir,
             StatementContext::Control(control),
+            self.ctx.checked_header_access,
         );
         let body = sg.generate_block(&action.statement_block, &mut names);
 
@@ -278,6 +406,8 @@ impl<'a> ControlGenerator<'a> {
 
                     #dump
 
+                    #constants
+
                     #body
                 }
             },
@@ -333,13 +463,18 @@ impl<'a> ControlGenerator<'a> {
             return (table_type, tokens);
         }
 
-        for entry in &table.const_entries {
+        // Entries without an explicit @priority are ordered by declaration,
+        // earlier entries winning over later ones as the spec requires.
+        let n_entries = table.const_entries.len() as u32;
+        for (index, entry) in table.const_entries.iter().enumerate() {
+            let priority = entry.priority().unwrap_or(n_entries - index as u32);
             let mut keyset = Vec::new();
             for (i, k) in entry.keyset.iter().enumerate() {
                 match &k.value {
                     KeySetElementValue::Expression(e) => {
-                        let eg = ExpressionGenerator::new(self.hlir);
-                        let xpr = eg.generate_expression(e.as_ref());
+                        let e = self.hlir.constant_value(e);
+                        let eg = ExpressionGenerator::new(self.ast, self.hlir);
+                        let xpr = eg.generate_expression(e);
                         let ks = match table.key[i].1 {
                             MatchKind::Exact => {
                                 let k = format_ident!("{}", "Exact");
@@ -348,13 +483,16 @@ impl<'a> ControlGenerator<'a> {
                                         p4rs::bitvec_to_biguint(&#xpr))
                                 }
                             }
-                            MatchKind::Ternary => {
-                                let k = format_ident!("{}", "Ternary");
-                                quote! {
-                                    p4rs::table::Key::#k(
-                                        p4rs::bitvec_to_biguint(&#xpr))
+                            MatchKind::Ternary => match &e.kind {
+                                ExpressionKind::Binary(v, BinOp::Mask, m) => {
+                                    self.masked_ternary_key(v, m)
                                 }
-                            }
+                                _ => quote! {
+                                    p4rs::table::Key::Ternary(
+                                        p4rs::table::Ternary::Value(
+                                            p4rs::bitvec_to_biguint(&#xpr)))
+                                },
+                            },
                             MatchKind::LongestPrefixMatch => {
                                 let len = match try_extract_prefix_len(e) {
                                     Some(len) => len,
@@ -383,6 +521,19 @@ impl<'a> ControlGenerator<'a> {
                         };
                         keyset.push(ks);
                     }
+                    KeySetElementValue::Masked(v, m)
+                        if matches!(table.key[i].1, MatchKind::Ternary) =>
+                    {
+                        keyset.push(self.masked_ternary_key(v, m));
+                    }
+                    KeySetElementValue::DontCare
+                        if matches!(table.key[i].1, MatchKind::Ternary) =>
+                    {
+                        keyset.push(quote! {
+                            p4rs::table::Key::Ternary(
+                                p4rs::table::Ternary::DontCare)
+                        });
+                    }
                     x => todo!("key set element {:?}", x),
                 }
             }
@@ -430,11 +581,9 @@ impl<'a> ControlGenerator<'a> {
                                     );
                                 }
                                 let size = n;
-                                action_fn_args.push(quote! {{
-                                    let mut x = bitvec![mut u8, Msb0; 0; #size];
-                                    x.store_le(#v);
-                                    x
-                                }});
+                                action_fn_args.push(quote! {
+                                    p4rs::endian::store(#size, #v)
+                                });
                             }
                             x => {
                                 todo!("action bit lit expression type {:?}", x)
@@ -458,13 +607,13 @@ impl<'a> ControlGenerator<'a> {
                         #action_fn_name(#(#action_fn_args),*);
                     });
 
-                #table_name.entries.insert(
+                #table_name.insert(
                     p4rs::table::TableEntry::<
                         #n,
                         std::sync::Arc<dyn Fn(#(#control_param_types),*)>,
                     >{
                         key: [#(#keyset),*],
-                        priority: 0,
+                        priority: #priority,
                         name: "your name here".into(),
                         action,
 
@@ -472,6 +621,7 @@ impl<'a> ControlGenerator<'a> {
                         //constant entries?
                         action_id: String::new(),
                         parameter_data: Vec::new(),
+                        immutable: true,
                     });
             })
         }
@@ -481,6 +631,24 @@ impl<'a> ControlGenerator<'a> {
         (table_type, tokens)
     }
 
+    /// A `value &&& mask` keyset element of a ternary key. The mask is used
+    /// bit for bit, it need not be a prefix.
+    fn masked_ternary_key(
+        &self,
+        value: &Expression,
+        mask: &Expression,
+    ) -> TokenStream {
+        let eg = ExpressionGenerator::new(self.ast, self.hlir);
+        let value = eg.generate_expression(self.hlir.constant_value(value));
+        let mask = eg.generate_expression(self.hlir.constant_value(mask));
+        quote! {{
+            let v = p4rs::bitvec_to_biguint(&#value);
+            let m = p4rs::bitvec_to_biguint(&#mask);
+            p4rs::table::Key::Ternary(
+                p4rs::table::Ternary::Masked(v.value, m.value, v.width))
+        }}
+    }
+
     fn generate_control_apply_body(
         &mut self,
         control: &Control,
@@ -493,25 +661,100 @@ impl<'a> ControlGenerator<'a> {
             if let Type::UserDefined(typename) = &var.ty {
                 if self.ast.get_extern(typename).is_some() {
                     let name = format_ident!("{}", var.name);
-                    let extern_type = format_ident!("{}", typename);
-                    tokens.extend(quote! {
-                        let #name = p4rs::externs::#extern_type::new();
-                    })
+                    let extern_type = extern_type(typename);
+                    if typename == "Meter" {
+                        // Meters are keyed by control like tables are, so
+                        // controls can each have a meter of the same name.
+                        let id = self.ctx.names.original_lvalue(&format!(
+                            "{}.{}",
+                            control.name, var.name
+                        ));
+                        tokens.extend(quote! {
+                            let #name = externs.meter(#id);
+                        })
+                    } else if is_builtin_extern(typename) {
+                        tokens.extend(quote! {
+                            let #name = #extern_type::new();
+                        })
+                    } else {
+                        tokens.extend(quote! {
+                            let #name = externs.instance(#typename);
+                        })
+                    }
                 }
             }
         }
 
+        tokens.extend(self.control_constants(control, &control.apply, &[]));
+
         let mut names = control.names();
         let sg = StatementGenerator::new(
             self.ast,
             self.hlir,
             StatementContext::Control(control),
+            self.ctx.checked_header_access,
         );
         tokens.extend(sg.generate_block(&control.apply, &mut names));
 
         tokens
     }
 
+    /// Declare the constants of `control` that `block` refers to as locals, so
+    /// the generated code for the block can read them. Constants with a name
+    /// in `shadowed` are hidden by another declaration and are left out.
+    fn control_constants(
+        &self,
+        control: &Control,
+        block: &StatementBlock,
+        shadowed: &[&str],
+    ) -> TokenStream {
+        let mut referenced = Referenced::default();
+        for s in &block.statements {
+            s.accept_mut(&mut referenced);
+        }
+        for name in shadowed {
+            referenced.0.remove(*name);
+        }
+        self.constant_bindings(control, referenced.0)
+    }
+
+    /// `let` bindings for the constants of `control` named in `referenced`.
+    pub(crate) fn constant_bindings(
+        &self,
+        control: &Control,
+        referenced: HashSet<String>,
+    ) -> TokenStream {
+        let mut referenced = Referenced(referenced);
+
+        // A constant may be initialized from an earlier one, which is then
+        // needed too.
+        for c in control.constants.iter().rev() {
+            if referenced.0.contains(&c.name) {
+                c.initializer.accept_mut(&mut referenced);
+            }
+        }
+
+        let eg = ExpressionGenerator::new(self.ast, self.hlir);
+        let mut tokens = TokenStream::new();
+        for c in &control.constants {
+            if !referenced.0.contains(&c.name) {
+                continue;
+            }
+            let name = format_ident!("{}", c.name);
+            let ty = rust_type(&c.ty);
+            let value = match (&c.ty, &c.initializer.kind) {
+                (Type::Bit(n), ExpressionKind::IntegerLit(v)) => {
+                    eg.generate_bit_literal(*n as u16, *v as u128)
+                }
+                _ => eg.generate_expression(&c.initializer),
+            };
+            tokens.extend(quote! {
+                let #name: #ty = #value;
+            });
+        }
+        tokens
+    }
+
     fn get_control_arg<'b>(
         control: &'b Control,
         arg_name: &str,
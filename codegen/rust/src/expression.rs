@@ -1,17 +1,38 @@
 // Copyright 2022 Oxide Computer Company
 
-use p4::ast::{BinOp, DeclarationInfo, Expression, ExpressionKind, Lvalue};
+use crate::{is_builtin_extern, is_header_member, lvalue_segment, mark_dirty};
+use p4::ast::{
+    BinOp, Call, DeclarationInfo, Direction, Expression, ExpressionKind,
+    ExternMethod, Lvalue, NameInfo, Type, AST,
+};
 use p4::hlir::Hlir;
 use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
 
 pub(crate) struct ExpressionGenerator<'a> {
+    ast: &'a AST,
     hlir: &'a Hlir,
+    checked_header_access: bool,
 }
 
 impl<'a> ExpressionGenerator<'a> {
-    pub fn new(hlir: &'a Hlir) -> Self {
-        Self { hlir }
+    pub fn new(ast: &'a AST, hlir: &'a Hlir) -> Self {
+        Self::with_checked_header_access(ast, hlir, false)
+    }
+
+    /// Create a generator that, if `checked_header_access` is set, checks
+    /// that a header is valid each time one of its fields is read. See
+    /// [`crate::Settings::checked_header_access`].
+    pub fn with_checked_header_access(
+        ast: &'a AST,
+        hlir: &'a Hlir,
+        checked_header_access: bool,
+    ) -> Self {
+        Self {
+            ast,
+            hlir,
+            checked_header_access,
+        }
     }
 
     pub(crate) fn generate_expression(&self, xpr: &Expression) -> TokenStream {
@@ -28,23 +49,55 @@ impl<'a> ExpressionGenerator<'a> {
             ExpressionKind::SignedLit(_width, _v) => {
                 todo!("generate expression signed lit");
             }
-            ExpressionKind::Lvalue(v) => self.generate_lvalue(v),
+            ExpressionKind::Lvalue(v) => self.generate_lvalue_read(v),
             ExpressionKind::Binary(lhs, op, rhs) => {
                 let lhs_tks = self.generate_expression(lhs.as_ref());
                 let op_tks = self.generate_binop(*op);
                 let rhs_tks = self.generate_expression(rhs.as_ref());
                 let mut ts = TokenStream::new();
+                // arithmetic on integers that are not bitvecs, such as
+                // header sizes, is native.
+                let native = [lhs, rhs].iter().all(|x| {
+                    matches!(
+                        self.hlir.expression_types.get(x.as_ref()),
+                        Some(Type::Int(_))
+                    )
+                });
                 match op {
+                    BinOp::Add | BinOp::Subtract | BinOp::Mod if native => {
+                        ts.extend(quote! { (#lhs_tks #op_tks #rhs_tks) });
+                    }
                     BinOp::Add => {
                         ts.extend(quote!{
                             p4rs::bitmath::add_le(#lhs_tks.clone(), #rhs_tks.clone())
                         });
                     }
+                    BinOp::Subtract => {
+                        ts.extend(quote!{
+                            p4rs::bitmath::sub_le(#lhs_tks.clone(), #rhs_tks.clone())
+                        });
+                    }
                     BinOp::Mod => {
                         ts.extend(quote!{
                             p4rs::bitmath::mod_le(#lhs_tks.clone(), #rhs_tks.clone())
                         });
                     }
+                    BinOp::SaturatingAdd if native => {
+                        ts.extend(quote! { #lhs_tks.saturating_add(#rhs_tks) });
+                    }
+                    BinOp::SaturatingSubtract if native => {
+                        ts.extend(quote! { #lhs_tks.saturating_sub(#rhs_tks) });
+                    }
+                    BinOp::SaturatingAdd => {
+                        ts.extend(quote!{
+                            p4rs::bitmath::sat_add_le(#lhs_tks.clone(), #rhs_tks.clone())
+                        });
+                    }
+                    BinOp::SaturatingSubtract => {
+                        ts.extend(quote!{
+                            p4rs::bitmath::sat_sub_le(#lhs_tks.clone(), #rhs_tks.clone())
+                        });
+                    }
                     BinOp::Eq | BinOp::NotEq => {
                         let lhs_tks_ = match &lhs.as_ref().kind {
                             ExpressionKind::Lvalue(lval) => {
@@ -59,6 +112,13 @@ impl<'a> ExpressionGenerator<'a> {
                                         )
                                     });
                                 match name_info.decl {
+                                    DeclarationInfo::ActionParameter(
+                                        Direction::Out | Direction::InOut,
+                                    ) => {
+                                        quote! {
+                                            &*#lhs_tks
+                                        }
+                                    }
                                     DeclarationInfo::ActionParameter(_) => {
                                         quote! {
                                             &#lhs_tks
@@ -82,6 +142,13 @@ impl<'a> ExpressionGenerator<'a> {
                                         )
                                     });
                                 match name_info.decl {
+                                    DeclarationInfo::ActionParameter(
+                                        Direction::Out | Direction::InOut,
+                                    ) => {
+                                        quote! {
+                                            &*#rhs_tks
+                                        }
+                                    }
                                     DeclarationInfo::ActionParameter(_) => {
                                         quote! {
                                             &#rhs_tks
@@ -106,7 +173,12 @@ impl<'a> ExpressionGenerator<'a> {
             }
             ExpressionKind::Index(lval, xpr) => {
                 let mut ts = self.generate_lvalue(lval);
-                ts.extend(self.generate_expression(xpr.as_ref()));
+                match &xpr.kind {
+                    ExpressionKind::Slice(begin, end) => {
+                        ts.extend(self.generate_slice(lval, begin, end));
+                    }
+                    _ => ts.extend(self.generate_expression(xpr.as_ref())),
+                }
                 ts
             }
             ExpressionKind::Slice(begin, end) => {
@@ -124,13 +196,17 @@ impl<'a> ExpressionGenerator<'a> {
                 }
             }
             ExpressionKind::Call(call) => {
-                let lv: Vec<TokenStream> = call
-                    .lval
-                    .name
-                    .split('.')
-                    .map(|x| format_ident!("{}", x))
-                    .map(|x| quote! { #x })
-                    .collect();
+                if let Some(ts) = self.generate_user_extern_call(call) {
+                    return ts;
+                }
+                if let Some(ts) = self.generate_meter_call(call) {
+                    return ts;
+                }
+                if let Some(ts) = self.generate_header_size_call(call) {
+                    return ts;
+                }
+                let lv: Vec<TokenStream> =
+                    call.lval.name.split('.').map(lvalue_segment).collect();
 
                 let lvalue = quote! { #(#lv).* };
                 let mut args = Vec::new();
@@ -150,6 +226,250 @@ impl<'a> ExpressionGenerator<'a> {
                     &[ #(&#parts),* ]
                 }
             }
+            ExpressionKind::StructLit(members) => {
+                self.generate_struct_literal(xpr, members)
+            }
+        }
+    }
+
+    /// A struct literal is lowered to a Rust struct literal of the struct
+    /// the hlir resolved it to. Integer literals initializing bit members take
+    /// the width of the member.
+    fn generate_struct_literal(
+        &self,
+        xpr: &Expression,
+        members: &[(String, Box<Expression>)],
+    ) -> TokenStream {
+        let decl = match self.hlir.expression_types.get(xpr) {
+            Some(Type::UserDefined(name)) => self.ast.get_struct(name),
+            _ => None,
+        };
+        let decl = decl.unwrap_or_else(|| {
+            panic!("codegen struct type not found for {:#?}", xpr)
+        });
+
+        let mut fields = Vec::new();
+        for (name, value) in members {
+            let ty = decl
+                .members
+                .iter()
+                .find(|m| &m.name == name)
+                .map(|m| &m.ty)
+                .unwrap_or_else(|| {
+                    panic!("struct {} has no member {}", decl.name, name)
+                });
+            let v = match (&value.kind, ty) {
+                (ExpressionKind::IntegerLit(v), Type::Bit(width)) => {
+                    self.generate_bit_literal(*width as u16, *v as u128)
+                }
+                (_, Type::Bool) => self.generate_expression(value),
+                _ => {
+                    let v = self.generate_expression(value);
+                    quote! { #v.clone() }
+                }
+            };
+            let field = format_ident!("{}", name);
+            fields.push(quote! { #field: #v });
+        }
+
+        let name = format_ident!("{}", decl.name);
+        quote! { #name { #(#fields),* } }
+    }
+
+    /// Calls to methods of externs that are not built in to p4rs are
+    /// dispatched by name to the implementation registered with the pipeline.
+    pub(crate) fn generate_user_extern_call(
+        &self,
+        call: &Call,
+    ) -> Option<TokenStream> {
+        let typename = match self.hlir.lvalue_decls.get(&call.lval.pop_right())
+        {
+            Some(NameInfo {
+                ty: Type::UserDefined(typename),
+                ..
+            }) => typename,
+            _ => return None,
+        };
+        let ext = self.ast.get_extern(typename)?;
+        if is_builtin_extern(typename) {
+            return None;
+        }
+        let instance: Vec<TokenStream> = call
+            .lval
+            .pop_right()
+            .name
+            .split('.')
+            .map(lvalue_segment)
+            .collect();
+        let method = call.lval.leaf();
+        let m = match ext.methods.iter().find(|m| m.name == method) {
+            Some(m) => m,
+            None => panic!("extern {} has no method {}", typename, method),
+        };
+        if m.parameters
+            .iter()
+            .any(|p| matches!(p.direction, Direction::Out | Direction::InOut))
+        {
+            return Some(
+                self.generate_user_extern_call_mut(&instance, method, m, call),
+            );
+        }
+        let args: Vec<TokenStream> = call
+            .args
+            .iter()
+            .map(|a| self.generate_expression(a.as_ref()))
+            .collect();
+        let elements = match &m.return_type {
+            Type::List(elements) => elements,
+            _ => {
+                return Some(quote! {
+                    #(#instance).*.call(#method, &[#(&#args),*])
+                })
+            }
+        };
+        // methods returning a tuple return one bitvec per element
+        let elements = elements.iter().map(|ty| match ty.as_ref() {
+            Type::Bool => quote! { r.next().unwrap_or_default().any() },
+            _ => quote! { r.next().unwrap_or_default() },
+        });
+        Some(quote! {
+            {
+                let mut r = #(#instance).*
+                    .call_tuple(#method, &[#(&#args),*])
+                    .into_iter();
+                (#(#elements,)*)
+            }
+        })
+    }
+
+    /// Methods with `out` or `inout` parameters are passed every argument by
+    /// mutable reference so the implementation can write through them. `in`
+    /// arguments are copied so the implementation cannot modify the values
+    /// they were taken from.
+    fn generate_user_extern_call_mut(
+        &self,
+        instance: &[TokenStream],
+        method: &str,
+        m: &ExternMethod,
+        call: &Call,
+    ) -> TokenStream {
+        let mut locals = Vec::new();
+        let mut args = Vec::new();
+        let mut dirty = Vec::new();
+        for (i, (a, p)) in call.args.iter().zip(&m.parameters).enumerate() {
+            let xpr = self.generate_expression(a.as_ref());
+            match p.direction {
+                Direction::Out | Direction::InOut => {
+                    args.push(quote! { &mut #xpr });
+                    if let ExpressionKind::Lvalue(lval) = &a.kind {
+                        dirty.push(self.mark_written(lval));
+                    }
+                }
+                _ => {
+                    let local = format_ident!("arg{}", i);
+                    locals.push(quote! { let mut #local = #xpr.clone(); });
+                    args.push(quote! { &mut #local });
+                }
+            }
+        }
+        quote! {
+            {
+                #(#locals)*
+                let r = #(#instance).*.call_mut(#method, &mut [#(#args),*]);
+                #(#dirty)*
+                r
+            }
+        }
+    }
+
+    /// Statements recording a write to `lval` in the headers it belongs to.
+    pub(crate) fn mark_written(&self, lval: &Lvalue) -> TokenStream {
+        if is_header_member(lval, self.hlir) {
+            let header = lval.pop_right();
+            let lv: Vec<TokenStream> =
+                header.name.split('.').map(lvalue_segment).collect();
+            return quote! { #(#lv).*.__dirty = true; };
+        }
+        match self.hlir.lvalue_decls.get(lval) {
+            Some(info) => mark_dirty(lval, &info.ty, self.ast),
+            None => TokenStream::new(),
+        }
+    }
+
+    /// Meters are executed against state held by the pipeline, arguments are
+    /// passed by reference so header fields are not moved out of.
+    pub(crate) fn generate_meter_call(
+        &self,
+        call: &Call,
+    ) -> Option<TokenStream> {
+        match self.hlir.lvalue_decls.get(&call.lval.pop_right()) {
+            Some(NameInfo {
+                ty: Type::UserDefined(typename),
+                ..
+            }) if typename == "Meter" => {}
+            _ => return None,
+        }
+        let lv: Vec<TokenStream> =
+            call.lval.name.split('.').map(lvalue_segment).collect();
+        let args: Vec<TokenStream> = call
+            .args
+            .iter()
+            .map(|a| self.generate_expression(a.as_ref()))
+            .collect();
+        Some(quote! {
+            #(#lv).*(#(&#args),*)
+        })
+    }
+
+    /// Header size queries are evaluated at compile time.
+    fn generate_header_size_call(&self, call: &Call) -> Option<TokenStream> {
+        let typename = match self.hlir.lvalue_decls.get(&call.lval.pop_right())
+        {
+            Some(NameInfo {
+                ty: Type::UserDefined(typename),
+                ..
+            }) => typename,
+            _ => return None,
+        };
+        let size = self
+            .ast
+            .get_header(typename)?
+            .size_query(call.lval.leaf())? as i128;
+        Some(quote! { #size })
+    }
+
+    /// Generate the range of the underlying bitvec `lval[begin:end]` reads.
+    /// Values wider than a byte are stored with their bytes reversed, so bit
+    /// `i` of such a value is bit `7 - i % 8` of byte `i / 8`. Values of a byte
+    /// or less keep their bits in order.
+    fn generate_slice(
+        &self,
+        lval: &Lvalue,
+        begin: &Expression,
+        end: &Expression,
+    ) -> TokenStream {
+        let hi = match &begin.kind {
+            ExpressionKind::IntegerLit(v) => *v as usize,
+            _ => panic!("slice ranges can only be integer literals"),
+        };
+        let lo = match &end.kind {
+            ExpressionKind::IntegerLit(v) => *v as usize,
+            _ => panic!("slice ranges can only be integer literals"),
+        };
+        let width = match self.hlir.lvalue_decls.get(lval).map(|x| &x.ty) {
+            Some(Type::Bit(n)) => Some(*n),
+            _ => None,
+        };
+        let (l, r) = match width {
+            Some(n) if n <= 8 => (n - 1 - hi, n - lo),
+            Some(_) if hi / 8 == lo / 8 => {
+                let byte = hi / 8 * 8;
+                (byte + 7 - hi % 8, byte + 8 - lo % 8)
+            }
+            _ => (lo, hi + 1),
+        };
+        quote! {
+            [#l..#r]
         }
     }
 
@@ -163,11 +483,7 @@ impl<'a> ExpressionGenerator<'a> {
         let width = width as usize;
 
         quote! {
-            {
-                let mut x = bitvec![mut u8, Msb0; 0; #width];
-                x.store_le(#value);
-                x
-            }
+            p4rs::endian::store(#width, #value)
         }
     }
 
@@ -175,6 +491,11 @@ impl<'a> ExpressionGenerator<'a> {
         match op {
             BinOp::Add => quote! { + },
             BinOp::Subtract => quote! { - },
+            // Saturating arithmetic has no operator in Rust and is always
+            // lowered to a method or function call.
+            BinOp::SaturatingAdd | BinOp::SaturatingSubtract => {
+                TokenStream::new()
+            }
             BinOp::Mod => quote! { % },
             BinOp::Geq => quote! { >= },
             BinOp::Gt => quote! { > },
@@ -189,13 +510,59 @@ impl<'a> ExpressionGenerator<'a> {
         }
     }
 
-    pub(crate) fn generate_lvalue(&self, lval: &Lvalue) -> TokenStream {
-        let lv: Vec<TokenStream> = lval
+    /// Generate an lvalue that is read from. Reads of header fields are
+    /// checked when checked header access is on.
+    fn generate_lvalue_read(&self, lval: &Lvalue) -> TokenStream {
+        let header_member = matches!(
+            self.hlir.lvalue_decls.get(lval),
+            Some(NameInfo {
+                decl: DeclarationInfo::HeaderMember,
+                ..
+            })
+        );
+        if !self.checked_header_access || !header_member {
+            return self.generate_lvalue(lval);
+        }
+        let header: Vec<TokenStream> = lval
+            .pop_right()
             .name
             .split('.')
-            .map(|x| format_ident!("{}", x))
-            .map(|x| quote! { #x })
+            .map(lvalue_segment)
             .collect();
+        let field = format_ident!("{}", lval.leaf());
+        let name = &lval.name;
+        quote! {
+            p4rs::valid_header(&#(#header).*, #name).#field
+        }
+    }
+
+    pub(crate) fn generate_lvalue(&self, lval: &Lvalue) -> TokenStream {
+        // members of the error namespace are variants of the generated error
+        // enum.
+        if lval.root() == "error" && lval.degree() == 2 {
+            let member = format_ident!("{}", lval.leaf());
+            return quote! { error::#member };
+        }
+
+        // elements of tuples are fields of rust tuples
+        let (root, index) = Lvalue::split_index(lval.root());
+        if let Some(i) = index {
+            let root_lval = Lvalue {
+                name: root.to_owned(),
+                token: lval.token.clone(),
+            };
+            if let Some(NameInfo {
+                ty: Type::List(_), ..
+            }) = self.hlir.lvalue_decls.get(&root_lval)
+            {
+                let root = format_ident!("{}", root);
+                let i = syn::Index::from(i);
+                return quote! { #root.#i };
+            }
+        }
+
+        let lv: Vec<TokenStream> =
+            lval.name.split('.').map(lvalue_segment).collect();
 
         let lvalue = quote! { #(#lv).* };
 
@@ -1,7 +1,7 @@
 // Copyright 2022 Oxide Computer Company
 
 use crate::{rust_type, type_size, Context};
-use p4::ast::{Header, AST};
+use p4::ast::{Header, HeaderUnion, AST};
 use quote::{format_ident, quote};
 
 pub(crate) struct HeaderGenerator<'a> {
@@ -18,6 +18,9 @@ impl<'a> HeaderGenerator<'a> {
         for h in &self.ast.headers {
             self.generate_header(h);
         }
+        for u in &self.ast.header_unions {
+            self.generate_header_union(u);
+        }
     }
 
     fn generate_header(&mut self, h: &Header) {
@@ -39,6 +42,10 @@ impl<'a> HeaderGenerator<'a> {
             #[derive(Debug, Default, Clone)]
             pub struct #name {
                 pub valid: bool,
+                /// Set when the header is written after it was extracted,
+                /// meaning it no longer matches the packet it came from. The
+                /// name cannot clash with a field of the P4 header.
+                pub __dirty: bool,
                 #(#members),*
             }
         };
@@ -51,10 +58,10 @@ impl<'a> HeaderGenerator<'a> {
         let mut member_values = Vec::new();
         let mut set_statements = Vec::new();
         let mut to_bitvec_statements = Vec::new();
-        let mut checksum_statements = Vec::new();
         let mut dump_statements = Vec::new();
         let fmt = "{} ".repeat(h.members.len() * 2);
         let fmt = fmt.trim();
+        let header_s = &h.name;
         let mut offset = 0;
         for member in &h.members {
             let name = format_ident!("{}", member.name);
@@ -65,52 +72,27 @@ impl<'a> HeaderGenerator<'a> {
             });
             let end = offset + size;
             set_statements.push(quote! {
-                self.#name = {
-                    let mut b = buf.view_bits::<Msb0>()[#offset..#end].to_owned();
-                    // NOTE this barfing and then unbarfing a vec is to handle
-                    // the p4 confused-endian data model.
-                    if #end-#offset > 8 {
-                        let mut v = b.into_vec();
-                        v.reverse();
-                        if ((#end-#offset) % 8) != 0 {
-                            if let Some(x) = v.iter_mut().last() {
-                                *x <<= (#offset % 8);
-                            }
-                        }
-                        let mut b = BitVec::<u8, Msb0>::from_vec(v);
-                        b.resize(#end-#offset, false);
-                        b
-                    } else {
-                        b
-                    }
+                if buf.len() << 3 < #end {
+                    return Err(TryFromSliceError {
+                        expected: #end,
+                        actual: buf.len() << 3,
+                        header: Some(#header_s),
+                        field: Some(#name_s),
+                    });
                 }
+                self.#name = p4rs::endian::Endian::Network.extract(
+                    buf,
+                    #offset,
+                    #end,
+                );
             });
             to_bitvec_statements.push(quote! {
-                // NOTE this barfing and then unbarfing a vec is to handle
-                // the p4 confused-endian data model.
-                if #end-#offset > 8 {
-                    let mut v = self.#name.clone().into_vec();
-                    if ((#end-#offset) % 8) != 0 {
-                        if let Some(x) = v.iter_mut().last() {
-                            *x >>= ((#end - #offset) % 8);
-                        }
-                    }
-                    v.reverse();
-                    let n = (#end-#offset);
-                    let m = n%8;
-                    let mut b = BitVec::<u8, Msb0>::from_vec(v);
-                    // this field may be null so check that we at least have
-                    // enough space to offset
-                    if b.len() > m {
-                        x[#offset..#end] |= &b[m..];
-                    }
-                } else {
-                    x[#offset..#end] |= self.#name.to_owned();
-                }
-
-            });
-            checksum_statements.push(quote! {
-                csum = p4rs::bitmath::add_le(csum.clone(), self.#name.csum())
+                p4rs::endian::Endian::Network.deposit(
+                    &self.#name,
+                    &mut x,
+                    #offset,
+                    #end,
+                )
             });
             dump_statements.push(quote! {
                 #name_s.cyan(),
@@ -130,6 +112,7 @@ impl<'a> HeaderGenerator<'a> {
                 fn new() -> Self {
                     Self {
                         valid: false,
+                        __dirty: false,
                         #(#member_values),*
                     }
                 }
@@ -161,18 +144,23 @@ impl<'a> HeaderGenerator<'a> {
 
             impl Checksum for #name {
                 fn csum(&self) -> BitVec::<u8, Msb0> {
-                    let mut csum = BitVec::new();
-                    #(#checksum_statements);*;
-                    csum
+                    p4rs::checksum::wire_csum(
+                        self.to_bitvec().as_raw_slice(),
+                    )
                 }
             }
 
             impl #name {
                 fn setValid(&mut self) {
                     self.valid = true;
+                    self.__dirty = true;
                 }
                 fn setInvalid(&mut self) {
                     self.valid = false;
+                    self.__dirty = true;
+                }
+                fn is_dirty(&self) -> bool {
+                    self.__dirty
                 }
                 fn isValid(&self) -> bool {
                     self.valid
@@ -189,4 +177,81 @@ impl<'a> HeaderGenerator<'a> {
 
         self.ctx.structs.insert(h.name.clone(), generated);
     }
+
+    fn generate_header_union(&mut self, u: &HeaderUnion) {
+        let name = format_ident!("{}", u.name);
+
+        let mut members = Vec::new();
+        let mut valid_checks = Vec::new();
+        let mut dirty_checks = Vec::new();
+        let mut invalidate_statements = Vec::new();
+        let mut size_statements = Vec::new();
+        let mut to_bitvec_statements = Vec::new();
+        let mut dump_statements = Vec::new();
+        for member in &u.members {
+            let name = format_ident!("{}", member.name);
+            let name_s = &member.name;
+            let ty = rust_type(&member.ty);
+            members.push(quote! { pub #name: #ty });
+            valid_checks.push(quote! { self.#name.valid });
+            dirty_checks.push(quote! { self.#name.__dirty });
+            invalidate_statements.push(quote! { self.#name.valid = false; });
+            size_statements.push(quote! {
+                if self.#name.valid {
+                    return self.#name.width_bits();
+                }
+            });
+            to_bitvec_statements.push(quote! {
+                if self.#name.valid {
+                    return self.#name.to_bitvec();
+                }
+            });
+            dump_statements.push(quote! {
+                if self.#name.valid {
+                    return format!("{}: {}", #name_s.cyan(), self.#name.dump());
+                }
+            });
+        }
+
+        // A header union is represented as a struct holding each alternative
+        // header. Extraction into, or validation of, a member invalidates the
+        // other members so at most one member is valid at any given time.
+        // Because of this, only the first valid member needs to be considered
+        // when computing size and serializing.
+        let generated = quote! {
+            #[derive(Debug, Default, Clone)]
+            pub struct #name {
+                #(#members),*
+            }
+
+            impl #name {
+                fn isValid(&self) -> bool {
+                    false #(|| #valid_checks)*
+                }
+                fn is_valid(&self) -> bool {
+                    self.isValid()
+                }
+                fn is_dirty(&self) -> bool {
+                    false #(|| #dirty_checks)*
+                }
+                fn set_invalid(&mut self) {
+                    #(#invalidate_statements)*
+                }
+                fn valid_header_size(&self) -> usize {
+                    #(#size_statements)*
+                    0
+                }
+                fn to_bitvec(&self) -> BitVec<u8, Msb0> {
+                    #(#to_bitvec_statements)*
+                    bitvec![u8, Msb0; 0; 0]
+                }
+                fn dump(&self) -> String {
+                    #(#dump_statements)*
+                    "∅".to_owned()
+                }
+            }
+        };
+
+        self.ctx.structs.insert(u.name.clone(), generated);
+    }
 }
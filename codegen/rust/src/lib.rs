@@ -1,5 +1,6 @@
 // Copyright 2022 Oxide Computer Company
 
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fs;
 use std::io::{self, Write};
@@ -8,9 +9,10 @@ use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
 
 use p4::ast::{
-    ActionParameter, Control, ControlParameter, DeclarationInfo, Direction,
-    Expression, ExpressionKind, HeaderMember, Lvalue, MutVisitor, NameInfo,
-    Parser, StructMember, Table, Type, UserDefinedType, AST,
+    Action, ActionParameter, ActionRef, Constant, Control, ControlParameter,
+    DeclarationInfo, Direction, Expression, ExpressionKind, HeaderMember,
+    Lvalue, MutVisitor, NameInfo, Parser, SelectElement, State, StructMember,
+    Table, Type, UserDefinedType, Variable, AST,
 };
 use p4::hlir::Hlir;
 use p4::util::resolve_lvalue;
@@ -40,48 +42,177 @@ struct Context {
 
     /// Pipeline structures we've generated.
     pipelines: HashMap<String, TokenStream>,
+
+    /// See [`Settings::checked_header_access`].
+    checked_header_access: bool,
+
+    /// See [`Settings::standalone_controls`].
+    standalone_controls: Vec<String>,
+
+    /// See [`Settings::names`].
+    names: SanitizedNames,
 }
 
 pub struct Settings {
     /// Name to give to the C-ABI constructor.
     pub pipeline_name: String,
+
+    /// Original names of identifiers rewritten by [`sanitize`]. Names the
+    /// runtime exposes, such as table ids, use these.
+    pub names: SanitizedNames,
+
+    /// Check that a header is valid each time one of its fields is read.
+    /// Reading a field of an invalid header is undefined in P4 and usually a
+    /// bug. Debug builds of the generated code panic on such reads.
+    pub checked_header_access: bool,
+
+    /// Controls to generate a standalone `<control>_control` struct for. The
+    /// struct holds the tables of the control and applies it directly, so a
+    /// control can be tested without assembling a pipeline around it.
+    pub standalone_controls: Vec<String>,
+
+    /// Emit the original header bytes of packets whose headers were not
+    /// modified by the pipeline instead of serializing the parsed headers
+    /// again. Pass-through packets then skip serialization entirely.
+    pub optimize: bool,
 }
 
-pub struct Sanitizer {}
+/// Rust keywords, including those reserved for future use. Names in a P4
+/// program that are Rust keywords cannot be used as Rust identifiers as is.
+const RUST_KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const",
+    "continue", "crate", "do", "dyn", "else", "enum", "extern", "false",
+    "final", "fn", "for", "if", "impl", "in", "let", "loop", "macro", "match",
+    "mod", "move", "mut", "override", "priv", "pub", "ref", "return", "self",
+    "Self", "static", "struct", "super", "trait", "true", "try", "type",
+    "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+/// Rewrites names in a P4 program that are Rust keywords so they can be used
+/// as Rust identifiers. Keywords get a trailing underscore, except for `type`
+/// which has always been rewritten to `typ`. Every rewrite is recorded so the
+/// names the runtime hands to controllers can stay the names in the source.
+#[derive(Default)]
+pub struct Sanitizer {
+    names: RefCell<SanitizedNames>,
+}
+
+/// The original P4 names of identifiers rewritten by the [`Sanitizer`], keyed
+/// by their sanitized name.
+#[derive(Debug, Default, Clone)]
+pub struct SanitizedNames {
+    original: HashMap<String, String>,
+}
+
+impl SanitizedNames {
+    /// The P4 name for a sanitized name. Names that were not rewritten are
+    /// returned as is.
+    pub fn original(&self, name: &str) -> String {
+        match self.original.get(name) {
+            Some(original) => original.clone(),
+            None => name.to_owned(),
+        }
+    }
+
+    /// The P4 name for a sanitized dotted lvalue name, e.g. `hdr.kw.typ`
+    /// becomes `hdr.kw.type`.
+    pub fn original_lvalue(&self, name: &str) -> String {
+        name.split('.')
+            .map(|segment| match segment.find('[') {
+                Some(i) => self.original(&segment[..i]) + &segment[i..],
+                None => self.original(segment),
+            })
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+}
 
 impl Sanitizer {
     pub fn sanitize_string(s: &mut String) {
-        //TODO sanitize other problematic rust tokens
         if s == "type" {
             "typ".clone_into(s)
+        } else if RUST_KEYWORDS.contains(&s.as_str()) {
+            s.push('_')
         }
-        if s == "match" {
-            "match_".clone_into(s)
+    }
+
+    /// The names rewritten so far.
+    pub fn names(&self) -> SanitizedNames {
+        self.names.borrow().clone()
+    }
+
+    fn rename(&self, s: &mut String) {
+        let original = s.clone();
+        Self::sanitize_string(s);
+        if *s != original {
+            self.names.borrow_mut().original.insert(s.clone(), original);
         }
     }
+
+    /// Sanitize each segment of a dotted lvalue name, leaving any index
+    /// on a segment in place.
+    fn rename_lvalue(&self, s: &mut String) {
+        let segments: Vec<String> = s
+            .split('.')
+            .map(|segment| {
+                let (name, index) = match segment.find('[') {
+                    Some(i) => segment.split_at(i),
+                    None => (segment, ""),
+                };
+                let mut name = name.to_owned();
+                self.rename(&mut name);
+                name + index
+            })
+            .collect();
+        *s = segments.join(".");
+    }
 }
 
 impl MutVisitor for Sanitizer {
     fn struct_member(&self, m: &mut StructMember) {
-        Self::sanitize_string(&mut m.name);
+        self.rename(&mut m.name);
     }
     fn header_member(&self, m: &mut HeaderMember) {
-        Self::sanitize_string(&mut m.name);
+        self.rename(&mut m.name);
     }
     fn control_parameter(&self, p: &mut ControlParameter) {
-        Self::sanitize_string(&mut p.name);
+        self.rename(&mut p.name);
     }
     fn action_parameter(&self, p: &mut ActionParameter) {
-        Self::sanitize_string(&mut p.name);
+        self.rename(&mut p.name);
+    }
+    fn variable(&self, v: &mut Variable) {
+        self.rename(&mut v.name);
+    }
+    fn constant(&self, c: &mut Constant) {
+        self.rename(&mut c.name);
     }
     fn lvalue(&self, lv: &mut Lvalue) {
-        Self::sanitize_string(&mut lv.name);
+        self.rename_lvalue(&mut lv.name);
+    }
+    fn state(&self, s: &mut State) {
+        self.rename(&mut s.name);
+    }
+    fn select_element(&self, e: &mut SelectElement) {
+        self.rename(&mut e.name);
+    }
+    fn table(&self, t: &mut Table) {
+        self.rename(&mut t.name);
+    }
+    fn action(&self, a: &mut Action) {
+        self.rename(&mut a.name);
+    }
+    fn action_ref(&self, a: &mut ActionRef) {
+        self.rename(&mut a.name);
     }
 }
 
-pub fn sanitize(ast: &mut AST) {
-    let s = Sanitizer {};
+/// Sanitize the names in `ast` for use as Rust identifiers, returning the
+/// original names of everything that was rewritten.
+pub fn sanitize(ast: &mut AST) -> SanitizedNames {
+    let s = Sanitizer::default();
     ast.mut_accept(&s);
+    s.names()
 }
 
 pub fn emit(
@@ -125,7 +256,12 @@ pub fn emit_tokens(ast: &AST, hlir: &Hlir, settings: Settings) -> TokenStream {
     // initialize a context to track state while we generate code
     //
 
-    let mut ctx = Context::default();
+    let mut ctx = Context {
+        checked_header_access: settings.checked_header_access,
+        standalone_controls: settings.standalone_controls.clone(),
+        names: settings.names.clone(),
+        ..Default::default()
+    };
 
     //
     // genearate rust code for the P4 AST
@@ -207,11 +343,44 @@ fn get_parser_arg<'a>(
     parser.parameters.iter().find(|&arg| arg.name == arg_name)
 }
 
+/// Return the rust tokens for one segment of a dotted lvalue path. Header
+/// stack elements such as `vlan[1]` become array indexing.
+fn lvalue_segment(part: &str) -> TokenStream {
+    let (name, index) = Lvalue::split_index(part);
+    let ident = format_ident!("{}", name);
+    match index {
+        Some(i) => quote! { #ident[#i] },
+        None => quote! { #ident },
+    }
+}
+
+/// Externs implemented natively by `p4rs::externs`. Any other extern is
+/// implemented by the host and dispatched through the pipeline's extern
+/// registry.
+const BUILTIN_EXTERNS: &[&str] = &["Checksum", "Meter"];
+
+fn is_builtin_extern(typename: &str) -> bool {
+    BUILTIN_EXTERNS.contains(&typename)
+}
+
+/// Return the rust type for an instance of the given extern type.
+fn extern_type(typename: &str) -> TokenStream {
+    // meter state lives in the pipeline, instances are handles onto it.
+    if typename == "Meter" {
+        quote! { p4rs::externs::MeterInstance }
+    } else if is_builtin_extern(typename) {
+        let typename = format_ident!("{}", typename);
+        quote! { p4rs::externs::#typename }
+    } else {
+        quote! { p4rs::externs::UserExtern }
+    }
+}
+
 /// Return the rust type for a given P4 type.
 fn rust_type(ty: &Type) -> TokenStream {
     match ty {
         Type::Bool => quote! { bool },
-        Type::Error => todo!("generate error type"),
+        Type::Error => quote! { error },
         Type::Bit(_size) => {
             quote! { BitVec::<u8, Msb0> }
         }
@@ -228,13 +397,20 @@ fn rust_type(ty: &Type) -> TokenStream {
         Type::HeaderMethod => {
             todo!("rust type for header method");
         }
+        Type::HeaderStack(name, size) => {
+            let typename = format_ident!("{}", name);
+            quote! { [#typename; #size] }
+        }
         Type::Table => {
             todo!("rust type for table");
         }
         Type::Void => {
             quote! { () }
         }
-        Type::List(_) => todo!("rust type for list"),
+        Type::List(elements) => {
+            let elements = elements.iter().map(|e| rust_type(e));
+            quote! { (#(#elements,)*) }
+        }
         Type::State => {
             todo!("rust type for state");
         }
@@ -271,6 +447,12 @@ fn type_size(ty: &Type, ast: &AST) -> usize {
                     }
                     sz
                 }
+                UserDefinedType::HeaderUnion(u) => u
+                    .members
+                    .iter()
+                    .map(|m| type_size(&m.ty, ast))
+                    .max()
+                    .unwrap_or(0),
                 UserDefinedType::Extern(_) => {
                     todo!("size for extern?");
                 }
@@ -282,6 +464,9 @@ fn type_size(ty: &Type, ast: &AST) -> usize {
         Type::HeaderMethod => {
             todo!("type size for header method");
         }
+        Type::HeaderStack(name, size) => {
+            size * type_size(&Type::UserDefined(name.clone()), ast)
+        }
         Type::Table => {
             todo!("type size for table");
         }
@@ -351,6 +536,34 @@ fn is_header_member(lval: &Lvalue, hlir: &Hlir) -> bool {
     }
 }
 
+/// Statements recording that the value `lval` of type `ty` was written, for
+/// each header it holds. A header written after it was extracted no longer
+/// matches the packet, so its original bytes cannot be emitted in its place.
+fn mark_dirty(lval: &Lvalue, ty: &Type, ast: &AST) -> TokenStream {
+    let lv: Vec<TokenStream> =
+        lval.name.split('.').map(lvalue_segment).collect();
+    let lvalue = quote! { #(#lv).* };
+    match ty {
+        Type::HeaderStack(..) => quote! {
+            for h in #lvalue.iter_mut() {
+                h.__dirty = true;
+            }
+        },
+        Type::UserDefined(name) => match ast.get_user_defined_type(name) {
+            Some(UserDefinedType::Header(_)) => quote! {
+                #lvalue.__dirty = true;
+            },
+            Some(UserDefinedType::HeaderUnion(u)) => {
+                let members =
+                    u.members.iter().map(|m| format_ident!("{}", m.name));
+                quote! { #(#lvalue.#members.__dirty = true;)* }
+            }
+            _ => TokenStream::new(),
+        },
+        _ => TokenStream::new(),
+    }
+}
+
 // TODO define in terms of hlir rather than names
 fn is_rust_reference(lval: &Lvalue, names: &HashMap<String, NameInfo>) -> bool {
     if lval.degree() == 1 {
@@ -371,6 +584,8 @@ fn is_rust_reference(lval: &Lvalue, names: &HashMap<String, NameInfo>) -> bool {
             DeclarationInfo::ControlMember => false,
             DeclarationInfo::State => false,
             DeclarationInfo::Action => false,
+            DeclarationInfo::ActionParameter(Direction::Out) => true,
+            DeclarationInfo::ActionParameter(Direction::InOut) => true,
             DeclarationInfo::ActionParameter(_) => false,
         }
     } else {
@@ -378,44 +593,47 @@ fn is_rust_reference(lval: &Lvalue, names: &HashMap<String, NameInfo>) -> bool {
     }
 }
 
+/// The qualified name of a table as used for runtime table ids, e.g.
+/// `ingress.router.ipv6_routes`. Table ids use the names from the P4 source,
+/// not their sanitized forms.
 fn qualified_table_name(
     control: Option<&Control>,
-    chain: &Vec<(String, &Control)>,
+    chain: &[(String, &Control)],
     table: &Table,
+    names: &SanitizedNames,
 ) -> String {
-    match control {
-        Some(control) => {
-            format!("{}.{}", control.name, table_qname(chain, table, '.'))
-        }
-        _ => table_qname(chain, table, '.'),
-    }
+    names.original_lvalue(&table_qname(control, chain, table, '.'))
 }
 
+/// The qualified name of a table as used for generated Rust identifiers, e.g.
+/// `ingress_router_ipv6_routes`.
 fn qualified_table_function_name(
     control: Option<&Control>,
-    chain: &Vec<(String, &Control)>,
+    chain: &[(String, &Control)],
     table: &Table,
 ) -> String {
-    match control {
-        Some(control) => {
-            format!("{}_{}", control.name, table_qname(chain, table, '_'))
-        }
-        _ => table_qname(chain, table, '_'),
-    }
+    table_qname(control, chain, table, '_')
 }
 
 fn table_qname(
-    chain: &Vec<(String, &Control)>,
+    control: Option<&Control>,
+    chain: &[(String, &Control)],
     table: &Table,
     sep: char,
 ) -> String {
-    let mut qname = String::new();
-    for c in chain {
-        if c.0.is_empty() {
-            continue;
-        }
-        qname += &format!("{}{}", c.0, sep);
+    let mut segments = Vec::new();
+    if let Some(control) = control {
+        segments.push(control.name.as_str());
     }
-    qname += &table.name;
-    qname
+    // The root of a control chain is the control the tables were collected
+    // from and carries an empty name, nested control instances carry their
+    // instance name.
+    segments.extend(
+        chain
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .filter(|name| !name.is_empty()),
+    );
+    segments.push(table.name.as_str());
+    segments.join(&sep.to_string())
 }
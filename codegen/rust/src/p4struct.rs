@@ -15,14 +15,40 @@ impl<'a> StructGenerator<'a> {
     }
 
     pub(crate) fn generate(&mut self) {
+        self.generate_error();
         for s in &self.ast.structs {
             self.generate_struct(s);
         }
     }
 
+    fn generate_error(&mut self) {
+        // NoError is always present as the default value of the error type,
+        // regardless of whether the program declares it.
+        let members: Vec<_> = self
+            .ast
+            .errors
+            .iter()
+            .filter(|e| e.as_str() != "NoError")
+            .map(|e| format_ident!("{}", e))
+            .collect();
+
+        let structure = quote! {
+            #[allow(non_camel_case_types)]
+            #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+            pub enum error {
+                #[default]
+                NoError,
+                #(#members),*
+            }
+        };
+
+        self.ctx.structs.insert("error".into(), structure);
+    }
+
     fn generate_struct(&mut self, s: &Struct) {
         let mut members = Vec::new();
         let mut valid_member_size = Vec::new();
+        let mut dirty_checks = Vec::new();
         let mut to_bitvec_stmts = Vec::new();
         let mut dump_statements = Vec::new();
         let fmt = "{}: {}\n".repeat(s.members.len());
@@ -41,16 +67,43 @@ impl<'a> StructGenerator<'a> {
 
                         // valid header size statements
                         valid_member_size.push(quote! {
-                            if self.#name.valid {
-                                x += #ty::size();
-                            }
+                            x += self.#name.width_bits();
                         });
 
+                        dirty_checks.push(quote! { self.#name.__dirty });
+
                         // to bitvec statements
-                        to_bitvec_stmts.push(quote!{
-                            if self.#name.valid {
-                                x[off..off+#ty::size()] |= self.#name.to_bitvec();
-                                off += #ty::size();
+                        to_bitvec_stmts.push(quote! {
+                            let n = self.#name.width_bits();
+                            if n > 0 {
+                                x[off..off+n] |= self.#name.to_bitvec();
+                                off += n;
+                            }
+                        });
+
+                        dump_statements.push(quote! {
+                            #name_s.blue(),
+                            self.#name.dump()
+                        });
+                    } else if self.ast.get_header_union(typename).is_some() {
+                        let ty = format_ident!("{}", typename);
+
+                        // member generation
+                        members.push(quote! { pub #name: #ty });
+
+                        // only the valid member of a header union, if any,
+                        // contributes to size and serialization
+                        valid_member_size.push(quote! {
+                            x += self.#name.valid_header_size();
+                        });
+
+                        dirty_checks.push(quote! { self.#name.is_dirty() });
+
+                        to_bitvec_stmts.push(quote! {
+                            let n = self.#name.valid_header_size();
+                            if n > 0 {
+                                x[off..off+n] |= self.#name.to_bitvec();
+                                off += n;
                             }
                         });
 
@@ -65,6 +118,42 @@ impl<'a> StructGenerator<'a> {
                         );
                     }
                 }
+                Type::HeaderStack(typename, size) => {
+                    let ty = format_ident!("{}", typename);
+
+                    // member generation
+                    members.push(quote! { pub #name: [#ty; #size] });
+
+                    // valid elements of the stack are serialized in order
+                    valid_member_size.push(quote! {
+                        for h in &self.#name {
+                            x += h.width_bits();
+                        }
+                    });
+
+                    dirty_checks.push(quote! {
+                        self.#name.iter().any(|h| h.__dirty)
+                    });
+
+                    to_bitvec_stmts.push(quote! {
+                        for h in &self.#name {
+                            let n = h.width_bits();
+                            if n > 0 {
+                                x[off..off+n] |= h.to_bitvec();
+                                off += n;
+                            }
+                        }
+                    });
+
+                    dump_statements.push(quote! {
+                        #name_s.blue(),
+                        self.#name
+                            .iter()
+                            .map(|h| h.dump())
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    });
+                }
                 Type::Bit(size) => {
                     members.push(quote! { pub #name: BitVec::<u8, Msb0> });
                     dump_statements.push(quote! {
@@ -79,6 +168,13 @@ impl<'a> StructGenerator<'a> {
                         off += #size;
                     });
                 }
+                Type::Error => {
+                    members.push(quote! { pub #name: error });
+                    dump_statements.push(quote! {
+                        #name_s.blue(),
+                        format!("{:?}", self.#name)
+                    });
+                }
                 Type::Bool => {
                     members.push(quote! { pub #name: bool });
                     dump_statements.push(quote! {
@@ -121,6 +217,12 @@ impl<'a> StructGenerator<'a> {
                         x
                     }
 
+                    /// Whether any header was written after it was
+                    /// extracted.
+                    fn is_dirty(&self) -> bool {
+                        false #(|| #dirty_checks)*
+                    }
+
                     fn dump(&self) -> String {
                         #dump
                     }
@@ -135,6 +237,8 @@ impl<'a> StructGenerator<'a> {
                         bitvec![u8, Msb0; 0; 0]
                     }
 
+                    fn is_dirty(&self) -> bool { false }
+
                     fn dump(&self) -> String {
                         std::string::String::new()
                     }
@@ -80,6 +80,7 @@ impl<'a> ParserGenerator<'a> {
             self.ast,
             self.hlir,
             StatementContext::Parser(parser),
+            self.ctx.checked_header_access,
         );
         let mut names = parser.names();
         sg.generate_block(&state.statements, &mut names)
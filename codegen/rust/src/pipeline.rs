@@ -1,11 +1,16 @@
 // Copyright 2022 Oxide Computer Company
 
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
 use crate::{
-    qualified_table_function_name, qualified_table_name, rust_type,
-    type_size_bytes, Context, Settings,
+    extern_type, is_builtin_extern, qualified_table_function_name,
+    qualified_table_name, rust_type, type_size, type_size_bytes, Context,
+    Settings,
 };
 use p4::ast::{
-    Control, Direction, MatchKind, PackageInstance, Parser, Table, Type, AST,
+    Action, Control, Direction, ExpressionKind, Lvalue, MatchKind,
+    PackageInstance, Parser, Statement, StatementBlock, Table, Transition,
+    Type, AST,
 };
 use p4::hlir::Hlir;
 use proc_macro2::TokenStream;
@@ -54,9 +59,9 @@ impl<'a> PipelineGenerator<'a> {
             panic!("Only the SoftNPU package is supported");
         }
 
-        if inst.parameters.len() != 3 {
+        if !(3..=4).contains(&inst.parameters.len()) {
             //TODO check this in the checker for a nicer failure mode.
-            panic!("SoftNPU instances take exactly 3 parameters");
+            panic!("SoftNPU instances take 3 or 4 parameters");
         }
 
         let parser = match self.ast.get_parser(&inst.parameters[0]) {
@@ -83,6 +88,21 @@ impl<'a> PipelineGenerator<'a> {
             }
         };
 
+        // The optional fourth argument is a deparser that determines the
+        // order headers are emitted in. Without one, headers are emitted in
+        // the order they are declared in the parsed headers struct.
+        let deparser = inst.parameters.get(3).map(|name| {
+            match self.ast.get_control(name) {
+                Some(c) => c,
+                None => {
+                    //TODO check this in the checker for a nicer failure mode.
+                    panic!(
+                        "Fourth argument to SoftNPU must be a control block"
+                    );
+                }
+            }
+        });
+
         let pipeline_name = format_ident!("{}_pipeline", inst.name);
 
         //
@@ -111,18 +131,38 @@ impl<'a> PipelineGenerator<'a> {
         let (egress_member, egress_initializer) =
             self.control_entrypoint("egress", egress);
 
-        let (pipeline_impl_process_packet, process_packet_headers) =
-            self.pipeline_impl_process_packet(parser, ingress, egress);
+        let (deparser_member, deparser_initializer) = match deparser {
+            Some(d) => {
+                let (m, i) = self.control_entrypoint("deparser", d);
+                (vec![m], vec![i])
+            }
+            None => (Vec::new(), Vec::new()),
+        };
+
+        let (pipeline_impl_process_packet, process_packet_headers) = self
+            .pipeline_impl_process_packet(parser, ingress, egress, deparser);
 
         let add_table_entry_method =
             self.add_table_entry_method(ingress, egress);
         let remove_table_entry_method =
             self.remove_table_entry_method(ingress, egress);
+        let clear_table_method = self.clear_table_method(ingress, egress);
+        let remove_table_entries_matching_method =
+            self.remove_table_entries_matching_method(ingress, egress);
         let get_table_entries_method =
             self.get_table_entries_method(ingress, egress);
         let get_table_ids_method = self.get_table_ids_method(ingress, egress);
+        let get_table_actions_method =
+            self.get_table_actions_method(ingress, egress);
+        let get_table_schema_method =
+            self.get_table_schema_method(ingress, egress);
+        let describe_method = self.describe_method(parser);
+        let table_location_method = self.table_location_method(ingress, egress);
 
         let table_modifiers = self.table_modifiers(ingress, egress);
+        let metadata_defaults = self.metadata_defaults(ingress, egress);
+
+        let user_externs = self.user_externs();
 
         let c_create_fn =
             format_ident!("_{}_pipeline_create", self.settings.pipeline_name);
@@ -133,18 +173,75 @@ impl<'a> PipelineGenerator<'a> {
                 #parse_member,
                 #ingress_member,
                 #egress_member,
+                #(#deparser_member,)*
+                externs: p4rs::externs::ExternRegistry,
+                externs_ready: bool,
                 radix: u16,
+                table_change: Vec<p4rs::TableChangeCallback>,
+                arena: Option<p4rs::arena::Arena>,
+                ingress_metadata: ingress_metadata_t,
+                egress_metadata: egress_metadata_t,
+                drops: std::cell::Cell<p4rs::DropStats>,
+                epoch: u64,
             }
 
             impl #pipeline_name {
+                /// Create a pipeline with `radix` ports, numbered from zero.
+                /// Panics if `radix` is zero, a pipeline needs at least one
+                /// port for packets to arrive on.
                 pub fn new(radix: u16) -> Self {
+                    assert!(radix > 0, "pipeline radix must be at least 1");
                     usdt::register_probes().unwrap();
+                    #metadata_defaults
                     Self {
                         #(#table_initializers,)*
                         #parser_initializer,
                         #ingress_initializer,
                         #egress_initializer,
+                        #(#deparser_initializer,)*
+                        externs: p4rs::externs::ExternRegistry::default(),
+                        externs_ready: Self::user_externs().is_empty(),
                         radix,
+                        table_change: Vec::new(),
+                        arena: None,
+                        ingress_metadata,
+                        egress_metadata,
+                        drops: Default::default(),
+                        epoch: 0,
+                    }
+                }
+
+                /// The extern types the program instantiates that are
+                /// implemented by the host rather than by p4rs.
+                fn user_externs() -> &'static [&'static str] {
+                    &[#(#user_externs),*]
+                }
+
+                /// Take a buffer for the header data of an output packet,
+                /// from the arena if it is enabled.
+                fn header_buffer(&mut self) -> Vec<u8> {
+                    match &mut self.arena {
+                        Some(arena) => arena.take(),
+                        None => Vec::new(),
+                    }
+                }
+
+                /// Count a dropped packet against the reason `count`
+                /// increments.
+                fn count_drop(&self, count: fn(&mut p4rs::DropStats)) {
+                    let mut drops = self.drops.get();
+                    count(&mut drops);
+                    self.drops.set(drops);
+                }
+
+                fn notify_table_change(
+                    &mut self,
+                    table_id: &str,
+                    change: p4rs::TableChange,
+                ) {
+                    self.epoch += 1;
+                    for cb in &mut self.table_change {
+                        cb(table_id, change.clone());
                     }
                 }
                 #process_packet_headers
@@ -155,8 +252,74 @@ impl<'a> PipelineGenerator<'a> {
                 #pipeline_impl_process_packet
                 #add_table_entry_method
                 #remove_table_entry_method
+                #clear_table_method
+                #remove_table_entries_matching_method
                 #get_table_entries_method
                 #get_table_ids_method
+                #get_table_actions_method
+                #get_table_schema_method
+                #describe_method
+                #table_location_method
+
+                fn register_extern(
+                    &mut self,
+                    name: &str,
+                    ext: Box<dyn p4rs::externs::ExternMethod>,
+                ) {
+                    self.externs.register(name, ext);
+                    self.externs_ready =
+                        self.missing_externs().is_empty();
+                }
+
+                fn missing_externs(&self) -> Vec<String> {
+                    self.externs.missing(Self::user_externs())
+                }
+
+                fn configure_meter(
+                    &mut self,
+                    meter_id: &str,
+                    index: u32,
+                    config: p4rs::externs::MeterConfig,
+                ) {
+                    self.externs.configure_meter(meter_id, index, config);
+                }
+
+                fn set_time_source(
+                    &mut self,
+                    clock: Box<dyn p4rs::externs::TimeSource>,
+                ) {
+                    self.externs.set_time_source(clock);
+                }
+
+                fn set_arena(&mut self, enabled: bool) {
+                    if !enabled {
+                        self.arena = None;
+                    } else if self.arena.is_none() {
+                        self.arena = Some(p4rs::arena::Arena::default());
+                    }
+                }
+
+                fn recycle(&mut self, out: packet_out<'_>) {
+                    if let Some(arena) = &mut self.arena {
+                        arena.recycle(out.header_data);
+                    }
+                }
+
+                fn radix(&self) -> u16 {
+                    self.radix
+                }
+
+                fn drop_stats(&self) -> p4rs::DropStats {
+                    self.drops.get()
+                }
+
+                fn epoch(&self) -> u64 {
+                    self.epoch
+                }
+
+                fn on_table_change(&mut self, cb: p4rs::TableChangeCallback) {
+                    self.table_change.push(cb);
+                }
             }
 
             unsafe impl Send for #pipeline_name { }
@@ -164,6 +327,11 @@ impl<'a> PipelineGenerator<'a> {
             #[no_mangle]
             pub extern "C" fn #c_create_fn(radix: u16)
             -> *mut dyn p4rs::Pipeline{
+                // Panicking across the C boundary aborts, so report a zero
+                // radix with a null pipeline instead.
+                if radix == 0 {
+                    return std::ptr::null_mut::<#pipeline_name>();
+                }
                 let pipeline = main_pipeline::new(radix);
                 let boxpipe: Box<dyn p4rs::Pipeline> = Box::new(pipeline);
                 Box::into_raw(boxpipe)
@@ -178,6 +346,7 @@ impl<'a> PipelineGenerator<'a> {
         parser: &Parser,
         ingress: &Control,
         egress: &Control,
+        deparser: Option<&Control>,
     ) -> (TokenStream, TokenStream) {
         let parsed_type = rust_type(&parser.parameters[1].ty);
         // determine table arguments
@@ -207,115 +376,241 @@ impl<'a> PipelineGenerator<'a> {
                 port: u16,
                 pkt: &mut packet_in<'a>,
             ) -> Vec<(packet_out<'a>, u16)> {
-                //
-                // Instantiate the parser out type
-                //
-
-                let mut parsed = #parsed_type::default();
+                let mut result = Vec::new();
+                self.process_packet_into(port, pkt, &mut result);
+                result
+            }
 
-                //
-                // Instantiate ingress/egress metadata
-                //
+            fn process_packets<'a>(
+                &mut self,
+                batch: &mut [(u16, packet_in<'a>)],
+            ) -> Vec<(packet_out<'a>, u16)> {
+                let mut result = Vec::with_capacity(batch.len());
+                // The metadata of each packet is reset in place from the
+                // defaults rather than allocated anew.
+                let mut ingress_metadata = self.ingress_metadata.clone();
+                let mut egress_metadata = self.egress_metadata.clone();
+                let mut egm = self.egress_metadata.clone();
+                for (port, pkt) in batch.iter_mut() {
+                    Self::reset_ingress_metadata(
+                        &mut ingress_metadata,
+                        &self.ingress_metadata,
+                    );
+                    self.process_packet_each(
+                        *port,
+                        pkt,
+                        &mut ingress_metadata,
+                        &mut egress_metadata,
+                        &mut egm,
+                        |out, eport, _| result.push((out, eport)),
+                    );
+                }
+                result
+            }
 
-                let mut ingress_metadata = ingress_metadata_t{
-                    port: {
-                        let mut x = bitvec![mut u8, Msb0; 0; 16];
-                        x.store_le(port);
-                        x
-                    },
-                    ..Default::default()
-                };
-                let mut egress_metadata = egress_metadata_t::default();
+            fn process_packet_outputs<'a>(
+                &mut self,
+                port: u16,
+                pkt: &mut packet_in<'a>,
+            ) -> p4rs::PacketOutputs<'a> {
+                let mut result = p4rs::PacketOutputs::default();
+                self.process_packet_into(port, pkt, &mut result);
+                result
+            }
+        };
 
-                //
-                // Run the parser block
-                //
+        let (clone_into, clone_headers) = if self.supports_clone() {
+            (
+                quote! {
+                    if let Some((cport, mut cparsed, mut cmeta)) = self
+                        .run_clone(
+                            port,
+                            pkt.packet_data(),
+                            ingress_metadata,
+                            egress_metadata,
+                        )
+                    {
+                        let mut egm = self.egress_metadata.clone();
+                        if self.run_egress(
+                            cport,
+                            &mut cparsed,
+                            &mut cmeta,
+                            &mut egm,
+                        ) {
+                            let mut out = packet_out{
+                                header_data: self.header_buffer(),
+                                payload_data:
+                                    &pkt.packet_data()[parsed_size..],
+                            };
+                            self.run_deparser(&cparsed, &mut out);
+                            emit(out, cport, &egm)
+                        }
+                    }
+                },
+                quote! {
+                    if let Some((cport, mut cparsed, mut cmeta)) = self
+                        .run_clone(
+                            port,
+                            pkt.packet_data(),
+                            &ingress_metadata,
+                            &egress_metadata,
+                        )
+                    {
+                        let mut egm = self.egress_metadata.clone();
+                        if self.run_egress(
+                            cport,
+                            &mut cparsed,
+                            &mut cmeta,
+                            &mut egm,
+                        ) {
+                            result.push((cparsed, cport))
+                        }
+                    }
+                },
+            )
+        } else {
+            (TokenStream::new(), TokenStream::new())
+        };
 
-                let accept = (self.parse)(pkt, &mut parsed, &mut ingress_metadata);
-                if !accept {
-                    // drop the packet
-                    softnpu_provider::parser_dropped!(||());
-                    return Vec::new();
+        // With the optimization on, a packet whose headers the pipeline did
+        // not write leaves with the header bytes it arrived with. This only
+        // holds when headers are emitted in the order they were extracted and
+        // nothing was skipped over between them, the latter is checked per
+        // packet by comparing the size of the headers with the extent of the
+        // packet the parser consumed.
+        let deparse = if self.settings.optimize
+            && deparser.is_none()
+            && self.extracts_in_order(parser)
+        {
+            quote! {
+                if !parsed_.is_dirty()
+                    && parsed_.valid_header_size() == pkt.index
+                {
+                    out.header_data.clear();
+                    out.header_data
+                        .extend_from_slice(&pkt.packet_data()[..parsed_size]);
+                } else {
+                    self.run_deparser(&parsed_, &mut out);
                 }
-                let dump = format!("\n{}", parsed.dump());
-                softnpu_provider::parser_accepted!(||(&dump));
-
-                //
-                // Calculate parsed header size
-                //
+            }
+        } else {
+            quote! { self.run_deparser(&parsed_, &mut out); }
+        };
 
-                let parsed_size = parsed.valid_header_size() >> 3;
+        let stages = self.pipeline_stages(
+            parser,
+            &parsed_type,
+            &ingress_tbl_args,
+            &egress_tbl_args,
+            deparser,
+        );
 
-                //
-                // Run the ingress block
-                //
+        let process_packet_headers = quote! {
+            #stages
 
-                (self.ingress)(
-                    &mut parsed,
+            /// Carry a packet through all stages of the pipeline, pushing the
+            /// resulting output packets onto `result`.
+            fn process_packet_into<'a>(
+                &mut self,
+                port: u16,
+                pkt: &mut packet_in<'a>,
+                result: &mut impl Extend<(packet_out<'a>, u16)>,
+            ) {
+                let mut ingress_metadata = self.ingress_metadata.clone();
+                let mut egress_metadata = self.egress_metadata.clone();
+                let mut egm = self.egress_metadata.clone();
+                self.process_packet_each(
+                    port,
+                    pkt,
                     &mut ingress_metadata,
                     &mut egress_metadata,
-                    #(#ingress_tbl_args),*
+                    &mut egm,
+                    |out, eport, _| result.extend(Some((out, eport))),
                 );
+            }
 
-                //
-                // Determine egress ports
-                //
-
-                let ports = if egress_metadata.broadcast {
-                    let mut ports = Vec::new();
-                    for p in 0..self.radix {
-                        if p == port {
-                            continue;
-                        }
-                        ports.push(p);
-                    }
-                    ports
-                } else {
-                    if egress_metadata.port.is_empty() || egress_metadata.drop {
-                        Vec::new()
-                    } else {
-                        vec![egress_metadata.port.load_le()]
-                    }
-                };
-
-                let dump = parsed.dump();
-
-                if ports.is_empty() {
-                    softnpu_provider::ingress_dropped!(||(&dump));
-                    return Vec::new();
-                }
+            /// Process a packet like `process_packet`, starting from the
+            /// ingress metadata `metadata` rather than its default. This lets
+            /// a harness pass sideband values, such as an arrival timestamp,
+            /// in members the program declares in its ingress metadata. The
+            /// `port` member is set to `port`. Each output packet is returned
+            /// with its port and the egress metadata it left the pipeline
+            /// with, so sideband values the program sets on the way out, such
+            /// as a queue id, can be read.
+            pub fn process_packet_with_metadata<'a>(
+                &mut self,
+                port: u16,
+                pkt: &mut packet_in<'a>,
+                mut metadata: ingress_metadata_t,
+            ) -> Vec<(packet_out<'a>, u16, egress_metadata_t)> {
+                let mut result = Vec::new();
+                let mut egress_metadata = self.egress_metadata.clone();
+                let mut egm = self.egress_metadata.clone();
+                self.process_packet_each(
+                    port,
+                    pkt,
+                    &mut metadata,
+                    &mut egress_metadata,
+                    &mut egm,
+                    |out, eport, egm| result.push((out, eport, egm.clone())),
+                );
+                result
+            }
 
-                let dump = format!("\n{}", parsed.dump());
-                softnpu_provider::ingress_accepted!(||(&dump));
+            /// Carry a packet through all stages of the pipeline, starting
+            /// from `ingress_metadata`, and call `emit` with each output
+            /// packet, its port and the egress metadata it leaves with. The
+            /// egress metadata set by ingress is held in `egress_metadata`
+            /// and the copy each output port's egress block runs with in
+            /// `egm`, both are reset in place before use.
+            fn process_packet_each<'a>(
+                &mut self,
+                port: u16,
+                pkt: &mut packet_in<'a>,
+                ingress_metadata: &mut ingress_metadata_t,
+                egress_metadata: &mut egress_metadata_t,
+                egm: &mut egress_metadata_t,
+                mut emit: impl FnMut(packet_out<'a>, u16, &egress_metadata_t),
+            ) {
+                let mut parsed =
+                    match self.parse_into(port, pkt, ingress_metadata) {
+                        Some(x) => x,
+                        None => return,
+                    };
 
                 //
-                // Run output of ingress block through egress block on each
-                // egress port.
+                // The payload starts where the parser stopped. Headers made
+                // valid after parsing, such as an encapsulating header added
+                // by ingress, are emitted by the deparser without moving the
+                // payload.
                 //
-                let mut result = Vec::new();
-                for eport in ports {
 
-                    let mut egm = egress_metadata.clone();
-                    let mut parsed_ = parsed.clone();
-
-                    //
-                    // Run the egress block
-                    //
+                let parsed_size = pkt.index >> 3;
 
-                    egm.port = {
-                        let mut x = bitvec![mut u8, Msb0; 0; 16];
-                        x.store_le(eport);
-                        x
-                    };
+                let ports = self.run_ingress_to_traffic_manager(
+                    port,
+                    &mut parsed,
+                    ingress_metadata,
+                    egress_metadata,
+                );
 
-                    (self.egress)(
+                // The last port takes the parsed headers rather than a copy.
+                let n = ports.len();
+                let mut parsed = Some(parsed);
+                for (i, eport) in ports.into_iter().enumerate() {
+                    Self::reset_egress_metadata(egm, egress_metadata);
+                    let mut parsed_ = if i + 1 == n {
+                        parsed.take()
+                    } else {
+                        parsed.clone()
+                    }
+                    .expect("parsed headers taken before the last port");
+                    if !self.run_egress(
+                        eport,
                         &mut parsed_,
-                        &mut ingress_metadata,
-                        &mut egm,
-                        #(#egress_tbl_args),*
-                    );
-
-                    if egm.drop {
+                        ingress_metadata,
+                        egm,
+                    ) {
                         continue;
                     }
 
@@ -323,150 +618,630 @@ impl<'a> PipelineGenerator<'a> {
                     // Create the packet output.
                     //
 
-                    let bv = parsed_.to_bitvec();
-                    let buf = bv.as_raw_slice();
-                    let out = packet_out{
-                        header_data: buf.to_owned(),
-                        payload_data: &pkt.data[parsed_size..],
+                    let mut out = packet_out{
+                        header_data: self.header_buffer(),
+                        payload_data: &pkt.packet_data()[parsed_size..],
                     };
-                    result.push((out, eport))
-
+                    #deparse
+                    emit(out, eport, egm)
                 }
-                result
+
+                #clone_into
             }
-        };
 
-        //TODO factor out commonalities with process_packet
-        let process_packet_headers = quote! {
-            fn process_packet_headers<'a>(
+            pub fn process_packet_headers<'a>(
                 &mut self,
                 port: u16,
                 pkt: &mut packet_in<'a>,
             ) -> Vec<(#parsed_type, u16)> {
-                //
-                // Instantiate the parser out type
-                //
-
-                let mut parsed = #parsed_type::default();
+                let (mut parsed, mut ingress_metadata) =
+                    match self.run_parser(port, pkt) {
+                        Some(x) => x,
+                        None => return Vec::new(),
+                    };
+                let mut egress_metadata = self.egress_metadata.clone();
+                let ports = self.run_ingress_to_traffic_manager(
+                    port,
+                    &mut parsed,
+                    &mut ingress_metadata,
+                    &mut egress_metadata,
+                );
 
-                //
-                // Instantiate ingress/egress metadata
-                //
+                let mut result = Vec::new();
+                let n = ports.len();
+                let mut parsed = Some(parsed);
+                for (i, eport) in ports.into_iter().enumerate() {
+                    let mut egm = egress_metadata.clone();
+                    let mut parsed_ = if i + 1 == n {
+                        parsed.take()
+                    } else {
+                        parsed.clone()
+                    }
+                    .expect("parsed headers taken before the last port");
+                    if self.run_egress(
+                        eport,
+                        &mut parsed_,
+                        &mut ingress_metadata,
+                        &mut egm,
+                    ) {
+                        result.push((parsed_, eport))
+                    }
+                }
 
-                let mut ingress_metadata = ingress_metadata_t{
-                    port: {
-                        let mut x = bitvec![mut u8, Msb0; 0; 16];
-                        x.store_le(port);
-                        x
-                    },
-                    ..Default::default()
-                };
-                let mut egress_metadata = egress_metadata_t::default();
+                #clone_headers
+                result
+            }
+        };
 
-                //
-                // Run the parser block
-                //
+        (process_packet, process_packet_headers)
+    }
 
-                let accept = (self.parse)(pkt, &mut parsed, &mut ingress_metadata);
+    /// Generate the stages a packet passes through: the parser, the ingress
+    /// block, the traffic manager that decides which ports a packet is sent
+    /// out of, and the egress block that runs once per output port. Each
+    /// stage hands its metadata to the next explicitly so stages can be run
+    /// in isolation.
+    fn pipeline_stages(
+        &self,
+        parser: &Parser,
+        parsed_type: &TokenStream,
+        ingress_tbl_args: &[TokenStream],
+        egress_tbl_args: &[TokenStream],
+        deparser: Option<&Control>,
+    ) -> TokenStream {
+        let ingress_port_width = self.port_width("ingress_metadata_t");
+        let egress_port_width = self.port_width("egress_metadata_t");
+        let packet_length = match self.packet_length_width() {
+            Some(width) => quote! {
+                ingress_metadata.packet_length = p4rs::endian::store(
+                    #width,
+                    pkt.data.len() as u128,
+                );
+            },
+            None => TokenStream::new(),
+        };
+        let clone = self.clone_stage(parsed_type);
+        let reset = self.metadata_resets();
+        // A parser annotated with `@reject_to_ingress` hands the packets it
+        // rejects to ingress rather than dropping them, so ingress can act on
+        // the parser error, for example by sending them to a CPU port.
+        let parse = if parser
+            .annotations
+            .iter()
+            .any(|a| a.name == "reject_to_ingress")
+        {
+            quote! {
+                (self.parse)(pkt, &mut parsed, ingress_metadata);
+            }
+        } else {
+            quote! {
+                let accept =
+                    (self.parse)(pkt, &mut parsed, ingress_metadata);
                 if !accept {
                     // drop the packet
                     softnpu_provider::parser_dropped!(||());
-                    return Vec::new();
+                    self.count_drop(|d| d.parser += 1);
+                    return None;
                 }
-                let dump = format!("\n{}", parsed.dump());
-                softnpu_provider::parser_accepted!(||(&dump));
+            }
+        };
+        let deparse = match deparser {
+            Some(d) => {
+                if !d.tables(self.ast).is_empty() {
+                    panic!("deparser {} must not apply tables", d.name);
+                }
+                let args: Vec<TokenStream> = d
+                    .parameters
+                    .iter()
+                    .map(|p| match p.ty {
+                        Type::UserDefined(ref t) if t == "packet_out" => {
+                            quote! { out }
+                        }
+                        _ => quote! { parsed },
+                    })
+                    .collect();
+                quote! { (self.deparser)(#(#args,)* &self.externs); }
+            }
+            None => quote! {
+                out.header_data.clear();
+                out.header_data
+                    .extend_from_slice(parsed.to_bitvec().as_raw_slice());
+            },
+        };
+        quote! {
+            /// Run the parser on a packet arriving on `port`. Returns the
+            /// parsed headers and the ingress metadata for the packet, or
+            /// `None` if the parser rejects the packet. A parser annotated
+            /// with `@reject_to_ingress` does not reject packets, they carry
+            /// the parser error to ingress instead. Packets are also dropped
+            /// while an extern the program uses has no registered
+            /// implementation. The ingress metadata starts out with the
+            /// pipeline's metadata defaults.
+            pub fn run_parser(
+                &self,
+                port: u16,
+                pkt: &mut packet_in<'_>,
+            ) -> Option<(#parsed_type, ingress_metadata_t)> {
+                self.run_parser_with(port, pkt, self.ingress_metadata.clone())
+            }
 
-                //
-                // Calculate parsed header size
-                //
+            /// Run the parser like `run_parser`, starting from the ingress
+            /// metadata `ingress_metadata` with its `port` member set to
+            /// `port` and its `packet_length` member, if it has one, set to
+            /// the length of the packet.
+            pub fn run_parser_with(
+                &self,
+                port: u16,
+                pkt: &mut packet_in<'_>,
+                mut ingress_metadata: ingress_metadata_t,
+            ) -> Option<(#parsed_type, ingress_metadata_t)> {
+                let parsed = self.parse_into(port, pkt, &mut ingress_metadata)?;
+                Some((parsed, ingress_metadata))
+            }
 
-                let parsed_size = parsed.valid_header_size() >> 3;
+            /// Run the parser like `run_parser_with`, on ingress metadata
+            /// the caller keeps hold of.
+            fn parse_into(
+                &self,
+                port: u16,
+                pkt: &mut packet_in<'_>,
+                ingress_metadata: &mut ingress_metadata_t,
+            ) -> Option<#parsed_type> {
+                if !self.externs_ready {
+                    self.count_drop(|d| d.unregistered_extern += 1);
+                    return None;
+                }
+                let mut parsed = #parsed_type::default();
+                ingress_metadata.port = p4rs::endian::store(
+                    #ingress_port_width,
+                    port.into(),
+                );
+                #packet_length
 
-                //
-                // Run the ingress block
-                //
+                #parse
+                let dump = format!("\n{}", parsed.dump());
+                softnpu_provider::parser_accepted!(||(&dump));
 
-                (self.ingress)(
-                    &mut parsed,
-                    &mut ingress_metadata,
+                Some(parsed)
+            }
+
+            /// Run the ingress block over a parsed packet. Returns the egress
+            /// metadata set by the ingress block, which the traffic manager
+            /// uses to determine where the packet goes.
+            pub fn run_ingress(
+                &self,
+                parsed: &mut #parsed_type,
+                ingress_metadata: &mut ingress_metadata_t,
+            ) -> egress_metadata_t {
+                let mut egress_metadata = self.egress_metadata.clone();
+                self.run_ingress_into(
+                    parsed,
+                    ingress_metadata,
                     &mut egress_metadata,
-                    #(#ingress_tbl_args),*
                 );
+                egress_metadata
+            }
 
-                //
-                // Determine egress ports
-                //
+            /// Run the ingress block like `run_ingress`, setting
+            /// `egress_metadata` in place. It is reset to the pipeline's
+            /// metadata defaults first.
+            fn run_ingress_into(
+                &self,
+                parsed: &mut #parsed_type,
+                ingress_metadata: &mut ingress_metadata_t,
+                egress_metadata: &mut egress_metadata_t,
+            ) {
+                Self::reset_egress_metadata(
+                    egress_metadata,
+                    &self.egress_metadata,
+                );
+                (self.ingress)(
+                    parsed,
+                    ingress_metadata,
+                    egress_metadata,
+                    #(#ingress_tbl_args,)*
+                    &self.externs,
+                );
+            }
 
-                let ports = if egress_metadata.broadcast {
-                    let mut ports = Vec::new();
-                    for p in 0..self.radix {
-                        if p == port {
-                            continue;
-                        }
-                        ports.push(p);
-                    }
-                    ports
+            /// Determine the ports a packet that arrived on `port` is
+            /// replicated to, given the egress metadata produced by the
+            /// ingress block. Broadcast packets go to every port except the
+            /// one they arrived on, so with a single port they go nowhere.
+            /// Packets sent to a port at or beyond the radix are dropped. An
+            /// empty result means the packet is dropped.
+            pub fn run_traffic_manager(
+                &self,
+                port: u16,
+                egress_metadata: &egress_metadata_t,
+            ) -> Vec<u16> {
+                if egress_metadata.broadcast {
+                    (0..self.radix).filter(|p| *p != port).collect()
+                } else if egress_metadata.port.is_empty()
+                    || egress_metadata.drop
+                {
+                    Vec::new()
                 } else {
-                    if egress_metadata.port.is_empty() || egress_metadata.drop {
-                        Vec::new()
+                    let eport = p4rs::endian::load(&egress_metadata.port);
+                    if eport < u128::from(self.radix) {
+                        vec![eport as u16]
                     } else {
-                        vec![egress_metadata.port.load_le()]
+                        Vec::new()
                     }
-                };
+                }
+            }
+
+            /// Run the egress block for a copy of a packet leaving on
+            /// `eport`. Returns false if the egress block drops the packet.
+            pub fn run_egress(
+                &self,
+                eport: u16,
+                parsed: &mut #parsed_type,
+                ingress_metadata: &mut ingress_metadata_t,
+                egress_metadata: &mut egress_metadata_t,
+            ) -> bool {
+                egress_metadata.port = p4rs::endian::store(
+                    #egress_port_width,
+                    eport.into(),
+                );
+
+                (self.egress)(
+                    parsed,
+                    ingress_metadata,
+                    egress_metadata,
+                    #(#egress_tbl_args,)*
+                    &self.externs,
+                );
 
-                let dump = parsed.dump();
+                if egress_metadata.drop {
+                    self.count_drop(|d| d.egress += 1);
+                }
+                !egress_metadata.drop
+            }
+
+            /// Produce the header data of a packet leaving the pipeline from
+            /// its parsed headers. Only valid headers are emitted.
+            pub fn run_deparser(
+                &self,
+                parsed: &#parsed_type,
+                out: &mut packet_out<'_>,
+            ) {
+                #deparse
+            }
+
+            /// Run a parsed packet through the ingress and traffic manager
+            /// stages, setting `egress_metadata` and returning the ports the
+            /// packet is to be sent out of. No ports are returned if the
+            /// packet is dropped.
+            fn run_ingress_to_traffic_manager(
+                &self,
+                port: u16,
+                parsed: &mut #parsed_type,
+                ingress_metadata: &mut ingress_metadata_t,
+                egress_metadata: &mut egress_metadata_t,
+            ) -> Vec<u16> {
+                self.run_ingress_into(
+                    parsed,
+                    ingress_metadata,
+                    egress_metadata,
+                );
+                let ports = self.run_traffic_manager(port, egress_metadata);
 
                 if ports.is_empty() {
+                    let dump = parsed.dump();
                     softnpu_provider::ingress_dropped!(||(&dump));
-                    return Vec::new();
+                    if egress_metadata.drop {
+                        self.count_drop(|d| d.ingress += 1);
+                    } else {
+                        self.count_drop(|d| d.no_port += 1);
+                    }
+                } else {
+                    let dump = format!("\n{}", parsed.dump());
+                    softnpu_provider::ingress_accepted!(||(&dump));
                 }
 
-                let dump = format!("\n{}", parsed.dump());
-                softnpu_provider::ingress_accepted!(||(&dump));
+                ports
+            }
 
-                //
-                // Run output of ingress block through egress block on each
-                // egress port.
-                //
-                let mut result = Vec::new();
-                for eport in ports {
+            #reset
+            #clone
+        }
+    }
 
-                    let mut egm = egress_metadata.clone();
-                    let mut parsed_ = parsed.clone();
+    /// Generate functions that reset ingress and egress metadata to another
+    /// instance, such as the pipeline's metadata defaults, without
+    /// allocating. Bit fields are overwritten in place, `clone_from` is used
+    /// for other members.
+    fn metadata_resets(&self) -> TokenStream {
+        let mut tokens = TokenStream::new();
+        for metadata in ["ingress_metadata_t", "egress_metadata_t"] {
+            let s = self
+                .ast
+                .get_struct(metadata)
+                .unwrap_or_else(|| panic!("{} must be defined", metadata));
+            let mut resets = Vec::new();
+            for m in &s.members {
+                let name = format_ident!("{}", m.name);
+                resets.push(match m.ty {
+                    Type::Bit(_) | Type::Varbit(_) | Type::Int(_) => quote! {
+                        metadata.#name.clear();
+                        metadata.#name.extend_from_bitslice(&to.#name);
+                    },
+                    _ => quote! { metadata.#name.clone_from(&to.#name); },
+                });
+            }
+            let ty = format_ident!("{}", metadata);
+            let reset =
+                format_ident!("reset_{}", metadata.trim_end_matches("_t"));
+            tokens.extend(quote! {
+                fn #reset(metadata: &mut #ty, to: &#ty) {
+                    #(#resets)*
+                }
+            });
+        }
+        tokens
+    }
 
-                    //
-                    // Run the egress block
-                    //
+    /// Generate the stage that produces the copy of a packet an ingress
+    /// block asks for by setting `clone` in the egress metadata. Programs
+    /// whose egress metadata has no `clone` member cannot clone packets and
+    /// get no clone stage.
+    fn clone_stage(&self, parsed_type: &TokenStream) -> TokenStream {
+        if !self.supports_clone() {
+            return TokenStream::new();
+        }
 
-                    egm.port = {
-                        let mut x = bitvec![mut u8, Msb0; 0; 16];
-                        x.store_le(eport);
-                        x
-                    };
+        // Ingress metadata members are put in field lists with the
+        // @field_list annotation, a member may be in more than one list.
+        let mut field_lists: BTreeMap<i128, Vec<TokenStream>> = BTreeMap::new();
+        let ingress_metadata = self
+            .ast
+            .get_struct("ingress_metadata_t")
+            .expect("ingress_metadata_t must be defined");
+        for m in &ingress_metadata.members {
+            let name = format_ident!("{}", m.name);
+            for a in &m.annotations {
+                if a.name != "field_list" {
+                    continue;
+                }
+                for id in a.integer_args() {
+                    field_lists.entry(id).or_default().push(quote! {
+                        clone_metadata.#name = ingress_metadata.#name.clone();
+                    });
+                }
+            }
+        }
+        let mut preserve = TokenStream::new();
+        for (id, copies) in &field_lists {
+            let id = *id as u128;
+            preserve.extend(quote! {
+                #id => { #(#copies)* }
+            });
+        }
 
-                    (self.egress)(
-                        &mut parsed_,
-                        &mut ingress_metadata,
-                        &mut egm,
-                        #(#egress_tbl_args),*
-                    );
+        quote! {
+            /// Produce the copy of a packet requested by an ingress block
+            /// that set `clone` in the egress metadata, along with the port
+            /// it is sent out of. The copy is the packet as it arrived. Of
+            /// the ingress metadata only the members in the field list
+            /// named by `clone_field_list` are carried over, the others
+            /// start out as they do for a newly arrived packet.
+            pub fn run_clone(
+                &self,
+                port: u16,
+                data: &[u8],
+                ingress_metadata: &ingress_metadata_t,
+                egress_metadata: &egress_metadata_t,
+            ) -> Option<(u16, #parsed_type, ingress_metadata_t)> {
+                if !egress_metadata.clone {
+                    return None;
+                }
+                let mut pkt = packet_in::new(data);
+                let (parsed, mut clone_metadata) =
+                    self.run_parser(port, &mut pkt)?;
+                match p4rs::endian::load(&egress_metadata.clone_field_list) {
+                    #preserve
+                    _ => {}
+                }
+                Some((
+                    p4rs::endian::load(&egress_metadata.clone_port) as u16,
+                    parsed,
+                    clone_metadata,
+                ))
+            }
+        }
+    }
 
-                    if egm.drop {
-                        continue;
-                    }
+    /// Programs whose egress metadata has a `clone` member may clone packets,
+    /// see [`Self::clone_stage`].
+    fn supports_clone(&self) -> bool {
+        self.ast
+            .get_struct("egress_metadata_t")
+            .map(|s| s.members.iter().any(|m| m.name == "clone"))
+            .unwrap_or(false)
+    }
 
-                    //
-                    // Create the packet output.
-                    //
+    /// Whether every path through `parser` extracts headers in the order
+    /// they are declared in the parsed headers struct, which is the order
+    /// they are emitted in without a deparser. Paths are followed from the
+    /// start state along with the last member extracted into, so loops over
+    /// a header stack are visited once.
+    fn extracts_in_order(&self, parser: &Parser) -> bool {
+        let headers = &parser.parameters[1];
+        let members = match &headers.ty {
+            Type::UserDefined(name) => match self.ast.get_struct(name) {
+                Some(s) => &s.members,
+                None => return false,
+            },
+            _ => return false,
+        };
 
-                    result.push((parsed_, eport))
+        let mut pending = vec![("start".to_owned(), 0)];
+        let mut visited = Vec::new();
+        while let Some((name, mut last)) = pending.pop() {
+            if visited.contains(&(name.clone(), last)) {
+                continue;
+            }
+            visited.push((name.clone(), last));
+            let state = match parser.states.iter().find(|s| s.name == name) {
+                Some(s) => s,
+                None => continue,
+            };
+            let mut extracted = Vec::new();
+            state_extractions(&state.statements, &mut extracted);
+            let mut next = Vec::new();
+            state_transitions(&state.statements, &mut next);
+            for lval in extracted {
+                let lval = match lval {
+                    Some(lval) => lval,
+                    None => return false,
+                };
+                let mut parts = lval.name.split('.');
+                if parts.next() != Some(headers.name.as_str()) {
+                    return false;
+                }
+                // header stacks are extracted into in order through `next`,
+                // elements named by index may be extracted in any order
+                let member = match parts.next().map(Lvalue::split_index) {
+                    Some((m, None)) => m,
+                    _ => return false,
+                };
+                match members.iter().position(|m| m.name == member) {
+                    Some(i) if i >= last => last = i,
+                    _ => return false,
+                }
+            }
+            for n in next {
+                pending.push((n, last));
+            }
+        }
+        true
+    }
 
+    /// The extern types instantiated by the controls of the program that the
+    /// host must register implementations of.
+    fn user_externs(&self) -> Vec<String> {
+        let mut names = BTreeSet::new();
+        for control in &self.ast.controls {
+            for var in &control.variables {
+                if let Type::UserDefined(typename) = &var.ty {
+                    if self.ast.get_extern(typename).is_some()
+                        && !is_builtin_extern(typename)
+                    {
+                        names.insert(typename.clone());
+                    }
                 }
-                result
             }
-        };
+        }
+        names.into_iter().collect()
+    }
 
-        (process_packet, process_packet_headers)
+    /// The width of the `port` member of the named metadata struct. Ports are
+    /// carried in and out of the pipeline as `u16` so the width is limited to
+    /// 16 bits.
+    fn port_width(&self, metadata: &str) -> usize {
+        let port = self
+            .ast
+            .get_struct(metadata)
+            .and_then(|s| s.members.iter().find(|m| m.name == "port"))
+            .unwrap_or_else(|| panic!("{} must have a port member", metadata));
+        match port.ty {
+            Type::Bit(n) if n <= 16 => n,
+            _ => panic!(
+                "{}.port must be a bit<N> with N <= 16, found {}",
+                metadata, port.ty,
+            ),
+        }
+    }
+
+    /// Statements declaring the `ingress_metadata` and `egress_metadata`
+    /// every packet starts out with. A constant of the ingress or egress
+    /// control annotated `@metadata_default(param.member)` sets `member` of
+    /// the metadata passed to the control as `param`, for example
+    /// `@metadata_default(egress.port) const bit<16> CPU_PORT = 16w3;`.
+    fn metadata_defaults(
+        &mut self,
+        ingress: &Control,
+        egress: &Control,
+    ) -> TokenStream {
+        let mut blocks = Vec::new();
+        let mut ingress_mut = TokenStream::new();
+        let mut egress_mut = TokenStream::new();
+        for control in [ingress, egress] {
+            let mut referenced = HashSet::new();
+            let mut assignments = Vec::new();
+            for c in &control.constants {
+                for a in &c.annotations {
+                    if a.name != "metadata_default" {
+                        continue;
+                    }
+                    let target = a.lvalue_arg().unwrap_or_else(|| {
+                        panic!(
+                            "{}: @metadata_default takes a metadata member",
+                            c.name,
+                        )
+                    });
+                    let mut segments = target.split('.');
+                    let param = segments.next().unwrap();
+                    let ty = match control.get_parameter(param) {
+                        Some(p) => &p.ty,
+                        None => &Type::Void,
+                    };
+                    let metadata = match ty {
+                        Type::UserDefined(t) if t == "ingress_metadata_t" => {
+                            ingress_mut = quote! { mut };
+                            quote! { ingress_metadata }
+                        }
+                        Type::UserDefined(t) if t == "egress_metadata_t" => {
+                            egress_mut = quote! { mut };
+                            quote! { egress_metadata }
+                        }
+                        _ => panic!(
+                            "{}: {} is not a metadata parameter of {}",
+                            c.name, param, control.name,
+                        ),
+                    };
+                    let member = segments.map(|m| format_ident!("{}", m));
+                    let name = format_ident!("{}", c.name);
+                    referenced.insert(c.name.clone());
+                    assignments.push(quote! {
+                        #metadata.#(#member).* = #name.clone();
+                    });
+                }
+            }
+            if assignments.is_empty() {
+                continue;
+            }
+            let cg =
+                crate::ControlGenerator::new(self.ast, self.hlir, self.ctx);
+            let bindings = cg.constant_bindings(control, referenced);
+            blocks.push(quote! {{ #bindings #(#assignments)* }});
+        }
+        quote! {
+            let #ingress_mut ingress_metadata = ingress_metadata_t::default();
+            let #egress_mut egress_metadata = egress_metadata_t::default();
+            #(#blocks)*
+        }
+    }
+
+    /// The width of the optional `packet_length` member of the ingress
+    /// metadata. Packets longer than the member can count are truncated to
+    /// its low order bits.
+    fn packet_length_width(&self) -> Option<usize> {
+        let member = self
+            .ast
+            .get_struct("ingress_metadata_t")?
+            .members
+            .iter()
+            .find(|m| m.name == "packet_length")?;
+        match member.ty {
+            Type::Bit(n) if n <= 128 => Some(n),
+            _ => panic!(
+                "ingress_metadata_t.packet_length must be a bit<N> with \
+                 N <= 128, found {}",
+                member.ty,
+            ),
+        }
     }
 
     pub(crate) fn table_members(
@@ -488,9 +1263,9 @@ impl<'a> PipelineGenerator<'a> {
             for var in &table_control.variables {
                 if let Type::UserDefined(typename) = &var.ty {
                     if self.ast.get_extern(typename).is_some() {
-                        let extern_type = format_ident!("{}", typename);
+                        let extern_type = extern_type(typename);
                         param_types.push(quote! {
-                            &p4rs::externs::#extern_type
+                            &#extern_type
                         })
                     }
                 }
@@ -512,11 +1287,255 @@ impl<'a> PipelineGenerator<'a> {
                 #qtn: #fqtn()
             })
         }
-
-        (members, initializers)
+
+        (members, initializers)
+    }
+
+    /// The `validate_table_entry` and `add_table_entry` methods. Adding an
+    /// entry validates it first, so the two fail in the same way.
+    fn add_table_entry_method(
+        &mut self,
+        ingress: &Control,
+        egress: &Control,
+    ) -> TokenStream {
+        let mut validate_body = TokenStream::new();
+        let mut body = TokenStream::new();
+
+        for control in &[ingress, egress] {
+            let tables = control.tables(self.ast);
+            for (cs, table) in tables.iter() {
+                let table_control = cs.last().unwrap().1;
+                let qtn = qualified_table_name(
+                    Some(control),
+                    cs,
+                    table,
+                    &self.settings.names,
+                );
+                let qtfn =
+                    qualified_table_function_name(Some(control), cs, table);
+                let call = format_ident!("add_{}_entry", qtfn);
+
+                // Entries may invoke any action of the table other than
+                // NoAction and actions annotated @defaultonly.
+                let mut entry_actions = Vec::new();
+                let mut parameter_sizes = Vec::new();
+                let mut default_only = Vec::new();
+                for action in &table.actions {
+                    if action.name == "NoAction" {
+                        continue;
+                    }
+                    let aname = self.settings.names.original(&action.name);
+                    if table.action_has_annotation(&action.name, "defaultonly")
+                    {
+                        default_only.push(aname);
+                    } else {
+                        entry_actions.push(aname);
+                        let a = match table_control.get_action(&action.name) {
+                            Some(a) => a,
+                            None => panic!(
+                                "codegen: action {} not found in control {}",
+                                action.name, table_control.name,
+                            ),
+                        };
+                        parameter_sizes.push(parameter_data_size(a));
+                    }
+                }
+
+                let key_size = self.keyset_size(table);
+
+                // Extracting an lpm key checks it is a valid prefix.
+                let keys = self.table_entry_keys(table, &qtn);
+                let check_keys: Vec<TokenStream> = keys
+                    .iter()
+                    .zip(&table.key)
+                    .filter(|(_, (_, match_kind))| {
+                        matches!(match_kind, MatchKind::LongestPrefixMatch)
+                    })
+                    .map(|(key, _)| quote! { #key; })
+                    .collect();
+
+                let check_parameters = if entry_actions.is_empty() {
+                    TokenStream::new()
+                } else {
+                    quote! {
+                        let expected = match action_id {
+                            #(#entry_actions => #parameter_sizes,)*
+                            _ => 0,
+                        };
+                        if parameter_data.len() < expected {
+                            return Err(
+                                p4rs::error::PipelineError::ParameterLengthMismatch {
+                                    table: table_id.to_owned(),
+                                    action: action_id.to_owned(),
+                                    expected,
+                                    found: parameter_data.len(),
+                                },
+                            );
+                        }
+                    }
+                };
+
+                validate_body.extend(quote! {
+                    #qtn => {
+                        if keyset_data.len() != #key_size {
+                            return Err(
+                                p4rs::error::PipelineError::KeyLengthMismatch {
+                                    table: table_id.to_owned(),
+                                    expected: #key_size,
+                                    found: keyset_data.len(),
+                                },
+                            );
+                        }
+                        #(#check_keys)*
+                        if [#(#default_only),*].contains(&action_id) {
+                            return Err(
+                                p4rs::error::PipelineError::DefaultOnlyAction {
+                                    table: table_id.to_owned(),
+                                    action: action_id.to_owned(),
+                                },
+                            );
+                        }
+                        if ![#(#entry_actions),*].contains(&action_id) {
+                            return Err(
+                                p4rs::error::PipelineError::UnknownAction {
+                                    table: table_id.to_owned(),
+                                    action: action_id.to_owned(),
+                                },
+                            );
+                        }
+                        #check_parameters
+                        Ok(())
+                    }
+                });
+
+                body.extend(quote! {
+                    #qtn => {
+                        self.#call(
+                            action_id,
+                            keyset_data,
+                            parameter_data,
+                            priority,
+                        )?;
+                        self.notify_table_change(
+                            table_id,
+                            p4rs::TableChange::Add {
+                                action_id: action_id.to_owned(),
+                                keyset_data: keyset_data.to_owned(),
+                                parameter_data: parameter_data.to_owned(),
+                                priority,
+                            },
+                        );
+                        Ok(())
+                    }
+                });
+            }
+        }
+
+        let unknown = quote! {
+            x => Err(p4rs::error::PipelineError::UnknownTable(x.to_owned())),
+        };
+
+        quote! {
+            fn validate_table_entry(
+                &self,
+                table_id: &str,
+                action_id: &str,
+                keyset_data: &[u8],
+                parameter_data: &[u8],
+            ) -> Result<(), p4rs::error::PipelineError> {
+                match table_id {
+                    #validate_body
+                    #unknown
+                }
+            }
+
+            fn add_table_entry(
+                &mut self,
+                table_id: &str,
+                action_id: &str,
+                keyset_data: &[u8],
+                parameter_data: &[u8],
+                priority: u32,
+            ) -> Result<(), p4rs::error::PipelineError> {
+                self.validate_table_entry(
+                    table_id,
+                    action_id,
+                    keyset_data,
+                    parameter_data,
+                )?;
+                match table_id {
+                    #body
+                    #unknown
+                }
+            }
+        }
+    }
+
+    fn remove_table_entry_method(
+        &mut self,
+        ingress: &Control,
+        egress: &Control,
+    ) -> TokenStream {
+        let mut body = TokenStream::new();
+
+        for control in &[ingress, egress] {
+            let tables = control.tables(self.ast);
+            for (cs, table) in tables.iter() {
+                let qtn = qualified_table_name(
+                    Some(control),
+                    cs,
+                    table,
+                    &self.settings.names,
+                );
+                let qftn =
+                    qualified_table_function_name(Some(control), cs, table);
+                let call = format_ident!("remove_{}_entry", qftn);
+                let key_size = self.keyset_size(table);
+                body.extend(quote! {
+                    #qtn => {
+                        if keyset_data.len() != #key_size {
+                            return Err(
+                                p4rs::error::PipelineError::KeyLengthMismatch {
+                                    table: table_id.to_owned(),
+                                    expected: #key_size,
+                                    found: keyset_data.len(),
+                                },
+                            );
+                        }
+                        // Removing an entry the table does not have is not a
+                        // change, so callbacks are not told about it.
+                        if self.#call(keyset_data)? {
+                            self.notify_table_change(
+                                table_id,
+                                p4rs::TableChange::Remove {
+                                    keyset_data: keyset_data.to_owned(),
+                                },
+                            );
+                        }
+                        Ok(())
+                    }
+                });
+            }
+        }
+
+        body.extend(quote! {
+            x => Err(p4rs::error::PipelineError::UnknownTable(x.to_owned())),
+        });
+
+        quote! {
+            fn remove_table_entry(
+                &mut self,
+                table_id: &str,
+                keyset_data: &[u8],
+            ) -> Result<(), p4rs::error::PipelineError> {
+                match table_id {
+                    #body
+                }
+            }
+        }
     }
 
-    fn add_table_entry_method(
+    fn clear_table_method(
         &mut self,
         ingress: &Control,
         egress: &Control,
@@ -526,34 +1545,38 @@ impl<'a> PipelineGenerator<'a> {
         for control in &[ingress, egress] {
             let tables = control.tables(self.ast);
             for (cs, table) in tables.iter() {
-                let qtn = qualified_table_name(Some(control), cs, table);
+                let qtn = qualified_table_name(
+                    Some(control),
+                    cs,
+                    table,
+                    &self.settings.names,
+                );
                 let qtfn =
                     qualified_table_function_name(Some(control), cs, table);
-                let call = format_ident!("add_{}_entry", qtfn);
+                let tname = format_ident!("{}", qtfn);
+                // The table constructor installs the const entries.
                 body.extend(quote! {
-                    #qtn => self.#call(
-                        action_id,
-                        keyset_data,
-                        parameter_data,
-                        priority,
-                    ),
+                    #qtn => {
+                        self.#tname = #tname();
+                        self.notify_table_change(
+                            table_id,
+                            p4rs::TableChange::Clear,
+                        );
+                        Ok(())
+                    }
                 });
             }
         }
 
         body.extend(quote! {
-            x => println!("add table entry: unknown table id {}, ignoring", x),
+            x => Err(p4rs::error::PipelineError::UnknownTable(x.to_owned())),
         });
 
         quote! {
-            fn add_table_entry(
+            fn clear_table(
                 &mut self,
                 table_id: &str,
-                action_id: &str,
-                keyset_data: &[u8],
-                parameter_data: &[u8],
-                priority: u32,
-            ) {
+            ) -> Result<(), p4rs::error::PipelineError> {
                 match table_id {
                     #body
                 }
@@ -561,7 +1584,7 @@ impl<'a> PipelineGenerator<'a> {
         }
     }
 
-    fn remove_table_entry_method(
+    fn remove_table_entries_matching_method(
         &mut self,
         ingress: &Control,
         egress: &Control,
@@ -571,29 +1594,56 @@ impl<'a> PipelineGenerator<'a> {
         for control in &[ingress, egress] {
             let tables = control.tables(self.ast);
             for (cs, table) in tables.iter() {
-                let qtn = qualified_table_name(Some(control), cs, table);
-                let qftn =
+                let qtn = qualified_table_name(
+                    Some(control),
+                    cs,
+                    table,
+                    &self.settings.names,
+                );
+                let qtfn =
                     qualified_table_function_name(Some(control), cs, table);
-                let call = format_ident!("remove_{}_entry", qftn);
+                let call = format_ident!("remove_{}_entries_matching", qtfn);
                 body.extend(quote! {
-                    #qtn => self.#call(keyset_data),
+                    #qtn => self.#call(predicate),
                 });
             }
         }
 
-        body.extend(quote!{
-            x => println!("remove table entry: unknown table id {}, ignoring", x),
+        // Without any tables there is nothing to match against.
+        if body.is_empty() {
+            return quote! {
+                fn remove_table_entries_matching(
+                    &mut self,
+                    _table_id: &str,
+                    _predicate: &dyn Fn(&p4rs::TableEntry) -> bool,
+                ) -> usize {
+                    0
+                }
+            };
+        }
+
+        body.extend(quote! {
+            _ => return 0,
         });
 
         quote! {
-            fn remove_table_entry(
+            fn remove_table_entries_matching(
                 &mut self,
                 table_id: &str,
-                keyset_data: &[u8],
-            ) {
-                match table_id {
+                predicate: &dyn Fn(&p4rs::TableEntry) -> bool,
+            ) -> usize {
+                let removed: Vec<p4rs::TableEntry> = match table_id {
                     #body
+                };
+                for e in &removed {
+                    self.notify_table_change(
+                        table_id,
+                        p4rs::TableChange::Remove {
+                            keyset_data: e.keyset_data.clone(),
+                        },
+                    );
                 }
+                removed.len()
             }
         }
     }
@@ -605,10 +1655,20 @@ impl<'a> PipelineGenerator<'a> {
     ) -> TokenStream {
         let mut names = Vec::new();
 
+        // Tables annotated @hidden are internal to the program, they work as
+        // any other table but are not listed for controllers.
         for control in &[ingress, egress] {
             let tables = control.tables(self.ast);
             for (cs, table) in &tables {
-                names.push(qualified_table_name(Some(control), cs, table));
+                if table.has_annotation("hidden") {
+                    continue;
+                }
+                names.push(qualified_table_name(
+                    Some(control),
+                    cs,
+                    table,
+                    &self.settings.names,
+                ));
             }
         }
         quote! {
@@ -618,6 +1678,273 @@ impl<'a> PipelineGenerator<'a> {
         }
     }
 
+    fn table_location_method(
+        &mut self,
+        ingress: &Control,
+        egress: &Control,
+    ) -> TokenStream {
+        let mut body = TokenStream::new();
+
+        for control in &[ingress, egress] {
+            let tables = control.tables(self.ast);
+            for (cs, table) in &tables {
+                let qtn = qualified_table_name(
+                    Some(control),
+                    cs,
+                    table,
+                    &self.settings.names,
+                );
+                // The root of the chain is the control itself and carries
+                // an empty instance name.
+                let controls: Vec<TokenStream> = cs
+                    .iter()
+                    .map(|(name, c)| {
+                        let instance = if name.is_empty() {
+                            self.settings.names.original(&c.name)
+                        } else {
+                            self.settings.names.original(name)
+                        };
+                        let control = self.settings.names.original(&c.name);
+                        quote! {
+                            p4rs::description::ControlLocation {
+                                instance: #instance.into(),
+                                control: #control.into(),
+                            }
+                        }
+                    })
+                    .collect();
+                let tname = self.settings.names.original(&table.name);
+                body.extend(quote! {
+                    #qtn => Some(p4rs::description::TableLocation {
+                        controls: vec![#(#controls),*],
+                        table: #tname.into(),
+                    }),
+                });
+            }
+        }
+
+        body.extend(quote! {
+            _ => None,
+        });
+
+        quote! {
+            fn table_location(
+                &self,
+                table_id: &str,
+            ) -> Option<p4rs::description::TableLocation> {
+                match table_id {
+                    #body
+                }
+            }
+        }
+    }
+
+    fn get_table_actions_method(
+        &mut self,
+        ingress: &Control,
+        egress: &Control,
+    ) -> TokenStream {
+        let mut body = TokenStream::new();
+
+        for control in &[ingress, egress] {
+            let tables = control.tables(self.ast);
+            for (cs, table) in &tables {
+                let qtn = qualified_table_name(
+                    Some(control),
+                    cs,
+                    table,
+                    &self.settings.names,
+                );
+                let actions: Vec<String> = table
+                    .actions
+                    .iter()
+                    .map(|a| self.settings.names.original(&a.name))
+                    .collect();
+                body.extend(quote! {
+                    #qtn => Some(vec![#(#actions),*]),
+                });
+            }
+        }
+
+        body.extend(quote! {
+            _ => None,
+        });
+
+        quote! {
+            fn get_table_actions(&self, table_id: &str) -> Option<Vec<&str>> {
+                match table_id {
+                    #body
+                }
+            }
+        }
+    }
+
+    fn get_table_schema_method(
+        &mut self,
+        ingress: &Control,
+        egress: &Control,
+    ) -> TokenStream {
+        let mut body = TokenStream::new();
+
+        for control in &[ingress, egress] {
+            let tables = control.tables(self.ast);
+            for (cs, table) in &tables {
+                let qtn = qualified_table_name(
+                    Some(control),
+                    cs,
+                    table,
+                    &self.settings.names,
+                );
+                let table_control = cs.last().unwrap().1;
+
+                let mut keys = Vec::new();
+                for (lval, match_kind) in &table.key {
+                    let name_info =
+                        self.hlir.lvalue_decls.get(lval).unwrap_or_else(|| {
+                            panic!("declaration info for {:#?}", lval)
+                        });
+                    let name = self.settings.names.original_lvalue(&lval.name);
+                    let kind = match match_kind {
+                        MatchKind::Exact => "exact",
+                        MatchKind::Ternary => "ternary",
+                        MatchKind::LongestPrefixMatch => "lpm",
+                        MatchKind::Range => "range",
+                    };
+                    let width = type_size_bytes(&name_info.ty, self.ast);
+                    keys.push(quote! {
+                        p4rs::FieldLayout {
+                            name: #name.into(),
+                            match_kind: Some(#kind.into()),
+                            width: #width,
+                        }
+                    });
+                }
+
+                let mut actions = Vec::new();
+                for action in &table.actions {
+                    let a = match table_control.get_action(&action.name) {
+                        Some(a) => a,
+                        None => continue,
+                    };
+                    let aname = self.settings.names.original(&action.name);
+                    let mut parameters = Vec::new();
+                    for p in &a.parameters {
+                        let pname = self.settings.names.original(&p.name);
+                        let width = type_size_bytes(&p.ty, self.ast);
+                        parameters.push(quote! {
+                            p4rs::FieldLayout {
+                                name: #pname.into(),
+                                match_kind: None,
+                                width: #width,
+                            }
+                        });
+                    }
+                    actions.push(quote! {
+                        p4rs::snapshot::ActionSchema {
+                            name: #aname.into(),
+                            parameters: vec![#(#parameters),*],
+                        }
+                    });
+                }
+
+                body.extend(quote! {
+                    #qtn => Some(p4rs::snapshot::TableSchema {
+                        keys: vec![#(#keys),*],
+                        actions: vec![#(#actions),*],
+                    }),
+                });
+            }
+        }
+
+        body.extend(quote! {
+            _ => None,
+        });
+
+        quote! {
+            fn get_table_schema(
+                &self,
+                table_id: &str,
+            ) -> Option<p4rs::snapshot::TableSchema> {
+                match table_id {
+                    #body
+                }
+            }
+        }
+    }
+
+    fn describe_method(&self, parser: &Parser) -> TokenStream {
+        let names = &self.settings.names;
+
+        let mut states = Vec::new();
+        for state in &parser.states {
+            let name = names.original(&state.name);
+            let mut transitions = Vec::new();
+            state_transitions(&state.statements, &mut transitions);
+            let mut targets: Vec<String> = Vec::new();
+            for t in &transitions {
+                let t = names.original(t);
+                if !targets.contains(&t) {
+                    targets.push(t);
+                }
+            }
+            states.push(quote! {
+                p4rs::description::ParserStateDescription {
+                    name: #name.into(),
+                    transitions: vec![#(#targets.into()),*],
+                }
+            });
+        }
+        let parser_name = &parser.name;
+
+        let mut headers = Vec::new();
+        for header in &self.ast.headers {
+            let name = &header.name;
+            let mut fields = Vec::new();
+            let mut offset = 0usize;
+            for member in &header.members {
+                let fname = names.original(&member.name);
+                let width = type_size(&member.ty, self.ast);
+                fields.push(quote! {
+                    p4rs::description::HeaderFieldDescription {
+                        name: #fname.into(),
+                        offset: #offset,
+                        width: #width,
+                    }
+                });
+                offset += width;
+            }
+            headers.push(quote! {
+                p4rs::description::HeaderDescription {
+                    name: #name.into(),
+                    fields: vec![#(#fields),*],
+                }
+            });
+        }
+
+        quote! {
+            fn describe(&self) -> p4rs::description::PipelineDescription {
+                let tables = self
+                    .get_table_ids()
+                    .into_iter()
+                    .filter_map(|table_id| {
+                        Some(p4rs::description::TableDescription {
+                            table_id: table_id.into(),
+                            schema: self.get_table_schema(table_id)?,
+                        })
+                    })
+                    .collect();
+                p4rs::description::PipelineDescription {
+                    tables,
+                    parser: p4rs::description::ParserDescription {
+                        name: #parser_name.into(),
+                        states: vec![#(#states),*],
+                    },
+                    headers: vec![#(#headers),*],
+                }
+            }
+        }
+    }
+
     fn get_table_entries_method(
         &mut self,
         ingress: &Control,
@@ -628,7 +1955,12 @@ impl<'a> PipelineGenerator<'a> {
         for control in &[ingress, egress] {
             let tables = control.tables(self.ast);
             for (cs, table) in tables.iter() {
-                let qtn = qualified_table_name(Some(control), cs, table);
+                let qtn = qualified_table_name(
+                    Some(control),
+                    cs,
+                    table,
+                    &self.settings.names,
+                );
                 let qtfn =
                     qualified_table_function_name(Some(control), cs, table);
                 let call = format_ident!("get_{}_entries", qtfn);
@@ -674,15 +2006,23 @@ impl<'a> PipelineGenerator<'a> {
         for (cs, table) in tables {
             let table_control = cs.last().unwrap().1;
             let qtfn = qualified_table_function_name(Some(control), &cs, table);
+            let qtn = qualified_table_name(
+                Some(control),
+                &cs,
+                table,
+                &self.settings.names,
+            );
             tokens.extend(self.add_table_entry_function(
                 table,
                 table_control,
                 &qtfn,
+                &qtn,
             ));
             tokens.extend(self.remove_table_entry_function(
                 table,
                 table_control,
                 &qtfn,
+                &qtn,
             ));
             tokens.extend(self.get_table_entries_function(
                 table,
@@ -692,7 +2032,38 @@ impl<'a> PipelineGenerator<'a> {
         }
     }
 
-    fn table_entry_keys(&mut self, table: &Table) -> Vec<TokenStream> {
+    /// The number of bytes of keyset data an entry for `table` carries. Each
+    /// key field takes its own size, plus a care byte for ternary keys and a
+    /// prefix length byte for lpm keys. Range keys carry both bounds.
+    fn keyset_size(&self, table: &Table) -> usize {
+        table
+            .key
+            .iter()
+            .map(|(lval, match_kind)| {
+                let name_info =
+                    self.hlir.lvalue_decls.get(lval).unwrap_or_else(|| {
+                        panic!("declaration info for {:#?}", lval,)
+                    });
+                let sz = type_size_bytes(&name_info.ty, self.ast);
+                match match_kind {
+                    MatchKind::Exact => sz,
+                    MatchKind::Ternary | MatchKind::LongestPrefixMatch => {
+                        sz + 1
+                    }
+                    MatchKind::Range => sz * 2,
+                }
+            })
+            .sum()
+    }
+
+    /// Expressions extracting each key of an entry for `table`, identified
+    /// by `qtn`, from `keyset_data`. Invalid lpm keys return an error from
+    /// the enclosing function.
+    fn table_entry_keys(
+        &mut self,
+        table: &Table,
+        qtn: &str,
+    ) -> Vec<TokenStream> {
         let mut keys = Vec::new();
         let mut offset: usize = 0;
         for (lval, match_kind) in &table.key {
@@ -719,20 +2090,32 @@ impl<'a> PipelineGenerator<'a> {
                     });
                     offset += 1; // for care/dontcare indicator
                 }
-                MatchKind::LongestPrefixMatch => keys.push(quote! {
-                    p4rs::extract_lpm_key(
-                        keyset_data,
-                        #offset,
-                        #sz,
-                    )
-                }),
-                MatchKind::Range => keys.push(quote! {
-                    p4rs::extract_range_key(
-                        keyset_data,
-                        #offset,
-                        #sz,
-                    )
-                }),
+                MatchKind::LongestPrefixMatch => {
+                    keys.push(quote! {
+                        p4rs::extract_lpm_key(
+                            keyset_data,
+                            #offset,
+                            #sz,
+                        )
+                        .map_err(|error| {
+                            p4rs::error::PipelineError::InvalidPrefix {
+                                table: #qtn.to_owned(),
+                                error,
+                            }
+                        })?
+                    });
+                    offset += 1; // for prefix length
+                }
+                MatchKind::Range => {
+                    keys.push(quote! {
+                        p4rs::extract_range_key(
+                            keyset_data,
+                            #offset,
+                            #sz,
+                        )
+                    });
+                    offset += sz; // for the upper bound
+                }
             }
             offset += sz;
         }
@@ -745,8 +2128,9 @@ impl<'a> PipelineGenerator<'a> {
         table: &Table,
         control: &Control,
         qtfn: &str,
+        qtn: &str,
     ) -> TokenStream {
-        let keys = self.table_entry_keys(table);
+        let keys = self.table_entry_keys(table, qtn);
 
         let mut action_match_body = TokenStream::new();
         for action in table.actions.iter() {
@@ -757,6 +2141,20 @@ impl<'a> PipelineGenerator<'a> {
             if &action.name == "NoAction" {
                 continue;
             }
+            if table.action_has_annotation(&action.name, "defaultonly") {
+                let aname = self.settings.names.original(&action.name);
+                action_match_body.extend(quote! {
+                    #aname => {
+                        return Err(
+                            p4rs::error::PipelineError::DefaultOnlyAction {
+                                table: #qtn.to_owned(),
+                                action: action_id.to_owned(),
+                            },
+                        );
+                    }
+                });
+                continue;
+            }
             let a = control.get_action(&action.name).unwrap_or_else(|| {
                 panic!(
                     "control {} must have action {}",
@@ -816,6 +2214,9 @@ impl<'a> PipelineGenerator<'a> {
                     Type::HeaderMethod => {
                         todo!();
                     }
+                    Type::HeaderStack(..) => {
+                        todo!();
+                    }
                     Type::Table => {
                         todo!();
                     }
@@ -861,15 +2262,15 @@ impl<'a> PipelineGenerator<'a> {
                 if let Type::UserDefined(typename) = &var.ty {
                     if self.ast.get_extern(typename).is_some() {
                         control_params.push(quote! { #name });
-                        let extern_type = format_ident!("{}", typename);
+                        let extern_type = extern_type(typename);
                         control_param_types.push(quote! {
-                            &p4rs::externs::#extern_type
+                            &#extern_type
                         });
                     }
                 }
             }
 
-            let aname = &action.name;
+            let aname = self.settings.names.original(&action.name);
             let tname = format_ident!("{}", qtfn);
             action_match_body.extend(quote! {
                 #aname => {
@@ -886,7 +2287,6 @@ impl<'a> PipelineGenerator<'a> {
                         )
                     });
                     self.#tname
-                        .entries
                         .insert(p4rs::table::TableEntry::<
                             #n,
                             std::sync::Arc<dyn Fn(
@@ -899,13 +2299,18 @@ impl<'a> PipelineGenerator<'a> {
                             action,
                             action_id: #aname.to_owned(),
                             parameter_data: parameter_data.to_owned(),
+                            immutable: false,
                         });
                 }
             });
         }
-        let name = &control.name;
         action_match_body.extend(quote! {
-            x => panic!("unknown {} action id {}", #name, x),
+            _ => {
+                return Err(p4rs::error::PipelineError::UnknownAction {
+                    table: #qtn.to_owned(),
+                    action: action_id.to_owned(),
+                });
+            }
         });
 
         let name = format_ident!("add_{}_entry", qtfn);
@@ -918,7 +2323,7 @@ impl<'a> PipelineGenerator<'a> {
                 keyset_data: &'a [u8],
                 parameter_data: &'a [u8],
                 priority: u32,
-            ) {
+            ) -> Result<(), p4rs::error::PipelineError> {
 
                 let key = [#(#keys),*];
 
@@ -926,6 +2331,7 @@ impl<'a> PipelineGenerator<'a> {
                     #action_match_body
                 }
 
+                Ok(())
             }
         }
     }
@@ -935,8 +2341,9 @@ impl<'a> PipelineGenerator<'a> {
         table: &Table,
         control: &Control,
         qtfn: &str,
+        qtn: &str,
     ) -> TokenStream {
-        let keys = self.table_entry_keys(table);
+        let keys = self.table_entry_keys(table, qtn);
         let n = table.key.len();
 
         let tname = format_ident!("{}", qtfn);
@@ -967,9 +2374,9 @@ impl<'a> PipelineGenerator<'a> {
             if let Type::UserDefined(typename) = &var.ty {
                 if self.ast.get_extern(typename).is_some() {
                     control_params.push(quote! { #name });
-                    let extern_type = format_ident!("{}", typename);
+                    let extern_type = extern_type(typename);
                     control_param_types.push(quote! {
-                        &p4rs::externs::#extern_type
+                        &#extern_type
                     });
                 }
             }
@@ -981,7 +2388,7 @@ impl<'a> PipelineGenerator<'a> {
             pub fn #name<'a>(
                 &mut self,
                 keyset_data: &'a [u8],
-            ) {
+            ) -> Result<bool, p4rs::error::PipelineError> {
 
                 let key = [#(#keys),*];
 
@@ -992,8 +2399,7 @@ impl<'a> PipelineGenerator<'a> {
                     #(#control_params),*
                 | { });
 
-                self.#tname
-                    .entries
+                let removed = self.#tname
                     .remove(
                         &p4rs::table::TableEntry::<
                             #n,
@@ -1007,49 +2413,108 @@ impl<'a> PipelineGenerator<'a> {
                             action,
                             action_id: String::new(),
                             parameter_data: Vec::new(),
+                            immutable: false,
                         }
                     );
 
+                Ok(removed)
             }
         }
     }
 
     fn get_table_entries_function(
         &mut self,
-        _table: &Table,
-        _control: &Control,
+        table: &Table,
+        control: &Control,
         qtfn: &str,
     ) -> TokenStream {
         let name = format_ident!("get_{}_entries", qtfn);
         let tname = format_ident!("{}", qtfn);
 
-        quote! {
-            pub fn #name(&self) -> Vec<p4rs::TableEntry> {
-                let mut result = Vec::new();
-
-                for e in &self.#tname.entries{
-
-                    let mut keyset_data = Vec::new();
-                    for k in &e.key {
-                        //TODO this is broken, to_bytes can squash N byte
-                        //objects into smaller than N bytes which violates
-                        //expectations of consumers. For example if this is a
-                        //16-bit integer with a value of 47 it will get squashed
-                        //down into 8-bits.
-                        keyset_data.extend_from_slice(&k.to_bytes());
+        let n = table.key.len();
+        let key_names: Vec<String> = table
+            .key
+            .iter()
+            .map(|(k, _)| self.settings.names.original_lvalue(&k.name))
+            .collect();
+
+        let mut parameter_layouts = TokenStream::new();
+        for action in &table.actions {
+            let a = match control.get_action(&action.name) {
+                Some(a) => a,
+                None => continue,
+            };
+            let aname = self.settings.names.original(&action.name);
+            let mut fields = Vec::new();
+            for p in &a.parameters {
+                let pname = self.settings.names.original(&p.name);
+                let width = type_size_bytes(&p.ty, self.ast);
+                fields.push(quote! {
+                    p4rs::FieldLayout {
+                        name: #pname.into(),
+                        match_kind: None,
+                        width: #width,
                     }
+                });
+            }
+            parameter_layouts.extend(quote! {
+                #aname => vec![#(#fields),*],
+            });
+        }
+        parameter_layouts.extend(quote! {
+            _ => Vec::new(),
+        });
 
-                    let x = p4rs::TableEntry{
-                        action_id: e.action_id.clone(),
-                        keyset_data,
-                        parameter_data: e.parameter_data.clone(),
-                    };
+        let convert = format_ident!("{}_entry", qtfn);
+        let remove_matching = format_ident!("remove_{}_entries_matching", qtfn);
+
+        quote! {
+            /// The controller's view of an entry of the table.
+            fn #convert<A: Clone>(
+                e: &p4rs::table::TableEntry<#n, A>,
+            ) -> p4rs::TableEntry {
+                let key_names: [&str; #n] = [#(#key_names),*];
+
+                let mut keyset_data = Vec::new();
+                let mut keyset_layout = Vec::new();
+                for (k, name) in e.key.iter().zip(key_names) {
+                    let data = k.to_bytes();
+                    keyset_layout.push(p4rs::FieldLayout {
+                        name: name.into(),
+                        match_kind: Some(k.match_kind().into()),
+                        width: data.len(),
+                    });
+                    keyset_data.extend_from_slice(&data);
+                }
 
-                    result.push(x);
+                let parameter_layout = match e.action_id.as_str() {
+                    #parameter_layouts
+                };
 
+                p4rs::TableEntry{
+                    action_id: e.action_id.clone(),
+                    keyset_data,
+                    parameter_data: e.parameter_data.clone(),
+                    priority: e.priority,
+                    immutable: e.immutable,
+                    keyset_layout,
+                    parameter_layout,
                 }
+            }
 
-                result
+            pub fn #name(&self) -> Vec<p4rs::TableEntry> {
+                self.#tname.entries().iter().map(Self::#convert).collect()
+            }
+
+            pub fn #remove_matching(
+                &mut self,
+                predicate: &dyn Fn(&p4rs::TableEntry) -> bool,
+            ) -> Vec<p4rs::TableEntry> {
+                self.#tname
+                    .remove_all_matching(|e| predicate(&Self::#convert(e)))
+                    .iter()
+                    .map(Self::#convert)
+                    .collect()
             }
         }
     }
@@ -1093,3 +2558,82 @@ impl<'a> PipelineGenerator<'a> {
         (member, quote! { #name: #initializer })
     }
 }
+
+// Collect the names of the states a parser state may transition to.
+fn state_transitions(block: &StatementBlock, names: &mut Vec<String>) {
+    for s in &block.statements {
+        match s {
+            Statement::Transition(Transition::Reference(lval)) => {
+                names.push(lval.name.clone());
+            }
+            Statement::Transition(Transition::Select(sel)) => {
+                for e in &sel.elements {
+                    names.push(e.name.clone());
+                }
+            }
+            Statement::If(ifb) => {
+                state_transitions(&ifb.block, names);
+                for e in &ifb.else_ifs {
+                    state_transitions(&e.block, names);
+                }
+                if let Some(b) = &ifb.else_block {
+                    state_transitions(b, names);
+                }
+            }
+            Statement::Block(b) => state_transitions(&b.block, names),
+            _ => {}
+        }
+    }
+}
+
+/// Collect the lvalues a block of parser statements extracts into, in order.
+/// Extractions into something other than an lvalue are collected as `None`.
+fn state_extractions<'a>(
+    block: &'a StatementBlock,
+    extracted: &mut Vec<Option<&'a Lvalue>>,
+) {
+    for stmt in &block.statements {
+        match stmt {
+            Statement::Call(c) if c.lval.leaf() == "extract" => {
+                extracted.push(match c.args.first().map(|a| &a.kind) {
+                    Some(ExpressionKind::Lvalue(lval)) => Some(lval),
+                    _ => None,
+                });
+            }
+            Statement::If(ifb) => {
+                state_extractions(&ifb.block, extracted);
+                for e in &ifb.else_ifs {
+                    state_extractions(&e.block, extracted);
+                }
+                if let Some(b) = &ifb.else_block {
+                    state_extractions(b, extracted);
+                }
+            }
+            Statement::Block(b) => state_extractions(&b.block, extracted),
+            _ => {}
+        }
+    }
+}
+
+/// The number of bytes of parameter data the generated code for adding an
+/// entry reads for `action`. Parameters are laid out in declaration order, a
+/// bool takes a byte and a `bit<N>` the whole bytes of N. A parameter that
+/// does not fill its last byte reads it all.
+fn parameter_data_size(action: &Action) -> usize {
+    let mut offset = 0;
+    let mut size = 0;
+    for p in &action.parameters {
+        match &p.ty {
+            Type::Bool => {
+                size = size.max(offset + 1);
+                offset += 1;
+            }
+            Type::Bit(n) => {
+                size = size.max(offset + n.div_ceil(8));
+                offset += n >> 3;
+            }
+            _ => {}
+        }
+    }
+    size
+}
@@ -2,17 +2,61 @@
 
 use crate::{
     expression::ExpressionGenerator, is_header, is_header_member,
-    is_rust_reference, rust_type,
+    is_rust_reference, lvalue_segment, mark_dirty, rust_type,
 };
 use p4::ast::{
-    Call, Control, DeclarationInfo, Direction, ExpressionKind, NameInfo,
-    Parser, Statement, StatementBlock, Transition, Type, AST,
+    BinOp, Call, Control, DeclarationInfo, Direction, Expression,
+    ExpressionKind, KeySetElementValue, Lvalue, NameInfo, Parser, Select,
+    Statement, StatementBlock, Transition, Type, UserDefinedType, AST,
 };
 use p4::hlir::Hlir;
+use p4::util::resolve_lvalue;
 use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
 use std::collections::HashMap;
 
+/// The name of the `packet_in` parameter of `parser`, if it has one.
+fn packet_in_parameter(parser: &Parser) -> Option<&str> {
+    parser
+        .parameters
+        .iter()
+        .find(|p| matches!(&p.ty, Type::UserDefined(t) if t == "packet_in"))
+        .map(|p| p.name.as_str())
+}
+
+/// Whether the parser state `name` can transition back to itself.
+fn state_on_loop(parser: &Parser, name: &str) -> bool {
+    let mut pending = vec![name];
+    let mut visited = Vec::new();
+    while let Some(current) = pending.pop() {
+        let state = match parser.states.iter().find(|s| s.name == current) {
+            Some(s) => s,
+            None => continue,
+        };
+        for stmt in &state.statements.statements {
+            let next: Vec<&str> = match stmt {
+                Statement::Transition(Transition::Reference(s)) => {
+                    vec![s.name.as_str()]
+                }
+                Statement::Transition(Transition::Select(s)) => {
+                    s.elements.iter().map(|e| e.name.as_str()).collect()
+                }
+                _ => continue,
+            };
+            for n in next {
+                if n == name {
+                    return true;
+                }
+                if !visited.contains(&n) {
+                    visited.push(n);
+                    pending.push(n);
+                }
+            }
+        }
+    }
+    false
+}
+
 #[derive(Debug)]
 pub(crate) enum StatementContext<'a> {
     Control(&'a Control),
@@ -24,6 +68,7 @@ pub(crate) struct StatementGenerator<'a> {
     hlir: &'a Hlir,
     ast: &'a AST,
     context: StatementContext<'a>,
+    checked_header_access: bool,
 }
 
 impl<'a> StatementGenerator<'a> {
@@ -31,8 +76,22 @@ impl<'a> StatementGenerator<'a> {
         ast: &'a AST,
         hlir: &'a Hlir,
         context: StatementContext<'a>,
+        checked_header_access: bool,
     ) -> Self {
-        Self { ast, hlir, context }
+        Self {
+            ast,
+            hlir,
+            context,
+            checked_header_access,
+        }
+    }
+
+    fn expression_generator(&self) -> ExpressionGenerator<'a> {
+        ExpressionGenerator::with_checked_header_access(
+            self.ast,
+            self.hlir,
+            self.checked_header_access,
+        )
     }
 
     pub(crate) fn generate_block(
@@ -47,6 +106,72 @@ impl<'a> StatementGenerator<'a> {
         ts
     }
 
+    /// Assign `rhs`, a value of type `rhs_ty`, to `lval`.
+    fn generate_assignment(
+        &self,
+        lval: &Lvalue,
+        rhs: TokenStream,
+        rhs_ty: &Type,
+        names: &HashMap<String, NameInfo>,
+    ) -> TokenStream {
+        let eg = self.expression_generator();
+        let lhs = eg.generate_lvalue(lval);
+        let name_info = self.hlir.lvalue_decls.get(lval).unwrap_or_else(|| {
+            panic!("codegen name not resolved for {:#?}", lval)
+        });
+
+        // Arithmetic results wider than the target keep their low
+        // order bits, the checker warns about this.
+        let rhs = match (rhs_ty, &name_info.ty) {
+            (Type::Bit(x), Type::Bit(y)) if x > y => {
+                quote! { p4rs::bitmath::truncate_le(#rhs.clone(), #y) }
+            }
+            _ => rhs,
+        };
+        let rhs_ty = match (rhs_ty, &name_info.ty) {
+            (Type::Bit(x), Type::Bit(y)) if x > y => &name_info.ty,
+            _ => rhs_ty,
+        };
+
+        if is_header_member(lval, self.hlir) {
+            let header: Vec<TokenStream> = lval
+                .pop_right()
+                .name
+                .split('.')
+                .map(lvalue_segment)
+                .collect();
+            return quote! {
+                #lhs = #rhs.clone();
+                #(#header).*.__dirty = true;
+            };
+        }
+
+        let rhs = if rhs_ty != &name_info.ty {
+            let converter = self.converter(rhs_ty, &name_info.ty);
+            quote!( #converter(#rhs) )
+        } else {
+            rhs
+        };
+
+        let rhs = if let Type::Bit(_) = rhs_ty {
+            // TODO eww, to better to figure out precisely when to_owned
+            // and clone are needed
+            quote! { #rhs.to_owned().clone() }
+        } else if let Type::UserDefined(_) = rhs_ty {
+            quote! { #rhs.clone() }
+        } else {
+            rhs
+        };
+
+        // whole headers copy the dirty flag of their source
+        let dirty = mark_dirty(lval, &name_info.ty, self.ast);
+        if is_rust_reference(lval, names) {
+            quote! { *#lhs = #rhs; #dirty }
+        } else {
+            quote! { #lhs = #rhs; #dirty }
+        }
+    }
+
     pub(crate) fn generate_statement(
         &self,
         stmt: &Statement,
@@ -55,10 +180,7 @@ impl<'a> StatementGenerator<'a> {
         match stmt {
             Statement::Empty => TokenStream::new(),
             Statement::Assignment(lval, xpr) => {
-                let eg = ExpressionGenerator::new(self.hlir);
-
-                let lhs = eg.generate_lvalue(lval);
-
+                let eg = self.expression_generator();
                 let rhs = eg.generate_expression(xpr.as_ref());
                 let rhs_ty = self
                     .hlir
@@ -67,37 +189,34 @@ impl<'a> StatementGenerator<'a> {
                     .unwrap_or_else(|| {
                         panic!("codegen type not found for {:#?}", xpr)
                     });
-
-                let name_info =
-                    self.hlir.lvalue_decls.get(lval).unwrap_or_else(|| {
-                        panic!("codegen name not resolved for {:#?}", lval)
-                    });
-
-                if is_header_member(lval, self.hlir) {
-                    return quote! { #lhs = #rhs.clone(); };
-                }
-
-                let rhs = if rhs_ty != &name_info.ty {
-                    let converter = self.converter(rhs_ty, &name_info.ty);
-                    quote!( #converter(#rhs) )
-                } else {
-                    rhs
-                };
-
-                let rhs = if let Type::Bit(_) = rhs_ty {
-                    // TODO eww, to better to figure out precisely when to_owned
-                    // and clone are needed
-                    quote! { #rhs.to_owned().clone() }
-                } else if let Type::UserDefined(_) = rhs_ty {
-                    quote! { #rhs.clone() }
-                } else {
-                    rhs
+                self.generate_assignment(lval, rhs, rhs_ty, names)
+            }
+            Statement::TupleAssignment(lvals, xpr) => {
+                let eg = self.expression_generator();
+                let rhs = eg.generate_expression(xpr.as_ref());
+                let elements = match self
+                    .hlir
+                    .expression_types
+                    .get(xpr.as_ref())
+                {
+                    Some(Type::List(elements)) => elements,
+                    _ => panic!("codegen: tuple type not found for {:#?}", xpr),
                 };
-
-                if is_rust_reference(lval, names) {
-                    quote! { *#lhs = #rhs; }
-                } else {
-                    quote! { #lhs = #rhs; }
+                let mut assignments = TokenStream::new();
+                for (i, (lval, ty)) in lvals.iter().zip(elements).enumerate() {
+                    let i = syn::Index::from(i);
+                    assignments.extend(self.generate_assignment(
+                        lval,
+                        quote! { tuple.#i },
+                        ty,
+                        names,
+                    ));
+                }
+                quote! {
+                    {
+                        let tuple = #rhs;
+                        #assignments
+                    }
                 }
             }
             Statement::Call(c) => match &self.context {
@@ -108,12 +227,18 @@ impl<'a> StatementGenerator<'a> {
                 }
                 StatementContext::Parser(parser) => {
                     let mut ts = TokenStream::new();
-                    self.generate_parser_body_call(parser, c, &mut ts);
+                    if c.lval.name == "verify" {
+                        self.generate_parser_verify(parser, c, &mut ts);
+                    } else if c.lval.leaf() == "advance" {
+                        self.generate_parser_advance(parser, c, &mut ts);
+                    } else {
+                        self.generate_parser_body_call(parser, c, &mut ts);
+                    }
                     ts
                 }
             },
             Statement::If(ifb) => {
-                let eg = ExpressionGenerator::new(self.hlir);
+                let eg = self.expression_generator();
                 let predicate = eg.generate_expression(ifb.predicate.as_ref());
                 let block = self.generate_block(&ifb.block, names);
                 let mut ts = quote! {
@@ -136,7 +261,7 @@ impl<'a> StatementGenerator<'a> {
                 let ty = rust_type(&v.ty);
                 let initializer = match &v.initializer {
                     Some(xpr) => {
-                        let eg = ExpressionGenerator::new(self.hlir);
+                        let eg = self.expression_generator();
                         let mut ini = eg.generate_expression(xpr.as_ref());
                         if let ExpressionKind::Lvalue(_) = xpr.kind {
                             ini = quote! { #ini.clone() };
@@ -178,7 +303,7 @@ impl<'a> StatementGenerator<'a> {
             Statement::Constant(c) => {
                 let name = format_ident!("{}", c.name);
                 let ty = rust_type(&c.ty);
-                let eg = ExpressionGenerator::new(self.hlir);
+                let eg = self.expression_generator();
                 let initializer =
                     eg.generate_expression(c.initializer.as_ref());
                 quote! {
@@ -197,34 +322,15 @@ impl<'a> StatementGenerator<'a> {
                 };
                 match transition {
                     Transition::Reference(next_state) => {
-                        match next_state.name.as_str() {
-                            "accept" => quote! { return true; },
-                            "reject" => quote! { return false; },
-                            state_ref => {
-                                let state_name = format_ident!(
-                                    "{}_{}",
-                                    parser.name,
-                                    state_ref
-                                );
-                                let mut args = Vec::new();
-                                for arg in &parser.parameters {
-                                    let name = format_ident!("{}", arg.name);
-                                    args.push(quote! { #name });
-                                }
-                                quote! {
-                                    softnpu_provider::parser_transition!(||(#state_ref));
-                                    return #state_name( #(#args),* );
-                                }
-                            }
-                        }
+                        self.generate_transition(parser, &next_state.name)
                     }
-                    Transition::Select(_) => {
-                        todo!();
+                    Transition::Select(select) => {
+                        self.generate_select(parser, select)
                     }
                 }
             }
             Statement::Return(xpr) => {
-                let eg = ExpressionGenerator::new(self.hlir);
+                let eg = self.expression_generator();
                 if let Some(xpr) = xpr {
                     let xp = eg.generate_expression(xpr.as_ref());
                     quote! { return #xp; }
@@ -232,7 +338,142 @@ impl<'a> StatementGenerator<'a> {
                     quote! { return }
                 }
             }
+            Statement::Block(blk) => {
+                let block = self.generate_block(&blk.block, names);
+                quote! { { #block } }
+            }
+        }
+    }
+
+    fn generate_transition(&self, parser: &Parser, state: &str) -> TokenStream {
+        match state {
+            "accept" => quote! { return true; },
+            "reject" => quote! { return false; },
+            state_ref => {
+                let state_name = format_ident!("{}_{}", parser.name, state_ref);
+                let mut args = Vec::new();
+                for arg in &parser.parameters {
+                    let name = format_ident!("{}", arg.name);
+                    args.push(quote! { #name });
+                }
+                // states on a loop are entered a bounded number of times
+                let bound = match packet_in_parameter(parser) {
+                    Some(pkt) if state_on_loop(parser, state_ref) => {
+                        let pkt = format_ident!("{}", pkt);
+                        let timeout = self
+                            .set_standard_parser_error(parser, "ParserTimeout");
+                        quote! {
+                            if !#pkt.enter_loop() {
+                                #timeout
+                                return false;
+                            }
+                        }
+                    }
+                    _ => TokenStream::new(),
+                };
+                quote! {
+                    #bound
+                    softnpu_provider::parser_transition!(||(#state_ref));
+                    return #state_name( #(#args),* );
+                }
+            }
+        }
+    }
+
+    /// Lower a select transition to a chain of conditionals, one per select
+    /// element, taken in order. A packet that matches no element is rejected.
+    fn generate_select(&self, parser: &Parser, select: &Select) -> TokenStream {
+        let mut tokens = TokenStream::new();
+        for element in &select.elements {
+            let transition = self.generate_transition(parser, &element.name);
+            let conditions: Vec<TokenStream> = select
+                .parameters
+                .iter()
+                .zip(&element.keyset)
+                .filter_map(|(param, k)| {
+                    self.generate_keyset_condition(param, &k.value)
+                })
+                .collect();
+            if conditions.is_empty() {
+                tokens.extend(transition);
+                return tokens;
+            }
+            tokens.extend(quote! {
+                if #(#conditions)&&* {
+                    #transition
+                }
+            });
         }
+        let no_match = self.set_standard_parser_error(parser, "NoMatch");
+        tokens.extend(quote! {
+            #no_match
+            return false;
+        });
+        tokens
+    }
+
+    /// The condition under which select parameter `param` matches a keyset
+    /// element, or `None` if the element matches anything.
+    fn generate_keyset_condition(
+        &self,
+        param: &Expression,
+        value: &KeySetElementValue,
+    ) -> Option<TokenStream> {
+        let eg = self.expression_generator();
+        let width = match self.hlir.expression_types.get(param) {
+            Some(Type::Bit(n)) => Some(*n),
+            _ => None,
+        };
+        // Integer literals take the width of the value they are compared to.
+        let value_of = |xpr: &Expression| {
+            let xpr = self.hlir.constant_value(xpr);
+            match (&xpr.kind, width) {
+                (ExpressionKind::IntegerLit(v), Some(n)) => {
+                    eg.generate_bit_literal(n as u16, *v as u128)
+                }
+                _ => eg.generate_expression(xpr),
+            }
+        };
+        let p = eg.generate_expression(param);
+        let (v, m) = match value {
+            KeySetElementValue::Default | KeySetElementValue::DontCare => {
+                return None
+            }
+            KeySetElementValue::Expression(xpr) => match &xpr.kind {
+                ExpressionKind::Lvalue(lval) if lval.name == "default" => {
+                    return None
+                }
+                // outside of a tuple a masked keyset parses as an expression
+                ExpressionKind::Binary(v, BinOp::Mask, m) => (v, m),
+                _ => {
+                    let v = value_of(xpr);
+                    return Some(quote! { #p == #v });
+                }
+            },
+            KeySetElementValue::Masked(v, m) => (v, m),
+            KeySetElementValue::Ranged(lo, hi) => {
+                let lo = value_of(lo);
+                let hi = value_of(hi);
+                return Some(quote! {
+                    {
+                        let p = p4rs::bitvec_to_biguint(&#p.to_bitvec()).value;
+                        let lo = p4rs::bitvec_to_biguint(&#lo.to_bitvec()).value;
+                        let hi = p4rs::bitvec_to_biguint(&#hi.to_bitvec()).value;
+                        lo <= p && p <= hi
+                    }
+                });
+            }
+        };
+        let v = value_of(v);
+        let m = value_of(m);
+        Some(quote! {
+            {
+                let m = p4rs::bitvec_to_biguint(&#m.to_bitvec()).value;
+                let p = p4rs::bitvec_to_biguint(&#p.to_bitvec()).value;
+                let v = p4rs::bitvec_to_biguint(&#v.to_bitvec()).value;
+                (p & &m) == (v & &m)
+            }
+        })
     }
 
     fn generate_parser_body_call(
@@ -241,18 +482,50 @@ impl<'a> StatementGenerator<'a> {
         c: &Call,
         tokens: &mut TokenStream,
     ) {
-        let lval: Vec<TokenStream> = c
-            .lval
-            .name
-            .split('.')
-            .map(|x| format_ident!("{}", x))
-            .map(|x| quote! { #x })
-            .collect();
+        let lval: Vec<TokenStream> =
+            c.lval.name.split('.').map(lvalue_segment).collect();
+
+        if let Some(stack) = self.extract_stack_arg(parser, c) {
+            let pkt: Vec<TokenStream> = c
+                .lval
+                .pop_right()
+                .name
+                .split('.')
+                .map(lvalue_segment)
+                .collect();
+            let too_short =
+                self.set_standard_parser_error(parser, "PacketTooShort");
+            let out_of_bounds =
+                self.set_standard_parser_error(parser, "StackOutOfBounds");
+            tokens.extend(quote! {
+                match #(#pkt).*.extract_stack(&mut #stack) {
+                    Ok(()) => {}
+                    Err(p4rs::error::ExtractStackError::PacketTooShort(_)) => {
+                        #too_short
+                        return false;
+                    }
+                    Err(p4rs::error::ExtractStackError::StackOutOfBounds(_)) => {
+                        #out_of_bounds
+                        return false;
+                    }
+                }
+            });
+            return;
+        }
 
         let mut args = Vec::new();
         for a in &c.args {
             match &a.kind {
                 ExpressionKind::Lvalue(lvarg) => {
+                    // extracting into a member of a header union invalidates
+                    // the other members of the union
+                    if c.lval.leaf() == "extract" {
+                        if let Some(u) =
+                            self.header_union_of(lvarg, &parser.names())
+                        {
+                            tokens.extend(quote! { #u.set_invalid(); });
+                        }
+                    }
                     let parts: Vec<&str> = lvarg.name.split('.').collect();
                     let root = parts[0];
                     let mut mut_arg = false;
@@ -266,11 +539,8 @@ impl<'a> StatementGenerator<'a> {
                             }
                         }
                     }
-                    let lvref: Vec<TokenStream> = parts
-                        .iter()
-                        .map(|x| format_ident!("{}", x))
-                        .map(|x| quote! { #x })
-                        .collect();
+                    let lvref: Vec<TokenStream> =
+                        parts.iter().map(|x| lvalue_segment(x)).collect();
                     if mut_arg {
                         args.push(quote! { &mut #(#lvref).* });
                     } else {
@@ -280,17 +550,177 @@ impl<'a> StatementGenerator<'a> {
                 x => todo!("extern arg {:?}", x),
             }
         }
+        // A packet too short for the header being extracted is rejected
+        // rather than extracted from past its end.
+        if c.lval.leaf() == "extract" && args.len() == 1 {
+            let pkt: Vec<TokenStream> = c
+                .lval
+                .pop_right()
+                .name
+                .split('.')
+                .map(lvalue_segment)
+                .collect();
+            let too_short =
+                self.set_standard_parser_error(parser, "PacketTooShort");
+            tokens.extend(quote! {
+                if #(#pkt).*.try_extract( #(#args),* ).is_err() {
+                    #too_short
+                    return false;
+                }
+            });
+            return;
+        }
         tokens.extend(quote! {
             #(#lval).* ( #(#args),* );
         });
     }
 
+    /// If `c` is an `extract(stack.next)` call, the header stack being
+    /// extracted into.
+    fn extract_stack_arg(
+        &self,
+        parser: &Parser,
+        c: &Call,
+    ) -> Option<TokenStream> {
+        if c.lval.leaf() != "extract" {
+            return None;
+        }
+        let lvarg = match c.args.as_slice() {
+            [arg] => match &arg.kind {
+                ExpressionKind::Lvalue(lvarg) if lvarg.leaf() == "next" => {
+                    lvarg.pop_right()
+                }
+                _ => return None,
+            },
+            _ => return None,
+        };
+        match resolve_lvalue(&lvarg, self.ast, &parser.names()) {
+            Ok(NameInfo {
+                ty: Type::HeaderStack(..),
+                ..
+            }) => {}
+            _ => return None,
+        }
+        let parts: Vec<TokenStream> =
+            lvarg.name.split('.').map(lvalue_segment).collect();
+        Some(quote! { #(#parts).* })
+    }
+
+    /// Lower `pkt.advance(bits)`. The number of bits is either an integer
+    /// literal or a bit-typed expression. Advancing past the end of the packet
+    /// rejects it.
+    fn generate_parser_advance(
+        &self,
+        parser: &Parser,
+        c: &Call,
+        tokens: &mut TokenStream,
+    ) {
+        let lval: Vec<TokenStream> =
+            c.lval.name.split('.').map(lvalue_segment).collect();
+        let bits = match &c.args[0].kind {
+            ExpressionKind::IntegerLit(v) => {
+                let v = *v as usize;
+                quote! { #v }
+            }
+            _ => {
+                let eg = self.expression_generator();
+                let xpr = eg.generate_expression(c.args[0].as_ref());
+                quote! {
+                    usize::try_from(
+                        &p4rs::bitvec_to_biguint(&#xpr).value
+                    ).unwrap_or(usize::MAX)
+                }
+            }
+        };
+        let too_short =
+            self.set_standard_parser_error(parser, "PacketTooShort");
+        tokens.extend(quote! {
+            if #(#lval).* ( #bits ).is_err() {
+                #too_short
+                return false;
+            }
+        });
+    }
+
+    /// Lower `verify(condition, err)` to a conditional that records `err` in
+    /// the `parser_error` member of any parser argument that carries one, and
+    /// rejects the packet when `condition` does not hold.
+    fn generate_parser_verify(
+        &self,
+        parser: &Parser,
+        c: &Call,
+        tokens: &mut TokenStream,
+    ) {
+        let eg = self.expression_generator();
+        let condition = eg.generate_expression(c.args[0].as_ref());
+        let err = eg.generate_expression(c.args[1].as_ref());
+        let set_error = self.set_parser_error(parser, err);
+
+        tokens.extend(quote! {
+            if !(#condition) {
+                #set_error
+                return false;
+            }
+        });
+    }
+
+    /// Record the error `err` in the `parser_error` member of any parser
+    /// argument that carries one.
+    fn set_parser_error(
+        &self,
+        parser: &Parser,
+        err: TokenStream,
+    ) -> TokenStream {
+        let mut set_error = TokenStream::new();
+        for arg in &parser.parameters {
+            if !matches!(arg.direction, Direction::Out | Direction::InOut) {
+                continue;
+            }
+            let typename = match &arg.ty {
+                Type::UserDefined(typename) => typename,
+                _ => continue,
+            };
+            let s = match self.ast.get_struct(typename) {
+                Some(s) => s,
+                None => continue,
+            };
+            if s.members
+                .iter()
+                .any(|m| m.name == "parser_error" && m.ty == Type::Error)
+            {
+                let name = format_ident!("{}", arg.name);
+                set_error.extend(quote! { #name.parser_error = #err; });
+            }
+        }
+        set_error
+    }
+
+    /// Record the error named `name` in the parser arguments that carry a
+    /// `parser_error`, if the program declares it. The standard errors are
+    /// declared by core.p4.
+    fn set_standard_parser_error(
+        &self,
+        parser: &Parser,
+        name: &str,
+    ) -> TokenStream {
+        if !self.ast.errors.iter().any(|e| e == name) {
+            return TokenStream::new();
+        }
+        let name = format_ident!("{}", name);
+        self.set_parser_error(parser, quote! { error::#name })
+    }
+
     fn generate_control_body_call(
         &self,
         control: &Control,
         c: &Call,
         tokens: &mut TokenStream,
     ) {
+        if matches!(c.lval.name.as_str(), "assert" | "assume") {
+            self.generate_assertion(c, tokens);
+            return;
+        }
+
         if c.lval.name.split('.').count() < 2 {
             self.generate_action_call(control, c, tokens);
             return;
@@ -309,6 +739,12 @@ impl<'a> StatementGenerator<'a> {
             "isValid" => {
                 self.generate_header_get_validity(c, tokens);
             }
+            "push_front" | "pop_front" if self.is_header_stack_call(c) => {
+                self.generate_header_stack_shift(c, tokens);
+            }
+            "emit" if self.is_packet_out_call(control, c) => {
+                self.generate_emit(c, tokens);
+            }
             _ => {
                 // assume we are at an extern call
 
@@ -326,12 +762,62 @@ impl<'a> StatementGenerator<'a> {
         c: &Call,
         tokens: &mut TokenStream,
     ) {
-        let eg = ExpressionGenerator::new(self.hlir);
+        let eg = self.expression_generator();
         let mut args = Vec::new();
 
-        for a in &c.args {
+        // The action function takes the control parameters and externs
+        // ahead of its own parameters.
+        for p in &control.parameters {
+            let name = format_ident!("{}", p.name);
+            args.push(quote! { #name });
+        }
+        for var in &control.variables {
+            let name = format_ident!("{}", var.name);
+            if let Type::UserDefined(typename) = &var.ty {
+                if self.ast.get_extern(typename).is_some() {
+                    args.push(quote! { &#name });
+                }
+            }
+        }
+
+        // Out and inout arguments are copied in to locals the action writes
+        // through, and copied back out once it returns. Lending the lvalue
+        // itself would alias the control parameters the action also takes.
+        let action = control.get_action(&c.lval.name);
+        let mut locals = Vec::new();
+        let mut write_back = Vec::new();
+        for (i, a) in c.args.iter().enumerate() {
             let arg_xpr = eg.generate_expression(a.as_ref());
-            args.push(arg_xpr);
+            let direction = action
+                .and_then(|action| action.parameters.get(i))
+                .map(|p| p.direction);
+            match (direction, &a.kind) {
+                (
+                    Some(Direction::Out | Direction::InOut),
+                    ExpressionKind::Lvalue(lval),
+                ) => {
+                    let local = format_ident!("arg{}", i);
+                    locals.push(quote! { let mut #local = #arg_xpr.clone(); });
+                    args.push(quote! { &mut #local });
+                    let lhs = eg.generate_lvalue(lval);
+                    let reference = lval.degree() == 1
+                        && matches!(
+                            self.hlir.lvalue_decls.get(lval).map(|n| &n.decl),
+                            Some(DeclarationInfo::Parameter(
+                                Direction::Out | Direction::InOut
+                            )) | Some(DeclarationInfo::ActionParameter(
+                                Direction::Out | Direction::InOut
+                            ))
+                        );
+                    if reference {
+                        write_back.push(quote! { *#lhs = #local; });
+                    } else {
+                        write_back.push(quote! { #lhs = #local; });
+                    }
+                    write_back.push(eg.mark_written(lval));
+                }
+                _ => args.push(arg_xpr),
+            }
         }
 
         let lvref: Vec<TokenStream> = c
@@ -342,13 +828,31 @@ impl<'a> StatementGenerator<'a> {
             .map(|x| quote! { #x })
             .collect();
 
-        for a in &control.parameters {
-            let arg = format_ident!("{}", a.name);
-            args.push(quote! { #arg });
+        if write_back.is_empty() {
+            tokens.extend(quote! {
+                #(#lvref).*(#(#args),*);
+            });
+        } else {
+            tokens.extend(quote! {
+                {
+                    #(#locals)*
+                    #(#lvref).*(#(#args),*);
+                    #(#write_back)*
+                }
+            });
         }
+    }
 
+    /// Lower `assert(condition)` and `assume(condition)` to runtime checks
+    /// that carry the location of the call in the P4 source.
+    fn generate_assertion(&self, c: &Call, tokens: &mut TokenStream) {
+        let eg = self.expression_generator();
+        let condition = eg.generate_expression(c.args[0].as_ref());
+        let check = format_ident!("{}", c.lval.name);
+        let location =
+            format!("{}:{}", c.lval.token.file, c.lval.token.line + 1);
         tokens.extend(quote! {
-            #(#lvref).*(#(#args),*);
+            p4rs::externs::#check(#condition, #location);
         })
     }
 
@@ -358,7 +862,16 @@ impl<'a> StatementGenerator<'a> {
         c: &Call,
         tokens: &mut TokenStream,
     ) {
-        let eg = ExpressionGenerator::new(self.hlir);
+        let eg = self.expression_generator();
+        if let Some(call) = eg.generate_user_extern_call(c) {
+            tokens.extend(quote! { #call; });
+            return;
+        }
+        if let Some(call) = eg.generate_meter_call(c) {
+            tokens.extend(quote! { #call; });
+            return;
+        }
+
         let mut args = Vec::new();
 
         for a in &c.args {
@@ -366,13 +879,8 @@ impl<'a> StatementGenerator<'a> {
             args.push(arg_xpr);
         }
 
-        let lvref: Vec<TokenStream> = c
-            .lval
-            .name
-            .split('.')
-            .map(|x| format_ident!("{}", x))
-            .map(|x| quote! { #x })
-            .collect();
+        let lvref: Vec<TokenStream> =
+            c.lval.name.split('.').map(lvalue_segment).collect();
 
         tokens.extend(quote! {
             #(#lvref).*(#(#args),*);
@@ -410,7 +918,7 @@ impl<'a> StatementGenerator<'a> {
 
         // This is a call to another control instance
         if control_instance.name != control.name {
-            let eg = ExpressionGenerator::new(self.hlir);
+            let eg = self.expression_generator();
             let mut locals = Vec::new();
             let mut args = Vec::new();
             for (i, a) in c.args.iter().enumerate() {
@@ -484,6 +992,7 @@ impl<'a> StatementGenerator<'a> {
                 let name = format_ident!("{}_{}", c.lval.root(), qtn);
                 args.push(quote! { #name });
             }
+            args.push(quote! { externs });
 
             let cname = &control_instance.name;
             let call = format_ident!("{}_apply", control_instance.name);
@@ -535,12 +1044,8 @@ impl<'a> StatementGenerator<'a> {
 
         let mut selector_components = Vec::new();
         for (lval, _match_kind) in &table.key {
-            let lvref: Vec<TokenStream> = lval
-                .name
-                .split('.')
-                .map(|x| format_ident!("{}", x))
-                .map(|x| quote! { #x })
-                .collect();
+            let lvref: Vec<TokenStream> =
+                lval.name.split('.').map(lvalue_segment).collect();
 
             // determine if this lvalue references a header or a struct,
             // if it's a header there's a bit of extra unsrapping we
@@ -601,16 +1106,26 @@ impl<'a> StatementGenerator<'a> {
             .pop_right()
             .name
             .split('.')
-            .map(|x| format_ident!("{}", x))
-            .map(|x| quote! { #x })
+            .map(lvalue_segment)
             .collect();
         if valid {
+            // validating a member of a header union invalidates the other
+            // members of the union
+            let names = match &self.context {
+                StatementContext::Control(control) => control.names(),
+                StatementContext::Parser(parser) => parser.names(),
+            };
+            if let Some(u) = self.header_union_of(&c.lval.pop_right(), &names) {
+                tokens.extend(quote! { #u.set_invalid(); });
+            }
             tokens.extend(quote! {
                 #(#lhs).*.set_valid();
+                #(#lhs).*.__dirty = true;
             });
         } else {
             tokens.extend(quote! {
                 #(#lhs).*.set_invalid();
+                #(#lhs).*.__dirty = true;
             });
         }
     }
@@ -621,14 +1136,131 @@ impl<'a> StatementGenerator<'a> {
             .pop_right()
             .name
             .split('.')
-            .map(|x| format_ident!("{}", x))
-            .map(|x| quote! { #x })
+            .map(lvalue_segment)
             .collect();
         tokens.extend(quote! {
             #(#lhs).*.is_valid()
         });
     }
 
+    fn is_header_stack_call(&self, c: &Call) -> bool {
+        matches!(
+            self.hlir.lvalue_decls.get(&c.lval.pop_right()),
+            Some(NameInfo {
+                ty: Type::HeaderStack(..),
+                ..
+            })
+        )
+    }
+
+    fn generate_header_stack_shift(&self, c: &Call, tokens: &mut TokenStream) {
+        let stack: Vec<TokenStream> = c
+            .lval
+            .pop_right()
+            .name
+            .split('.')
+            .map(lvalue_segment)
+            .collect();
+        let count = match c.args.first().map(|a| &a.kind) {
+            Some(ExpressionKind::IntegerLit(n)) => *n as usize,
+            _ => panic!("codegen: header stack shift count must be a literal"),
+        };
+        let shift = match c.lval.leaf() {
+            "push_front" => quote! { p4rs::header_stack_push_front },
+            _ => quote! { p4rs::header_stack_pop_front },
+        };
+        tokens.extend(quote! {
+            #shift(&mut #(#stack).*, #count);
+            for h in #(#stack).*.iter_mut() {
+                h.__dirty = true;
+            }
+        });
+    }
+
+    fn is_packet_out_call(&self, control: &Control, c: &Call) -> bool {
+        let root = c.lval.pop_right();
+        control.parameters.iter().any(|p| {
+            p.name == root.name
+                && p.ty == Type::UserDefined("packet_out".into())
+        })
+    }
+
+    /// Lower `pkt.emit(x)` in a deparser. Headers are appended to the output
+    /// when valid; structs and header stacks emit their headers in
+    /// declaration order.
+    fn generate_emit(&self, c: &Call, tokens: &mut TokenStream) {
+        let pkt = format_ident!("{}", c.lval.pop_right().name);
+        let lval = match c.args.first().map(|a| &a.kind) {
+            Some(ExpressionKind::Lvalue(lval)) => lval,
+            _ => panic!("codegen: emit argument must be an lvalue"),
+        };
+        let ty = match self.hlir.lvalue_decls.get(lval) {
+            Some(info) => &info.ty,
+            None => panic!("codegen: undefined lvalue {}", lval.name),
+        };
+        let parts: Vec<TokenStream> =
+            lval.name.split('.').map(lvalue_segment).collect();
+        self.generate_emit_value(&pkt, quote! { #(#parts).* }, ty, tokens);
+    }
+
+    fn generate_emit_value(
+        &self,
+        pkt: &proc_macro2::Ident,
+        value: TokenStream,
+        ty: &Type,
+        tokens: &mut TokenStream,
+    ) {
+        match ty {
+            Type::HeaderStack(..) => tokens.extend(quote! {
+                for h in #value.iter() {
+                    #pkt.emit(h);
+                }
+            }),
+            Type::UserDefined(name) => {
+                match self.ast.get_user_defined_type(name) {
+                    Some(UserDefinedType::Header(_))
+                    | Some(UserDefinedType::HeaderUnion(_)) => {
+                        tokens.extend(quote! { #pkt.emit(&#value); })
+                    }
+                    Some(UserDefinedType::Struct(s)) => {
+                        for m in &s.members {
+                            let member = format_ident!("{}", m.name);
+                            self.generate_emit_value(
+                                pkt,
+                                quote! { #value.#member },
+                                &m.ty,
+                                tokens,
+                            );
+                        }
+                    }
+                    _ => panic!("codegen: cannot emit {}", name),
+                }
+            }
+            _ => panic!("codegen: cannot emit {}", ty),
+        }
+    }
+
+    /// If `lval` refers to a member of a header union, return the enclosing
+    /// header union.
+    fn header_union_of(
+        &self,
+        lval: &Lvalue,
+        names: &HashMap<String, NameInfo>,
+    ) -> Option<TokenStream> {
+        if lval.degree() < 2 {
+            return None;
+        }
+        let parent = lval.pop_right();
+        let typename = match resolve_lvalue(&parent, self.ast, names).ok()?.ty {
+            Type::UserDefined(name) => name,
+            _ => return None,
+        };
+        self.ast.get_header_union(&typename)?;
+        let parts: Vec<TokenStream> =
+            parent.name.split('.').map(lvalue_segment).collect();
+        Some(quote! { #(#parts).* })
+    }
+
     fn converter(&self, from: &Type, to: &Type) -> TokenStream {
         match (from, to) {
             (Type::Int(_), Type::Bit(_)) => {
@@ -20,11 +20,47 @@
 //! ```
 //! This will result in a `muffin_pipeline` struct being being generated.
 //!
+//! To check that headers are valid each time one of their fields is read,
+//! set `checked_header_access`. Debug builds then panic on reads of fields of
+//! invalid headers.
+//!
+//! ```ignore
+//! p4_macro::use_p4!(
+//!     p4 = "path/to/p4/program.p4",
+//!     pipeline_name = "muffin",
+//!     checked_header_access = true,
+//! );
+//! ```
+//!
+//! To test a control on its own, name it in `standalone_controls`. This
+//! generates an `acl_control` struct that holds the tables of the `acl`
+//! control and applies it to the headers and metadata it is given.
+//!
+//! ```ignore
+//! p4_macro::use_p4!(
+//!     p4 = "path/to/p4/program.p4",
+//!     pipeline_name = "muffin",
+//!     standalone_controls = ["acl"],
+//! );
+//! ```
+//!
+//! To emit the original header bytes of packets whose headers the pipeline
+//! leaves unmodified, rather than serializing the headers again, set
+//! `optimize`.
+//!
+//! ```ignore
+//! p4_macro::use_p4!(
+//!     p4 = "path/to/p4/program.p4",
+//!     pipeline_name = "muffin",
+//!     optimize = true,
+//! );
+//! ```
+//!
 //! For documentation on using [Pipeline](../p4rs/trait.Pipeline.html) trait, see the
 //! [p4rs](../p4rs/index.html) docs.
 
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use p4::check::Diagnostics;
@@ -40,16 +76,28 @@ use syn::{parse, LitStr};
 struct MacroSettings {
     p4: ParseWrapper<LitStr>,
     pipeline_name: ParseWrapper<LitStr>,
+    #[serde(default)]
+    checked_header_access: bool,
+    #[serde(default)]
+    standalone_controls: Vec<String>,
+    #[serde(default)]
+    optimize: bool,
 }
 
 struct GenerationSettings {
     pipeline_name: String,
+    checked_header_access: bool,
+    standalone_controls: Vec<String>,
+    optimize: bool,
 }
 
 impl Default for GenerationSettings {
     fn default() -> Self {
         Self {
             pipeline_name: "main".to_owned(),
+            checked_header_access: false,
+            standalone_controls: Vec::new(),
+            optimize: false,
         }
     }
 }
@@ -74,12 +122,20 @@ fn do_use_p4(item: TokenStream) -> Result<TokenStream, syn::Error> {
         if let Ok(filename) = parse::<LitStr>(item.clone()) {
             (filename.value(), GenerationSettings::default())
         } else {
-            let MacroSettings { p4, pipeline_name } =
-                serde_tokenstream::from_tokenstream(&item.into())?;
+            let MacroSettings {
+                p4,
+                pipeline_name,
+                checked_header_access,
+                standalone_controls,
+                optimize,
+            } = serde_tokenstream::from_tokenstream(&item.into())?;
             (
                 p4.into_inner().value(),
                 GenerationSettings {
                     pipeline_name: pipeline_name.into_inner().value(),
+                    checked_header_access,
+                    standalone_controls,
+                    optimize,
                 },
             )
         };
@@ -93,8 +149,16 @@ fn generate_rs(
 ) -> Result<TokenStream, syn::Error> {
     //TODO gracefull error handling
 
+    // System includes are found next to the top level program.
+    let search: Vec<PathBuf> = Path::new(&filename)
+        .parent()
+        .map(Into::into)
+        .into_iter()
+        .collect();
+
     let mut ast = AST::default();
-    process_file(Arc::new(filename), &mut ast, &settings)?;
+    process_file(Arc::new(filename), &mut ast, &search, &settings)?;
+    let names = p4_rust::sanitize(&mut ast);
 
     let (hlir, _) = check::all(&ast);
 
@@ -103,6 +167,10 @@ fn generate_rs(
         &hlir,
         p4_rust::Settings {
             pipeline_name: settings.pipeline_name.clone(),
+            names,
+            checked_header_access: settings.checked_header_access,
+            standalone_controls: settings.standalone_controls,
+            optimize: settings.optimize,
         },
     )
     .into();
@@ -113,25 +181,37 @@ fn generate_rs(
 fn process_file(
     filename: Arc<String>,
     ast: &mut AST,
+    search: &[PathBuf],
     _settings: &GenerationSettings,
 ) -> Result<(), syn::Error> {
     let contents = match fs::read_to_string(&*filename) {
         Ok(c) => c,
         Err(e) => panic!("failed to read file {}: {}", filename, e),
     };
-    let ppr = preprocessor::run(&contents, filename.clone()).unwrap();
+    process_source(filename, &contents, ast, search, _settings)
+}
+
+fn process_source(
+    filename: Arc<String>,
+    contents: &str,
+    ast: &mut AST,
+    search: &[PathBuf],
+    _settings: &GenerationSettings,
+) -> Result<(), syn::Error> {
+    let ppr = preprocessor::run(contents, filename.clone()).unwrap();
     for included in &ppr.elements.includes {
-        let path = Path::new(included);
-        if !path.is_absolute() {
-            let parent = Path::new(&*filename).parent().unwrap();
-            let joined = parent.join(included);
+        if let Some(path) = included.resolve(Path::new(&*filename), search) {
             process_file(
-                Arc::new(joined.to_str().unwrap().to_string()),
+                Arc::new(path.to_str().unwrap().to_string()),
                 ast,
+                search,
                 _settings,
-            )?
+            )?;
+        } else if let Some(source) = included.bundled() {
+            let name = Arc::new(format!("<{}>", included.path));
+            process_source(name, source, ast, search, _settings)?;
         } else {
-            process_file(Arc::new(included.clone()), ast, _settings)?;
+            panic!("{}: include not found: {}", filename, included.path);
         }
     }
 
@@ -141,7 +221,6 @@ fn process_file(
     let lxr = lexer::Lexer::new(lines.clone(), filename);
     let mut psr = parser::Parser::new(lxr);
     psr.run(ast).unwrap();
-    p4_rust::sanitize(ast);
     Ok(())
 }
 
@@ -153,6 +232,7 @@ fn check(lines: &[&str], diagnostics: &Diagnostics) {
         for e in errors {
             err.push(SemanticError {
                 at: e.token.clone(),
+                code: e.code,
                 message: e.message.clone(),
                 source: lines[e.token.line].into(),
             });
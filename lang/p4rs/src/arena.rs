@@ -0,0 +1,73 @@
+// Copyright 2022 Oxide Computer Company
+
+//! A scratch arena for the buffers a pipeline needs for each packet it
+//! processes. Output header buffers handed back to the pipeline once a packet
+//! has been sent are kept here and reused for later packets, so a pipeline in
+//! steady state does not go to the heap for them.
+
+/// The number of buffers an arena keeps by default. This covers a broadcast
+/// over a typical number of ports without holding on to an unbounded amount
+/// of memory after a burst.
+pub const DEFAULT_ARENA_BUFFERS: usize = 64;
+
+#[derive(Debug)]
+pub struct Arena {
+    buffers: Vec<Vec<u8>>,
+    limit: usize,
+}
+
+impl Default for Arena {
+    fn default() -> Self {
+        Self::new(DEFAULT_ARENA_BUFFERS)
+    }
+}
+
+impl Arena {
+    /// Create an arena that keeps up to `limit` buffers for reuse.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            buffers: Vec::with_capacity(limit),
+            limit,
+        }
+    }
+
+    /// Take an empty buffer, reusing a recycled one if there is one.
+    pub fn take(&mut self) -> Vec<u8> {
+        self.buffers.pop().unwrap_or_default()
+    }
+
+    /// Hand a buffer back for reuse. Its contents are discarded but its
+    /// capacity is kept. Buffers beyond the limit of the arena are freed.
+    pub fn recycle(&mut self, mut buf: Vec<u8>) {
+        if self.buffers.len() < self.limit {
+            buf.clear();
+            self.buffers.push(buf);
+        }
+    }
+
+    /// The number of buffers available for reuse.
+    pub fn available(&self) -> usize {
+        self.buffers.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arena_reuses_buffers() {
+        let mut arena = Arena::new(1);
+        let mut buf = arena.take();
+        buf.extend_from_slice(&[1, 2, 3]);
+        let ptr = buf.as_ptr();
+        arena.recycle(buf);
+        arena.recycle(vec![4]);
+        assert_eq!(arena.available(), 1);
+
+        let buf = arena.take();
+        assert!(buf.is_empty());
+        assert_eq!(buf.as_ptr(), ptr);
+        assert_eq!(arena.available(), 0);
+    }
+}
@@ -1,5 +1,16 @@
 // Copyright 2022 Oxide Computer Company
 
+//! Arithmetic over P4 bit vectors.
+//!
+//! Overflow follows the P4 spec. For `bit<N>` values addition and subtraction
+//! wrap around modulo 2^N. For `int<N>` values they wrap around in two's
+//! complement, which for a fixed width is the same bit pattern as unsigned
+//! wrap-around, so both types share the functions in this module. The width
+//! of a result is the width of the wider operand.
+//!
+//! The saturating functions implement the P4 `|+|` and `|-|` operators. They
+//! clamp results to the range of an unsigned value of the result width.
+
 use bitvec::prelude::*;
 
 pub fn add_be(a: BitVec<u8, Msb0>, b: BitVec<u8, Msb0>) -> BitVec<u8, Msb0> {
@@ -9,7 +20,7 @@ pub fn add_be(a: BitVec<u8, Msb0>, b: BitVec<u8, Msb0>) -> BitVec<u8, Msb0> {
     // softnpu to have an architectural bit-type width limit of 128.
     let x: u128 = a.load_be();
     let y: u128 = b.load_be();
-    let z = x + y;
+    let z = x.wrapping_add(y);
     let mut c = BitVec::new();
     c.resize(len, false);
     c.store_be(z);
@@ -23,13 +34,86 @@ pub fn add_le(a: BitVec<u8, Msb0>, b: BitVec<u8, Msb0>) -> BitVec<u8, Msb0> {
     // softnpu to have an architectural bit-type width limit of 128.
     let x: u128 = a.load_le();
     let y: u128 = b.load_le();
-    let z = x + y;
+    let z = x.wrapping_add(y);
+    let mut c = BitVec::new();
+    c.resize(len, false);
+    c.store_le(z);
+    c
+}
+
+pub fn sub_be(a: BitVec<u8, Msb0>, b: BitVec<u8, Msb0>) -> BitVec<u8, Msb0> {
+    let len = usize::max(a.len(), b.len());
+
+    let x: u128 = a.load_be();
+    let y: u128 = b.load_be();
+    let z = x.wrapping_sub(y);
+    let mut c = BitVec::new();
+    c.resize(len, false);
+    c.store_be(z);
+    c
+}
+
+pub fn sub_le(a: BitVec<u8, Msb0>, b: BitVec<u8, Msb0>) -> BitVec<u8, Msb0> {
+    let len = usize::max(a.len(), b.len());
+
+    let x: u128 = a.load_le();
+    let y: u128 = b.load_le();
+    let z = x.wrapping_sub(y);
+    let mut c = BitVec::new();
+    c.resize(len, false);
+    c.store_le(z);
+    c
+}
+
+pub fn sat_add_le(
+    a: BitVec<u8, Msb0>,
+    b: BitVec<u8, Msb0>,
+) -> BitVec<u8, Msb0> {
+    let len = usize::max(a.len(), b.len());
+
+    let x: u128 = a.load_le();
+    let y: u128 = b.load_le();
+    let z = u128::min(x.saturating_add(y), width_max(len));
+    let mut c = BitVec::new();
+    c.resize(len, false);
+    c.store_le(z);
+    c
+}
+
+pub fn sat_sub_le(
+    a: BitVec<u8, Msb0>,
+    b: BitVec<u8, Msb0>,
+) -> BitVec<u8, Msb0> {
+    let len = usize::max(a.len(), b.len());
+
+    let x: u128 = a.load_le();
+    let y: u128 = b.load_le();
+    let z = x.saturating_sub(y);
     let mut c = BitVec::new();
     c.resize(len, false);
     c.store_le(z);
     c
 }
 
+// The largest unsigned value that fits in `width` bits.
+fn width_max(width: usize) -> u128 {
+    if width >= 128 {
+        u128::MAX
+    } else {
+        (1u128 << width) - 1
+    }
+}
+
+/// Keep the low order `width` bits of `a`. This is what assigning a wider
+/// arithmetic result to a narrower field does.
+pub fn truncate_le(a: BitVec<u8, Msb0>, width: usize) -> BitVec<u8, Msb0> {
+    let x: u128 = a.load_le();
+    let mut c = BitVec::new();
+    c.resize(width, false);
+    c.store_le(x);
+    c
+}
+
 // leaving here in case we have a need for a true arbitrary-width adder.
 #[allow(dead_code)]
 pub fn add_generic(
@@ -177,6 +261,53 @@ mod tests {
         assert_eq!(y, 0xe9 + 14 + 8 + 8);
     }
 
+    #[test]
+    fn bitmath_wrap() {
+        use super::*;
+        let mut a = bitvec![mut u8, Msb0; 0; 8];
+        a.store_le(200u8);
+        let mut b = bitvec![mut u8, Msb0; 0; 8];
+        b.store_le(100u8);
+
+        let c: u8 = add_le(a.clone(), b.clone()).load_le();
+        assert_eq!(c, 200u8.wrapping_add(100));
+
+        let d: u8 = sub_le(b, a).load_le();
+        assert_eq!(d, 100u8.wrapping_sub(200));
+
+        let mut e = bitvec![mut u8, Msb0; 0; 128];
+        e.store_le(u128::MAX);
+        let mut f = bitvec![mut u8, Msb0; 0; 128];
+        f.store_le(2u128);
+        let g: u128 = add_le(e, f).load_le();
+        assert_eq!(g, 1);
+    }
+
+    #[test]
+    fn bitmath_saturate() {
+        use super::*;
+        let mut a = bitvec![mut u8, Msb0; 0; 8];
+        a.store_le(250u8);
+        let mut b = bitvec![mut u8, Msb0; 0; 8];
+        b.store_le(10u8);
+
+        let c: u8 = sat_add_le(a.clone(), b.clone()).load_le();
+        assert_eq!(c, 255);
+
+        let d: u8 = sat_sub_le(b.clone(), a.clone()).load_le();
+        assert_eq!(d, 0);
+
+        let e: u8 = sat_sub_le(a, b).load_le();
+        assert_eq!(e, 240);
+
+        let mut f = bitvec![mut u8, Msb0; 0; 128];
+        f.store_le(u128::MAX - 1);
+        let mut g = bitvec![mut u8, Msb0; 0; 128];
+        g.store_le(2u128);
+        let h: u128 = sat_add_le(f, g).load_le();
+        assert_eq!(h, u128::MAX);
+    }
+
     #[test]
     fn bitmath_mod() {
         use super::*;
@@ -6,6 +6,12 @@ use bitvec::prelude::*;
 pub struct Csum(u16);
 
 impl Csum {
+    /// Resume summing from `checksum`, the result of an earlier sum such as
+    /// the checksum field of a header, so it can be updated incrementally.
+    pub fn from_checksum(checksum: u16) -> Self {
+        Self(!checksum)
+    }
+
     pub fn add(&mut self, a: u8, b: u8) {
         let x = u16::from_be_bytes([a, b]);
         let (mut result, overflow) = self.0.overflowing_add(x);
@@ -34,6 +40,17 @@ impl Csum {
     pub fn result(&self) -> u16 {
         !self.0
     }
+
+    /// Account for a 16 bit word of the summed data changing from `old` to
+    /// `new`, such as a decremented TTL or a rewritten address, without
+    /// summing the data again. This is equation 3 of RFC 1624,
+    /// `HC' = ~(~HC + ~m + m')`.
+    pub fn update_word(&mut self, old: u16, new: u16) {
+        let [a, b] = (!old).to_be_bytes();
+        self.add(a, b);
+        let [a, b] = new.to_be_bytes();
+        self.add(a, b);
+    }
 }
 
 pub fn udp6_checksum(data: &[u8]) -> u16 {
@@ -83,14 +100,26 @@ pub trait Checksum {
 
 fn bvec_csum(bv: &BitVec<u8, Msb0>) -> BitVec<u8, Msb0> {
     let x: u128 = bv.load();
-    let buf = x.to_be_bytes();
-    let mut c: u16 = 0;
-    for i in (0..16).step_by(2) {
-        c += u16::from_be_bytes([buf[i], buf[i + 1]])
-    }
-    let c = !c;
-    let mut result = bitvec![u8, Msb0; 0u8, 16];
-    result.store(c);
+    let mut csum = Csum(0);
+    csum.add128(x.to_be_bytes());
+    let mut result = bitvec![u8, Msb0; 0; 16];
+    result.store(csum.result());
+    result
+}
+
+/// The checksum of data laid out as it is on the wire, such as a whole header,
+/// as a 16 bit field value. An odd trailing byte is padded with zero.
+pub fn wire_csum(data: &[u8]) -> BitVec<u8, Msb0> {
+    let mut csum = Csum(0);
+    let mut words = data.chunks_exact(2);
+    for w in &mut words {
+        csum.add(w[0], w[1]);
+    }
+    if let [last] = words.remainder() {
+        csum.add(*last, 0);
+    }
+    let mut result = bitvec![u8, Msb0; 0; 16];
+    result.store(csum.result());
     result
 }
 
@@ -154,4 +183,27 @@ mod tests {
 
         assert_eq!(x, y);
     }
+
+    #[test]
+    fn incremental_update() {
+        fn sum(words: &[u16]) -> u16 {
+            let mut csum = Csum::default();
+            for w in words {
+                csum.add16(w.to_be_bytes());
+            }
+            csum.result()
+        }
+
+        // an IPv4 header with the checksum field left out
+        let mut words = [
+            0x4500, 0x0073, 0x0000, 0x4000, 0x4011, 0xc0a8, 0x0001, 0xc0a8,
+            0x00c7,
+        ];
+        for (i, new) in [(4, 0x3f11), (5, 0x0a00), (8, 0xffff), (2, 0x1c46)] {
+            let mut csum = Csum::from_checksum(sum(&words));
+            csum.update_word(words[i], new);
+            words[i] = new;
+            assert_eq!(csum.result(), sum(&words), "word {}", i);
+        }
+    }
 }
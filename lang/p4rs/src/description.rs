@@ -0,0 +1,90 @@
+// Copyright 2022 Oxide Computer Company
+
+//! The structure of a P4 program as seen by a controller: its tables, parser
+//! states and header layouts. Descriptions are generated along with the
+//! pipeline and serialize to JSON for use by tooling.
+
+use serde::{Deserialize, Serialize};
+
+use crate::snapshot::TableSchema;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PipelineDescription {
+    pub tables: Vec<TableDescription>,
+    pub parser: ParserDescription,
+    pub headers: Vec<HeaderDescription>,
+}
+
+impl PipelineDescription {
+    pub fn get_table(&self, table_id: &str) -> Option<&TableDescription> {
+        self.tables.iter().find(|t| t.table_id == table_id)
+    }
+
+    pub fn get_header(&self, name: &str) -> Option<&HeaderDescription> {
+        self.headers.iter().find(|h| h.name == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableDescription {
+    pub table_id: String,
+    pub schema: TableSchema,
+}
+
+/// Where a table is declared among the nested controls of a pipeline.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableLocation {
+    /// The controls the table is nested in, outermost first. The last
+    /// control is the one that declares the table.
+    pub controls: Vec<ControlLocation>,
+
+    /// The name of the table within the control that declares it.
+    pub table: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ControlLocation {
+    /// The name of the control instance, as it appears in table ids. The
+    /// outermost control is named after the control itself.
+    pub instance: String,
+
+    /// The name of the control type.
+    pub control: String,
+}
+
+/// The parser as a graph of states.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParserDescription {
+    pub name: String,
+
+    /// Parser states in declaration order. The first state is `start`.
+    pub states: Vec<ParserStateDescription>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParserStateDescription {
+    pub name: String,
+
+    /// The states this state may transition to, including the terminal
+    /// `accept` and `reject` states.
+    pub transitions: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeaderDescription {
+    pub name: String,
+
+    /// Header fields in the order they appear on the wire.
+    pub fields: Vec<HeaderFieldDescription>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeaderFieldDescription {
+    pub name: String,
+
+    /// Offset of the field from the start of the header in bits.
+    pub offset: usize,
+
+    /// Width of the field in bits.
+    pub width: usize,
+}
@@ -0,0 +1,128 @@
+// Copyright 2022 Oxide Computer Company
+
+//! Byte order of field values.
+//!
+//! Every field value is held in memory least significant byte first, so
+//! [`load`] and [`store`] read and write the numeric value of any field.
+//! Fields differ in how they are laid out outside of the pipeline. Header
+//! fields are carried in packets in network byte order and are converted when
+//! a header is extracted from or emitted into a packet. Metadata, such as the
+//! ingress and egress port, is only ever exchanged with the host and is kept
+//! in the in-memory layout as is.
+
+use bitvec::prelude::*;
+
+/// The byte order of a field outside of the pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    /// Big endian, the order of header fields in a packet.
+    Network,
+
+    /// The in-memory layout, used for metadata.
+    Host,
+}
+
+impl Endian {
+    /// Read the field occupying bits `offset..end` of `buf` into its
+    /// in-memory layout.
+    pub fn extract(
+        self,
+        buf: &[u8],
+        offset: usize,
+        end: usize,
+    ) -> BitVec<u8, Msb0> {
+        let b = buf.view_bits::<Msb0>()[offset..end].to_owned();
+        let width = end - offset;
+        if self == Endian::Host || width <= 8 {
+            return b;
+        }
+        let mut v = b.into_vec();
+        v.reverse();
+        // A field that is not a whole number of bytes shares its first byte
+        // with the previous field. After reversing, that byte is last.
+        if width % 8 != 0 {
+            if let Some(x) = v.iter_mut().last() {
+                *x <<= offset % 8;
+            }
+        }
+        let mut b = BitVec::<u8, Msb0>::from_vec(v);
+        b.resize(width, false);
+        b
+    }
+
+    /// Write `value`, in its in-memory layout, into bits `offset..end` of
+    /// `out`.
+    pub fn deposit(
+        self,
+        value: &BitVec<u8, Msb0>,
+        out: &mut BitSlice<u8, Msb0>,
+        offset: usize,
+        end: usize,
+    ) {
+        let width = end - offset;
+        if self == Endian::Host || width <= 8 {
+            out[offset..end] |= value.as_bitslice();
+            return;
+        }
+        let mut v = value.clone().into_vec();
+        // The most significant bits of a field that is not a whole number of
+        // bytes are held at the top of its last byte, move them down so they
+        // end up at the bottom of the first byte after reversing.
+        let m = width % 8;
+        if m != 0 {
+            if let Some(x) = v.iter_mut().last() {
+                *x >>= 8 - m;
+            }
+        }
+        v.reverse();
+        let b = BitVec::<u8, Msb0>::from_vec(v);
+        // the value is empty if the field was never set
+        if let Some(pad) = b.len().checked_sub(width) {
+            out[offset..end] |= &b[pad..];
+        }
+    }
+}
+
+/// The numeric value of a field.
+pub fn load(value: &BitSlice<u8, Msb0>) -> u128 {
+    value.load_le()
+}
+
+/// A field of `width` bits holding `value`.
+pub fn store(width: usize, value: u128) -> BitVec<u8, Msb0> {
+    let mut x = bitvec![u8, Msb0; 0; width];
+    x.store_le(value);
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn network_round_trip() {
+        // a 16 bit field at a byte boundary and a 13 bit field that shares
+        // its first byte with a 3 bit field
+        let buf = [0x12, 0x34, 0b101_00001, 0x02];
+        let port = Endian::Network.extract(&buf, 0, 16);
+        assert_eq!(load(&port), 0x1234);
+        let frag = Endian::Network.extract(&buf, 19, 32);
+        assert_eq!(load(&frag), 0x0102);
+
+        let mut out = bitvec![u8, Msb0; 0; 32];
+        Endian::Network.deposit(&port, &mut out, 0, 16);
+        out[16..19].store_be(0b101u8);
+        Endian::Network.deposit(&frag, &mut out, 19, 32);
+        assert_eq!(out.as_raw_slice(), buf);
+    }
+
+    #[test]
+    fn host_is_in_memory_layout() {
+        let port = store(16, 0x0102);
+        assert_eq!(port.as_raw_slice(), [0x02, 0x01]);
+        assert_eq!(load(&port), 0x0102);
+
+        let data = port.as_raw_slice().to_vec();
+        assert_eq!(Endian::Host.extract(&data, 0, 16), port);
+    }
+}
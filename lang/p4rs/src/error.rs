@@ -2,14 +2,250 @@
 
 use std::error::Error;
 use std::fmt;
+use std::net::IpAddr;
 
 #[derive(Debug)]
-pub struct TryFromSliceError(pub usize);
+pub struct TryFromSliceError {
+    /// Number of bits required.
+    pub expected: usize,
+
+    /// Number of bits available in the slice.
+    pub actual: usize,
+
+    /// Header being extracted, if known.
+    pub header: Option<&'static str>,
+
+    /// Header field being extracted, if known.
+    pub field: Option<&'static str>,
+}
+
+impl TryFromSliceError {
+    pub fn new(expected: usize, actual: usize) -> Self {
+        Self {
+            expected,
+            actual,
+            header: None,
+            field: None,
+        }
+    }
+}
 
 impl fmt::Display for TryFromSliceError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "slice not big enough for {} bits", self.0)
+        match (self.header, self.field) {
+            (Some(h), Some(m)) => write!(f, "{}.{}: ", h, m)?,
+            (Some(h), None) => write!(f, "{}: ", h)?,
+            (None, Some(m)) => write!(f, "{}: ", m)?,
+            (None, None) => {}
+        }
+        write!(
+            f,
+            "slice of {} bits not big enough for {} bits",
+            self.actual, self.expected,
+        )
     }
 }
 
 impl Error for TryFromSliceError {}
+
+/// Every element of a header stack is already valid, so there is no next
+/// element to extract into.
+#[derive(Debug)]
+pub struct StackOutOfBoundsError {
+    /// Number of elements in the stack.
+    pub size: usize,
+}
+
+impl fmt::Display for StackOutOfBoundsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "header stack of size {} is full", self.size)
+    }
+}
+
+impl Error for StackOutOfBoundsError {}
+
+/// The packet does not have enough data left to extract a header from, or to
+/// advance past.
+#[derive(Debug)]
+pub struct PacketTooShortError {
+    /// Number of bits required.
+    pub expected: usize,
+
+    /// Number of bits left in the packet.
+    pub remaining: usize,
+}
+
+impl fmt::Display for PacketTooShortError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "packet has {} bits left, {} bits required",
+            self.remaining, self.expected,
+        )
+    }
+}
+
+impl Error for PacketTooShortError {}
+
+/// Errors extracting into the next element of a header stack.
+#[derive(Debug)]
+pub enum ExtractStackError {
+    StackOutOfBounds(StackOutOfBoundsError),
+    PacketTooShort(PacketTooShortError),
+}
+
+impl fmt::Display for ExtractStackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StackOutOfBounds(e) => e.fmt(f),
+            Self::PacketTooShort(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for ExtractStackError {}
+
+impl From<StackOutOfBoundsError> for ExtractStackError {
+    fn from(e: StackOutOfBoundsError) -> Self {
+        Self::StackOutOfBounds(e)
+    }
+}
+
+impl From<PacketTooShortError> for ExtractStackError {
+    fn from(e: PacketTooShortError) -> Self {
+        Self::PacketTooShort(e)
+    }
+}
+
+/// Errors returned by the table manipulation methods of a
+/// [`Pipeline`](crate::Pipeline).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PipelineError {
+    /// The pipeline has no table with the given id.
+    UnknownTable(String),
+
+    /// The table has no action with the given id that entries may invoke.
+    UnknownAction { table: String, action: String },
+
+    /// The action is annotated `@defaultonly` and cannot be used by entries.
+    DefaultOnlyAction { table: String, action: String },
+
+    /// The keyset data is not the size the keys of the table require.
+    KeyLengthMismatch {
+        table: String,
+        expected: usize,
+        found: usize,
+    },
+
+    /// The parameter data is shorter than the parameters of the action
+    /// require.
+    ParameterLengthMismatch {
+        table: String,
+        action: String,
+        expected: usize,
+        found: usize,
+    },
+
+    /// A key or action parameter of the table was not provided.
+    MissingField { table: String, field: String },
+
+    /// A provided field is not a key or action parameter of the table.
+    UnknownField { table: String, field: String },
+
+    /// An lpm key of the entry is not a valid prefix.
+    InvalidPrefix { table: String, error: PrefixError },
+
+    /// A provided field is not the size the table schema requires.
+    FieldLengthMismatch {
+        table: String,
+        field: String,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownTable(table) => write!(f, "unknown table {}", table),
+            Self::UnknownAction { table, action } => {
+                write!(f, "table {} has no action {}", table, action)
+            }
+            Self::DefaultOnlyAction { table, action } => write!(
+                f,
+                "action {} is default only in table {}",
+                action, table,
+            ),
+            Self::KeyLengthMismatch {
+                table,
+                expected,
+                found,
+            } => write!(
+                f,
+                "table {} keys take {} bytes of keyset data, found {}",
+                table, expected, found,
+            ),
+            Self::ParameterLengthMismatch {
+                table,
+                action,
+                expected,
+                found,
+            } => write!(
+                f,
+                "action {} of table {} takes {} bytes of parameter data, \
+                 found {}",
+                action, table, expected, found,
+            ),
+            Self::MissingField { table, field } => {
+                write!(f, "table {} entry is missing field {}", table, field)
+            }
+            Self::UnknownField { table, field } => {
+                write!(f, "table {} has no field {}", table, field)
+            }
+            Self::InvalidPrefix { table, error } => {
+                write!(f, "table {} entry key: {}", table, error)
+            }
+            Self::FieldLengthMismatch {
+                table,
+                field,
+                expected,
+                found,
+            } => write!(
+                f,
+                "field {} of table {} takes {} bytes, found {}",
+                field, table, expected, found,
+            ),
+        }
+    }
+}
+
+impl Error for PipelineError {}
+
+/// Errors building or parsing a [`Prefix`](crate::table::Prefix).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrefixError {
+    /// The text is not an address and length of the form `addr/len`.
+    Syntax(String),
+
+    /// The length is longer than the address.
+    Length { len: u8, max: u8 },
+
+    /// The address has bits set beyond the length of the prefix.
+    HostBits { addr: IpAddr, len: u8 },
+}
+
+impl fmt::Display for PrefixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Syntax(s) => write!(f, "invalid prefix {}", s),
+            Self::Length { len, max } => {
+                write!(f, "prefix length {} is longer than {}", len, max)
+            }
+            Self::HostBits { addr, len } => {
+                write!(f, "{} has bits set beyond /{}", addr, len)
+            }
+        }
+    }
+}
+
+impl Error for PrefixError {}
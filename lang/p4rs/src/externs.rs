@@ -1,6 +1,9 @@
 // Copyright 2022 Oxide Computer Company
 
 use bitvec::prelude::*;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 pub struct Checksum {}
 
@@ -9,17 +12,19 @@ impl Checksum {
         Self {}
     }
 
+    /// The internet checksum of `elements`, the ones' complement of the ones'
+    /// complement sum of each element's 16 bit words.
     pub fn run(
         &self,
         elements: &[&dyn crate::checksum::Checksum],
     ) -> BitVec<u8, Msb0> {
-        let mut csum: u16 = 0;
+        let mut csum = crate::checksum::Csum::default();
         for e in elements {
             let c: u16 = e.csum().load();
-            csum += c;
+            csum.add16((!c).to_be_bytes());
         }
-        let mut result = bitvec![u8, Msb0; 0u8, 16];
-        result.store(csum);
+        let mut result = bitvec![u8, Msb0; 0; 16];
+        result.store(csum.result());
         result
     }
 }
@@ -29,3 +34,328 @@ impl Default for Checksum {
         Self::new()
     }
 }
+
+/// Check a condition a P4 program states with `assert`. Failed assertions
+/// panic when debug assertions are enabled and are compiled out otherwise.
+#[inline]
+pub fn assert(condition: bool, location: &str) {
+    debug_assert!(condition, "P4 assertion failed at {}", location);
+}
+
+/// Check a condition a P4 program states with `assume`. Behaves the same as
+/// [`assert`].
+#[inline]
+pub fn assume(condition: bool, location: &str) {
+    debug_assert!(condition, "P4 assumption failed at {}", location);
+}
+
+/// A host supplied implementation of an extern declared by a P4 program, such
+/// as a hardware timestamp source. Implementations are shared by every copy of
+/// the pipeline's controls and move with the pipeline across threads.
+pub trait ExternMethod: Send + Sync {
+    /// Invoke `method` with the given arguments. Methods that do not return a
+    /// value return an empty bitvec.
+    fn call(
+        &self,
+        method: &str,
+        args: &[&BitVec<u8, Msb0>],
+    ) -> BitVec<u8, Msb0>;
+
+    /// Invoke `method` that returns a tuple, with one bitvec per element of
+    /// the tuple. Boolean elements are true if any bit is set. By default the
+    /// result of `call` is the first element and the rest are zero.
+    fn call_tuple(
+        &self,
+        method: &str,
+        args: &[&BitVec<u8, Msb0>],
+    ) -> Vec<BitVec<u8, Msb0>> {
+        vec![self.call(method, args)]
+    }
+
+    /// Invoke `method` that has `out` or `inout` parameters. There is one
+    /// argument per parameter, and implementations write the values of `out`
+    /// and `inout` parameters through them. `in` arguments are copies, writes
+    /// to them are discarded. By default `call` is invoked and no argument is
+    /// written.
+    fn call_mut(
+        &self,
+        method: &str,
+        args: &mut [&mut BitVec<u8, Msb0>],
+    ) -> BitVec<u8, Msb0> {
+        let args: Vec<&BitVec<u8, Msb0>> = args.iter().map(|a| &**a).collect();
+        self.call(method, &args)
+    }
+}
+
+/// Extern implementations registered with a pipeline, keyed by the name of
+/// the extern type declared in the P4 program, along with the state of the
+/// stateful externs the pipeline provides natively.
+pub struct ExternRegistry {
+    externs: HashMap<String, Arc<dyn ExternMethod>>,
+    meters: HashMap<String, Meter>,
+    unconfigured_meter: Meter,
+    clock: Box<dyn TimeSource>,
+}
+
+impl Default for ExternRegistry {
+    fn default() -> Self {
+        Self {
+            externs: HashMap::new(),
+            meters: HashMap::new(),
+            unconfigured_meter: Meter::default(),
+            clock: Box::<SystemClock>::default(),
+        }
+    }
+}
+
+impl ExternRegistry {
+    pub fn register(&mut self, name: &str, ext: Box<dyn ExternMethod>) {
+        self.externs.insert(name.to_owned(), Arc::from(ext));
+    }
+
+    /// Configure the token buckets at `index` of the meter instance `name`.
+    pub fn configure_meter(
+        &mut self,
+        name: &str,
+        index: u32,
+        config: MeterConfig,
+    ) {
+        let now = self.clock.now();
+        self.meters
+            .entry(name.to_owned())
+            .or_default()
+            .configure(index, config, now);
+    }
+
+    /// Replace the time source meters use to refill their token buckets.
+    pub fn set_time_source(&mut self, clock: Box<dyn TimeSource>) {
+        self.clock = clock;
+    }
+
+    /// Get a handle for the meter instance `name`. Meters that have not been
+    /// configured leave the color of every packet unchanged.
+    pub fn meter(&self, name: &str) -> MeterInstance<'_> {
+        MeterInstance {
+            meter: self.meters.get(name).unwrap_or(&self.unconfigured_meter),
+            clock: self.clock.as_ref(),
+        }
+    }
+
+    /// The extern types in `names` that have no registered implementation.
+    pub fn missing(&self, names: &[&str]) -> Vec<String> {
+        names
+            .iter()
+            .filter(|name| !self.externs.contains_key(**name))
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    /// Get a handle for an instance of the extern type `name`. The handle is
+    /// returned even if no implementation is registered, see [`UserExtern`].
+    pub fn instance(&self, name: &str) -> UserExtern {
+        UserExtern {
+            ext: self.externs.get(name).cloned(),
+        }
+    }
+}
+
+/// An instance of an extern that is implemented by the host rather than by
+/// this crate. Calls through an instance without an implementation do nothing
+/// and return empty values. Pipelines check for missing implementations with
+/// [`ExternRegistry::missing`] and drop packets rather than run them through
+/// such calls.
+pub struct UserExtern {
+    ext: Option<Arc<dyn ExternMethod>>,
+}
+
+impl UserExtern {
+    pub fn call(
+        &self,
+        method: &str,
+        args: &[&BitVec<u8, Msb0>],
+    ) -> BitVec<u8, Msb0> {
+        match &self.ext {
+            Some(ext) => ext.call(method, args),
+            None => BitVec::new(),
+        }
+    }
+
+    pub fn call_tuple(
+        &self,
+        method: &str,
+        args: &[&BitVec<u8, Msb0>],
+    ) -> Vec<BitVec<u8, Msb0>> {
+        match &self.ext {
+            Some(ext) => ext.call_tuple(method, args),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn call_mut(
+        &self,
+        method: &str,
+        args: &mut [&mut BitVec<u8, Msb0>],
+    ) -> BitVec<u8, Msb0> {
+        match &self.ext {
+            Some(ext) => ext.call_mut(method, args),
+            None => BitVec::new(),
+        }
+    }
+}
+
+/// A source of monotonic time for stateful externs. The pipeline uses the
+/// system clock by default, tests may substitute their own.
+pub trait TimeSource: Send + Sync {
+    /// Time elapsed since an arbitrary fixed epoch.
+    fn now(&self) -> Duration;
+}
+
+/// A time source backed by the monotonic system clock.
+pub struct SystemClock {
+    epoch: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self {
+            epoch: Instant::now(),
+        }
+    }
+}
+
+impl TimeSource for SystemClock {
+    fn now(&self) -> Duration {
+        self.epoch.elapsed()
+    }
+}
+
+/// Packet colors produced by a meter. In a P4 program colors are represented
+/// as a `bit<2>` with the values given here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeterColor {
+    Green = 0,
+    Yellow = 1,
+    Red = 2,
+}
+
+impl MeterColor {
+    fn from_bitvec(x: &BitVec<u8, Msb0>) -> Self {
+        match x.load_le::<u8>() {
+            0 => MeterColor::Green,
+            1 => MeterColor::Yellow,
+            _ => MeterColor::Red,
+        }
+    }
+
+    fn to_bitvec(self) -> BitVec<u8, Msb0> {
+        let mut x = bitvec![u8, Msb0; 0; 2];
+        x.store_le(self as u8);
+        x
+    }
+}
+
+/// Token bucket parameters for a single rate three color marker as described
+/// in RFC 2697. Rates and bursts are in packets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeterConfig {
+    /// Committed information rate in packets per second.
+    pub rate: u64,
+    /// Committed burst size. Packets within this burst are marked green.
+    pub burst: u64,
+    /// Excess burst size. Packets in excess of the committed burst but within
+    /// this burst are marked yellow.
+    pub excess_burst: u64,
+}
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// Token buckets for one meter index. Token counts are kept in units of
+/// 1/NANOS_PER_SEC packets so refills at any rate are exact.
+struct TokenBucket {
+    config: MeterConfig,
+    committed: u128,
+    excess: u128,
+    last: Duration,
+}
+
+impl TokenBucket {
+    fn new(config: MeterConfig, now: Duration) -> Self {
+        Self {
+            config,
+            committed: config.burst as u128 * NANOS_PER_SEC,
+            excess: config.excess_burst as u128 * NANOS_PER_SEC,
+            last: now,
+        }
+    }
+
+    /// Add the tokens accrued since the last update, filling the committed
+    /// bucket first and letting the overflow spill into the excess bucket.
+    fn refill(&mut self, now: Duration) {
+        let elapsed = now.saturating_sub(self.last).as_nanos();
+        self.last = now;
+
+        let cbs = self.config.burst as u128 * NANOS_PER_SEC;
+        let ebs = self.config.excess_burst as u128 * NANOS_PER_SEC;
+        let mut tokens = elapsed * self.config.rate as u128;
+
+        let c = tokens.min(cbs - self.committed);
+        self.committed += c;
+        tokens -= c;
+        self.excess = (self.excess + tokens).min(ebs);
+    }
+
+    fn execute(&mut self, color: MeterColor, now: Duration) -> MeterColor {
+        self.refill(now);
+        if color == MeterColor::Green && self.committed >= NANOS_PER_SEC {
+            self.committed -= NANOS_PER_SEC;
+            return MeterColor::Green;
+        }
+        if color != MeterColor::Red && self.excess >= NANOS_PER_SEC {
+            self.excess -= NANOS_PER_SEC;
+            return MeterColor::Yellow;
+        }
+        MeterColor::Red
+    }
+}
+
+/// An indexed array of color aware token bucket meters.
+#[derive(Default)]
+pub struct Meter {
+    buckets: Mutex<HashMap<u32, TokenBucket>>,
+}
+
+impl Meter {
+    fn configure(&mut self, index: u32, config: MeterConfig, now: Duration) {
+        self.buckets
+            .get_mut()
+            .unwrap()
+            .insert(index, TokenBucket::new(config, now));
+    }
+}
+
+/// A meter as seen by the pipeline while processing a packet.
+pub struct MeterInstance<'a> {
+    meter: &'a Meter,
+    clock: &'a dyn TimeSource,
+}
+
+impl MeterInstance<'_> {
+    /// Meter a packet at `index` whose color has already been determined to
+    /// be `color`, returning the resulting color. A packet is never promoted
+    /// to a better color than the one it arrives with. Packets at indices that
+    /// have not been configured keep their color.
+    pub fn execute(
+        &self,
+        index: &BitVec<u8, Msb0>,
+        color: &BitVec<u8, Msb0>,
+    ) -> BitVec<u8, Msb0> {
+        let color = MeterColor::from_bitvec(color);
+        let index = index.load_le::<u32>();
+        let mut buckets = self.meter.buckets.lock().unwrap();
+        let result = match buckets.get_mut(&index) {
+            Some(bucket) => bucket.execute(color, self.clock.now()),
+            None => color,
+        };
+        result.to_bitvec()
+    }
+}
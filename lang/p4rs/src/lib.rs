@@ -40,14 +40,18 @@
 //!
 //!     /// Add a routing table entry. Packets for the provided destination will
 //!     /// be sent out the specified port.
-//!     fn add_router_entry(&mut self, dest: Ipv6Addr, port: u16) {
+//!     fn add_router_entry(
+//!         &mut self,
+//!         dest: Ipv6Addr,
+//!         port: u16,
+//!     ) -> Result<(), p4rs::error::PipelineError> {
 //!         self.pipe.add_table_entry(
 //!             "ingress.router.ipv6_routes", // qualified name of the table
 //!             "forward_out_port",           // action to invoke on a hit
 //!             &dest.octets(),
 //!             &port.to_le_bytes(),
 //!             0,
-//!         );
+//!         )
 //!     }
 //!
 //!     /// Send a packet out the specified port.
@@ -60,7 +64,7 @@
 #![allow(incomplete_features)]
 #![allow(non_camel_case_types)]
 
-use std::fmt;
+use std::fmt::{self, Write};
 use std::net::IpAddr;
 
 pub use error::TryFromSliceError;
@@ -71,9 +75,14 @@ use bitvec::prelude::*;
 pub mod error;
 //pub mod hicuts;
 //pub mod rice;
+pub mod arena;
 pub mod bitmath;
 pub mod checksum;
+pub mod description;
+pub mod endian;
 pub mod externs;
+pub mod mgmt;
+pub mod snapshot;
 pub mod table;
 
 #[usdt::provider]
@@ -91,10 +100,25 @@ impl<'a, const N: usize> Bit<'a, N> {
     pub fn new(data: &'a [u8]) -> Result<Self, TryFromSliceError> {
         let required_bytes = if N & 7 > 0 { (N >> 3) + 1 } else { N >> 3 };
         if data.len() < required_bytes {
-            return Err(TryFromSliceError(N));
+            return Err(TryFromSliceError::new(N, data.len() << 3));
         }
         Ok(Self(&data[..required_bytes]))
     }
+
+    /// The bytes of this value with the bits past `N` in the last byte
+    /// cleared. A `bit<N>` value occupies the leading `N` bits of its data, so
+    /// fields narrower than a byte compare and hash on those bits only.
+    fn masked_bytes(&self) -> impl Iterator<Item = u8> + 'a {
+        let n = self.0.len();
+        let mask = match N & 7 {
+            0 => 0xff,
+            r => 0xffu8 << (8 - r),
+        };
+        self.0
+            .iter()
+            .enumerate()
+            .map(move |(i, b)| if i + 1 == n { b & mask } else { *b })
+    }
 }
 
 impl<'a, const N: usize> fmt::LowerHex for Bit<'a, N> {
@@ -113,20 +137,42 @@ impl<'a> From<Bit<'a, 16>> for u16 {
     }
 }
 
-// TODO more of these for other sizes
-impl<'a> std::hash::Hash for Bit<'a, 8> {
+/// Interpret the leading `N` bits as a big-endian number, `N` may be at most
+/// 128.
+impl<'a, const N: usize> From<Bit<'a, N>> for u128 {
+    fn from(b: Bit<'a, N>) -> u128 {
+        assert!(N <= 128, "bit<{}> does not fit in a u128", N);
+        let x = b.masked_bytes().fold(0u128, |acc, x| {
+            acc.checked_shl(8).unwrap_or(0) | u128::from(x)
+        });
+        match N & 7 {
+            0 => x,
+            r => x >> (8 - r),
+        }
+    }
+}
+
+impl<'a> From<Bit<'a, 1>> for bool {
+    fn from(b: Bit<'a, 1>) -> bool {
+        b.0[0] & 0x80 != 0
+    }
+}
+
+impl<'a, const N: usize> std::hash::Hash for Bit<'a, N> {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.0[0].hash(state);
+        for x in self.masked_bytes() {
+            x.hash(state);
+        }
     }
 }
 
-impl<'a> std::cmp::PartialEq for Bit<'a, 8> {
+impl<'a, const N: usize> std::cmp::PartialEq for Bit<'a, N> {
     fn eq(&self, other: &Self) -> bool {
-        self.0[0] == other.0[0]
+        self.masked_bytes().eq(other.masked_bytes())
     }
 }
 
-impl<'a> std::cmp::Eq for Bit<'a, 8> {}
+impl<'a, const N: usize> std::cmp::Eq for Bit<'a, N> {}
 
 /// Every packet that goes through a P4 pipeline is represented as a `packet_in`
 /// instance. `packet_in` objects wrap an underlying mutable data reference that
@@ -140,33 +186,330 @@ pub struct packet_in<'a> {
     /// Only data after `index` is eligble for extraction. Extraction is always
     /// for contiguous segments of the underlying packet ring data.
     pub index: usize,
+
+    /// Number of bytes at the front of `data` that precede the packet, such as
+    /// headroom in a ring buffer slot. Extraction starts after them.
+    pub headroom: usize,
+
+    /// Number of times the parser has entered a state that is part of a loop,
+    /// such as a state walking a chain of IPv6 extension headers. See
+    /// [`PARSER_LOOP_LIMIT`].
+    pub loops: usize,
 }
 
+/// The number of times the parser may enter states that are part of a loop
+/// before the packet is rejected. This bounds the work done for packets that
+/// carry long chains of headers a parser loops over.
+pub const PARSER_LOOP_LIMIT: usize = 16;
+
 #[derive(Debug)]
 pub struct packet_out<'a> {
     pub header_data: Vec<u8>,
     pub payload_data: &'a [u8],
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// The output packets of a pipeline along with the ports they are sent out
+/// of. Up to two outputs, such as a unicast packet and its mirror, are held
+/// inline. Only when there are more outputs, as for broadcast packets, are
+/// they moved to the heap.
+#[derive(Debug, Default)]
+pub struct PacketOutputs<'a> {
+    inline: [Option<(packet_out<'a>, u16)>; 2],
+    spilled: Vec<(packet_out<'a>, u16)>,
+}
+
+impl<'a> PacketOutputs<'a> {
+    pub fn push(&mut self, output: (packet_out<'a>, u16)) {
+        if self.spilled.is_empty() {
+            if let Some(slot) = self.inline.iter_mut().find(|x| x.is_none()) {
+                *slot = Some(output);
+                return;
+            }
+            self.spilled =
+                self.inline.iter_mut().filter_map(Option::take).collect();
+        }
+        self.spilled.push(output);
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the outputs have been moved to the heap.
+    pub fn spilled(&self) -> bool {
+        !self.spilled.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(packet_out<'a>, u16)> {
+        self.inline.iter().flatten().chain(self.spilled.iter())
+    }
+}
+
+impl<'a> Extend<(packet_out<'a>, u16)> for PacketOutputs<'a> {
+    fn extend<I: IntoIterator<Item = (packet_out<'a>, u16)>>(
+        &mut self,
+        iter: I,
+    ) {
+        for output in iter {
+            self.push(output);
+        }
+    }
+}
+
+impl<'a> IntoIterator for PacketOutputs<'a> {
+    type Item = (packet_out<'a>, u16);
+    type IntoIter = std::iter::Chain<
+        std::iter::Flatten<std::array::IntoIter<Option<Self::Item>, 2>>,
+        std::vec::IntoIter<Self::Item>,
+    >;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.inline.into_iter().flatten().chain(self.spilled)
+    }
+}
+
+/// Counts of the packets a pipeline dropped, by the reason they were dropped.
+/// Each copy of a packet sent to several ports is counted on its own.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub struct DropStats {
+    /// Packets rejected by the parser.
+    pub parser: u64,
+
+    /// Packets the ingress block dropped by setting `drop`.
+    pub ingress: u64,
+
+    /// Copies of packets the egress block dropped by setting `drop`.
+    pub egress: u64,
+
+    /// Packets the ingress block sent to no port, or to a port at or beyond
+    /// the radix.
+    pub no_port: u64,
+
+    /// Packets that arrived while an extern the program uses had no
+    /// registered implementation.
+    #[serde(default)]
+    pub unregistered_extern: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TableEntry {
     pub action_id: String,
     pub keyset_data: Vec<u8>,
     pub parameter_data: Vec<u8>,
+
+    /// The priority the entry was added with.
+    #[serde(default)]
+    pub priority: u32,
+
+    /// Whether the entry is one of the const entries of the program. Const
+    /// entries are installed when the pipeline is created and are put back by
+    /// `clear_table`, controllers should leave them alone.
+    #[serde(default)]
+    pub immutable: bool,
+
+    /// Layout of `keyset_data`, one element per key field in table key order.
+    #[serde(default)]
+    pub keyset_layout: Vec<FieldLayout>,
+
+    /// Layout of `parameter_data`, one element per action parameter in
+    /// declaration order.
+    #[serde(default)]
+    pub parameter_layout: Vec<FieldLayout>,
+}
+
+/// Describes a single field within the opaque data of a [`TableEntry`], so
+/// serialized entries can be interpreted without the program they came from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldLayout {
+    /// The key expression for key fields, or the parameter name for action
+    /// parameters.
+    pub name: String,
+
+    /// The match kind of key fields, one of `exact`, `ternary`, `lpm` or
+    /// `range`. Action parameters have no match kind.
+    pub match_kind: Option<String>,
+
+    /// Width of the field in bytes.
+    pub width: usize,
+}
+
+impl FieldLayout {
+    /// The number of bytes the field occupies in keyset or parameter data.
+    /// Ternary keys are preceded by a care byte, lpm keys are followed by a
+    /// prefix length byte and range keys hold both of their bounds.
+    pub fn data_len(&self) -> usize {
+        match self.match_kind.as_deref() {
+            Some("ternary") | Some("lpm") => self.width + 1,
+            Some("range") => self.width * 2,
+            _ => self.width,
+        }
+    }
+}
+
+/// A modification of the entries of a table, as reported to the callbacks
+/// registered with [`Pipeline::on_table_change`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TableChange {
+    /// An entry was added with the given key and action.
+    Add {
+        action_id: String,
+        keyset_data: Vec<u8>,
+        parameter_data: Vec<u8>,
+        priority: u32,
+    },
+
+    /// The entry with the given key was removed.
+    Remove { keyset_data: Vec<u8> },
+
+    /// Every entry added to the table was removed.
+    Clear,
+}
+
+/// A callback registered with [`Pipeline::on_table_change`], called with the
+/// table id and the change. Pipelines are `Send`, so callbacks must be too.
+pub type TableChangeCallback = Box<dyn FnMut(&str, TableChange) + Send>;
+
+impl fmt::Display for TableEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut keys = Vec::new();
+        if self.keyset_layout.is_empty() {
+            keys.push(hex_le(&self.keyset_data));
+        } else {
+            let mut offset = 0;
+            for field in &self.keyset_layout {
+                let end =
+                    usize::min(offset + field.width, self.keyset_data.len());
+                let data = &self.keyset_data[offset..end];
+                keys.push(format!(
+                    "{}={}",
+                    field.name,
+                    fmt_key(field.match_kind.as_deref(), data),
+                ));
+                offset = end;
+            }
+        }
+
+        let mut params = Vec::new();
+        if self.parameter_layout.is_empty() {
+            if !self.parameter_data.is_empty() {
+                params.push(hex_le(&self.parameter_data));
+            }
+        } else {
+            let mut offset = 0;
+            for field in &self.parameter_layout {
+                let end =
+                    usize::min(offset + field.width, self.parameter_data.len());
+                let data = &self.parameter_data[offset..end];
+                params.push(format!("{}={}", field.name, hex_le(data)));
+                offset = end;
+            }
+        }
+
+        write!(
+            f,
+            "{} => {}({})",
+            keys.join(", "),
+            self.action_id,
+            params.join(", ")
+        )
+    }
+}
+
+// Format little-endian bytes as a hex number.
+fn hex_le(data: &[u8]) -> String {
+    let mut s = String::from("0x");
+    for b in data.iter().rev() {
+        s += &format!("{:02x}", b);
+    }
+    s
+}
+
+// Format serialized key data according to its match kind. See
+// `table::Key::to_bytes` for the serialized forms.
+fn fmt_key(match_kind: Option<&str>, data: &[u8]) -> String {
+    match (match_kind, data.len()) {
+        (Some("lpm"), 5) => {
+            let addr: [u8; 4] = data[..4].try_into().unwrap();
+            format!("{}/{}", IpAddr::from(addr), data[4])
+        }
+        (Some("lpm"), 17) => {
+            let addr: [u8; 16] = data[..16].try_into().unwrap();
+            format!("{}/{}", IpAddr::from(addr), data[16])
+        }
+        (Some("range"), n) => {
+            let (lo, hi) = data.split_at(n / 2);
+            format!("{}..{}", hex_le(lo), hex_le(hi))
+        }
+        (Some("ternary"), 0) => "_".to_owned(),
+        _ => hex_le(data),
+    }
 }
 
 pub trait Pipeline: Send {
     /// Process an input packet and produce a set of output packets. Normally
     /// there will be a single output packet. However, if the pipeline sets
     /// `egress_metadata_t.broadcast` there may be multiple output packets.
+    /// Port numbers are carried in the `port` member of the ingress and
+    /// egress metadata structs, whose width the program decides up to 16
+    /// bits.
     fn process_packet<'a>(
         &mut self,
         port: u16,
         pkt: &mut packet_in<'a>,
     ) -> Vec<(packet_out<'a>, u16)>;
 
+    /// Process a batch of input packets, each paired with the port it arrived
+    /// on. This is equivalent to calling `process_packet` on each packet in
+    /// order and concatenating the results, but amortizes per-call setup
+    /// across the batch.
+    fn process_packets<'a>(
+        &mut self,
+        batch: &mut [(u16, packet_in<'a>)],
+    ) -> Vec<(packet_out<'a>, u16)>;
+
+    /// Process an input packet like `process_packet`, but without allocating
+    /// a container for the outputs unless there are more than two of them.
+    fn process_packet_outputs<'a>(
+        &mut self,
+        port: u16,
+        pkt: &mut packet_in<'a>,
+    ) -> PacketOutputs<'a>;
+
+    /// Keep the header buffers of output packets handed back through
+    /// `recycle` in a scratch arena, and draw the header buffers of later
+    /// output packets from it. Disabling the arena frees the buffers it
+    /// holds.
+    fn set_arena(&mut self, enabled: bool);
+
+    /// Hand an output packet back to the pipeline once it has been sent. Its
+    /// header buffer is reused for later packets if the arena is enabled.
+    fn recycle(&mut self, out: packet_out<'_>);
+
+    /// The number of ports the pipeline was created with. Ports are numbered
+    /// from zero.
+    fn radix(&self) -> u16;
+
+    /// The number of packets dropped since the pipeline was created, by the
+    /// reason they were dropped.
+    fn drop_stats(&self) -> DropStats;
+
+    /// A counter that advances each time a table is modified, by adding,
+    /// removing or clearing entries. A controller that caches table state can
+    /// compare it with the epoch its cache was taken at, such as that of a
+    /// [`snapshot::PipelineSnapshot`], to tell whether the cache is current.
+    fn epoch(&self) -> u64;
+
     //TODO use struct TableEntry?
-    /// Add an entry to a table identified by table_id.
+    /// Add an entry to a table identified by table_id. Fails if the table
+    /// does not exist, entries in it may not invoke action_id, the keyset
+    /// data is not the size its keys require, or the parameter data is too
+    /// short for the parameters of the action.
     fn add_table_entry(
         &mut self,
         table_id: &str,
@@ -174,16 +517,196 @@ pub trait Pipeline: Send {
         keyset_data: &[u8],
         parameter_data: &[u8],
         priority: u32,
-    );
+    ) -> Result<(), error::PipelineError>;
+
+    /// Check that an entry could be added to the table identified by
+    /// table_id, without adding it. Fails with the error `add_table_entry`
+    /// would for the same entry, so a batch of entries can be checked before
+    /// any of them are added.
+    fn validate_table_entry(
+        &self,
+        table_id: &str,
+        action_id: &str,
+        keyset_data: &[u8],
+        parameter_data: &[u8],
+    ) -> Result<(), error::PipelineError>;
+
+    /// Add an entry to a table identified by table_id, with keys and action
+    /// parameters given by name rather than by position. Each field carries
+    /// the bytes it would occupy in the keyset or parameter data passed to
+    /// `add_table_entry`, and fields may be given in any order. The fields
+    /// are laid out in the order of the table schema before the entry is
+    /// added.
+    fn add_table_entry_by_fields(
+        &mut self,
+        table_id: &str,
+        action_id: &str,
+        keys: &[(&str, &[u8])],
+        params: &[(&str, &[u8])],
+        priority: u32,
+    ) -> Result<(), error::PipelineError> {
+        let schema = self.get_table_schema(table_id).ok_or_else(|| {
+            error::PipelineError::UnknownTable(table_id.into())
+        })?;
+        let action = schema.get_action(action_id).ok_or_else(|| {
+            error::PipelineError::UnknownAction {
+                table: table_id.into(),
+                action: action_id.into(),
+            }
+        })?;
+        let keyset_data = layout_fields(table_id, &schema.keys, keys)?;
+        let parameter_data =
+            layout_fields(table_id, &action.parameters, params)?;
+        self.add_table_entry(
+            table_id,
+            action_id,
+            &keyset_data,
+            &parameter_data,
+            priority,
+        )
+    }
+
+    /// Remove an entry from a table identified by table_id. Fails if the
+    /// table does not exist.
+    fn remove_table_entry(
+        &mut self,
+        table_id: &str,
+        keyset_data: &[u8],
+    ) -> Result<(), error::PipelineError>;
+
+    /// Remove every entry that was added to the table identified by
+    /// table_id, leaving only the const entries of the program. Fails if the
+    /// table does not exist.
+    fn clear_table(
+        &mut self,
+        table_id: &str,
+    ) -> Result<(), error::PipelineError>;
+
+    /// Remove every entry of the table identified by table_id for which
+    /// `predicate` returns true, such as all routes through a failed next
+    /// hop, in one step. Returns the number of entries removed, zero if the
+    /// table does not exist. Each removal is reported to table change
+    /// callbacks as a `TableChange::Remove`.
+    fn remove_table_entries_matching(
+        &mut self,
+        table_id: &str,
+        predicate: &dyn Fn(&TableEntry) -> bool,
+    ) -> usize;
 
-    /// Remove an entry from a table identified by table_id.
-    fn remove_table_entry(&mut self, table_id: &str, keyset_data: &[u8]);
+    /// Register a callback that is called with the table id and the change
+    /// each time `add_table_entry`, `remove_table_entry`,
+    /// `remove_table_entries_matching` or `clear_table` modifies a table.
+    /// Callbacks are called in the order they were registered.
+    fn on_table_change(&mut self, cb: TableChangeCallback);
 
     /// Get all the entries in a table.
     fn get_table_entries(&self, table_id: &str) -> Option<Vec<TableEntry>>;
 
-    /// Get a list of table ids
+    /// Get a list of table ids. Tables annotated `@hidden` are left out.
     fn get_table_ids(&self) -> Vec<&str>;
+
+    /// Get the names of the actions an entry in the table identified by
+    /// table_id may invoke.
+    fn get_table_actions(&self, table_id: &str) -> Option<Vec<&str>>;
+
+    /// Get the key fields and actions of the table identified by table_id.
+    fn get_table_schema(&self, table_id: &str)
+        -> Option<snapshot::TableSchema>;
+
+    /// Describe the tables, parser states and headers of the program.
+    fn describe(&self) -> description::PipelineDescription;
+
+    /// Get the chain of controls the table identified by table_id is
+    /// declared in, along with its name within the innermost one.
+    fn table_location(
+        &self,
+        table_id: &str,
+    ) -> Option<description::TableLocation>;
+
+    /// Capture the schema and entries of every table.
+    fn snapshot(&self) -> snapshot::PipelineSnapshot {
+        snapshot::PipelineSnapshot::capture(self)
+    }
+
+    /// Restore the entries of a snapshot, typically taken from a pipeline
+    /// running an earlier version of this program. Entries are restored into
+    /// tables whose keys are unchanged and whose action still exists with the
+    /// same parameters. Const entries are skipped, the program installs its
+    /// own. The report lists what was dropped for each table.
+    fn migrate_from(
+        &mut self,
+        snapshot: &snapshot::PipelineSnapshot,
+    ) -> snapshot::MigrationReport {
+        snapshot::migrate(self, snapshot)
+    }
+
+    /// Register the implementation of an extern type declared by the P4
+    /// program. Until every extern type the program uses has an
+    /// implementation, packets are dropped and counted in
+    /// [`DropStats::unregistered_extern`].
+    fn register_extern(
+        &mut self,
+        name: &str,
+        ext: Box<dyn externs::ExternMethod>,
+    );
+
+    /// The extern types the program uses that have no registered
+    /// implementation. A harness can check this is empty once it has
+    /// registered its externs, before sending packets.
+    fn missing_externs(&self) -> Vec<String>;
+
+    /// Set the rate and burst sizes of the meter instance identified by
+    /// meter_id at the given index. Meter ids are qualified by the control
+    /// that declares them, e.g. `ingress.rate_limit`. Meter indices that have
+    /// not been configured do not change the color of packets.
+    fn configure_meter(
+        &mut self,
+        meter_id: &str,
+        index: u32,
+        config: externs::MeterConfig,
+    );
+
+    /// Set the time source used by stateful externs such as meters. The
+    /// system clock is used by default.
+    fn set_time_source(&mut self, clock: Box<dyn externs::TimeSource>);
+}
+
+// Concatenate the data of named fields in the order of `layout`.
+fn layout_fields(
+    table_id: &str,
+    layout: &[FieldLayout],
+    fields: &[(&str, &[u8])],
+) -> Result<Vec<u8>, error::PipelineError> {
+    if let Some((name, _)) = fields
+        .iter()
+        .find(|(name, _)| !layout.iter().any(|l| l.name == *name))
+    {
+        return Err(error::PipelineError::UnknownField {
+            table: table_id.into(),
+            field: (*name).into(),
+        });
+    }
+    let mut data = Vec::new();
+    for l in layout {
+        match fields.iter().find(|(name, _)| *name == l.name) {
+            Some((_, value)) if value.len() != l.data_len() => {
+                return Err(error::PipelineError::FieldLengthMismatch {
+                    table: table_id.into(),
+                    field: l.name.clone(),
+                    expected: l.data_len(),
+                    found: value.len(),
+                })
+            }
+            Some((_, value)) => data.extend_from_slice(value),
+            None => {
+                return Err(error::PipelineError::MissingField {
+                    table: table_id.into(),
+                    field: l.name.clone(),
+                })
+            }
+        }
+    }
+    Ok(data)
 }
 
 /// A fixed length header trait.
@@ -195,11 +718,77 @@ pub trait Header {
     fn set_invalid(&mut self);
     fn is_valid(&self) -> bool;
     fn to_bitvec(&self) -> BitVec<u8, Msb0>;
+
+    /// The number of bits the header takes up in a packet as it is now. An
+    /// invalid header takes up none. A valid header takes up the width of its
+    /// fields, which is `size()` for headers without variable length fields.
+    fn width_bits(&self) -> usize {
+        if self.is_valid() {
+            Self::size()
+        } else {
+            0
+        }
+    }
+}
+
+/// Check that `h` is valid before one of its fields is read, as code generated
+/// with checked header access does. Reading a field of an invalid header is
+/// undefined in P4 and usually a bug. Debug builds panic on such reads, naming
+/// the `field` being read.
+pub fn valid_header<'h, H: Header>(h: &'h H, field: &str) -> &'h H {
+    debug_assert!(h.is_valid(), "read of {} from an invalid header", field);
+    h
+}
+
+/// Shift the elements of a header stack `count` positions towards the end of
+/// the stack, as the P4 `push_front` method does. Elements shifted past the end
+/// of the stack are discarded and the first `count` elements become invalid.
+pub fn header_stack_push_front<H: Header>(stack: &mut [H], count: usize) {
+    let count = count.min(stack.len());
+    stack.rotate_right(count);
+    for h in &mut stack[..count] {
+        h.set_invalid();
+    }
+}
+
+/// Shift the elements of a header stack `count` positions towards the front
+/// of the stack, as the P4 `pop_front` method does. The first `count` elements
+/// are discarded and the last `count` elements become invalid.
+pub fn header_stack_pop_front<H: Header>(stack: &mut [H], count: usize) {
+    let count = count.min(stack.len());
+    stack.rotate_left(count);
+    let n = stack.len();
+    for h in &mut stack[n - count..] {
+        h.set_invalid();
+    }
 }
 
 impl<'a> packet_in<'a> {
     pub fn new(data: &'a [u8]) -> Self {
-        Self { data, index: 0 }
+        Self::with_offset(data, 0)
+    }
+
+    /// Create a packet that starts `start_bytes` into `data`, without
+    /// re-slicing the underlying buffer.
+    pub fn with_offset(data: &'a [u8], start_bytes: usize) -> Self {
+        Self {
+            data,
+            index: 0,
+            headroom: start_bytes,
+            loops: 0,
+        }
+    }
+
+    /// The packet data, excluding any headroom.
+    pub fn packet_data(&self) -> &'a [u8] {
+        let start = usize::min(self.headroom, self.data.len());
+        &self.data[start..]
+    }
+
+    /// The number of bits of the packet that have not been extracted or
+    /// advanced past yet.
+    pub fn remaining_bits(&self) -> usize {
+        (self.packet_data().len() * 8).saturating_sub(self.index)
     }
 
     // TODO: this function signature is a bit unforunate in the sense that the
@@ -209,36 +798,137 @@ impl<'a> packet_in<'a> {
     // interrogated. In fact, the signature for packet_in::extract from the p4
     // standard library requires the return type to be `void`, so this signature
     // cannot return a result without the compiler having special knowledge of
-    // functions that happen to be called "extract".
+    // functions that happen to be called "extract". Generated parsers call
+    // `try_extract` instead.
     pub fn extract<H: Header>(&mut self, h: &mut H) {
+        if let Err(e) = self.try_extract(h) {
+            //TODO better than this
+            println!("packet extraction failed: {}", e);
+        }
+    }
+
+    /// Extract `h` from the packet. Fails without extracting anything, leaving
+    /// `h` untouched and the extraction index where it was, if fewer than
+    /// `H::size()` bits of the packet remain.
+    pub fn try_extract<H: Header>(
+        &mut self,
+        h: &mut H,
+    ) -> Result<(), error::PacketTooShortError> {
         //TODO what if a header does not end on a byte boundary?
         let n = H::size();
-        let start = if self.index > 0 { self.index >> 3 } else { 0 };
-        match h.set(&self.data[start..start + (n >> 3)]) {
-            Ok(_) => {}
-            Err(e) => {
-                //TODO better than this
-                println!("packet extraction failed: {}", e);
-            }
+        self.check_remaining(n)?;
+        let start = self.index >> 3;
+        if let Err(e) = h.set(self.window(start, n >> 3)) {
+            // Only reachable for headers that do not end on a byte boundary.
+            println!("packet extraction failed: {}", e);
         }
         self.index += n;
+        debug_assert!(self.index <= self.packet_data().len() * 8);
         h.set_valid();
+        Ok(())
+    }
+
+    /// Extract into the next element of a header stack, the first element
+    /// that is not valid, as the P4 `extract(stack.next)` does. Fails without
+    /// extracting anything if every element of the stack is valid, or if the
+    /// packet is too short to hold another element.
+    pub fn extract_stack<H: Header>(
+        &mut self,
+        stack: &mut [H],
+    ) -> Result<(), error::ExtractStackError> {
+        let size = stack.len();
+        match stack.iter_mut().find(|h| !h.is_valid()) {
+            Some(h) => Ok(self.try_extract(h)?),
+            None => Err(error::StackOutOfBoundsError { size }.into()),
+        }
+    }
+
+    /// Skip `bits` bits of the packet without extracting them, as the P4
+    /// `advance` method does. Fails without advancing if fewer than `bits`
+    /// bits of the packet remain.
+    pub fn advance(
+        &mut self,
+        bits: usize,
+    ) -> Result<(), error::PacketTooShortError> {
+        self.check_remaining(bits)?;
+        self.index += bits;
+        debug_assert!(self.index <= self.packet_data().len() * 8);
+        Ok(())
+    }
+
+    fn check_remaining(
+        &self,
+        bits: usize,
+    ) -> Result<(), error::PacketTooShortError> {
+        let remaining = self.remaining_bits();
+        if bits > remaining {
+            return Err(error::PacketTooShortError {
+                expected: bits,
+                remaining,
+            });
+        }
+        Ok(())
+    }
+
+    /// Record that the parser entered a state that is part of a loop. Returns
+    /// false once [`PARSER_LOOP_LIMIT`] is exceeded, in which case the parser
+    /// rejects the packet.
+    pub fn enter_loop(&mut self) -> bool {
+        self.loops += 1;
+        self.loops <= PARSER_LOOP_LIMIT
     }
 
     // This is the same as extract except we return a new header instead of
     // modifying an existing one.
     pub fn extract_new<H: Header>(&mut self) -> Result<H, TryFromSliceError> {
         let n = H::size();
-        let start = if self.index > 0 { self.index >> 3 } else { 0 };
-        self.index += n;
+        let start = self.index >> 3;
         let mut x = H::new();
-        x.set(&self.data[start..start + (n >> 3)])?;
+        x.set(self.window(start, n >> 3))?;
+        self.index += n;
+        debug_assert!(self.index <= self.packet_data().len() * 8);
         Ok(x)
     }
+
+    // Get up to len bytes of the packet data starting at start. The window
+    // may be short, or empty, if the packet is truncated. Headers report that
+    // through the result of Header::set.
+    fn window(&self, start: usize, len: usize) -> &'a [u8] {
+        let data = self.packet_data();
+        let start = usize::min(start, data.len());
+        let end = usize::min(start + len, data.len());
+        &data[start..end]
+    }
+}
+
+impl<'a> packet_out<'a> {
+    /// Append `h` to the outgoing header data, as the P4 `emit` method does.
+    /// Invalid headers are not emitted.
+    pub fn emit<H: Header>(&mut self, h: &H) {
+        if h.is_valid() {
+            self.header_data
+                .extend_from_slice(h.to_bitvec().as_raw_slice());
+        }
+    }
 }
 
 //XXX: remove once classifier defined in terms of bitvecs
 pub fn bitvec_to_biguint(bv: &BitVec<u8, Msb0>) -> table::BigUintKey {
+    // Values of at most a byte are loaded from their bits, as such values may
+    // not start at the beginning of their underlying byte and the other bits
+    // of that byte belong to neighboring fields.
+    if bv.len() <= 8 {
+        let value: u8 = if bv.is_empty() { 0 } else { bv.load_le() };
+        return table::BigUintKey {
+            value: num::BigUint::from(value),
+            width: usize::min(bv.len(), 1),
+        };
+    }
+    // Wider values are read from their bytes. Make sure those bytes hold
+    // nothing but the value, so keys compare on exactly the field's bits.
+    let mut bv = bv.clone();
+    bv.force_align();
+    bv.set_uninitialized(false);
     let s = bv.as_raw_slice();
     table::BigUintKey {
         value: num::BigUint::from_bytes_le(s),
@@ -271,8 +961,17 @@ pub fn dump_bv(x: &BitVec<u8, Msb0>) -> String {
     if x.is_empty() {
         "∅".into()
     } else {
-        let v: u128 = x.load_le();
-        format!("{:x}", v)
+        // Format one byte at a time so fields wider than 128 bits do not
+        // overflow. Bytes are stored little endian, so the last byte is the
+        // most significant.
+        let mut hex = String::with_capacity(x.len().div_ceil(8) * 2);
+        for b in x.chunks(8).rev() {
+            write!(hex, "{:02x}", b.load_le::<u8>()).unwrap();
+        }
+        match hex.trim_start_matches('0') {
+            "" => "0".into(),
+            v => v.into(),
+        }
     }
 }
 
@@ -331,30 +1030,35 @@ pub fn extract_ternary_key(
     }
 }
 
+/// Extract an lpm key. Fails if the prefix length is longer than the address
+/// or the address has bits set beyond it.
 pub fn extract_lpm_key(
     keyset_data: &[u8],
     offset: usize,
-    _len: usize,
-) -> table::Key {
-    let (addr, len) = match keyset_data.len() {
+    len: usize,
+) -> Result<table::Key, error::PrefixError> {
+    // The address is followed by a byte holding the prefix length. The width
+    // of the key field, not of the whole keyset, determines the address
+    // family, as the keyset may hold other keys too.
+    let (addr, len) = match len {
         // IPv4
-        5 => {
+        4 => {
             let data: [u8; 4] =
                 keyset_data[offset..offset + 4].try_into().unwrap();
             (IpAddr::from(data), keyset_data[offset + 4])
         }
         // IPv6
-        17 => {
+        16 => {
             let data: [u8; 16] =
                 keyset_data[offset..offset + 16].try_into().unwrap();
             (IpAddr::from(data), keyset_data[offset + 16])
         }
         x => {
-            panic!("lpm: key must be len 5 (ipv4) or 17 (ipv6) found {}", x);
+            panic!("lpm: key must be len 4 (ipv4) or 16 (ipv6) found {}", x);
         }
     };
 
-    table::Key::Lpm(table::Prefix { addr, len })
+    Ok(table::Key::Lpm(table::Prefix::new(addr, len)?))
 }
 
 pub fn extract_bool_action_parameter(
@@ -378,3 +1082,98 @@ pub fn extract_bit_action_parameter(
     b.resize(size, false);
     b
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dump_bv_narrow() {
+        let mut x = bitvec![mut u8, Msb0; 0; 16];
+        x.store_le(0xabcu16);
+        assert_eq!(dump_bv(&x), "abc");
+
+        let x = bitvec![mut u8, Msb0; 0; 12];
+        assert_eq!(dump_bv(&x), "0");
+    }
+
+    #[test]
+    fn bit_narrow() {
+        let a = Bit::<1>::new(&[0x80]).unwrap();
+        let b = Bit::<1>::new(&[0xbf]).unwrap();
+        let c = Bit::<1>::new(&[0x7f]).unwrap();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert!(bool::from(b));
+        assert!(!bool::from(c));
+
+        let x = Bit::<12>::new(&[0xab, 0xcd]).unwrap();
+        assert_eq!(u128::from(x), 0xabc);
+
+        let e = Bit::<0>::new(&[]).unwrap();
+        assert_eq!(e, Bit::<0>::new(&[0xff]).unwrap());
+        assert_eq!(u128::from(e), 0);
+    }
+
+    #[test]
+    fn bitvec_to_biguint_narrow() {
+        let bytes = [0b1011_0000u8];
+        let flag = bytes.view_bits::<Msb0>()[2..3].to_bitvec();
+        assert_eq!(bitvec_to_biguint(&flag).value, num::BigUint::from(1u8));
+
+        let mut lit = bitvec![mut u8, Msb0; 0; 1];
+        lit.store_le(1u8);
+        assert_eq!(bitvec_to_biguint(&lit), bitvec_to_biguint(&flag));
+
+        let empty = BitVec::<u8, Msb0>::new();
+        assert_eq!(bitvec_to_biguint(&empty).width, 0);
+    }
+
+    #[test]
+    fn bitvec_to_biguint_wide() {
+        // Bits past the end of a value do not take part in the key.
+        let mut a = BitVec::<u8, Msb0>::from_vec(vec![0xab, 0xcf]);
+        a.truncate(12);
+        let mut b = BitVec::<u8, Msb0>::from_vec(vec![0xab, 0xc0]);
+        b.truncate(12);
+        assert_eq!(bitvec_to_biguint(&a), bitvec_to_biguint(&b));
+
+        // Keys that share leading bytes differ on the rest.
+        let x = BitVec::<u8, Msb0>::from_vec(vec![1, 0, 0, 10]);
+        let y = BitVec::<u8, Msb0>::from_vec(vec![2, 0, 0, 10]);
+        assert_ne!(bitvec_to_biguint(&x), bitvec_to_biguint(&y));
+
+        // Values that do not start at the beginning of a byte.
+        let bytes = [0x0fu8, 0xff, 0xf0];
+        let z = bytes.view_bits::<Msb0>()[4..20].to_bitvec();
+        assert_eq!(bitvec_to_biguint(&z).value, num::BigUint::from(0xffffu16));
+    }
+
+    #[test]
+    fn advance_past_end() {
+        let data = [0u8; 4];
+        let mut pkt = packet_in::with_offset(&data, 1);
+        assert_eq!(pkt.remaining_bits(), 24);
+        pkt.advance(16).unwrap();
+        assert_eq!(pkt.remaining_bits(), 8);
+
+        let err = pkt.advance(usize::MAX).unwrap_err();
+        assert_eq!(err.expected, usize::MAX);
+        assert_eq!(err.remaining, 8);
+        assert_eq!(pkt.index, 16);
+
+        pkt.advance(8).unwrap();
+        assert_eq!(pkt.remaining_bits(), 0);
+        assert!(pkt.advance(1).is_err());
+    }
+
+    #[test]
+    fn dump_bv_wide() {
+        let bytes: Vec<u8> = (0..32).collect();
+        let x: BitVec<u8, Msb0> = BitVec::from_slice(&bytes);
+        assert_eq!(
+            dump_bv(&x),
+            "1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100",
+        );
+    }
+}
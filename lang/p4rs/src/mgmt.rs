@@ -0,0 +1,260 @@
+// Copyright 2022 Oxide Computer Company
+
+//! Encoding and decoding of messages a controller can use to manage the
+//! tables of a pipeline and learn its port count.
+//!
+//! The framing is local to p4rs. It is not the management protocol of
+//! SoftNPU and is not wire compatible with it, so it is only useful where
+//! both ends use this module.
+//!
+//! Every message starts with a one byte message type. Integers are big
+//! endian. Strings and byte strings are prefixed with their length as a
+//! `u32`.
+//!
+//! | type | message              | body                                      |
+//! |------|----------------------|-------------------------------------------|
+//! | 0    | [`TableAdd`]         | table, action, priority, keyset, params   |
+//! | 1    | [`TableRemove`]      | table, keyset                             |
+//! | 2    | [`PortCountRequest`] | empty                                     |
+//! | 3    | [`PortCount`]        | port count as a `u16`                     |
+//!
+//! [`TableAdd`]: ControllerMessage::TableAdd
+//! [`TableRemove`]: ControllerMessage::TableRemove
+//! [`PortCountRequest`]: ControllerMessage::PortCountRequest
+//! [`PortCount`]: ControllerMessage::PortCount
+
+use std::error::Error;
+use std::fmt;
+
+use crate::{error::PipelineError, Pipeline};
+
+const TABLE_ADD: u8 = 0;
+const TABLE_REMOVE: u8 = 1;
+const PORT_COUNT_REQUEST: u8 = 2;
+const PORT_COUNT: u8 = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControllerMessage {
+    /// Add an entry to a table.
+    TableAdd(TableModifier),
+
+    /// Remove an entry from a table. Only the table and keyset of the
+    /// modifier are carried.
+    TableRemove(TableModifier),
+
+    /// Ask for the number of ports.
+    PortCountRequest,
+
+    /// The response to a [`ControllerMessage::PortCountRequest`].
+    PortCount(u16),
+}
+
+/// A change to the entries of a table, with arguments as taken by
+/// [`Pipeline::add_table_entry`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TableModifier {
+    pub table: String,
+    pub action: String,
+    pub priority: u32,
+    pub keyset_data: Vec<u8>,
+    pub parameter_data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The message ended before all of its fields were read.
+    Truncated,
+
+    /// The message type is not known.
+    UnknownType(u8),
+
+    /// A string field is not valid UTF-8.
+    InvalidString,
+
+    /// There is data after the end of the message.
+    TrailingData(usize),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "truncated controller message"),
+            Self::UnknownType(t) => {
+                write!(f, "unknown controller message type {}", t)
+            }
+            Self::InvalidString => {
+                write!(f, "controller message string is not valid utf-8")
+            }
+            Self::TrailingData(n) => {
+                write!(f, "{} bytes after end of controller message", n)
+            }
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+impl ControllerMessage {
+    pub fn to_wire(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match self {
+            Self::TableAdd(m) => {
+                buf.push(TABLE_ADD);
+                put_bytes(&mut buf, m.table.as_bytes());
+                put_bytes(&mut buf, m.action.as_bytes());
+                buf.extend_from_slice(&m.priority.to_be_bytes());
+                put_bytes(&mut buf, &m.keyset_data);
+                put_bytes(&mut buf, &m.parameter_data);
+            }
+            Self::TableRemove(m) => {
+                buf.push(TABLE_REMOVE);
+                put_bytes(&mut buf, m.table.as_bytes());
+                put_bytes(&mut buf, &m.keyset_data);
+            }
+            Self::PortCountRequest => buf.push(PORT_COUNT_REQUEST),
+            Self::PortCount(n) => {
+                buf.push(PORT_COUNT);
+                buf.extend_from_slice(&n.to_be_bytes());
+            }
+        }
+        buf
+    }
+
+    pub fn from_wire(buf: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = Reader { buf };
+        let msg = match r.take(1)?[0] {
+            TABLE_ADD => Self::TableAdd(TableModifier {
+                table: r.string()?,
+                action: r.string()?,
+                priority: u32::from_be_bytes(r.array()?),
+                keyset_data: r.bytes()?.to_vec(),
+                parameter_data: r.bytes()?.to_vec(),
+            }),
+            TABLE_REMOVE => Self::TableRemove(TableModifier {
+                table: r.string()?,
+                keyset_data: r.bytes()?.to_vec(),
+                ..Default::default()
+            }),
+            PORT_COUNT_REQUEST => Self::PortCountRequest,
+            PORT_COUNT => Self::PortCount(u16::from_be_bytes(r.array()?)),
+            t => return Err(DecodeError::UnknownType(t)),
+        };
+        if !r.buf.is_empty() {
+            return Err(DecodeError::TrailingData(r.buf.len()));
+        }
+        Ok(msg)
+    }
+
+    /// Apply a table add or remove message to `pipeline`. Other messages do
+    /// not modify the pipeline and are ignored.
+    pub fn apply<P: Pipeline + ?Sized>(
+        &self,
+        pipeline: &mut P,
+    ) -> Result<(), PipelineError> {
+        match self {
+            Self::TableAdd(m) => pipeline.add_table_entry(
+                &m.table,
+                &m.action,
+                &m.keyset_data,
+                &m.parameter_data,
+                m.priority,
+            ),
+            Self::TableRemove(m) => {
+                pipeline.remove_table_entry(&m.table, &m.keyset_data)
+            }
+            Self::PortCountRequest | Self::PortCount(_) => Ok(()),
+        }
+    }
+}
+
+fn put_bytes(buf: &mut Vec<u8>, data: &[u8]) {
+    buf.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    buf.extend_from_slice(data);
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        if self.buf.len() < n {
+            return Err(DecodeError::Truncated);
+        }
+        let (data, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Ok(data)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], DecodeError> {
+        let n = u32::from_be_bytes(self.array()?) as usize;
+        self.take(n)
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let data = self.bytes()?;
+        String::from_utf8(data.to_vec()).map_err(|_| DecodeError::InvalidString)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_add_round_trip() {
+        let msg = ControllerMessage::TableAdd(TableModifier {
+            table: "ingress.router.router".into(),
+            action: "forward".into(),
+            priority: 10,
+            keyset_data: vec![0xfd, 0x00, 0x10, 0x00, 24],
+            parameter_data: 47u16.to_le_bytes().to_vec(),
+        });
+        let wire = msg.to_wire();
+        assert_eq!(wire[0], TABLE_ADD);
+        assert_eq!(ControllerMessage::from_wire(&wire), Ok(msg));
+    }
+
+    #[test]
+    fn other_messages_round_trip() {
+        let msgs = [
+            ControllerMessage::TableRemove(TableModifier {
+                table: "ingress.fwd".into(),
+                keyset_data: vec![1, 2, 3, 4],
+                ..Default::default()
+            }),
+            ControllerMessage::PortCountRequest,
+            ControllerMessage::PortCount(8),
+        ];
+        for msg in msgs {
+            assert_eq!(ControllerMessage::from_wire(&msg.to_wire()), Ok(msg));
+        }
+    }
+
+    #[test]
+    fn malformed_messages() {
+        let wire = ControllerMessage::PortCount(8).to_wire();
+        assert_eq!(
+            ControllerMessage::from_wire(&wire[..2]),
+            Err(DecodeError::Truncated),
+        );
+        let mut long = wire.clone();
+        long.push(0);
+        assert_eq!(
+            ControllerMessage::from_wire(&long),
+            Err(DecodeError::TrailingData(1)),
+        );
+        assert_eq!(
+            ControllerMessage::from_wire(&[9]),
+            Err(DecodeError::UnknownType(9)),
+        );
+        assert_eq!(
+            ControllerMessage::from_wire(&[]),
+            Err(DecodeError::Truncated)
+        );
+    }
+}
@@ -0,0 +1,334 @@
+// Copyright 2022 Oxide Computer Company
+
+//! Capturing the table state of a pipeline and carrying it over to another
+//! pipeline, for example when a controller reloads an updated P4 program.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{FieldLayout, Pipeline, TableEntry};
+
+/// The shape of a table: its key fields and the actions its entries may
+/// invoke. Entries can only be carried between tables with the same keys, and
+/// only for actions whose parameters are unchanged.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableSchema {
+    /// Key fields in table key order. The width is that of the field the key
+    /// is taken from.
+    pub keys: Vec<FieldLayout>,
+
+    /// The actions entries in this table may invoke.
+    pub actions: Vec<ActionSchema>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActionSchema {
+    pub name: String,
+
+    /// Action parameters in declaration order.
+    pub parameters: Vec<FieldLayout>,
+}
+
+impl TableSchema {
+    pub fn get_action(&self, name: &str) -> Option<&ActionSchema> {
+        self.actions.iter().find(|a| a.name == name)
+    }
+}
+
+/// The entries and schema of one table at the time of a snapshot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableSnapshot {
+    pub table_id: String,
+    pub schema: TableSchema,
+    pub entries: Vec<TableEntry>,
+}
+
+/// The table state of a pipeline at a point in time.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PipelineSnapshot {
+    pub tables: Vec<TableSnapshot>,
+
+    /// The epoch of the pipeline when the snapshot was captured, see
+    /// [`Pipeline::epoch`].
+    #[serde(default)]
+    pub epoch: u64,
+}
+
+impl PipelineSnapshot {
+    /// Capture the schema and entries of every table in `pipeline`.
+    pub fn capture<P: Pipeline + ?Sized>(pipeline: &P) -> Self {
+        let mut tables = Vec::new();
+        for table_id in pipeline.get_table_ids() {
+            let schema = match pipeline.get_table_schema(table_id) {
+                Some(schema) => schema,
+                None => continue,
+            };
+            let entries =
+                pipeline.get_table_entries(table_id).unwrap_or_default();
+            tables.push(TableSnapshot {
+                table_id: table_id.to_owned(),
+                schema,
+                entries,
+            });
+        }
+        Self {
+            tables,
+            epoch: pipeline.epoch(),
+        }
+    }
+
+    pub fn get_table(&self, table_id: &str) -> Option<&TableSnapshot> {
+        self.tables.iter().find(|t| t.table_id == table_id)
+    }
+
+    /// The changes that take the tables of this snapshot to those of
+    /// `other`, for example from the current state of a pipeline to the
+    /// desired one. Entries are identified by their key and priority, an
+    /// entry in both snapshots with a different action or parameters is
+    /// changed. Tables without differences are left out.
+    pub fn diff(&self, other: &PipelineSnapshot) -> SnapshotDiff {
+        let mut diff = SnapshotDiff::default();
+        let mut table_ids: Vec<&str> =
+            self.tables.iter().map(|t| t.table_id.as_str()).collect();
+        for t in &other.tables {
+            if !table_ids.contains(&t.table_id.as_str()) {
+                table_ids.push(&t.table_id);
+            }
+        }
+
+        for id in table_ids {
+            let from = self.get_table(id).map(|t| t.entries.as_slice());
+            let to = other.get_table(id).map(|t| t.entries.as_slice());
+            let from = from.unwrap_or_default();
+            let to = to.unwrap_or_default();
+
+            let mut table = TableDiff::default();
+            for entry in from {
+                match to.iter().find(|e| same_key(e, entry)) {
+                    None => table.removed.push(entry.clone()),
+                    Some(e) if !same_action(e, entry) => {
+                        table.changed.push((entry.clone(), e.clone()))
+                    }
+                    Some(_) => {}
+                }
+            }
+            for entry in to {
+                if !from.iter().any(|e| same_key(e, entry)) {
+                    table.added.push(entry.clone());
+                }
+            }
+            if !table.is_empty() {
+                diff.tables.insert(id.to_owned(), table);
+            }
+        }
+        diff
+    }
+}
+
+fn same_key(a: &TableEntry, b: &TableEntry) -> bool {
+    a.keyset_data == b.keyset_data && a.priority == b.priority
+}
+
+fn same_action(a: &TableEntry, b: &TableEntry) -> bool {
+    a.action_id == b.action_id && a.parameter_data == b.parameter_data
+}
+
+/// The entries to add, remove and change to take one table to another, see
+/// [`PipelineSnapshot::diff`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TableDiff {
+    /// Entries only in the snapshot being diffed against.
+    pub added: Vec<TableEntry>,
+
+    /// Entries only in the snapshot the diff was taken from.
+    pub removed: Vec<TableEntry>,
+
+    /// Entries in both snapshots that invoke a different action or pass it
+    /// different parameters, as the entry before and after.
+    pub changed: Vec<(TableEntry, TableEntry)>,
+}
+
+impl TableDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+    }
+}
+
+/// The differences between two snapshots, per table id.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SnapshotDiff {
+    pub tables: BTreeMap<String, TableDiff>,
+}
+
+impl SnapshotDiff {
+    pub fn get_table(&self, table_id: &str) -> Option<&TableDiff> {
+        self.tables.get(table_id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+}
+
+/// Why an entry from a snapshot was not carried over by a migration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DropReason {
+    /// The table no longer exists.
+    TableRemoved,
+
+    /// The key fields of the table changed.
+    KeyMismatch,
+
+    /// The action the entry invokes no longer exists in the table.
+    ActionRemoved,
+
+    /// The parameters of the action the entry invokes changed.
+    ParameterMismatch,
+
+    /// The entry uses a key encoding that cannot be installed again, such as a
+    /// masked ternary key.
+    Unsupported,
+
+    /// The pipeline rejected the entry when it was added.
+    Rejected,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DroppedEntry {
+    pub entry: TableEntry,
+    pub reason: DropReason,
+}
+
+/// The outcome of migrating a snapshot into a pipeline, per table id.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MigrationReport {
+    /// The number of entries restored into each table.
+    pub restored: BTreeMap<String, usize>,
+
+    /// The entries that were not restored into each table, and why.
+    pub dropped: BTreeMap<String, Vec<DroppedEntry>>,
+}
+
+impl MigrationReport {
+    pub fn restored(&self, table_id: &str) -> usize {
+        self.restored.get(table_id).copied().unwrap_or(0)
+    }
+
+    pub fn dropped(&self, table_id: &str) -> &[DroppedEntry] {
+        self.dropped
+            .get(table_id)
+            .map(|x| x.as_slice())
+            .unwrap_or(&[])
+    }
+}
+
+/// Restore the entries of `snapshot` into `pipeline` for every table whose
+/// schema still matches. See [`Pipeline::migrate_from`].
+pub fn migrate<P: Pipeline + ?Sized>(
+    pipeline: &mut P,
+    snapshot: &PipelineSnapshot,
+) -> MigrationReport {
+    let mut report = MigrationReport::default();
+
+    for table in &snapshot.tables {
+        let id = table.table_id.as_str();
+        let mut dropped = Vec::new();
+        let mut restored = 0;
+
+        let schema = pipeline.get_table_schema(id);
+        for entry in &table.entries {
+            // The new program installs its own const entries.
+            if entry.immutable {
+                continue;
+            }
+            let reason = match &schema {
+                None => Some(DropReason::TableRemoved),
+                Some(s) if s.keys != table.schema.keys => {
+                    Some(DropReason::KeyMismatch)
+                }
+                Some(s) => match (
+                    s.get_action(&entry.action_id),
+                    table.schema.get_action(&entry.action_id),
+                ) {
+                    (None, _) => Some(DropReason::ActionRemoved),
+                    (Some(new), Some(old)) if new != old => {
+                        Some(DropReason::ParameterMismatch)
+                    }
+                    _ => None,
+                },
+            };
+            if let Some(reason) = reason {
+                dropped.push(DroppedEntry {
+                    entry: entry.clone(),
+                    reason,
+                });
+                continue;
+            }
+
+            let keyset_data = match insertion_keyset(entry, &table.schema) {
+                Some(data) => data,
+                None => {
+                    dropped.push(DroppedEntry {
+                        entry: entry.clone(),
+                        reason: DropReason::Unsupported,
+                    });
+                    continue;
+                }
+            };
+            let added = pipeline.add_table_entry(
+                id,
+                &entry.action_id,
+                &keyset_data,
+                &entry.parameter_data,
+                entry.priority,
+            );
+            if added.is_err() {
+                dropped.push(DroppedEntry {
+                    entry: entry.clone(),
+                    reason: DropReason::Rejected,
+                });
+                continue;
+            }
+            restored += 1;
+        }
+
+        report.restored.insert(id.to_owned(), restored);
+        if !dropped.is_empty() {
+            report.dropped.insert(id.to_owned(), dropped);
+        }
+    }
+
+    report
+}
+
+// Entries read back from a table encode ternary keys without the leading
+// care byte `add_table_entry` expects, see `table::Key::to_bytes`. Re-encode
+// them for insertion. Masked ternary keys cannot be inserted.
+fn insertion_keyset(
+    entry: &TableEntry,
+    schema: &TableSchema,
+) -> Option<Vec<u8>> {
+    if entry.keyset_layout.is_empty() {
+        return Some(entry.keyset_data.clone());
+    }
+    let mut data = Vec::new();
+    let mut offset = 0;
+    for (field, key) in entry.keyset_layout.iter().zip(&schema.keys) {
+        let value = entry.keyset_data.get(offset..offset + field.width)?;
+        offset += field.width;
+        if field.match_kind.as_deref() != Some("ternary") {
+            data.extend_from_slice(value);
+        } else if value.is_empty() {
+            data.resize(data.len() + 1 + key.width, 0);
+        } else if value.len() == key.width {
+            data.push(1);
+            data.extend_from_slice(value);
+        } else {
+            return None;
+        }
+    }
+    Some(data)
+}
@@ -1,8 +1,11 @@
 // Copyright 2022 Oxide Computer Company
 
-use std::collections::HashSet;
-use std::fmt::Write;
-use std::net::IpAddr;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use crate::error::PrefixError;
 
 use num::bigint::BigUint;
 use num::ToPrimitive;
@@ -31,6 +34,16 @@ impl Default for Key {
 }
 
 impl Key {
+    /// The P4 name of the match kind for this key.
+    pub fn match_kind(&self) -> &'static str {
+        match self {
+            Key::Exact(_) => "exact",
+            Key::Range(..) => "range",
+            Key::Ternary(_) => "ternary",
+            Key::Lpm(_) => "lpm",
+        }
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         match self {
             Key::Exact(x) => {
@@ -102,8 +115,80 @@ pub struct Prefix {
     pub len: u8,
 }
 
+impl Prefix {
+    /// The prefix of length `len` starting at `addr`. Fails if `len` is
+    /// longer than the address or `addr` has bits set beyond `len`.
+    pub fn new(addr: IpAddr, len: u8) -> Result<Self, PrefixError> {
+        let prefix = Self::masked(addr, len)?;
+        if prefix.addr != addr {
+            return Err(PrefixError::HostBits { addr, len });
+        }
+        Ok(prefix)
+    }
+
+    /// The prefix of length `len` containing `addr`, clearing the bits of
+    /// `addr` beyond `len`. Fails if `len` is longer than the address.
+    pub fn masked(addr: IpAddr, len: u8) -> Result<Self, PrefixError> {
+        let addr = match addr {
+            IpAddr::V4(a) => {
+                if len > 32 {
+                    return Err(PrefixError::Length { len, max: 32 });
+                }
+                let mask = u32::MAX.checked_shl(32 - len as u32).unwrap_or(0);
+                IpAddr::V4(Ipv4Addr::from(u32::from(a) & mask))
+            }
+            IpAddr::V6(a) => {
+                if len > 128 {
+                    return Err(PrefixError::Length { len, max: 128 });
+                }
+                let mask = u128::MAX.checked_shl(128 - len as u32).unwrap_or(0);
+                IpAddr::V6(Ipv6Addr::from(u128::from(a) & mask))
+            }
+        };
+        Ok(Self { addr, len })
+    }
+}
+
+/// CIDR notation, for example `10.0.0.0/8` or `fd00::/24`.
+impl fmt::Display for Prefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.len)
+    }
+}
+
+/// Parse CIDR notation. The address must not have bits set beyond the
+/// length, see [`Prefix::new`].
+impl FromStr for Prefix {
+    type Err = PrefixError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, len) = s
+            .split_once('/')
+            .ok_or_else(|| PrefixError::Syntax(s.to_owned()))?;
+        let addr: IpAddr = addr
+            .parse()
+            .map_err(|_| PrefixError::Syntax(s.to_owned()))?;
+        let len: u8 =
+            len.parse().map_err(|_| PrefixError::Syntax(s.to_owned()))?;
+        Self::new(addr, len)
+    }
+}
+
 pub struct Table<const D: usize, A: Clone> {
-    pub entries: HashSet<TableEntry<D, A>>,
+    /// The entries of the table. Entries are added and removed with
+    /// [`Table::insert`] and [`Table::remove`] so that the indexes below stay
+    /// in step with them.
+    entries: HashSet<TableEntry<D, A>>,
+
+    /// Entries whose keys are all exact, by key value. When every entry in
+    /// the table is exact, lookups go through this index instead of testing
+    /// each entry.
+    exact: HashMap<[BigUint; D], TableEntry<D, A>>,
+
+    /// Entries by the prefix of their first lpm key. When every entry in the
+    /// table has its lpm key in the same position, lookups walk this trie
+    /// instead of testing each entry.
+    lpm: Option<LpmIndex<D, A>>,
 }
 
 impl<const D: usize, A: Clone> Default for Table<D, A> {
@@ -112,17 +197,94 @@ impl<const D: usize, A: Clone> Default for Table<D, A> {
     }
 }
 
+impl<const D: usize, A: Clone> FromIterator<TableEntry<D, A>> for Table<D, A> {
+    fn from_iter<I: IntoIterator<Item = TableEntry<D, A>>>(iter: I) -> Self {
+        let mut table = Self::new();
+        for entry in iter {
+            table.insert(entry);
+        }
+        table
+    }
+}
+
 impl<const D: usize, A: Clone> Table<D, A> {
     pub fn new() -> Self {
         Self {
             entries: HashSet::new(),
+            exact: HashMap::new(),
+            lpm: None,
+        }
+    }
+
+    /// The entries of the table.
+    pub fn entries(&self) -> &HashSet<TableEntry<D, A>> {
+        &self.entries
+    }
+
+    /// Add an entry to the table. Returns false, leaving the table as it
+    /// was, if an entry with the same key is already present.
+    pub fn insert(&mut self, entry: TableEntry<D, A>) -> bool {
+        if self.entries.contains(&entry) {
+            return false;
+        }
+        if let Some(key) = exact_key(&entry.key) {
+            self.exact.insert(key, entry.clone());
+        }
+        if let Some(dim) =
+            entry.key.iter().position(|k| matches!(k, Key::Lpm(_)))
+        {
+            self.lpm
+                .get_or_insert_with(|| LpmIndex::new(dim))
+                .insert(&entry);
+        }
+        self.entries.insert(entry)
+    }
+
+    /// Remove the entry with the same key as `entry`. Returns false if there
+    /// is no such entry.
+    pub fn remove(&mut self, entry: &TableEntry<D, A>) -> bool {
+        if let Some(key) = exact_key(&entry.key) {
+            self.exact.remove(&key);
+        }
+        if let Some(lpm) = &mut self.lpm {
+            lpm.remove(entry);
+        }
+        self.entries.remove(entry)
+    }
+
+    /// Remove every entry for which `predicate` returns true. Returns the
+    /// removed entries.
+    pub fn remove_all_matching(
+        &mut self,
+        mut predicate: impl FnMut(&TableEntry<D, A>) -> bool,
+    ) -> Vec<TableEntry<D, A>> {
+        let matching: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|e| predicate(e))
+            .cloned()
+            .collect();
+        for e in &matching {
+            self.remove(e);
         }
+        matching
     }
 
     pub fn match_selector(
         &self,
         keyset: &[BigUint; D],
     ) -> Vec<TableEntry<D, A>> {
+        if self.exact.len() == self.entries.len() {
+            return match self.exact.get(keyset) {
+                Some(entry) => vec![entry.clone()],
+                None => Vec::new(),
+            };
+        }
+        if let Some(lpm) = &self.lpm {
+            if lpm.len == self.entries.len() {
+                return lpm.match_selector(keyset);
+            }
+        }
         let mut result = Vec::new();
         for entry in &self.entries {
             if keyset_matches(keyset, &entry.key) {
@@ -303,6 +465,240 @@ pub fn key_matches(selector: &BigUint, key: &Key) -> bool {
     }
 }
 
+/// A path compressed binary trie over the prefixes of one lpm dimension of a
+/// table. IPv4 prefixes are kept in their own trie, aligned to the top of a
+/// u128 so both families share the same bit operations.
+struct LpmIndex<const D: usize, A: Clone> {
+    dim: usize,
+    v4: Option<Box<LpmNode<D, A>>>,
+    v6: Option<Box<LpmNode<D, A>>>,
+    len: usize,
+}
+
+struct LpmNode<const D: usize, A: Clone> {
+    prefix: u128,
+    len: u8,
+    entries: Vec<TableEntry<D, A>>,
+    children: [Option<Box<LpmNode<D, A>>>; 2],
+}
+
+impl<const D: usize, A: Clone> LpmIndex<D, A> {
+    fn new(dim: usize) -> Self {
+        Self {
+            dim,
+            v4: None,
+            v6: None,
+            len: 0,
+        }
+    }
+
+    fn root(
+        &mut self,
+        prefix: &Prefix,
+    ) -> (&mut Option<Box<LpmNode<D, A>>>, u128) {
+        match prefix.addr {
+            IpAddr::V4(a) => (&mut self.v4, u128::from(u32::from(a)) << 96),
+            IpAddr::V6(a) => (&mut self.v6, u128::from(a)),
+        }
+    }
+
+    fn insert(&mut self, entry: &TableEntry<D, A>) {
+        let Key::Lpm(prefix) = &entry.key[self.dim] else {
+            return;
+        };
+        let (root, addr) = self.root(prefix);
+        lpm_insert(root, addr & lpm_mask(prefix.len), prefix.len, entry);
+        self.len += 1;
+    }
+
+    fn remove(&mut self, entry: &TableEntry<D, A>) {
+        let Key::Lpm(prefix) = &entry.key[self.dim] else {
+            return;
+        };
+        let (root, addr) = self.root(prefix);
+        if lpm_remove(root, addr & lpm_mask(prefix.len), prefix.len, entry) {
+            self.len -= 1;
+        }
+    }
+
+    /// The entries that match `keyset` with the longest prefix, highest
+    /// priority first. This is the same result as a linear match followed by
+    /// [`sort_entries`].
+    fn match_selector(&self, keyset: &[BigUint; D]) -> Vec<TableEntry<D, A>> {
+        let selector = &keyset[self.dim];
+        let mut path = Vec::new();
+        if let Some(a) = selector.to_u32() {
+            lpm_path(&self.v4, u128::from(a) << 96, &mut path);
+        }
+        if let Some(a) = selector.to_u128() {
+            lpm_path(&self.v6, a, &mut path);
+        }
+        path.sort_by_key(|n| std::cmp::Reverse(n.len));
+
+        // Walk from the most specific prefix to the least, stopping at the
+        // first length with an entry that matches on every key.
+        let mut result: Vec<TableEntry<D, A>> = Vec::new();
+        for node in path {
+            if result
+                .first()
+                .is_some_and(|e| lpm_len(e, self.dim) > node.len)
+            {
+                break;
+            }
+            result.extend(
+                node.entries
+                    .iter()
+                    .filter(|e| keyset_matches(keyset, &e.key))
+                    .cloned(),
+            );
+        }
+        sort_entries_by_priority(&mut result);
+        result
+    }
+}
+
+fn lpm_len<const D: usize, A: Clone>(
+    entry: &TableEntry<D, A>,
+    dim: usize,
+) -> u8 {
+    match &entry.key[dim] {
+        Key::Lpm(p) => p.len,
+        _ => 0,
+    }
+}
+
+fn lpm_mask(len: u8) -> u128 {
+    if len == 0 {
+        0
+    } else {
+        u128::MAX << (128 - u32::from(len))
+    }
+}
+
+/// The bit of `x` at position `i`, counting from the most significant bit.
+fn lpm_bit(x: u128, i: u8) -> usize {
+    ((x >> (127 - u32::from(i))) & 1) as usize
+}
+
+/// The length of the prefix `a` and `b` have in common, up to `max` bits.
+fn lpm_common(a: u128, b: u128, max: u8) -> u8 {
+    ((a ^ b).leading_zeros() as u8).min(max)
+}
+
+fn lpm_insert<const D: usize, A: Clone>(
+    node: &mut Option<Box<LpmNode<D, A>>>,
+    prefix: u128,
+    len: u8,
+    entry: &TableEntry<D, A>,
+) {
+    let Some(n) = node else {
+        *node = Some(Box::new(LpmNode {
+            prefix,
+            len,
+            entries: vec![entry.clone()],
+            children: [None, None],
+        }));
+        return;
+    };
+
+    let common = lpm_common(n.prefix, prefix, n.len.min(len));
+    if common == n.len && common == len {
+        n.entries.push(entry.clone());
+    } else if common == n.len {
+        lpm_insert(&mut n.children[lpm_bit(prefix, n.len)], prefix, len, entry);
+    } else {
+        // The new prefix diverges from this node, or is shorter than it.
+        // Put a node for the common part in its place with this node under
+        // it.
+        let old = node.take().unwrap();
+        let mut split = Box::new(LpmNode {
+            prefix: prefix & lpm_mask(common),
+            len: common,
+            entries: Vec::new(),
+            children: [None, None],
+        });
+        let old_bit = lpm_bit(old.prefix, common);
+        split.children[old_bit] = Some(old);
+        if common == len {
+            split.entries.push(entry.clone());
+        } else {
+            lpm_insert(
+                &mut split.children[lpm_bit(prefix, common)],
+                prefix,
+                len,
+                entry,
+            );
+        }
+        *node = Some(split);
+    }
+}
+
+/// Remove `entry` from the node for `prefix`, collapsing nodes that are left
+/// with no entries and at most one child. Returns false if it was not found.
+fn lpm_remove<const D: usize, A: Clone>(
+    node: &mut Option<Box<LpmNode<D, A>>>,
+    prefix: u128,
+    len: u8,
+    entry: &TableEntry<D, A>,
+) -> bool {
+    let Some(n) = node else {
+        return false;
+    };
+    if lpm_common(n.prefix, prefix, n.len.min(len)) != n.len {
+        return false;
+    }
+
+    let removed = if n.len == len {
+        let before = n.entries.len();
+        n.entries.retain(|e| e != entry);
+        n.entries.len() != before
+    } else {
+        lpm_remove(&mut n.children[lpm_bit(prefix, n.len)], prefix, len, entry)
+    };
+
+    if n.entries.is_empty() {
+        match (n.children[0].is_some(), n.children[1].is_some()) {
+            (false, false) => *node = None,
+            (true, false) => *node = n.children[0].take(),
+            (false, true) => *node = n.children[1].take(),
+            (true, true) => {}
+        }
+    }
+    removed
+}
+
+/// Collect the nodes with entries whose prefixes contain `addr`.
+fn lpm_path<'a, const D: usize, A: Clone>(
+    mut node: &'a Option<Box<LpmNode<D, A>>>,
+    addr: u128,
+    path: &mut Vec<&'a LpmNode<D, A>>,
+) {
+    while let Some(n) = node {
+        if lpm_common(n.prefix, addr, n.len) != n.len {
+            break;
+        }
+        if !n.entries.is_empty() {
+            path.push(n);
+        }
+        if n.len == 128 {
+            break;
+        }
+        node = &n.children[lpm_bit(addr, n.len)];
+    }
+}
+
+/// The key values of a keyset made up only of exact keys, or `None` if any key
+/// is of another match kind.
+fn exact_key<const D: usize>(key: &[Key; D]) -> Option<[BigUint; D]> {
+    if !key.iter().all(|k| matches!(k, Key::Exact(_))) {
+        return None;
+    }
+    Some(std::array::from_fn(|i| match &key[i] {
+        Key::Exact(k) => k.value.clone(),
+        _ => unreachable!(),
+    }))
+}
+
 pub fn keyset_matches<const D: usize>(
     selector: &[BigUint; D],
     key: &[Key; D],
@@ -326,6 +722,10 @@ pub struct TableEntry<const D: usize, A: Clone> {
     // the closure contained in `A` is hard to get at.
     pub action_id: String,
     pub parameter_data: Vec<u8>,
+
+    /// Whether the entry is one of the const entries of the program, rather
+    /// than one added at run time.
+    pub immutable: bool,
 }
 
 // TODO: Cannot hash on just the key, this does not work for multipath.
@@ -390,6 +790,7 @@ mod tests {
             action: (),
             action_id: String::new(),
             parameter_data: Vec::new(),
+            immutable: false,
         }
     }
 
@@ -409,96 +810,94 @@ mod tests {
     /// | a7     | _           | 47           | _       |
     /// +--------+-------------+--------------+---------+
     fn match_ternary_1() {
-        let table = Table::<3, ()> {
-            entries: HashSet::from([
-                tk(
-                    "a0",
-                    Ternary::Value(BigUintKey {
-                        value: 1u8.into(),
-                        width: 1,
-                    }),
-                    Ternary::DontCare,
-                    Ternary::Value(BigUintKey {
-                        value: 1u8.into(),
-                        width: 1,
-                    }),
-                    10,
-                ),
-                tk(
-                    "a1",
-                    Ternary::Value(BigUintKey {
-                        value: 1u8.into(),
-                        width: 1,
-                    }),
-                    Ternary::DontCare,
-                    Ternary::Value(BigUintKey {
-                        value: 0u8.into(),
-                        width: 1,
-                    }),
-                    1,
-                ),
-                tk(
-                    "a2",
-                    Ternary::DontCare,
-                    Ternary::Value(BigUintKey {
-                        value: 2u16.into(),
-                        width: 2,
-                    }),
-                    Ternary::DontCare,
-                    1,
-                ),
-                tk(
-                    "a3",
-                    Ternary::DontCare,
-                    Ternary::Value(BigUintKey {
-                        value: 4u16.into(),
-                        width: 2,
-                    }),
-                    Ternary::DontCare,
-                    1,
-                ),
-                tk(
-                    "a4",
-                    Ternary::DontCare,
-                    Ternary::Value(BigUintKey {
-                        value: 7u16.into(),
-                        width: 2,
-                    }),
-                    Ternary::DontCare,
-                    1,
-                ),
-                tk(
-                    "a5",
-                    Ternary::DontCare,
-                    Ternary::Value(BigUintKey {
-                        value: 19u16.into(),
-                        width: 2,
-                    }),
-                    Ternary::DontCare,
-                    1,
-                ),
-                tk(
-                    "a6",
-                    Ternary::DontCare,
-                    Ternary::Value(BigUintKey {
-                        value: 33u16.into(),
-                        width: 2,
-                    }),
-                    Ternary::DontCare,
-                    1,
-                ),
-                tk(
-                    "a7",
-                    Ternary::DontCare,
-                    Ternary::Value(BigUintKey {
-                        value: 47u16.into(),
-                        width: 2,
-                    }),
-                    Ternary::DontCare,
-                    1,
-                ),
-            ]),
-        };
+        let table = Table::<3, ()>::from_iter([
+            tk(
+                "a0",
+                Ternary::Value(BigUintKey {
+                    value: 1u8.into(),
+                    width: 1,
+                }),
+                Ternary::DontCare,
+                Ternary::Value(BigUintKey {
+                    value: 1u8.into(),
+                    width: 1,
+                }),
+                10,
+            ),
+            tk(
+                "a1",
+                Ternary::Value(BigUintKey {
+                    value: 1u8.into(),
+                    width: 1,
+                }),
+                Ternary::DontCare,
+                Ternary::Value(BigUintKey {
+                    value: 0u8.into(),
+                    width: 1,
+                }),
+                1,
+            ),
+            tk(
+                "a2",
+                Ternary::DontCare,
+                Ternary::Value(BigUintKey {
+                    value: 2u16.into(),
+                    width: 2,
+                }),
+                Ternary::DontCare,
+                1,
+            ),
+            tk(
+                "a3",
+                Ternary::DontCare,
+                Ternary::Value(BigUintKey {
+                    value: 4u16.into(),
+                    width: 2,
+                }),
+                Ternary::DontCare,
+                1,
+            ),
+            tk(
+                "a4",
+                Ternary::DontCare,
+                Ternary::Value(BigUintKey {
+                    value: 7u16.into(),
+                    width: 2,
+                }),
+                Ternary::DontCare,
+                1,
+            ),
+            tk(
+                "a5",
+                Ternary::DontCare,
+                Ternary::Value(BigUintKey {
+                    value: 19u16.into(),
+                    width: 2,
+                }),
+                Ternary::DontCare,
+                1,
+            ),
+            tk(
+                "a6",
+                Ternary::DontCare,
+                Ternary::Value(BigUintKey {
+                    value: 33u16.into(),
+                    width: 2,
+                }),
+                Ternary::DontCare,
+                1,
+            ),
+            tk(
+                "a7",
+                Ternary::DontCare,
+                Ternary::Value(BigUintKey {
+                    value: 47u16.into(),
+                    width: 2,
+                }),
+                Ternary::DontCare,
+                1,
+            ),
+        ]);
 
         //println!("M1 ~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~");
         let selector =
@@ -529,6 +928,7 @@ mod tests {
             action: (),
             action_id: String::new(),
             parameter_data: Vec::new(),
+            immutable: false,
         }
     }
 
@@ -557,32 +957,22 @@ mod tests {
     /// +--------+----------------+---------+
     fn match_lpm_1() {
         let mut table = Table::<1, ()>::new();
-        table.entries.insert(lpm("a0", "fd00:4700::", 24));
-        table.entries.insert(lpm("a1", "fd00:4701::", 32));
-        table.entries.insert(lpm("a2", "fd00:4702::", 32));
-        table.entries.insert(lpm("a3", "fd00:4701:0001::", 48));
-        table.entries.insert(lpm("a4", "fd00:4701:0002::", 48));
-        table.entries.insert(lpm("a5", "fd00:4702:0001::", 48));
-        table.entries.insert(lpm("a6", "fd00:4702:0002::", 48));
-        table.entries.insert(lpm("a7", "fd00:4701:0001:0001::", 64));
-        table.entries.insert(lpm("a8", "fd00:4701:0001:0002::", 64));
-        table.entries.insert(lpm("a9", "fd00:4702:0001:0001::", 64));
-        table
-            .entries
-            .insert(lpm("a10", "fd00:4702:0001:0002::", 64));
-        table
-            .entries
-            .insert(lpm("a11", "fd00:4701:0002:0001::", 64));
-        table
-            .entries
-            .insert(lpm("a12", "fd00:4701:0002:0002::", 64));
-        table
-            .entries
-            .insert(lpm("a13", "fd00:4702:0002:0001::", 64));
-        table
-            .entries
-            .insert(lpm("a14", "fd00:4702:0002:0002::", 64));
-        table.entries.insert(lpm("a15", "fd00:1701::", 32));
+        table.insert(lpm("a0", "fd00:4700::", 24));
+        table.insert(lpm("a1", "fd00:4701::", 32));
+        table.insert(lpm("a2", "fd00:4702::", 32));
+        table.insert(lpm("a3", "fd00:4701:0001::", 48));
+        table.insert(lpm("a4", "fd00:4701:0002::", 48));
+        table.insert(lpm("a5", "fd00:4702:0001::", 48));
+        table.insert(lpm("a6", "fd00:4702:0002::", 48));
+        table.insert(lpm("a7", "fd00:4701:0001:0001::", 64));
+        table.insert(lpm("a8", "fd00:4701:0001:0002::", 64));
+        table.insert(lpm("a9", "fd00:4702:0001:0001::", 64));
+        table.insert(lpm("a10", "fd00:4702:0001:0002::", 64));
+        table.insert(lpm("a11", "fd00:4701:0002:0001::", 64));
+        table.insert(lpm("a12", "fd00:4701:0002:0002::", 64));
+        table.insert(lpm("a13", "fd00:4702:0002:0001::", 64));
+        table.insert(lpm("a14", "fd00:4702:0002:0002::", 64));
+        table.insert(lpm("a15", "fd00:1701::", 32));
 
         let addr: Ipv6Addr = "fd00:4700::1".parse().unwrap();
         let selector = [BigUint::from(u128::from_be_bytes(addr.octets()))];
@@ -607,6 +997,52 @@ mod tests {
         assert_eq!(matches[0].name.as_str(), "a14");
     }
 
+    #[test]
+    fn remove_all_matching_keeps_indexes() {
+        let mut table = Table::<1, ()>::new();
+        table.insert(lpm("a0", "fd00:4700::", 24));
+        table.insert(lpm("a1", "fd00:4701::", 32));
+        table.insert(lpm("b0", "fd00:4701:0001::", 48));
+        table.insert(lpm("b1", "fd00:4702::", 32));
+
+        let removed = table.remove_all_matching(|e| e.name.starts_with('b'));
+        assert_eq!(removed.len(), 2);
+        assert_eq!(table.entries.len(), 2);
+
+        // the more specific b0 is gone from the trie as well
+        let addr: Ipv6Addr = "fd00:4701:0001::1".parse().unwrap();
+        let selector = [BigUint::from(u128::from_be_bytes(addr.octets()))];
+        let matches = table.match_selector(&selector);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].name, "a1");
+    }
+
+    #[test]
+    fn prefix_round_trip() {
+        for cidr in ["10.0.0.0/8", "fd00::/24"] {
+            let prefix: Prefix = cidr.parse().unwrap();
+            assert_eq!(prefix.to_string(), cidr);
+        }
+
+        let prefix: Prefix = "fd00::/24".parse().unwrap();
+        assert_eq!(prefix.addr, "fd00::".parse::<IpAddr>().unwrap());
+        assert_eq!(prefix.len, 24);
+
+        // host bits are rejected when parsing and cleared when masking
+        let addr: IpAddr = "10.1.2.3".parse().unwrap();
+        assert_eq!(
+            "10.1.2.3/8".parse::<Prefix>(),
+            Err(PrefixError::HostBits { addr, len: 8 }),
+        );
+        let masked = Prefix::masked(addr, 8).unwrap();
+        assert_eq!(masked.to_string(), "10.0.0.0/8");
+        assert_eq!(
+            "10.0.0.0/33".parse::<Prefix>(),
+            Err(PrefixError::Length { len: 33, max: 32 }),
+        );
+        assert!("fd00::".parse::<Prefix>().is_err());
+    }
+
     fn tlpm(
         name: &str,
         addr: &str,
@@ -627,46 +1063,45 @@ mod tests {
             action: (),
             action_id: String::new(),
             parameter_data: Vec::new(),
+            immutable: false,
         }
     }
 
     #[test]
     fn match_lpm_ternary_1() {
-        let table = Table::<2, ()> {
-            entries: HashSet::from([
-                tlpm("a0", "fd00:1::", 64, Ternary::DontCare, 1),
-                tlpm(
-                    "a1",
-                    "fd00:1::",
-                    64,
-                    Ternary::Value(BigUintKey {
-                        value: 1u16.into(),
-                        width: 2,
-                    }),
-                    10,
-                ),
-                tlpm(
-                    "a2",
-                    "fd00:1::",
-                    64,
-                    Ternary::Value(BigUintKey {
-                        value: 2u16.into(),
-                        width: 2,
-                    }),
-                    10,
-                ),
-                tlpm(
-                    "a3",
-                    "fd00:1::",
-                    64,
-                    Ternary::Value(BigUintKey {
-                        value: 3u16.into(),
-                        width: 2,
-                    }),
-                    10,
-                ),
-            ]),
-        };
+        let table = Table::<2, ()>::from_iter([
+            tlpm("a0", "fd00:1::", 64, Ternary::DontCare, 1),
+            tlpm(
+                "a1",
+                "fd00:1::",
+                64,
+                Ternary::Value(BigUintKey {
+                    value: 1u16.into(),
+                    width: 2,
+                }),
+                10,
+            ),
+            tlpm(
+                "a2",
+                "fd00:1::",
+                64,
+                Ternary::Value(BigUintKey {
+                    value: 2u16.into(),
+                    width: 2,
+                }),
+                10,
+            ),
+            tlpm(
+                "a3",
+                "fd00:1::",
+                64,
+                Ternary::Value(BigUintKey {
+                    value: 3u16.into(),
+                    width: 2,
+                }),
+                10,
+            ),
+        ]);
 
         let dst: Ipv6Addr = "fd00:1::1".parse().unwrap();
         let selector = [
@@ -719,6 +1154,7 @@ mod tests {
             action: (),
             action_id: String::new(),
             parameter_data: Vec::new(),
+            immutable: false,
         }
     }
 
@@ -728,42 +1164,24 @@ mod tests {
 
     #[test]
     fn match_lpm_ternary_range() {
-        let table = Table::<4, ()> {
-            entries: HashSet::from([
-                lpre("a0", "fd00:1::", 64, Ternary::DontCare, (80, 80), 100, 1),
-                lpre(
-                    "a1",
-                    "fd00:1::",
-                    64,
-                    Ternary::DontCare,
-                    (443, 443),
-                    100,
-                    1,
-                ),
-                lpre("a2", "fd00:1::", 64, Ternary::DontCare, (80, 80), 200, 1),
-                lpre(
-                    "a3",
-                    "fd00:1::",
-                    64,
-                    Ternary::DontCare,
-                    (443, 443),
-                    200,
-                    1,
-                ),
-                lpre(
-                    "a4",
-                    "fd00:1::",
-                    64,
-                    Ternary::Value(BigUintKey {
-                        value: 99u16.into(),
-                        width: 2,
-                    }),
-                    (443, 443),
-                    200,
-                    10,
-                ),
-            ]),
-        };
+        let table = Table::<4, ()>::from_iter([
+            lpre("a0", "fd00:1::", 64, Ternary::DontCare, (80, 80), 100, 1),
+            lpre("a1", "fd00:1::", 64, Ternary::DontCare, (443, 443), 100, 1),
+            lpre("a2", "fd00:1::", 64, Ternary::DontCare, (80, 80), 200, 1),
+            lpre("a3", "fd00:1::", 64, Ternary::DontCare, (443, 443), 200, 1),
+            lpre(
+                "a4",
+                "fd00:1::",
+                64,
+                Ternary::Value(BigUintKey {
+                    value: 99u16.into(),
+                    width: 2,
+                }),
+                (443, 443),
+                200,
+                10,
+            ),
+        ]);
         let dst: Ipv6Addr = "fd00:1::1".parse().unwrap();
         let selector = [
             BigUint::from(u128::from_le_bytes(dst.octets())),
@@ -806,36 +1224,36 @@ mod tests {
     fn match_with_action() {
         let mut data = ActionData { value: 47 };
 
-        let table = Table::<1, Arc<dyn Fn(&mut ActionData)>> {
-            entries: HashSet::from([
-                TableEntry::<1, Arc<dyn Fn(&mut ActionData)>> {
-                    key: [Key::Exact(BigUintKey {
-                        value: 1u8.into(),
-                        width: 1,
-                    })],
-                    priority: 0,
-                    name: "a0".into(),
-                    action: Arc::new(|a: &mut ActionData| {
-                        a.value += 10;
-                    }),
-                    action_id: String::new(),
-                    parameter_data: Vec::new(),
-                },
-                TableEntry::<1, Arc<dyn Fn(&mut ActionData)>> {
-                    key: [Key::Exact(BigUintKey {
-                        value: 2u8.into(),
-                        width: 1,
-                    })],
-                    priority: 0,
-                    name: "a1".into(),
-                    action: Arc::new(|a: &mut ActionData| {
-                        a.value -= 10;
-                    }),
-                    action_id: String::new(),
-                    parameter_data: Vec::new(),
-                },
-            ]),
-        };
+        let table = Table::<1, Arc<dyn Fn(&mut ActionData)>>::from_iter([
+            TableEntry::<1, Arc<dyn Fn(&mut ActionData)>> {
+                key: [Key::Exact(BigUintKey {
+                    value: 1u8.into(),
+                    width: 1,
+                })],
+                priority: 0,
+                name: "a0".into(),
+                action: Arc::new(|a: &mut ActionData| {
+                    a.value += 10;
+                }),
+                action_id: String::new(),
+                parameter_data: Vec::new(),
+                immutable: false,
+            },
+            TableEntry::<1, Arc<dyn Fn(&mut ActionData)>> {
+                key: [Key::Exact(BigUintKey {
+                    value: 2u8.into(),
+                    width: 1,
+                })],
+                priority: 0,
+                name: "a1".into(),
+                action: Arc::new(|a: &mut ActionData| {
+                    a.value -= 10;
+                }),
+                action_id: String::new(),
+                parameter_data: Vec::new(),
+                immutable: false,
+            },
+        ]);
 
         let selector = [BigUint::from(1u8)];
         let matches = table.match_selector(&selector);
@@ -844,4 +1262,202 @@ mod tests {
         (matches[0].action)(&mut data);
         assert_eq!(data.value, 57);
     }
+
+    #[test]
+    fn match_exact_indexed() {
+        const N: u32 = 1_000;
+        let table = Table::<1, ()>::from_iter((0..N).map(|i| TableEntry {
+            key: [Key::Exact(BigUintKey {
+                value: i.into(),
+                width: 4,
+            })],
+            priority: 0,
+            name: format!("a{}", i),
+            action: (),
+            action_id: String::new(),
+            parameter_data: Vec::new(),
+            immutable: false,
+        }));
+
+        let selectors: Vec<[BigUint; 1]> =
+            (0..20u32).map(|i| [BigUint::from(i * 49 + 3)]).collect();
+
+        let indexed: Vec<_> =
+            selectors.iter().map(|s| table.match_selector(s)).collect();
+        let linear: Vec<Vec<_>> = selectors
+            .iter()
+            .map(|s| {
+                table
+                    .entries
+                    .iter()
+                    .filter(|e| keyset_matches(s, &e.key))
+                    .cloned()
+                    .collect()
+            })
+            .collect();
+
+        for (s, (a, b)) in selectors.iter().zip(indexed.iter().zip(&linear)) {
+            assert_eq!(a.len(), 1);
+            assert_eq!(a[0].name, format!("a{}", s[0]));
+            assert_eq!(a[0].name, b[0].name);
+        }
+        let miss = table.match_selector(&[BigUint::from(N)]);
+        assert!(miss.is_empty());
+    }
+
+    /// A linear match, as done for tables without an index.
+    fn linear_match<const D: usize, A: Clone>(
+        table: &Table<D, A>,
+        selector: &[BigUint; D],
+    ) -> Vec<TableEntry<D, A>> {
+        let entries = table
+            .entries
+            .iter()
+            .filter(|e| keyset_matches(selector, &e.key))
+            .cloned()
+            .collect();
+        sort_entries(entries)
+    }
+
+    /// A deterministic stream of pseudo random numbers.
+    fn lcg(seed: &mut u64) -> u64 {
+        *seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        *seed
+    }
+
+    /// An address that differs from a fixed one in at most two random bits,
+    /// so that prefixes of different lengths often nest.
+    fn near_addr(seed: &mut u64, v4: bool) -> IpAddr {
+        let bits = if v4 { 32 } else { 128 };
+        let root = 0xfd00_4701_0002_0003_0000_0000_c0a8_0001u128;
+        let a = root
+            ^ (1u128 << (lcg(seed) % bits))
+            ^ (1u128 << (lcg(seed) % bits));
+        if v4 {
+            IpAddr::V4((a as u32).into())
+        } else {
+            IpAddr::V6(a.into())
+        }
+    }
+
+    #[test]
+    fn match_lpm_trie_most_specific() {
+        for v4 in [true, false] {
+            let mut seed = 47;
+            let mut table = Table::<2, ()>::new();
+            let mut i = 0;
+            for len in [0u8, 8, 16, 17, 24, 31, 32, 48, 64, 96, 127, 128] {
+                if v4 && len > 32 {
+                    continue;
+                }
+                for _ in 0..20 {
+                    let zone = if i % 3 == 0 {
+                        Ternary::DontCare
+                    } else {
+                        Ternary::Value(BigUintKey {
+                            value: (i % 3).into(),
+                            width: 1,
+                        })
+                    };
+                    let addr = near_addr(&mut seed, v4);
+                    let name = format!("a{}", i);
+                    table.insert(tlpm_addr(&name, addr, len, zone, i));
+                    i += 1;
+                }
+            }
+
+            let check = |table: &Table<2, ()>, seed: &mut u64| {
+                for n in 0..1000u32 {
+                    let a = match near_addr(seed, v4) {
+                        IpAddr::V4(a) => BigUint::from(u32::from(a)),
+                        IpAddr::V6(a) => BigUint::from(u128::from(a)),
+                    };
+                    let selector = [a, BigUint::from(n % 3)];
+                    let trie: Vec<_> = table
+                        .match_selector(&selector)
+                        .into_iter()
+                        .map(|e| e.name)
+                        .collect();
+                    let linear: Vec<_> = linear_match(table, &selector)
+                        .into_iter()
+                        .map(|e| e.name)
+                        .collect();
+                    assert_eq!(trie, linear, "{:?}", selector);
+                }
+            };
+            check(&table, &mut seed);
+
+            // remove every other entry, the trie must stay consistent
+            let removed: Vec<_> =
+                table.entries.iter().step_by(2).cloned().collect();
+            for e in &removed {
+                assert!(table.remove(e));
+                assert!(!table.remove(e));
+            }
+            check(&table, &mut seed);
+        }
+    }
+
+    fn tlpm_addr(
+        name: &str,
+        addr: IpAddr,
+        len: u8,
+        zone: Ternary,
+        priority: u32,
+    ) -> TableEntry<2, ()> {
+        TableEntry::<2, ()> {
+            key: [Key::Lpm(Prefix { addr, len }), Key::Ternary(zone)],
+            priority,
+            name: name.into(),
+            action: (),
+            action_id: String::new(),
+            parameter_data: Vec::new(),
+            immutable: false,
+        }
+    }
+
+    #[test]
+    #[ignore = "inserts 500k routes, run with --ignored"]
+    fn match_lpm_trie_large() {
+        const N: usize = 500_000;
+        let mut seed = 1701;
+        let mut table = Table::<1, ()>::new();
+        for i in 0..N {
+            let addr: u128 =
+                (0xfd00u128 << 112) | (u128::from(lcg(&mut seed)) << 48);
+            let len = 48 + (i % 17) as u8;
+            table.insert(lpm(
+                &format!("a{}", i),
+                &Ipv6Addr::from(addr).to_string(),
+                len,
+            ));
+        }
+
+        let selectors: Vec<[BigUint; 1]> = table
+            .entries
+            .iter()
+            .take(5)
+            .map(|e| match &e.key[0] {
+                Key::Lpm(Prefix {
+                    addr: IpAddr::V6(a),
+                    ..
+                }) => [BigUint::from(u128::from(*a) | 1)],
+                _ => unreachable!(),
+            })
+            .collect();
+
+        let trie: Vec<_> =
+            selectors.iter().map(|s| table.match_selector(s)).collect();
+        let linear: Vec<_> =
+            selectors.iter().map(|s| linear_match(&table, s)).collect();
+
+        for (a, b) in trie.iter().zip(&linear) {
+            assert!(!a.is_empty());
+            let a: Vec<_> = a.iter().map(|e| &e.name).collect();
+            let b: Vec<_> = b.iter().map(|e| &e.name).collect();
+            assert_eq!(a, b);
+        }
+    }
 }
@@ -5,12 +5,15 @@ use std::collections::HashMap;
 use std::fmt;
 use std::hash::{Hash, Hasher};
 
-use crate::lexer::Token;
+use serde::{Serialize, Serializer};
+
+use crate::lexer::{Kind, Token};
 
 #[derive(Debug, Default)]
 pub struct AST {
     pub constants: Vec<Constant>,
     pub headers: Vec<Header>,
+    pub header_unions: Vec<HeaderUnion>,
     pub structs: Vec<Struct>,
     pub typedefs: Vec<Typedef>,
     pub controls: Vec<Control>,
@@ -18,11 +21,16 @@ pub struct AST {
     pub packages: Vec<Package>,
     pub package_instance: Option<PackageInstance>,
     pub externs: Vec<Extern>,
+
+    /// Members of the `error` namespace, collected from all `error { ... }`
+    /// declarations in the program.
+    pub errors: Vec<String>,
 }
 
 pub enum UserDefinedType<'a> {
     Struct(&'a Struct),
     Header(&'a Header),
+    HeaderUnion(&'a HeaderUnion),
     Extern(&'a Extern),
 }
 
@@ -35,6 +43,10 @@ impl AST {
         self.headers.iter().find(|&h| h.name == name)
     }
 
+    pub fn get_header_union(&self, name: &str) -> Option<&HeaderUnion> {
+        self.header_unions.iter().find(|&u| u.name == name)
+    }
+
     pub fn get_extern(&self, name: &str) -> Option<&Extern> {
         self.externs.iter().find(|&e| e.name == name)
     }
@@ -47,6 +59,10 @@ impl AST {
         self.parsers.iter().find(|&p| p.name == name)
     }
 
+    pub fn is_error(&self, name: &str) -> bool {
+        self.errors.iter().any(|e| e == name)
+    }
+
     pub fn get_user_defined_type(&self, name: &str) -> Option<UserDefinedType> {
         if let Some(user_struct) = self.get_struct(name) {
             return Some(UserDefinedType::Struct(user_struct));
@@ -54,6 +70,9 @@ impl AST {
         if let Some(user_header) = self.get_header(name) {
             return Some(UserDefinedType::Header(user_header));
         }
+        if let Some(user_union) = self.get_header_union(name) {
+            return Some(UserDefinedType::HeaderUnion(user_union));
+        }
         if let Some(platform_extern) = self.get_extern(name) {
             return Some(UserDefinedType::Extern(platform_extern));
         }
@@ -67,6 +86,9 @@ impl AST {
         for h in &self.headers {
             h.accept(v);
         }
+        for u in &self.header_unions {
+            u.accept(v);
+        }
         for s in &self.structs {
             s.accept(v);
         }
@@ -97,6 +119,9 @@ impl AST {
         for h in &self.headers {
             h.accept_mut(v);
         }
+        for u in &self.header_unions {
+            u.accept_mut(v);
+        }
         for s in &self.structs {
             s.accept_mut(v);
         }
@@ -127,6 +152,9 @@ impl AST {
         for h in &mut self.headers {
             h.mut_accept(v);
         }
+        for u in &mut self.header_unions {
+            u.mut_accept(v);
+        }
         for s in &mut self.structs {
             s.mut_accept(v);
         }
@@ -157,6 +185,9 @@ impl AST {
         for h in &mut self.headers {
             h.mut_accept_mut(v);
         }
+        for u in &mut self.header_unions {
+            u.mut_accept_mut(v);
+        }
         for s in &mut self.structs {
             s.mut_accept_mut(v);
         }
@@ -308,6 +339,8 @@ pub enum Type {
     State,
     Action,
     HeaderMethod,
+    /// A header stack `T[N]` of `N` headers of type `T`.
+    HeaderStack(String, usize),
 }
 
 impl Type {
@@ -348,6 +381,13 @@ impl Type {
     }
 }
 
+/// Types serialize as their P4 source form, e.g. `bit<128>`.
+impl Serialize for Type {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
 impl fmt::Display for Type {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self {
@@ -364,10 +404,14 @@ impl fmt::Display for Type {
             Type::State => write!(f, "state"),
             Type::Action => write!(f, "action"),
             Type::HeaderMethod => write!(f, "header method"),
+            Type::HeaderStack(name, size) => write!(f, "{}[{}]", name, size),
             Type::List(elems) => {
-                write!(f, "list<")?;
-                for e in elems {
-                    write!(f, "{},", e)?;
+                write!(f, "tuple<")?;
+                for (i, e) in elems.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", e)?;
                 }
                 write!(f, ">")
             }
@@ -408,6 +452,7 @@ pub struct Constant {
     pub ty: Type,
     pub name: String,
     pub initializer: Box<Expression>,
+    pub annotations: Vec<Annotation>,
 }
 
 impl Constant {
@@ -491,7 +536,7 @@ impl Variable {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Expression {
     pub token: Token,
     pub kind: ExpressionKind,
@@ -525,6 +570,11 @@ impl Expression {
                     xp.accept(v);
                 }
             }
+            ExpressionKind::StructLit(members) => {
+                for (_, xp) in members {
+                    xp.accept(v);
+                }
+            }
             _ => {} // covered by top level visit
         }
     }
@@ -552,6 +602,11 @@ impl Expression {
                     xp.accept_mut(v);
                 }
             }
+            ExpressionKind::StructLit(members) => {
+                for (_, xp) in members {
+                    xp.accept_mut(v);
+                }
+            }
             _ => {} // covered by top level visit
         }
     }
@@ -579,6 +634,11 @@ impl Expression {
                     xp.mut_accept(v);
                 }
             }
+            ExpressionKind::StructLit(members) => {
+                for (_, xp) in members {
+                    xp.mut_accept(v);
+                }
+            }
             _ => {} // covered by top level visit
         }
     }
@@ -606,6 +666,11 @@ impl Expression {
                     xp.mut_accept_mut(v);
                 }
             }
+            ExpressionKind::StructLit(members) => {
+                for (_, xp) in members {
+                    xp.mut_accept_mut(v);
+                }
+            }
             _ => {} // covered by top level visit
         }
     }
@@ -625,7 +690,7 @@ impl PartialEq for Expression {
 
 impl Eq for Expression {}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum ExpressionKind {
     BoolLit(bool),
     IntegerLit(i128),
@@ -637,12 +702,21 @@ pub enum ExpressionKind {
     Slice(Box<Expression>, Box<Expression>),
     Call(Call),
     List(Vec<Box<Expression>>),
+    /// A struct value, `{ a = x, b = y }`, with the member each expression
+    /// initializes.
+    StructLit(Vec<(String, Box<Expression>)>),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum BinOp {
     Add,
     Subtract,
+    /// `|+|`, addition that clamps to the largest value of the type rather
+    /// than wrapping around.
+    SaturatingAdd,
+    /// `|-|`, subtraction that clamps to the smallest value of the type
+    /// rather than wrapping around.
+    SaturatingSubtract,
     Mod,
     Geq,
     Gt,
@@ -661,6 +735,8 @@ impl BinOp {
         match self {
             BinOp::Add => "add",
             BinOp::Subtract => "subtract",
+            BinOp::SaturatingAdd => "saturating add",
+            BinOp::SaturatingSubtract => "saturating subtract",
             BinOp::Mod => "mod",
             BinOp::Geq | BinOp::Gt | BinOp::Leq | BinOp::Lt | BinOp::Eq => {
                 "compare"
@@ -673,6 +749,19 @@ impl BinOp {
         }
     }
 
+    /// Returns true if this operator produces a boolean from its operands.
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            BinOp::Geq
+                | BinOp::Gt
+                | BinOp::Leq
+                | BinOp::Lt
+                | BinOp::Eq
+                | BinOp::NotEq
+        )
+    }
+
     pub fn accept<V: Visitor>(&self, v: &V) {
         v.binop(self);
     }
@@ -726,6 +815,15 @@ impl Header {
                 decl: DeclarationInfo::Method,
             },
         );
+        for method in Self::SIZE_METHODS {
+            names.insert(
+                method.into(),
+                NameInfo {
+                    ty: Type::HeaderMethod,
+                    decl: DeclarationInfo::Method,
+                },
+            );
+        }
         for p in &self.members {
             names.insert(
                 p.name.clone(),
@@ -738,6 +836,53 @@ impl Header {
         names
     }
 
+    /// Methods that query the size of a header. The size of a header is known
+    /// at compile time, varbit members only make the minimum and maximum sizes
+    /// differ.
+    pub const SIZE_METHODS: [&'static str; 4] = [
+        "minSizeInBits",
+        "minSizeInBytes",
+        "maxSizeInBits",
+        "maxSizeInBytes",
+    ];
+
+    /// Evaluate one of the `SIZE_METHODS`.
+    pub fn size_query(&self, method: &str) -> Option<usize> {
+        let (bits, max) = match method {
+            "minSizeInBits" => (true, false),
+            "minSizeInBytes" => (false, false),
+            "maxSizeInBits" => (true, true),
+            "maxSizeInBytes" => (false, true),
+            _ => return None,
+        };
+        let size: usize = self
+            .members
+            .iter()
+            .map(|m| match m.ty {
+                Type::Bit(n) | Type::Int(n) => n,
+                Type::Varbit(n) if max => n,
+                Type::Bool => 1,
+                _ => 0,
+            })
+            .sum();
+        Some(if bits { size } else { (size + 7) >> 3 })
+    }
+
+    /// Names of the methods available on a header stack.
+    pub fn stack_names() -> HashMap<String, NameInfo> {
+        let mut names = HashMap::new();
+        for method in ["push_front", "pop_front"] {
+            names.insert(
+                method.into(),
+                NameInfo {
+                    ty: Type::HeaderMethod,
+                    decl: DeclarationInfo::Method,
+                },
+            );
+        }
+        names
+    }
+
     pub fn accept<V: Visitor>(&self, v: &V) {
         v.header(self);
         for m in &self.members {
@@ -796,6 +941,72 @@ impl HeaderMember {
     }
 }
 
+/// A header union holds a set of alternative headers of which at most one may
+/// be valid at any given time.
+#[derive(Debug, Clone)]
+pub struct HeaderUnion {
+    pub name: String,
+    pub members: Vec<StructMember>,
+}
+
+impl HeaderUnion {
+    pub fn new(name: String) -> Self {
+        HeaderUnion {
+            name,
+            members: Vec::new(),
+        }
+    }
+
+    pub fn names(&self) -> HashMap<String, NameInfo> {
+        let mut names = HashMap::new();
+        names.insert(
+            "isValid".into(),
+            NameInfo {
+                ty: Type::HeaderMethod,
+                decl: DeclarationInfo::Method,
+            },
+        );
+        for m in &self.members {
+            names.insert(
+                m.name.clone(),
+                NameInfo {
+                    ty: m.ty.clone(),
+                    decl: DeclarationInfo::StructMember,
+                },
+            );
+        }
+        names
+    }
+
+    pub fn accept<V: Visitor>(&self, v: &V) {
+        v.header_union(self);
+        for m in &self.members {
+            m.accept(v);
+        }
+    }
+
+    pub fn accept_mut<V: VisitorMut>(&self, v: &mut V) {
+        v.header_union(self);
+        for m in &self.members {
+            m.accept_mut(v);
+        }
+    }
+
+    pub fn mut_accept<V: MutVisitor>(&mut self, v: &V) {
+        v.header_union(self);
+        for m in &mut self.members {
+            m.mut_accept(v);
+        }
+    }
+
+    pub fn mut_accept_mut<V: MutVisitorMut>(&mut self, v: &mut V) {
+        v.header_union(self);
+        for m in &mut self.members {
+            m.mut_accept_mut(v);
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Struct {
     pub name: String,
@@ -857,6 +1068,7 @@ pub struct StructMember {
     pub ty: Type,
     pub name: String,
     pub token: Token,
+    pub annotations: Vec<Annotation>,
 }
 
 impl StructMember {
@@ -1011,6 +1223,22 @@ impl Control {
                 },
             );
         }
+        for f in ["assert", "assume"] {
+            names.insert(
+                f.into(),
+                NameInfo {
+                    ty: Type::ExternFunction,
+                    decl: DeclarationInfo::Method,
+                },
+            );
+        }
+        names.insert(
+            "error".into(),
+            NameInfo {
+                ty: Type::Error,
+                decl: DeclarationInfo::Local,
+            },
+        );
         names
     }
 
@@ -1116,6 +1344,7 @@ pub struct Parser {
     pub parameters: Vec<ControlParameter>,
     pub states: Vec<State>,
     pub decl_only: bool,
+    pub annotations: Vec<Annotation>,
 
     /// The first token of this parser, used for error reporting.
     pub token: Token,
@@ -1129,6 +1358,7 @@ impl Parser {
             parameters: Vec::new(),
             states: Vec::new(),
             decl_only: false,
+            annotations: Vec::new(),
             token,
         }
     }
@@ -1162,6 +1392,20 @@ impl Parser {
                 },
             );
         }
+        names.insert(
+            "verify".into(),
+            NameInfo {
+                ty: Type::ExternFunction,
+                decl: DeclarationInfo::Method,
+            },
+        );
+        names.insert(
+            "error".into(),
+            NameInfo {
+                ty: Type::Error,
+                decl: DeclarationInfo::Local,
+            },
+        );
         names
     }
 
@@ -1243,7 +1487,7 @@ impl ControlParameter {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum Direction {
     In,
     Out,
@@ -1269,14 +1513,18 @@ pub struct Action {
     pub name: String,
     pub parameters: Vec<ActionParameter>,
     pub statement_block: StatementBlock,
+    pub annotations: Vec<Annotation>,
+    pub token: Token,
 }
 
 impl Action {
-    pub fn new(name: String) -> Self {
+    pub fn new(name: String, token: Token) -> Self {
         Self {
             name,
             parameters: Vec::new(),
             statement_block: StatementBlock::default(),
+            annotations: Vec::new(),
+            token,
         }
     }
 
@@ -1367,14 +1615,69 @@ impl ActionParameter {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct Annotation {
+    pub name: String,
+    /// The unparsed tokens between the parentheses of the annotation, if any.
+    pub body: Vec<Token>,
+    pub token: Token,
+}
+
+impl Annotation {
+    /// The integer literals in the body of the annotation, e.g. `[1, 2]` for
+    /// `@field_list(1, 2)`.
+    pub fn integer_args(&self) -> Vec<i128> {
+        self.body
+            .iter()
+            .filter_map(|t| match t.kind {
+                Kind::IntLiteral(n) => Some(n),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The string literals in the body of the annotation, e.g. `["unused"]`
+    /// for `@noWarn("unused")`.
+    pub fn string_args(&self) -> Vec<&str> {
+        self.body
+            .iter()
+            .filter_map(|t| match &t.kind {
+                Kind::StringLiteral(s) => Some(s.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The body of the annotation as a dotted name, e.g. `ingress.port` for
+    /// `@metadata_default(ingress.port)`. `None` if the body is anything else.
+    pub fn lvalue_arg(&self) -> Option<String> {
+        let mut name = String::new();
+        for (i, t) in self.body.iter().enumerate() {
+            match (&t.kind, i % 2) {
+                (Kind::Identifier(s), 0) => name.push_str(s),
+                (Kind::Dot, 1) => name.push('.'),
+                _ => return None,
+            }
+        }
+        if name.is_empty() || name.ends_with('.') {
+            return None;
+        }
+        Some(name)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Table {
     pub name: String,
     pub actions: Vec<Lvalue>,
+    /// Annotations attached to entries of the actions list, keyed by action
+    /// name.
+    pub action_annotations: HashMap<String, Vec<Annotation>>,
     pub default_action: String,
     pub key: Vec<(Lvalue, MatchKind)>,
     pub const_entries: Vec<ConstTableEntry>,
     pub size: usize,
+    pub annotations: Vec<Annotation>,
     pub token: Token,
 }
 
@@ -1383,14 +1686,35 @@ impl Table {
         Self {
             name,
             actions: Vec::new(),
+            action_annotations: HashMap::new(),
             default_action: String::new(),
             key: Vec::new(),
             const_entries: Vec::new(),
             size: 0,
+            annotations: Vec::new(),
             token,
         }
     }
 
+    pub fn has_annotation(&self, annotation: &str) -> bool {
+        self.annotations.iter().any(|a| a.name == annotation)
+    }
+
+    /// Returns true if the reference to `action` in this table's actions list
+    /// carries an annotation with the given name.
+    pub fn action_has_annotation(
+        &self,
+        action: &str,
+        annotation: &str,
+    ) -> bool {
+        match self.action_annotations.get(action) {
+            Some(annotations) => {
+                annotations.iter().any(|a| a.name == annotation)
+            }
+            None => false,
+        }
+    }
+
     pub fn accept<V: Visitor>(&self, v: &V) {
         v.table(self);
         for a in &self.actions {
@@ -1452,9 +1776,19 @@ impl Table {
 pub struct ConstTableEntry {
     pub keyset: Vec<KeySetElement>,
     pub action: ActionRef,
+    pub annotations: Vec<Annotation>,
 }
 
 impl ConstTableEntry {
+    /// The value of this entry's `@priority` annotation, if it has one.
+    pub fn priority(&self) -> Option<u32> {
+        self.annotations
+            .iter()
+            .find(|a| a.name == "priority")
+            .and_then(|a| a.integer_args().first().copied())
+            .and_then(|n| u32::try_from(n).ok())
+    }
+
     pub fn accept<V: Visitor>(&self, v: &V) {
         v.const_table_entry(self);
         for k in &self.keyset {
@@ -1599,6 +1933,10 @@ impl KeySetElementValue {
 pub struct ActionRef {
     pub name: String,
     pub parameters: Vec<Box<Expression>>,
+    /// The names of the parameters `parameters` are for, when they are given
+    /// by name and could not be put in the order the action declares them.
+    /// Empty for positional arguments.
+    pub parameter_names: Vec<String>,
 
     pub token: Token,
 }
@@ -1609,6 +1947,7 @@ impl ActionRef {
             name,
             token,
             parameters: Vec::new(),
+            parameter_names: Vec::new(),
         }
     }
 
@@ -1671,6 +2010,9 @@ impl MatchKind {
 pub enum Statement {
     Empty,
     Assignment(Lvalue, Box<Expression>),
+    /// Assignment of the elements of a tuple to a list of lvalues, e.g.
+    /// `(value, even) = digest.compute(x);`.
+    TupleAssignment(Vec<Lvalue>, Box<Expression>),
     //TODO get rid of this in favor of ExpressionKind::Call ???
     Call(Call),
     If(IfBlock),
@@ -1678,6 +2020,7 @@ pub enum Statement {
     Constant(Constant),
     Transition(Transition),
     Return(Option<Box<Expression>>),
+    Block(BlockStatement),
     // TODO ...
 }
 
@@ -1690,6 +2033,12 @@ impl Statement {
                 lval.accept(v);
                 xpr.accept(v);
             }
+            Statement::TupleAssignment(lvals, xpr) => {
+                for lval in lvals {
+                    lval.accept(v);
+                }
+                xpr.accept(v);
+            }
             Statement::Call(call) => call.accept(v),
             Statement::If(if_block) => if_block.accept(v),
             Statement::Variable(var) => var.accept(v),
@@ -1700,6 +2049,11 @@ impl Statement {
                     rx.accept(v);
                 }
             }
+            Statement::Block(blk) => {
+                for s in &blk.block.statements {
+                    s.accept(v);
+                }
+            }
         }
     }
 
@@ -1711,6 +2065,12 @@ impl Statement {
                 lval.accept_mut(v);
                 xpr.accept_mut(v);
             }
+            Statement::TupleAssignment(lvals, xpr) => {
+                for lval in lvals {
+                    lval.accept_mut(v);
+                }
+                xpr.accept_mut(v);
+            }
             Statement::Call(call) => call.accept_mut(v),
             Statement::If(if_block) => if_block.accept_mut(v),
             Statement::Variable(var) => var.accept_mut(v),
@@ -1721,6 +2081,11 @@ impl Statement {
                     rx.accept_mut(v);
                 }
             }
+            Statement::Block(blk) => {
+                for s in &blk.block.statements {
+                    s.accept_mut(v);
+                }
+            }
         }
     }
 
@@ -1732,6 +2097,12 @@ impl Statement {
                 lval.mut_accept(v);
                 xpr.mut_accept(v);
             }
+            Statement::TupleAssignment(lvals, xpr) => {
+                for lval in lvals {
+                    lval.mut_accept(v);
+                }
+                xpr.mut_accept(v);
+            }
             Statement::Call(call) => call.mut_accept(v),
             Statement::If(if_block) => if_block.mut_accept(v),
             Statement::Variable(var) => var.mut_accept(v),
@@ -1742,6 +2113,11 @@ impl Statement {
                     rx.mut_accept(v);
                 }
             }
+            Statement::Block(blk) => {
+                for s in &mut blk.block.statements {
+                    s.mut_accept(v);
+                }
+            }
         }
     }
 
@@ -1753,6 +2129,12 @@ impl Statement {
                 lval.mut_accept_mut(v);
                 xpr.mut_accept_mut(v);
             }
+            Statement::TupleAssignment(lvals, xpr) => {
+                for lval in lvals {
+                    lval.mut_accept_mut(v);
+                }
+                xpr.mut_accept_mut(v);
+            }
             Statement::Call(call) => call.mut_accept_mut(v),
             Statement::If(if_block) => if_block.mut_accept_mut(v),
             Statement::Variable(var) => var.mut_accept_mut(v),
@@ -1763,10 +2145,23 @@ impl Statement {
                     rx.mut_accept_mut(v);
                 }
             }
+            Statement::Block(blk) => {
+                for s in &mut blk.block.statements {
+                    s.mut_accept_mut(v);
+                }
+            }
         }
     }
 }
 
+/// A nested statement block, such as the body of an `@atomic { ... }`
+/// section.
+#[derive(Debug, Clone)]
+pub struct BlockStatement {
+    pub annotations: Vec<Annotation>,
+    pub block: StatementBlock,
+}
+
 #[derive(Debug, Clone)]
 pub struct IfBlock {
     pub predicate: Box<Expression>,
@@ -1882,7 +2277,7 @@ impl ElseIfBlock {
 }
 
 /// A function or method call
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Call {
     pub lval: Lvalue,
     pub args: Vec<Box<Expression>>,
@@ -1922,7 +2317,7 @@ impl Call {
     }
 }
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize)]
 pub struct Lvalue {
     pub name: String,
     pub token: Token,
@@ -1935,6 +2330,14 @@ impl Lvalue {
     pub fn root(&self) -> &str {
         self.parts()[0]
     }
+    /// Split a path segment such as `vlan[1]` into its name and header stack
+    /// index.
+    pub fn split_index(part: &str) -> (&str, Option<usize>) {
+        match part.strip_suffix(']').and_then(|p| p.split_once('[')) {
+            Some((name, index)) => (name, index.parse().ok()),
+            None => (part, None),
+        }
+    }
     pub fn leaf(&self) -> &str {
         let parts = self.parts();
         parts[parts.len() - 1]
@@ -2253,7 +2656,7 @@ impl ExternMethod {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub enum DeclarationInfo {
     Parameter(Direction),
     Method,
@@ -2267,7 +2670,7 @@ pub enum DeclarationInfo {
     ActionParameter(Direction),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct NameInfo {
     pub ty: Type,
     pub decl: DeclarationInfo,
@@ -2276,6 +2679,7 @@ pub struct NameInfo {
 pub trait Visitor {
     fn constant(&self, _: &Constant) {}
     fn header(&self, _: &Header) {}
+    fn header_union(&self, _: &HeaderUnion) {}
     fn p4struct(&self, _: &Struct) {}
     fn typedef(&self, _: &Typedef) {}
     fn control(&self, _: &Control) {}
@@ -2315,6 +2719,7 @@ pub trait Visitor {
 pub trait VisitorMut {
     fn constant(&mut self, _: &Constant) {}
     fn header(&mut self, _: &Header) {}
+    fn header_union(&mut self, _: &HeaderUnion) {}
     fn p4struct(&mut self, _: &Struct) {}
     fn typedef(&mut self, _: &Typedef) {}
     fn control(&mut self, _: &Control) {}
@@ -2354,6 +2759,7 @@ pub trait VisitorMut {
 pub trait MutVisitor {
     fn constant(&self, _: &mut Constant) {}
     fn header(&self, _: &mut Header) {}
+    fn header_union(&self, _: &mut HeaderUnion) {}
     fn p4struct(&self, _: &mut Struct) {}
     fn typedef(&self, _: &mut Typedef) {}
     fn control(&self, _: &mut Control) {}
@@ -2393,6 +2799,7 @@ pub trait MutVisitor {
 pub trait MutVisitorMut {
     fn constant(&mut self, _: &mut Constant) {}
     fn header(&mut self, _: &mut Header) {}
+    fn header_union(&mut self, _: &mut HeaderUnion) {}
     fn p4struct(&mut self, _: &mut Struct) {}
     fn typedef(&mut self, _: &mut Typedef) {}
     fn control(&mut self, _: &mut Control) {}
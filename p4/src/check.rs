@@ -1,26 +1,33 @@
 // Copyright 2022 Oxide Computer Company
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::ast::{
-    Call, Control, DeclarationInfo, Expression, ExpressionKind, Header, Lvalue,
-    NameInfo, Parser, State, Statement, StatementBlock, Struct, Table,
-    Transition, Type, VisitorMut, AST,
+    ActionRef, Annotation, BinOp, Call, Control, DeclarationInfo, Expression,
+    ExpressionKind, Header, HeaderUnion, IfBlock, Lvalue, MatchKind, NameInfo,
+    Parser, State, Statement, StatementBlock, Struct, Table, Transition, Type,
+    Variable, VisitorMut, AST,
 };
 use crate::hlir::{Hlir, HlirGenerator};
 use crate::lexer::Token;
+use crate::util::resolve_lvalue;
 use colored::Colorize;
 
-// TODO Check List
-// This is a running list of things to check
-//
-// - Table keys should be constrained to bit, varbit, int
-
 #[derive(Debug, Clone)]
 pub struct Diagnostic {
     /// Level of this diagnostic.
     pub level: Level,
 
+    /// The category of a warning, such as `unused`. Warnings of a category
+    /// are suppressed for an element annotated `@noWarn("<category>")`.
+    pub category: Option<&'static str>,
+
+    /// A stable code identifying the check that raised this diagnostic, such
+    /// as `E0023`. Errors have codes starting with `E` and warnings codes
+    /// starting with `W`. Unlike messages, codes do not change over time so
+    /// tooling may refer to them.
+    pub code: &'static str,
+
     /// Message associated with this diagnostic.
     pub message: String,
 
@@ -47,12 +54,34 @@ impl Diagnostics {
     pub fn errors(&self) -> Vec<&Diagnostic> {
         self.0.iter().filter(|x| x.level == Level::Error).collect()
     }
+    pub fn warnings(&self) -> Vec<&Diagnostic> {
+        self.0
+            .iter()
+            .filter(|x| x.level == Level::Warning)
+            .collect()
+    }
     pub fn extend(&mut self, diags: &Diagnostics) {
         self.0.extend(diags.0.clone())
     }
     pub fn push(&mut self, d: Diagnostic) {
         self.0.push(d);
     }
+
+    /// Drop the warnings whose category or code is named by a `@noWarn`
+    /// annotation among `annotations`, the annotations of the element the
+    /// warnings were raised for. This is the `#[allow(...)]` of P4 programs.
+    pub fn allow(&mut self, annotations: &[Annotation]) {
+        let allowed: Vec<&str> = annotations
+            .iter()
+            .filter(|a| a.name == "noWarn")
+            .flat_map(|a| a.string_args())
+            .collect();
+        self.0.retain(|d| {
+            d.level != Level::Warning
+                || !(allowed.contains(&d.code)
+                    || d.category.is_some_and(|c| allowed.contains(&c)))
+        });
+    }
 }
 
 pub fn all(ast: &AST) -> (Hlir, Diagnostics) {
@@ -66,7 +95,7 @@ pub fn all(ast: &AST) -> (Hlir, Diagnostics) {
     }
 
     for p in &ast.parsers {
-        diags.extend(&ParserChecker::check(p, ast));
+        diags.extend(&ParserChecker::check(p, ast, &hg.hlir));
     }
     for c in &ast.controls {
         diags.extend(&ControlChecker::check(c, ast, &hg.hlir));
@@ -77,9 +106,349 @@ pub fn all(ast: &AST) -> (Hlir, Diagnostics) {
     for h in &ast.headers {
         diags.extend(&HeaderChecker::check(h, ast));
     }
+    for u in &ast.header_unions {
+        diags.extend(&HeaderUnionChecker::check(u, ast));
+    }
+    diags.extend(&AtomicBlockChecker::check(ast));
     (hg.hlir, diags)
 }
 
+/// Atomic blocks are accepted and compiled as ordinary statement blocks, but
+/// the SoftNPU target does not provide any atomicity guarantees for them.
+pub struct AtomicBlockChecker {
+    diags: Diagnostics,
+}
+
+impl AtomicBlockChecker {
+    pub fn check(ast: &AST) -> Diagnostics {
+        let mut abc = AtomicBlockChecker {
+            diags: Diagnostics::new(),
+        };
+        ast.accept_mut(&mut abc);
+        abc.diags
+    }
+}
+
+impl VisitorMut for AtomicBlockChecker {
+    fn statement(&mut self, s: &Statement) {
+        let Statement::Block(blk) = s else {
+            return;
+        };
+        for a in blk.annotations.iter().filter(|a| a.name == "atomic") {
+            self.diags.push(Diagnostic {
+                level: Level::Warning,
+                category: Some("atomic"),
+                code: "W0001",
+                message: "atomicity is not enforced by the SoftNPU target, \
+                    @atomic block will be executed as an ordinary block"
+                    .into(),
+                token: a.token.clone(),
+            });
+        }
+    }
+}
+
+/// Run the lints over `ast`. Lints flag code that is legal but is likely not
+/// what was intended. They are not run as part of [`all`].
+pub fn lint(ast: &AST) -> Diagnostics {
+    let mut diags = Diagnostics::new();
+    for c in &ast.controls {
+        let mut locals = Vec::new();
+        block_locals(&c.apply, &mut locals);
+        DeadAssignmentChecker::check(&c.apply, &locals, &mut diags);
+        let used = UsedActions::collect(c);
+        for a in &c.actions {
+            let mut action_diags = Diagnostics::new();
+            let mut locals: Vec<&str> =
+                a.parameters.iter().map(|p| p.name.as_str()).collect();
+            block_locals(&a.statement_block, &mut locals);
+            DeadAssignmentChecker::check(
+                &a.statement_block,
+                &locals,
+                &mut action_diags,
+            );
+            if !used.0.contains(&a.name) {
+                action_diags.push(Diagnostic {
+                    level: Level::Warning,
+                    category: Some("unused"),
+                    code: "W0002",
+                    message: format!("action {} is never used", a.name),
+                    token: a.token.clone(),
+                });
+            }
+            action_diags.allow(&a.annotations);
+            diags.extend(&action_diags);
+        }
+    }
+    diags
+}
+
+/// The names of the actions a control refers to, from the actions lists and
+/// entries of its tables or by calling them.
+#[derive(Default)]
+struct UsedActions(HashSet<String>);
+
+impl UsedActions {
+    fn collect(c: &Control) -> Self {
+        let mut used = UsedActions::default();
+        c.accept_mut(&mut used);
+        used
+    }
+}
+
+impl VisitorMut for UsedActions {
+    fn lvalue(&mut self, lval: &Lvalue) {
+        self.0.insert(lval.name.clone());
+    }
+
+    fn action_ref(&mut self, aref: &ActionRef) {
+        self.0.insert(aref.name.clone());
+    }
+
+    fn table(&mut self, t: &Table) {
+        self.0.insert(t.default_action.clone());
+    }
+}
+
+// Collect the names of the variables declared in `block`.
+fn block_locals<'a>(block: &'a StatementBlock, locals: &mut Vec<&'a str>) {
+    for stmt in &block.statements {
+        match stmt {
+            Statement::Variable(v) => locals.push(v.name.as_str()),
+            Statement::Constant(c) => locals.push(c.name.as_str()),
+            Statement::If(ifb) => {
+                block_locals(&ifb.block, locals);
+                for ei in &ifb.else_ifs {
+                    block_locals(&ei.block, locals);
+                }
+                if let Some(eb) = &ifb.else_block {
+                    block_locals(eb, locals);
+                }
+            }
+            Statement::Block(b) => block_locals(&b.block, locals),
+            _ => {}
+        }
+    }
+}
+
+/// What happens to the value of a variable after it is assigned.
+#[derive(Debug, PartialEq)]
+enum Fate {
+    /// The value may be read.
+    Read,
+
+    /// The value is overwritten on all paths before it is read.
+    Overwritten,
+
+    /// Neither, as far as the statements looked at go.
+    Unknown,
+}
+
+/// Finds assignments whose value is overwritten on all paths before it is
+/// read. Anything the analysis cannot see through, such as a table apply that
+/// may run an action reading the variable, counts as a read.
+struct DeadAssignmentChecker<'a> {
+    /// Variables and parameters local to the block being checked. Only these
+    /// are out of reach of the actions and controls the block calls.
+    locals: &'a [&'a str],
+}
+
+impl<'a> DeadAssignmentChecker<'a> {
+    fn check(
+        block: &StatementBlock,
+        locals: &'a [&'a str],
+        diags: &mut Diagnostics,
+    ) {
+        let dac = DeadAssignmentChecker { locals };
+        dac.check_block(block, diags);
+    }
+
+    fn check_block(&self, block: &StatementBlock, diags: &mut Diagnostics) {
+        for (i, stmt) in block.statements.iter().enumerate() {
+            let (target, token) = match stmt {
+                Statement::Assignment(lval, _) => (&lval.name, &lval.token),
+                Statement::Variable(Variable {
+                    name,
+                    token,
+                    initializer: Some(_),
+                    ..
+                }) => (name, token),
+                Statement::If(ifb) => {
+                    self.check_block(&ifb.block, diags);
+                    for ei in &ifb.else_ifs {
+                        self.check_block(&ei.block, diags);
+                    }
+                    if let Some(eb) = &ifb.else_block {
+                        self.check_block(eb, diags);
+                    }
+                    continue;
+                }
+                Statement::Block(b) => {
+                    self.check_block(&b.block, diags);
+                    continue;
+                }
+                _ => continue,
+            };
+            let rest = &block.statements[i + 1..];
+            if self.fate(target, rest) == Fate::Overwritten {
+                diags.push(Diagnostic {
+                    level: Level::Warning,
+                    category: Some("dead-assignment"),
+                    code: "W0003",
+                    message: format!(
+                        "value assigned to {} is overwritten before it is read",
+                        target,
+                    ),
+                    token: token.clone(),
+                });
+            }
+        }
+    }
+
+    fn fate(&self, target: &str, stmts: &[Statement]) -> Fate {
+        for stmt in stmts {
+            let fate = match stmt {
+                Statement::Empty => Fate::Unknown,
+                Statement::Assignment(lval, xpr) => {
+                    if self.reads(target, xpr) {
+                        Fate::Read
+                    } else if overwrites(&lval.name, target) {
+                        Fate::Overwritten
+                    } else {
+                        Fate::Unknown
+                    }
+                }
+                Statement::TupleAssignment(lvals, xpr) => {
+                    if self.reads(target, xpr) {
+                        Fate::Read
+                    } else if lvals.iter().any(|l| overwrites(&l.name, target))
+                    {
+                        Fate::Overwritten
+                    } else {
+                        Fate::Unknown
+                    }
+                }
+                Statement::Variable(v) => match &v.initializer {
+                    Some(xpr) if self.reads(target, xpr) => Fate::Read,
+                    _ => Fate::Unknown,
+                },
+                Statement::Constant(c) => {
+                    if self.reads(target, &c.initializer) {
+                        Fate::Read
+                    } else {
+                        Fate::Unknown
+                    }
+                }
+                Statement::Call(call) => {
+                    if self.call_reads(target, call) {
+                        Fate::Read
+                    } else {
+                        Fate::Unknown
+                    }
+                }
+                Statement::If(ifb) => self.if_fate(target, ifb),
+                Statement::Block(b) => self.fate(target, &b.block.statements),
+                Statement::Transition(_) | Statement::Return(_) => Fate::Read,
+            };
+            if fate != Fate::Unknown {
+                return fate;
+            }
+        }
+        Fate::Unknown
+    }
+
+    // An if statement overwrites a value only if every branch, including an
+    // else branch, does.
+    fn if_fate(&self, target: &str, ifb: &IfBlock) -> Fate {
+        if self.reads(target, &ifb.predicate)
+            || ifb
+                .else_ifs
+                .iter()
+                .any(|ei| self.reads(target, &ei.predicate))
+        {
+            return Fate::Read;
+        }
+        let mut blocks = vec![&ifb.block];
+        blocks.extend(ifb.else_ifs.iter().map(|ei| &ei.block));
+        let fates: Vec<Fate> = blocks
+            .iter()
+            .map(|b| self.fate(target, &b.statements))
+            .collect();
+        let else_fate = match &ifb.else_block {
+            Some(eb) => self.fate(target, &eb.statements),
+            None => Fate::Unknown,
+        };
+        if fates.contains(&Fate::Read) || else_fate == Fate::Read {
+            Fate::Read
+        } else if fates.iter().all(|f| *f == Fate::Overwritten)
+            && else_fate == Fate::Overwritten
+        {
+            Fate::Overwritten
+        } else {
+            Fate::Unknown
+        }
+    }
+
+    fn is_local(&self, target: &str) -> bool {
+        let root = target.split('.').next().unwrap_or(target);
+        self.locals.contains(&root)
+    }
+
+    fn call_reads(&self, target: &str, call: &Call) -> bool {
+        if refers(&call.lval.name, target)
+            || call.args.iter().any(|a| self.reads(target, a))
+        {
+            return true;
+        }
+        // header methods only touch the header they are called on, anything
+        // else may run actions or controls that read non-local values
+        let header_method = matches!(
+            call.lval.leaf(),
+            "setValid" | "setInvalid" | "isValid" | "push_front" | "pop_front"
+        );
+        !header_method && !self.is_local(target)
+    }
+
+    fn reads(&self, target: &str, xpr: &Expression) -> bool {
+        match &xpr.kind {
+            ExpressionKind::BoolLit(_)
+            | ExpressionKind::IntegerLit(_)
+            | ExpressionKind::BitLit(..)
+            | ExpressionKind::SignedLit(..) => false,
+            ExpressionKind::Lvalue(lval) => refers(&lval.name, target),
+            ExpressionKind::Binary(lhs, _, rhs) => {
+                self.reads(target, lhs) || self.reads(target, rhs)
+            }
+            ExpressionKind::Index(lval, index) => {
+                refers(&lval.name, target) || self.reads(target, index)
+            }
+            ExpressionKind::Slice(begin, end) => {
+                self.reads(target, begin) || self.reads(target, end)
+            }
+            ExpressionKind::Call(call) => self.call_reads(target, call),
+            ExpressionKind::List(elements) => {
+                elements.iter().any(|e| self.reads(target, e))
+            }
+            ExpressionKind::StructLit(members) => {
+                members.iter().any(|(_, e)| self.reads(target, e))
+            }
+        }
+    }
+}
+
+// Whether a reference to `name` may read or write part of `target`.
+fn refers(name: &str, target: &str) -> bool {
+    overwrites(name, target) || overwrites(target, name)
+}
+
+// Whether assigning to `name` replaces all of `target`.
+fn overwrites(name: &str, target: &str) -> bool {
+    name == target
+        || target
+            .strip_prefix(name)
+            .is_some_and(|rest| rest.starts_with('.') || rest.starts_with('['))
+}
+
 pub struct ControlChecker {}
 
 impl ControlChecker {
@@ -100,6 +469,8 @@ impl ControlChecker {
                 if ast.get_user_defined_type(typename).is_none() {
                     diags.push(Diagnostic {
                         level: Level::Error,
+                        category: None,
+                        code: "E0002",
                         message: format!("Typename {} not found", typename),
                         token: p.ty_token.clone(),
                     })
@@ -126,15 +497,149 @@ impl ControlChecker {
         ast: &AST,
         diags: &mut Diagnostics,
     ) {
-        for (lval, _match_kind) in &t.key {
-            diags.extend(&check_lvalue(lval, ast, names, Some(&c.name)))
+        let mut lpm_keys = 0;
+        for (lval, match_kind) in &t.key {
+            if matches!(match_kind, MatchKind::LongestPrefixMatch) {
+                lpm_keys += 1;
+                if lpm_keys == 2 {
+                    diags.push(Diagnostic {
+                        level: Level::Error,
+                        category: None,
+                        code: "E0001",
+                        message: format!(
+                            "Table {} has more than one lpm key",
+                            t.name.bright_blue(),
+                        ),
+                        token: lval.token.clone(),
+                    });
+                }
+            }
+            diags.extend(&check_lvalue(lval, ast, names, Some(&c.name)));
+            let ty = match resolve_lvalue(lval, ast, names) {
+                Ok(info) => info.ty,
+                Err(_) => continue,
+            };
+            if !is_table_key_type(&ty, ast) {
+                diags.push(Diagnostic {
+                    level: Level::Error,
+                    category: None,
+                    code: "E0003",
+                    message: format!(
+                        "Table key {} has type {}, table keys must be bit, \
+                        varbit or int",
+                        lval.name.bright_blue(),
+                        ty.to_string().bright_blue(),
+                    ),
+                    token: lval.token.clone(),
+                });
+            }
         }
         if t.default_action.is_empty() {
             diags.push(Diagnostic {
                 level: Level::Error,
+                category: None,
+                code: "E0004",
                 message: "Table must have a default action".into(),
                 token: t.token.clone(),
             });
+        } else if t.default_action != "NoAction"
+            && !t.actions.iter().any(|a| a.name == t.default_action)
+        {
+            // NoAction is always available as a default and need not be
+            // listed.
+            diags.push(Diagnostic {
+                level: Level::Error,
+                category: None,
+                code: "E0035",
+                message: format!(
+                    "Default action {} of table {} is not in its actions list",
+                    t.default_action.bright_blue(),
+                    t.name.bright_blue(),
+                ),
+                token: t.token.clone(),
+            });
+        } else if t.action_has_annotation(&t.default_action, "tableonly") {
+            diags.push(Diagnostic {
+                level: Level::Error,
+                category: None,
+                code: "E0005",
+                message: format!(
+                    "Action {} is annotated {} and cannot be the default \
+                    action of table {}",
+                    t.default_action.bright_blue(),
+                    "@tableonly".bright_blue(),
+                    t.name.bright_blue(),
+                ),
+                token: t.token.clone(),
+            });
+        }
+        for entry in &t.const_entries {
+            Self::check_named_arguments(c, &entry.action, diags);
+            if t.action_has_annotation(&entry.action.name, "defaultonly") {
+                diags.push(Diagnostic {
+                    level: Level::Error,
+                    category: None,
+                    code: "E0006",
+                    message: format!(
+                        "Action {} is annotated {} and cannot be used in \
+                        an entry of table {}",
+                        entry.action.name.bright_blue(),
+                        "@defaultonly".bright_blue(),
+                        t.name.bright_blue(),
+                    ),
+                    token: entry.action.token.clone(),
+                });
+            }
+        }
+    }
+
+    /// Named arguments are put in parameter order by the parser when their
+    /// names are exactly the parameters of the action, report the names of
+    /// those that are not.
+    fn check_named_arguments(
+        c: &Control,
+        aref: &ActionRef,
+        diags: &mut Diagnostics,
+    ) {
+        let action = match c.get_action(&aref.name) {
+            Some(a) => a,
+            None => return,
+        };
+        let mut error = |message: String| {
+            diags.push(Diagnostic {
+                level: Level::Error,
+                category: None,
+                code: "E0036",
+                message,
+                token: aref.token.clone(),
+            })
+        };
+        for (i, name) in aref.parameter_names.iter().enumerate() {
+            if !action.parameters.iter().any(|p| p.name == *name) {
+                error(format!(
+                    "Action {} has no parameter {}",
+                    aref.name.bright_blue(),
+                    name.bright_blue(),
+                ));
+            } else if aref.parameter_names[..i].contains(name) {
+                error(format!(
+                    "Argument {} of action {} is given more than once",
+                    name.bright_blue(),
+                    aref.name.bright_blue(),
+                ));
+            }
+        }
+        if aref.parameter_names.is_empty() {
+            return;
+        }
+        for p in &action.parameters {
+            if !aref.parameter_names.contains(&p.name) {
+                error(format!(
+                    "Argument {} of action {} is missing",
+                    p.name.bright_blue(),
+                    aref.name.bright_blue(),
+                ));
+            }
         }
     }
 
@@ -149,6 +654,8 @@ impl ControlChecker {
                 }
                 diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0002",
                     message: format!("Typename {} not found", typename),
                     token: v.token.clone(),
                 })
@@ -180,6 +687,8 @@ impl ControlChecker {
             if c.get_action(&a.name).is_none() {
                 diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0007",
                     message: format!(
                         "Table {} does not have action {}",
                         t.name, &a.name,
@@ -197,6 +706,7 @@ impl ControlChecker {
         diags: &mut Diagnostics,
     ) {
         diags.extend(&check_statement_block_lvalues(&c.apply, ast, &c.names()));
+        check_statement_block(&c.apply, hlir, diags, ast, false);
 
         let mut apc = ApplyCallChecker {
             c,
@@ -208,6 +718,97 @@ impl ControlChecker {
     }
 }
 
+/// Assigning the result of `bit<N>` arithmetic to a narrower field keeps the
+/// low order bits of the result. This is allowed, but is usually a mistake.
+fn is_truncating_arithmetic(xpr: &Expression, from: &Type, to: &Type) -> bool {
+    let arithmetic = matches!(
+        xpr.kind,
+        ExpressionKind::Binary(
+            _,
+            BinOp::Add
+                | BinOp::Subtract
+                | BinOp::SaturatingAdd
+                | BinOp::SaturatingSubtract,
+            _
+        )
+    );
+    match (from, to) {
+        (Type::Bit(x), Type::Bit(y)) => arithmetic && x > y,
+        _ => false,
+    }
+}
+
+/// Check that the expression assigned to the lvalues of a tuple assignment is
+/// a tuple with an element of the right type for each of them.
+fn check_tuple_assignment(
+    lvals: &[Lvalue],
+    xpr: &Expression,
+    hlir: &Hlir,
+    diags: &mut Diagnostics,
+) {
+    let elements = match hlir.expression_types.get(xpr) {
+        Some(Type::List(elements)) => elements,
+        Some(ty) => {
+            diags.push(Diagnostic {
+                level: Level::Error,
+                category: None,
+                code: "E0038",
+                message: format!("Cannot destructure {}, expected a tuple", ty),
+                token: xpr.token.clone(),
+            });
+            return;
+        }
+        None => {
+            diags.push(Diagnostic {
+                level: Level::Error,
+                category: None,
+                code: "E0009",
+                message: "Could not determine expression type".to_owned(),
+                token: xpr.token.clone(),
+            });
+            return;
+        }
+    };
+    if elements.len() != lvals.len() {
+        diags.push(Diagnostic {
+            level: Level::Error,
+            category: None,
+            code: "E0038",
+            message: format!(
+                "Cannot assign a tuple of {} elements to {} lvalues",
+                elements.len(),
+                lvals.len(),
+            ),
+            token: xpr.token.clone(),
+        });
+        return;
+    }
+    for (lval, ty) in lvals.iter().zip(elements) {
+        let name_info = match hlir.lvalue_decls.get(lval) {
+            Some(info) => info,
+            None => {
+                diags.push(Diagnostic {
+                    level: Level::Error,
+                    category: None,
+                    code: "E0008",
+                    message: format!("Could not resolve lvalue {}", &lval.name),
+                    token: lval.token.clone(),
+                });
+                continue;
+            }
+        };
+        if &name_info.ty != ty.as_ref() {
+            diags.push(Diagnostic {
+                level: Level::Error,
+                category: None,
+                code: "E0010",
+                message: format!("Cannot assign {} to {}", ty, &name_info.ty),
+                token: lval.token.clone(),
+            });
+        }
+    }
+}
+
 fn check_statement_block(
     block: &StatementBlock,
     hlir: &Hlir,
@@ -223,6 +824,8 @@ fn check_statement_block(
                     None => {
                         diags.push(Diagnostic {
                             level: Level::Error,
+                            category: None,
+                            code: "E0008",
                             message: format!(
                                 "Could not resolve lvalue {}",
                                 &lval.name,
@@ -239,6 +842,8 @@ fn check_statement_block(
                         None => {
                             diags.push(Diagnostic {
                                 level: Level::Error,
+                                category: None,
+                                code: "E0009",
                                 message: "Could not determine expression type"
                                     .to_owned(),
                                 token: xpr.token.clone(),
@@ -247,9 +852,23 @@ fn check_statement_block(
                         }
                     };
 
-                if &name_info.ty != expression_type {
+                if is_truncating_arithmetic(xpr, expression_type, &name_info.ty)
+                {
+                    diags.push(Diagnostic {
+                        level: Level::Warning,
+                        category: Some("truncation"),
+                        code: "W0004",
+                        message: format!(
+                            "Implicit truncation of {} arithmetic result to {}",
+                            expression_type, &name_info.ty,
+                        ),
+                        token: xpr.token.clone(),
+                    });
+                } else if &name_info.ty != expression_type {
                     diags.push(Diagnostic {
                         level: Level::Error,
+                        category: None,
+                        code: "E0010",
                         message: format!(
                             "Cannot assign {} to {}",
                             expression_type, &name_info.ty,
@@ -258,7 +877,13 @@ fn check_statement_block(
                     });
                 }
             }
+            Statement::TupleAssignment(lvals, xpr) => {
+                check_tuple_assignment(lvals, xpr, hlir, diags);
+            }
             Statement::Empty => {}
+            Statement::Block(blk) => {
+                check_statement_block(&blk.block, hlir, diags, ast, in_action);
+            }
             Statement::Call(c) if in_action => {
                 let lval = c.lval.pop_right();
                 let name_info = match hlir.lvalue_decls.get(&lval) {
@@ -266,6 +891,8 @@ fn check_statement_block(
                     None => {
                         diags.push(Diagnostic {
                             level: Level::Error,
+                            category: None,
+                            code: "E0008",
                             message: format!(
                                 "Could not resolve lvalue {}",
                                 &c.lval.name,
@@ -279,6 +906,8 @@ fn check_statement_block(
                     Type::Table => {
                         diags.push(Diagnostic {
                             level: Level::Error,
+                            category: None,
+                            code: "E0011",
                             message: String::from(
                                 "Cannot apply table within action",
                             ),
@@ -289,6 +918,8 @@ fn check_statement_block(
                         if ast.get_control(name).is_some() {
                             diags.push(Diagnostic {
                                 level: Level::Error,
+                                category: None,
+                                code: "E0012",
                                 message: String::from(
                                     "Cannot apply control within action",
                                 ),
@@ -322,6 +953,8 @@ impl<'a> VisitorMut for ApplyCallChecker<'a> {
             None => {
                 self.diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0013",
                     message: format!("{} is undefined", name),
                     token: call.lval.token.clone(),
                 });
@@ -340,6 +973,9 @@ impl<'a> VisitorMut for ApplyCallChecker<'a> {
                     self.check_apply_table_apply(call, tbl)
                 }
             }
+            Type::ExternFunction if matches!(name, "assert" | "assume") => {
+                self.check_assertion(call, name)
+            }
             _ => {
                 //TODO
             }
@@ -348,6 +984,47 @@ impl<'a> VisitorMut for ApplyCallChecker<'a> {
 }
 
 impl<'a> ApplyCallChecker<'a> {
+    /// Ensure calls to assert and assume take a single boolean condition.
+    pub fn check_assertion(&mut self, call: &Call, name: &str) {
+        if call.args.len() != 1 {
+            self.diags.push(Diagnostic {
+                level: Level::Error,
+                category: None,
+                code: "E0014",
+                message: format!(
+                    "{} arguments provided to {}, 1 required\n    \
+                    expected signature: {}(bool condition)",
+                    call.args.len().to_string().yellow(),
+                    name.bright_blue(),
+                    name,
+                ),
+                token: call.lval.token.clone(),
+            });
+            return;
+        }
+        let arg = &call.args[0];
+        let arg_t = match self.hlir.expression_types.get(arg.as_ref()) {
+            Some(typ) => typ,
+            None => panic!("bug: no type for expression {:?}", arg),
+        };
+        if arg_t != &Type::Bool {
+            self.diags.push(Diagnostic {
+                level: Level::Error,
+                category: None,
+                code: "E0015",
+                message: format!(
+                    "wrong argument type for {}\n    \
+                     argument provided:  {}\n    \
+                     parameter requires: {}",
+                    name.bright_blue(),
+                    format!("{}", arg_t).bright_blue(),
+                    format!("{}", Type::Bool).bright_blue(),
+                ),
+                token: arg.token.clone(),
+            });
+        }
+    }
+
     pub fn check_apply_table_apply(&mut self, _call: &Call, _tbl: &Table) {
         //TODO
     }
@@ -364,6 +1041,8 @@ impl<'a> ApplyCallChecker<'a> {
 
             self.diags.push(Diagnostic {
                 level: Level::Error,
+                category: None,
+                code: "E0014",
                 message: format!(
                     "{} arguments provided to control {}, {} required\n    \
                     expected signature: {}",
@@ -386,6 +1065,8 @@ impl<'a> ApplyCallChecker<'a> {
             if arg_t != &param.ty {
                 self.diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0015",
                     message: format!(
                         "wrong argument type for {} parameter {}\n    \
                          argument provided:  {}\n    \
@@ -405,13 +1086,14 @@ impl<'a> ApplyCallChecker<'a> {
 pub struct ParserChecker {}
 
 impl ParserChecker {
-    pub fn check(p: &Parser, ast: &AST) -> Diagnostics {
+    pub fn check(p: &Parser, ast: &AST, hlir: &Hlir) -> Diagnostics {
         let mut diags = Diagnostics::new();
 
         if !p.decl_only {
             Self::start_state(p, &mut diags);
             for s in &p.states {
                 Self::ensure_transition(s, &mut diags);
+                Self::verify_calls(&s.statements, hlir, &mut diags);
             }
             Self::lvalues(p, ast, &mut diags);
         }
@@ -429,6 +1111,8 @@ impl ParserChecker {
 
         diags.push(Diagnostic {
             level: Level::Error,
+            category: None,
+            code: "E0016",
             message: format!(
                 "start state not found for parser {}",
                 parser.name.bright_blue(),
@@ -443,6 +1127,8 @@ impl ParserChecker {
         if stmts.is_empty() {
             diags.push(Diagnostic {
                 level: Level::Error,
+                category: None,
+                code: "E0017",
                 message: "state must include transition".into(),
                 token: state.token.clone(),
             });
@@ -455,6 +1141,8 @@ impl ParserChecker {
         if !matches!(last, Some(Statement::Transition(_))) {
             diags.push(Diagnostic {
                 level: Level::Error,
+                category: None,
+                code: "E0018",
                 message: "final parser state statement must be a transition"
                     .into(),
                 token: state.token.clone(),
@@ -462,6 +1150,75 @@ impl ParserChecker {
         }
     }
 
+    /// Ensure calls to verify take a boolean condition and an error.
+    pub fn verify_calls(
+        block: &StatementBlock,
+        hlir: &Hlir,
+        diags: &mut Diagnostics,
+    ) {
+        for stmt in &block.statements {
+            match stmt {
+                Statement::Call(c) if c.lval.name == "verify" => {
+                    Self::verify_call(c, hlir, diags);
+                }
+                Statement::If(ifb) => {
+                    Self::verify_calls(&ifb.block, hlir, diags);
+                    for ei in &ifb.else_ifs {
+                        Self::verify_calls(&ei.block, hlir, diags);
+                    }
+                    if let Some(eb) = &ifb.else_block {
+                        Self::verify_calls(eb, hlir, diags);
+                    }
+                }
+                Statement::Block(blk) => {
+                    Self::verify_calls(&blk.block, hlir, diags);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn verify_call(c: &Call, hlir: &Hlir, diags: &mut Diagnostics) {
+        if c.args.len() != 2 {
+            diags.push(Diagnostic {
+                level: Level::Error,
+                category: None,
+                code: "E0014",
+                message: format!(
+                    "{} arguments provided to {}, 2 required\n    \
+                    expected signature: verify(bool condition, error err)",
+                    c.args.len().to_string().yellow(),
+                    "verify".bright_blue(),
+                ),
+                token: c.lval.token.clone(),
+            });
+            return;
+        }
+        let expected = [Type::Bool, Type::Error];
+        for (arg, param_t) in c.args.iter().zip(expected.iter()) {
+            let arg_t = match hlir.expression_types.get(arg.as_ref()) {
+                Some(typ) => typ,
+                None => panic!("bug: no type for expression {:?}", arg),
+            };
+            if arg_t != param_t {
+                diags.push(Diagnostic {
+                    level: Level::Error,
+                    category: None,
+                    code: "E0015",
+                    message: format!(
+                        "wrong argument type for {}\n    \
+                         argument provided:  {}\n    \
+                         parameter requires: {}",
+                        "verify".bright_blue(),
+                        format!("{}", arg_t).bright_blue(),
+                        format!("{}", param_t).bright_blue(),
+                    ),
+                    token: arg.token.clone(),
+                });
+            }
+        }
+    }
+
     /// Check lvalue references
     pub fn lvalues(parser: &Parser, ast: &AST, diags: &mut Diagnostics) {
         for state in &parser.states {
@@ -488,6 +1245,8 @@ impl StructChecker {
                 if ast.get_user_defined_type(typename).is_none() {
                     diags.push(Diagnostic {
                         level: Level::Error,
+                        category: None,
+                        code: "E0002",
                         message: format!(
                             "Typename {} not found",
                             typename.bright_blue()
@@ -496,6 +1255,20 @@ impl StructChecker {
                     })
                 }
             }
+            if let Type::HeaderStack(typename, _) = &m.ty {
+                if ast.get_header(typename).is_none() {
+                    diags.push(Diagnostic {
+                        level: Level::Error,
+                        category: None,
+                        code: "E0019",
+                        message: format!(
+                            "Header stack element type {} is not a header",
+                            typename.bright_blue()
+                        ),
+                        token: m.token.clone(),
+                    })
+                }
+            }
         }
         diags
     }
@@ -511,6 +1284,8 @@ impl HeaderChecker {
                 if ast.get_user_defined_type(typename).is_none() {
                     diags.push(Diagnostic {
                         level: Level::Error,
+                        category: None,
+                        code: "E0002",
                         message: format!(
                             "Typename {} not found",
                             typename.bright_blue()
@@ -524,6 +1299,38 @@ impl HeaderChecker {
     }
 }
 
+pub struct HeaderUnionChecker {}
+
+impl HeaderUnionChecker {
+    /// Ensure all members of a header union are headers.
+    pub fn check(u: &HeaderUnion, ast: &AST) -> Diagnostics {
+        let mut diags = Diagnostics::new();
+        for m in &u.members {
+            let is_header = match &m.ty {
+                Type::UserDefined(typename) => {
+                    ast.get_header(typename).is_some()
+                }
+                _ => false,
+            };
+            if !is_header {
+                diags.push(Diagnostic {
+                    level: Level::Error,
+                    category: None,
+                    code: "E0020",
+                    message: format!(
+                        "Header union {} member {} must be a header, found {}",
+                        u.name.bright_blue(),
+                        m.name.bright_blue(),
+                        format!("{}", m.ty).bright_blue(),
+                    ),
+                    token: m.token.clone(),
+                })
+            }
+        }
+        diags
+    }
+}
+
 fn check_name(
     name: &str,
     names: &HashMap<String, NameInfo>,
@@ -535,6 +1342,11 @@ fn check_name(
         None => (
             Diagnostics(vec![Diagnostic {
                 level: Level::Error,
+                category: None,
+                code: match parent {
+                    Some(_) => "E0023",
+                    None => "E0013",
+                },
                 message: match parent {
                     Some(p) => format!(
                         "{} does not have member {}",
@@ -588,8 +1400,15 @@ fn check_statement_lvalues(
             diags.extend(&check_lvalue(lval, ast, names, None));
             diags.extend(&check_expression_lvalues(expr, ast, names));
         }
+        Statement::TupleAssignment(lvals, expr) => {
+            for lval in lvals {
+                diags.extend(&check_lvalue(lval, ast, names, None));
+            }
+            diags.extend(&check_expression_lvalues(expr, ast, names));
+        }
         Statement::Call(call) => {
             diags.extend(&check_lvalue(&call.lval, ast, names, None));
+            diags.extend(&check_header_stack_shift(call, ast, names));
             for arg in &call.args {
                 diags.extend(&check_expression_lvalues(
                     arg.as_ref(),
@@ -627,18 +1446,22 @@ fn check_statement_lvalues(
                 ));
             }
         }
-        Statement::Transition(transition) => {
-            match transition {
-                Transition::Reference(lval) => {
-                    if lval.name != "accept" && lval.name != "reject" {
-                        diags.extend(&check_lvalue(lval, ast, names, None));
-                    }
+        Statement::Transition(transition) => match transition {
+            Transition::Reference(lval) => {
+                if lval.name != "accept" && lval.name != "reject" {
+                    diags.extend(&check_lvalue(lval, ast, names, None));
                 }
-                Transition::Select(_sel) => {
-                    //TODO
+            }
+            Transition::Select(sel) => {
+                for p in &sel.parameters {
+                    diags.extend(&check_expression_lvalues(
+                        p.as_ref(),
+                        ast,
+                        names,
+                    ));
                 }
             }
-        }
+        },
         Statement::Return(xpr) => {
             if let Some(xpr) = xpr {
                 diags.extend(&check_expression_lvalues(
@@ -648,6 +1471,47 @@ fn check_statement_lvalues(
                 ));
             }
         }
+        Statement::Block(blk) => {
+            diags
+                .extend(&check_statement_block_lvalues(&blk.block, ast, names));
+        }
+    }
+    diags
+}
+
+/// The count passed to `push_front` or `pop_front` on a header stack must be a
+/// single integer literal.
+fn check_header_stack_shift(
+    call: &Call,
+    ast: &AST,
+    names: &HashMap<String, NameInfo>,
+) -> Diagnostics {
+    let mut diags = Diagnostics::new();
+    if !matches!(call.lval.leaf(), "push_front" | "pop_front") {
+        return diags;
+    }
+    match resolve_lvalue(&call.lval.pop_right(), ast, names) {
+        Ok(NameInfo {
+            ty: Type::HeaderStack(..),
+            ..
+        }) => {}
+        _ => return diags,
+    }
+    let valid = match call.args.as_slice() {
+        [arg] => matches!(arg.kind, ExpressionKind::IntegerLit(n) if n >= 0),
+        _ => false,
+    };
+    if !valid {
+        diags.push(Diagnostic {
+            level: Level::Error,
+            category: None,
+            code: "E0021",
+            message: format!(
+                "{} takes a single non-negative integer literal count",
+                call.lval.leaf().bright_blue(),
+            ),
+            token: call.lval.token.clone(),
+        });
     }
     diags
 }
@@ -690,10 +1554,31 @@ fn check_expression_lvalues(
             diags.extend(&check_expression_lvalues(rhs.as_ref(), ast, names));
             diags
         }
+        ExpressionKind::StructLit(members) => {
+            let mut diags = Diagnostics::new();
+            for (_, xpr) in members {
+                diags.extend(&check_expression_lvalues(xpr, ast, names));
+            }
+            diags
+        }
         _ => Diagnostics::new(),
     }
 }
 
+/// Table keys are constrained to bit, varbit and int types, or typedefs of
+/// them.
+fn is_table_key_type(ty: &Type, ast: &AST) -> bool {
+    match ty {
+        Type::Bit(_) | Type::Varbit(_) | Type::Int(_) => true,
+        Type::UserDefined(name) => ast
+            .typedefs
+            .iter()
+            .find(|t| &t.name == name)
+            .is_some_and(|t| is_table_key_type(&t.ty, ast)),
+        _ => false,
+    }
+}
+
 fn check_lvalue(
     lval: &Lvalue,
     ast: &AST,
@@ -701,19 +1586,36 @@ fn check_lvalue(
     parent: Option<&str>,
 ) -> Diagnostics {
     let parts = lval.parts();
+    let (root, index) = Lvalue::split_index(parts[0]);
 
-    let ty = match check_name(parts[0], names, &lval.token, parent) {
+    let ty = match check_name(root, names, &lval.token, parent) {
         (_, Some(ty)) => ty,
         (diags, None) => return diags,
     };
 
     let mut diags = Diagnostics::new();
 
+    if index.is_some() && !matches!(ty, Type::HeaderStack(..) | Type::List(_)) {
+        diags.push(Diagnostic {
+            level: Level::Error,
+            category: None,
+            code: "E0022",
+            message: format!(
+                "{} is not a header stack or tuple and cannot be indexed",
+                root.bright_blue(),
+            ),
+            token: lval.token.clone(),
+        });
+        return diags;
+    }
+
     match ty {
         Type::Bool => {
             if parts.len() > 1 {
                 diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0023",
                     message: format!(
                         "type {} does not have a member {}",
                         "bool".bright_blue(),
@@ -727,6 +1629,8 @@ fn check_lvalue(
             if parts.len() > 1 {
                 diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0023",
                     message: format!(
                         "type {} does not have a member {}",
                         "state".bright_blue(),
@@ -740,6 +1644,8 @@ fn check_lvalue(
             if parts.len() > 1 {
                 diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0023",
                     message: format!(
                         "type {} does not have a member {}",
                         "action".bright_blue(),
@@ -750,9 +1656,25 @@ fn check_lvalue(
             }
         }
         Type::Error => {
-            if parts.len() > 1 {
+            if parts[0] == "error" && parts.len() == 2 {
+                if !ast.is_error(parts[1]) {
+                    diags.push(Diagnostic {
+                        level: Level::Error,
+                        category: None,
+                        code: "E0024",
+                        message: format!(
+                            "{} is not a declared {}",
+                            parts[1].bright_blue(),
+                            "error".bright_blue(),
+                        ),
+                        token: lval.token.clone(),
+                    });
+                }
+            } else if parts.len() > 1 {
                 diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0023",
                     message: format!(
                         "type {} does not have a member {}",
                         "error".bright_blue(),
@@ -766,6 +1688,8 @@ fn check_lvalue(
             if parts.len() > 1 {
                 diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0023",
                     message: format!(
                         "type {} does not have a member {}",
                         format!("bit<{}>", size).bright_blue(),
@@ -779,6 +1703,8 @@ fn check_lvalue(
             if parts.len() > 1 {
                 diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0023",
                     message: format!(
                         "type {} does not have a member {}",
                         format!("varbit<{}>", size).bright_blue(),
@@ -792,6 +1718,8 @@ fn check_lvalue(
             if parts.len() > 1 {
                 diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0023",
                     message: format!(
                         "type int<{}> does not have a member {}",
                         format!("int<{}>", size).bright_blue(),
@@ -805,6 +1733,8 @@ fn check_lvalue(
             if parts.len() > 1 {
                 diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0023",
                     message: format!(
                         "type {} does not have a member {}",
                         "string".bright_blue(),
@@ -818,6 +1748,8 @@ fn check_lvalue(
             if parts.len() > 1 {
                 diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0023",
                     message: "extern functions do not have members".into(),
                     token: lval.token.clone(),
                 });
@@ -827,6 +1759,8 @@ fn check_lvalue(
             if parts.len() > 1 {
                 diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0023",
                     message: "header methods do not have members".into(),
                     token: lval.token.clone(),
                 });
@@ -836,6 +1770,8 @@ fn check_lvalue(
             if parts.len() > 1 && parts.last() != Some(&"apply") {
                 diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0023",
                     message: format!(
                         "type {} does not have a member {}",
                         "table".bright_blue(),
@@ -849,6 +1785,8 @@ fn check_lvalue(
             if parts.len() > 1 {
                 diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0023",
                     message: format!(
                         "type {} does not have a member {}",
                         "void".bright_blue(),
@@ -862,6 +1800,8 @@ fn check_lvalue(
             if parts.len() > 1 {
                 diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0023",
                     message: format!(
                         "type {} does not have a member {}",
                         "list".bright_blue(),
@@ -871,6 +1811,65 @@ fn check_lvalue(
                 });
             }
         }
+        Type::HeaderStack(name, size) => match index {
+            Some(i) if i >= size => {
+                diags.push(Diagnostic {
+                    level: Level::Error,
+                    category: None,
+                    code: "E0025",
+                    message: format!(
+                        "index {} out of bounds for header stack {} of size {}",
+                        i,
+                        root.bright_blue(),
+                        size,
+                    ),
+                    token: lval.token.clone(),
+                });
+            }
+            Some(_) => {
+                // an element of a header stack is checked like any other
+                // header
+                let mut element_names = names.clone();
+                element_names.insert(
+                    root.to_owned(),
+                    NameInfo {
+                        ty: Type::UserDefined(name),
+                        decl: DeclarationInfo::HeaderMember,
+                    },
+                );
+                let mut element_parts = vec![root];
+                element_parts.extend(&parts[1..]);
+                let element = Lvalue {
+                    name: element_parts.join("."),
+                    token: lval.token.clone(),
+                };
+                diags.extend(&check_lvalue(
+                    &element,
+                    ast,
+                    &element_names,
+                    parent,
+                ));
+            }
+            None => {
+                if parts.len() > 2
+                    || (parts.len() == 2
+                        && parts[1] != "next"
+                        && !Header::stack_names().contains_key(parts[1]))
+                {
+                    diags.push(Diagnostic {
+                        level: Level::Error,
+                        category: None,
+                        code: "E0023",
+                        message: format!(
+                            "type {} does not have a member {}",
+                            format!("{}[{}]", name, size).bright_blue(),
+                            parts[1].bright_blue(),
+                        ),
+                        token: lval.token.clone(),
+                    });
+                }
+            }
+        },
         Type::UserDefined(name) => {
             // get the parent type definition from the AST and check for the
             // referenced member
@@ -910,6 +1909,24 @@ fn check_lvalue(
                     );
                     diags.extend(&sub_diags);
                 }
+            } else if let Some(parent) = ast.get_header_union(&name) {
+                if parts.len() > 1 {
+                    let mut union_names = names.clone();
+                    union_names.extend(parent.names());
+                    let mut token = lval.token.clone();
+                    token.col += parts[0].len() + 1;
+                    let sub_lval = Lvalue {
+                        name: parts[1..].join("."),
+                        token,
+                    };
+                    let sub_diags = check_lvalue(
+                        &sub_lval,
+                        ast,
+                        &union_names,
+                        Some(&parent.name),
+                    );
+                    diags.extend(&sub_diags);
+                }
             } else if let Some(parent) = ast.get_extern(&name) {
                 if parts.len() > 1 {
                     let mut extern_names = names.clone();
@@ -932,6 +1949,8 @@ fn check_lvalue(
                 if parts.len() > 1 && parts.last() != Some(&"apply") {
                     diags.push(Diagnostic {
                         level: Level::Error,
+                        category: None,
+                        code: "E0023",
                         message: format!(
                             "Control {} has no member {}",
                             name.bright_blue(),
@@ -943,6 +1962,8 @@ fn check_lvalue(
             } else {
                 diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0002",
                     message: format!(
                         "type {} is not defined",
                         name.bright_blue(),
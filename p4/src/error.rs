@@ -10,6 +10,10 @@ pub struct SemanticError {
     /// Token where the error was encountered
     pub at: Token,
 
+    /// Code of the check that raised this error, see
+    /// [`crate::check::Diagnostic::code`].
+    pub code: &'static str,
+
     /// Message associated with this error.
     pub message: String,
 
@@ -19,7 +23,8 @@ pub struct SemanticError {
 
 impl fmt::Display for SemanticError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt_common(&self.at, &self.message, &self.source, f)
+        let label = format!("error[{}]", self.code);
+        fmt_common(&label, &self.at, &self.message, &self.source, f)
     }
 }
 
@@ -39,7 +44,7 @@ pub struct ParserError {
 
 impl fmt::Display for ParserError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt_common(&self.at, &self.message, &self.source, f)
+        fmt_common("error", &self.at, &self.message, &self.source, f)
     }
 }
 
@@ -71,7 +76,7 @@ impl fmt::Display for TokenError {
             col: self.col,
             file: Arc::new(self.source.clone()),
         };
-        fmt_common(&at, "unrecognized token", &self.source, f)
+        fmt_common("error", &at, "unrecognized token", &self.source, f)
     }
 }
 
@@ -176,6 +181,7 @@ fn carat_line(line: &str, at: &Token) -> String {
 }
 
 fn fmt_common(
+    label: &str,
     at: &Token,
     message: &str,
     source: &str,
@@ -194,7 +200,7 @@ fn fmt_common(
     writeln!(
         f,
         "{}: {}{}\n{} {}\n",
-        "error".bright_red(),
+        label.bright_red(),
         msg.bright_white().bold(),
         extra,
         loc,
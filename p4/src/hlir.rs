@@ -2,10 +2,12 @@
 
 use crate::ast::{
     BinOp, Constant, Control, DeclarationInfo, Expression, ExpressionKind,
-    Lvalue, NameInfo, Parser, Statement, StatementBlock, Type, AST,
+    Header, KeySetElementValue, Lvalue, NameInfo, Parser, Statement,
+    StatementBlock, Transition, Type, AST,
 };
 use crate::check::{Diagnostic, Diagnostics, Level};
 use crate::util::resolve_lvalue;
+use serde::{ser::SerializeStruct, Serialize, Serializer};
 use std::collections::HashMap;
 
 /// The P4 high level intermediate representation (hlir) is a slight lowering of
@@ -22,12 +24,106 @@ use std::collections::HashMap;
 pub struct Hlir {
     pub expression_types: HashMap<Expression, Type>,
     pub lvalue_decls: HashMap<Lvalue, NameInfo>,
+
+    /// The values of constants referenced by keysets, folded to the
+    /// initializer expression of the constant.
+    pub constant_values: HashMap<Lvalue, Expression>,
+}
+
+impl Hlir {
+    /// The value of a keyset expression, with a reference to a constant
+    /// replaced by the value of the constant.
+    pub fn constant_value<'a>(&'a self, xpr: &'a Expression) -> &'a Expression {
+        match &xpr.kind {
+            ExpressionKind::Lvalue(lval) => {
+                self.constant_values.get(lval).unwrap_or(xpr)
+            }
+            _ => xpr,
+        }
+    }
+}
+
+/// The hlir serializes its maps as lists of entries ordered by source
+/// location, so the same program always produces the same output.
+impl Serialize for Hlir {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct ExpressionType<'a> {
+            expression: &'a Expression,
+            #[serde(rename = "type")]
+            ty: &'a Type,
+        }
+
+        #[derive(Serialize)]
+        struct LvalueDecl<'a> {
+            lvalue: &'a Lvalue,
+            info: &'a NameInfo,
+        }
+
+        let mut expression_types: Vec<ExpressionType> = self
+            .expression_types
+            .iter()
+            .map(|(expression, ty)| ExpressionType { expression, ty })
+            .collect();
+        expression_types.sort_by_cached_key(|e| {
+            let t = &e.expression.token;
+            (
+                t.file.clone(),
+                t.line,
+                t.col,
+                format!("{:?}", e.expression.kind),
+            )
+        });
+
+        let mut lvalue_decls: Vec<LvalueDecl> = self
+            .lvalue_decls
+            .iter()
+            .map(|(lvalue, info)| LvalueDecl { lvalue, info })
+            .collect();
+        lvalue_decls.sort_by(|a, b| {
+            let (x, y) = (&a.lvalue.token, &b.lvalue.token);
+            (&x.file, x.line, x.col, &a.lvalue.name).cmp(&(
+                &y.file,
+                y.line,
+                y.col,
+                &b.lvalue.name,
+            ))
+        });
+
+        #[derive(Serialize)]
+        struct ConstantValue<'a> {
+            lvalue: &'a Lvalue,
+            value: &'a Expression,
+        }
+
+        let mut constant_values: Vec<ConstantValue> = self
+            .constant_values
+            .iter()
+            .map(|(lvalue, value)| ConstantValue { lvalue, value })
+            .collect();
+        constant_values.sort_by(|a, b| {
+            let (x, y) = (&a.lvalue.token, &b.lvalue.token);
+            (&x.file, x.line, x.col).cmp(&(&y.file, y.line, y.col))
+        });
+
+        let mut st = s.serialize_struct("Hlir", 3)?;
+        st.serialize_field("expression_types", &expression_types)?;
+        st.serialize_field("lvalue_decls", &lvalue_decls)?;
+        st.serialize_field("constant_values", &constant_values)?;
+        st.end()
+    }
 }
 
 pub struct HlirGenerator<'a> {
     ast: &'a AST,
     pub hlir: Hlir,
     pub diags: Diagnostics,
+
+    /// The constants in scope, by name.
+    constants: HashMap<String, Constant>,
+
+    /// The control being lowered, action calls are resolved against it.
+    control: Option<&'a Control>,
 }
 
 impl<'a> HlirGenerator<'a> {
@@ -36,6 +132,8 @@ impl<'a> HlirGenerator<'a> {
             ast,
             hlir: Hlir::default(),
             diags: Diagnostics::default(),
+            constants: HashMap::new(),
+            control: None,
         }
     }
     pub fn run(&mut self) {
@@ -50,12 +148,21 @@ impl<'a> HlirGenerator<'a> {
         }
     }
 
-    fn constant(&mut self, _c: &Constant) {
-        // TODO
+    fn constant(&mut self, c: &Constant) {
+        self.constants.insert(c.name.clone(), c.clone());
     }
 
-    fn control(&mut self, c: &Control) {
+    fn control(&mut self, c: &'a Control) {
+        self.control = Some(c);
+        let globals = self.constants.clone();
+        for k in &c.constants {
+            self.constant(k);
+        }
         let mut names = c.names();
+        // a constant may be initialized from another constant of the control
+        for k in &c.constants {
+            self.expression(k.initializer.as_ref(), &mut names);
+        }
         for a in &c.actions {
             let mut local_names = names.clone();
             local_names.extend(a.names());
@@ -69,8 +176,15 @@ impl<'a> HlirGenerator<'a> {
             for lval in &t.actions {
                 self.lvalue(lval, &mut local_names);
             }
+            for entry in &t.const_entries {
+                for k in &entry.keyset {
+                    self.keyset_constants(&k.value);
+                }
+            }
         }
         self.statement_block(&c.apply, &mut names);
+        self.constants = globals;
+        self.control = None;
     }
 
     fn statement_block(
@@ -82,14 +196,26 @@ impl<'a> HlirGenerator<'a> {
             match stmt {
                 Statement::Empty => {}
                 Statement::Assignment(lval, xpr) => {
-                    self.lvalue(lval, names);
+                    let ty = self.lvalue(lval, names);
+                    self.typed_expression(xpr, ty.as_ref(), names);
+                }
+                Statement::TupleAssignment(lvals, xpr) => {
+                    for lval in lvals {
+                        self.lvalue(lval, names);
+                    }
                     self.expression(xpr, names);
                 }
                 Statement::Call(c) => {
                     // pop the function name off the lval before resolving
                     self.lvalue(&c.lval.pop_right(), names);
-                    for xpr in &c.args {
-                        self.expression(xpr.as_ref(), names);
+                    let action = self
+                        .control
+                        .and_then(|ctl| ctl.get_action(&c.lval.name));
+                    for (i, xpr) in c.args.iter().enumerate() {
+                        let ty = action
+                            .and_then(|a| a.parameters.get(i))
+                            .map(|p| &p.ty);
+                        self.typed_expression(xpr.as_ref(), ty, names);
                     }
                 }
                 Statement::If(ifb) => {
@@ -112,7 +238,7 @@ impl<'a> HlirGenerator<'a> {
                         },
                     );
                     if let Some(initializer) = &v.initializer {
-                        self.expression(initializer, names);
+                        self.typed_expression(initializer, Some(&v.ty), names);
                     }
                 }
                 Statement::Constant(c) => {
@@ -124,19 +250,125 @@ impl<'a> HlirGenerator<'a> {
                         },
                     );
                     self.expression(c.initializer.as_ref(), names);
+                    self.constant(c);
                 }
-                Statement::Transition(_t) => {
-                    //TODO
+                Statement::Transition(Transition::Select(sel)) => {
+                    for xpr in &sel.parameters {
+                        self.expression(xpr.as_ref(), names);
+                    }
+                    for e in &sel.elements {
+                        for k in &e.keyset {
+                            self.key_set_element_value(&k.value, names);
+                        }
+                    }
                 }
+                Statement::Transition(Transition::Reference(_)) => {}
                 Statement::Return(xpr) => {
                     if let Some(xpr) = xpr {
                         self.expression(xpr.as_ref(), names);
                     }
                 }
+                Statement::Block(blk) => {
+                    self.statement_block(&blk.block, names);
+                }
             }
         }
     }
 
+    fn key_set_element_value(
+        &mut self,
+        value: &KeySetElementValue,
+        names: &mut HashMap<String, NameInfo>,
+    ) {
+        // constants referenced by the keyset are resolved along with the
+        // names in scope
+        let mut names = names.clone();
+        for (name, c) in &self.constants {
+            names.entry(name.clone()).or_insert_with(|| NameInfo {
+                ty: c.ty.clone(),
+                decl: DeclarationInfo::Local,
+            });
+        }
+        match value {
+            KeySetElementValue::Expression(xpr) => match &xpr.kind {
+                // the default keyset parses as an lvalue
+                ExpressionKind::Lvalue(lval) if lval.name == "default" => {}
+                _ => {
+                    self.expression(xpr.as_ref(), &mut names);
+                }
+            },
+            KeySetElementValue::Default | KeySetElementValue::DontCare => {}
+            KeySetElementValue::Masked(a, b)
+            | KeySetElementValue::Ranged(a, b) => {
+                self.expression(a.as_ref(), &mut names);
+                self.expression(b.as_ref(), &mut names);
+            }
+        }
+        self.keyset_constants(value);
+    }
+
+    /// Fold references to constants in a keyset to their values. Keysets
+    /// are matched against at compile time, so any other name is an error.
+    fn keyset_constants(&mut self, value: &KeySetElementValue) {
+        match value {
+            KeySetElementValue::Expression(xpr) => {
+                self.keyset_constant(xpr.as_ref())
+            }
+            KeySetElementValue::Default | KeySetElementValue::DontCare => {}
+            KeySetElementValue::Masked(a, b)
+            | KeySetElementValue::Ranged(a, b) => {
+                self.keyset_constant(a.as_ref());
+                self.keyset_constant(b.as_ref());
+            }
+        }
+    }
+
+    fn keyset_constant(&mut self, xpr: &Expression) {
+        match &xpr.kind {
+            // the default keyset parses as an lvalue
+            ExpressionKind::Lvalue(lval) if lval.name == "default" => {}
+            ExpressionKind::Lvalue(lval) => {
+                match self.fold_constant(&lval.name) {
+                    Some(value) => {
+                        self.hlir.constant_values.insert(lval.clone(), value);
+                    }
+                    None => self.diags.push(Diagnostic {
+                        level: Level::Error,
+                        category: None,
+                        code: "E0026",
+                        message: format!(
+                            "{} is not a compile-time constant, keysets may \
+                            only reference constants",
+                            lval.name,
+                        ),
+                        token: lval.token.clone(),
+                    }),
+                }
+            }
+            ExpressionKind::Binary(a, BinOp::Mask, b) => {
+                self.keyset_constant(a.as_ref());
+                self.keyset_constant(b.as_ref());
+            }
+            _ => {}
+        }
+    }
+
+    // The value of the named constant, following constants initialized from
+    // other constants.
+    fn fold_constant(&self, name: &str) -> Option<Expression> {
+        let mut c = self.constants.get(name)?;
+        for _ in 0..self.constants.len() {
+            match &c.initializer.kind {
+                ExpressionKind::Lvalue(lval) => {
+                    c = self.constants.get(&lval.name)?;
+                }
+                _ => return Some(c.initializer.as_ref().clone()),
+            }
+        }
+        // constants initialized from each other in a cycle
+        None
+    }
+
     fn expression(
         &mut self,
         xpr: &Expression,
@@ -183,6 +415,8 @@ impl<'a> HlirGenerator<'a> {
             ExpressionKind::Slice(end, _begin) => {
                 self.diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0027",
                     message: "slice cannot occur outside of an index".into(),
                     token: end.token.clone(),
                 });
@@ -206,11 +440,18 @@ impl<'a> HlirGenerator<'a> {
                         }
                     }
                 };
+                if Header::SIZE_METHODS.contains(&call.lval.leaf()) {
+                    let ty = Type::Int(128);
+                    self.hlir.expression_types.insert(xpr.clone(), ty.clone());
+                    return Some(ty);
+                }
                 //TODO less special case-y?
-                Some(match call.lval.leaf() {
+                let ty = match call.lval.leaf() {
                     "isValid" => Type::Bool,
                     _ => Type::Void,
-                })
+                };
+                self.hlir.expression_types.insert(xpr.clone(), ty.clone());
+                Some(ty)
             }
             ExpressionKind::List(elements) => {
                 let mut type_elements = Vec::new();
@@ -223,7 +464,130 @@ impl<'a> HlirGenerator<'a> {
                 }
                 Some(Type::List(type_elements))
             }
+            ExpressionKind::StructLit(_) => {
+                self.diags.push(Diagnostic {
+                    level: Level::Error,
+                    category: None,
+                    code: "E0037",
+                    message: "cannot determine the type of struct literal"
+                        .into(),
+                    token: xpr.token.clone(),
+                });
+                None
+            }
+        }
+    }
+
+    /// Resolve the type of `xpr` where a value of type `expected` is wanted.
+    /// A struct literal takes its type from where it is used.
+    fn typed_expression(
+        &mut self,
+        xpr: &Expression,
+        expected: Option<&Type>,
+        names: &mut HashMap<String, NameInfo>,
+    ) -> Option<Type> {
+        match (&xpr.kind, expected) {
+            (ExpressionKind::StructLit(members), Some(ty)) => {
+                self.struct_literal(xpr, members, ty, names)
+            }
+            _ => self.expression(xpr, names),
+        }
+    }
+
+    /// Check the members of a struct literal against the struct `ty` it
+    /// initializes. Every member must be initialized exactly once, with a
+    /// value of its type or an integer literal for a bit member.
+    fn struct_literal(
+        &mut self,
+        xpr: &Expression,
+        members: &[(String, Box<Expression>)],
+        ty: &Type,
+        names: &mut HashMap<String, NameInfo>,
+    ) -> Option<Type> {
+        let decl = match ty {
+            Type::UserDefined(name) => self.ast.get_struct(name),
+            _ => None,
+        };
+        let decl = match decl {
+            Some(decl) => decl,
+            None => {
+                self.diags.push(Diagnostic {
+                    level: Level::Error,
+                    category: None,
+                    code: "E0037",
+                    message: format!("cannot initialize {} with a struct", ty),
+                    token: xpr.token.clone(),
+                });
+                return None;
+            }
+        };
+
+        let mut seen = Vec::new();
+        for (name, value) in members {
+            let member = match decl.members.iter().find(|m| &m.name == name) {
+                Some(m) => m,
+                None => {
+                    self.diags.push(Diagnostic {
+                        level: Level::Error,
+                        category: None,
+                        code: "E0037",
+                        message: format!(
+                            "struct {} has no member {}",
+                            decl.name, name,
+                        ),
+                        token: value.token.clone(),
+                    });
+                    continue;
+                }
+            };
+            if seen.contains(&name) {
+                self.diags.push(Diagnostic {
+                    level: Level::Error,
+                    category: None,
+                    code: "E0037",
+                    message: format!("member {} initialized twice", name),
+                    token: value.token.clone(),
+                });
+            }
+            seen.push(name);
+
+            let value_ty =
+                match self.typed_expression(value, Some(&member.ty), names) {
+                    Some(ty) => ty,
+                    None => continue,
+                };
+            let literal = matches!(
+                (&value.kind, &member.ty),
+                (ExpressionKind::IntegerLit(_), Type::Bit(_))
+            );
+            if value_ty != member.ty && !literal {
+                self.diags.push(Diagnostic {
+                    level: Level::Error,
+                    category: None,
+                    code: "E0037",
+                    message: format!(
+                        "cannot initialize member {} of type {} with {}",
+                        name, member.ty, value_ty,
+                    ),
+                    token: value.token.clone(),
+                });
+            }
         }
+
+        for m in &decl.members {
+            if !seen.contains(&&m.name) {
+                self.diags.push(Diagnostic {
+                    level: Level::Error,
+                    category: None,
+                    code: "E0037",
+                    message: format!("member {} is not initialized", m.name),
+                    token: xpr.token.clone(),
+                });
+            }
+        }
+
+        self.hlir.expression_types.insert(xpr.clone(), ty.clone());
+        Some(ty.clone())
     }
 
     fn index(
@@ -240,6 +604,8 @@ impl<'a> HlirGenerator<'a> {
             Type::Bool => {
                 self.diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0028",
                     message: "cannot index a bool".into(),
                     token: lval.token.clone(),
                 });
@@ -248,6 +614,8 @@ impl<'a> HlirGenerator<'a> {
             Type::State => {
                 self.diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0028",
                     message: "cannot index a state".into(),
                     token: lval.token.clone(),
                 });
@@ -256,6 +624,8 @@ impl<'a> HlirGenerator<'a> {
             Type::Action => {
                 self.diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0028",
                     message: "cannot index an action".into(),
                     token: lval.token.clone(),
                 });
@@ -264,6 +634,8 @@ impl<'a> HlirGenerator<'a> {
             Type::Error => {
                 self.diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0028",
                     message: "cannot index an error".into(),
                     token: lval.token.clone(),
                 });
@@ -272,6 +644,8 @@ impl<'a> HlirGenerator<'a> {
             Type::Void => {
                 self.diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0028",
                     message: "cannot index a void".into(),
                     token: lval.token.clone(),
                 });
@@ -280,6 +654,8 @@ impl<'a> HlirGenerator<'a> {
             Type::List(_) => {
                 self.diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0028",
                     message: "cannot index a list".into(),
                     token: lval.token.clone(),
                 });
@@ -294,6 +670,8 @@ impl<'a> HlirGenerator<'a> {
                 _ => {
                     self.diags.push(Diagnostic {
                         level: Level::Error,
+                        category: None,
+                        code: "E0029",
                         message: "only slices supported as index arguments"
                             .into(),
                         token: lval.token.clone(),
@@ -310,6 +688,8 @@ impl<'a> HlirGenerator<'a> {
                 _ => {
                     self.diags.push(Diagnostic {
                         level: Level::Error,
+                        category: None,
+                        code: "E0029",
                         message: "only slices supported as index arguments"
                             .into(),
                         token: lval.token.clone(),
@@ -326,6 +706,8 @@ impl<'a> HlirGenerator<'a> {
                 _ => {
                     self.diags.push(Diagnostic {
                         level: Level::Error,
+                        category: None,
+                        code: "E0029",
                         message: "only slices supported as index arguments"
                             .into(),
                         token: lval.token.clone(),
@@ -336,6 +718,8 @@ impl<'a> HlirGenerator<'a> {
             Type::String => {
                 self.diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0028",
                     message: "cannot index a string".into(),
                     token: lval.token.clone(),
                 });
@@ -344,6 +728,8 @@ impl<'a> HlirGenerator<'a> {
             Type::UserDefined(_) => {
                 self.diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0028",
                     message: "cannot index a user defined type".into(),
                     token: lval.token.clone(),
                 });
@@ -352,6 +738,8 @@ impl<'a> HlirGenerator<'a> {
             Type::ExternFunction => {
                 self.diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0028",
                     message: "cannot index an external function".into(),
                     token: lval.token.clone(),
                 });
@@ -360,14 +748,31 @@ impl<'a> HlirGenerator<'a> {
             Type::HeaderMethod => {
                 self.diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0028",
                     message: "cannot index a header method".into(),
                     token: lval.token.clone(),
                 });
                 None
             }
+            Type::HeaderStack(..) => {
+                // constant indices are part of the lvalue itself
+                self.diags.push(Diagnostic {
+                    level: Level::Error,
+                    category: None,
+                    code: "E0030",
+                    message: "header stacks may only be indexed by integer \
+                        literals"
+                        .into(),
+                    token: lval.token.clone(),
+                });
+                None
+            }
             Type::Table => {
                 self.diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0028",
                     message: "cannot index a table".into(),
                     token: lval.token.clone(),
                 });
@@ -391,6 +796,8 @@ impl<'a> HlirGenerator<'a> {
             _ => {
                 self.diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0031",
                     message:
                         "only interger literals are supported as slice bounds"
                             .into(),
@@ -404,6 +811,8 @@ impl<'a> HlirGenerator<'a> {
             _ => {
                 self.diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0031",
                     message:
                         "only interger literals are supported as slice bounds"
                             .into(),
@@ -416,6 +825,8 @@ impl<'a> HlirGenerator<'a> {
         if begin_val < 0 || begin_val >= w {
             self.diags.push(Diagnostic {
                 level: Level::Error,
+                category: None,
+                code: "E0032",
                 message: "slice begin value out of bounds".into(),
                 token: begin.token.clone(),
             });
@@ -424,6 +835,8 @@ impl<'a> HlirGenerator<'a> {
         if end_val < 0 || end_val >= w {
             self.diags.push(Diagnostic {
                 level: Level::Error,
+                category: None,
+                code: "E0032",
                 message: "slice end value out of bounds".into(),
                 token: begin.token.clone(),
             });
@@ -432,6 +845,8 @@ impl<'a> HlirGenerator<'a> {
         if begin_val >= end_val {
             self.diags.push(Diagnostic {
                 level: Level::Error,
+                category: None,
+                code: "E0033",
                 message: "slice upper bound must be \
                     greater than the lower bound"
                     .into(),
@@ -449,6 +864,18 @@ impl<'a> HlirGenerator<'a> {
     ) -> Option<Type> {
         match resolve_lvalue(lval, self.ast, names) {
             Ok(name_info) => {
+                // record the tuple an element is taken from, so code
+                // generators can tell tuple elements from stack elements.
+                let (root, index) = Lvalue::split_index(lval.root());
+                if let (Some(_), Some(info)) = (index, names.get(root)) {
+                    if matches!(info.ty, Type::List(_)) {
+                        let root = Lvalue {
+                            name: root.to_owned(),
+                            token: lval.token.clone(),
+                        };
+                        self.hlir.lvalue_decls.insert(root, info.clone());
+                    }
+                }
                 self.hlir
                     .lvalue_decls
                     .insert(lval.clone(), name_info.clone());
@@ -457,6 +884,8 @@ impl<'a> HlirGenerator<'a> {
             Err(e) => {
                 self.diags.push(Diagnostic {
                     level: Level::Error,
+                    category: None,
+                    code: "E0008",
                     message: format!(
                         "could not resolve lvalue: {}\n    {}",
                         lval.name, e,
@@ -490,6 +919,8 @@ impl<'a> HlirGenerator<'a> {
         if lhs_ty != rhs_ty {
             self.diags.push(Diagnostic {
                 level: Level::Error,
+                category: None,
+                code: "E0034",
                 message: format!(
                     "cannot {} a {} and a {}",
                     op.english_verb(),
@@ -500,17 +931,36 @@ impl<'a> HlirGenerator<'a> {
             });
         }
 
-        self.hlir
-            .expression_types
-            .insert(xpr.clone(), lhs_ty.clone());
-        Some(lhs_ty)
+        // Saturation clamps to the bounds of a fixed width integer type.
+        if matches!(op, BinOp::SaturatingAdd | BinOp::SaturatingSubtract)
+            && !matches!(lhs_ty, Type::Bit(_) | Type::Int(_))
+        {
+            self.diags.push(Diagnostic {
+                level: Level::Error,
+                category: None,
+                code: "E0034",
+                message: format!("cannot {} a {}", op.english_verb(), lhs_ty),
+                token: xpr.token.clone(),
+            });
+        }
+
+        let ty = if op.is_comparison() {
+            Type::Bool
+        } else {
+            lhs_ty
+        };
+
+        self.hlir.expression_types.insert(xpr.clone(), ty.clone());
+        Some(ty)
     }
 
     fn parser(&mut self, p: &Parser) {
+        let globals = self.constants.clone();
         let names = p.names();
         for s in &p.states {
             let mut local_names = names.clone();
             self.statement_block(&s.statements, &mut local_names);
+            self.constants = globals.clone();
         }
     }
 }
@@ -1,6 +1,7 @@
 // Copyright 2022 Oxide Computer Company
 
 use crate::error::TokenError;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
 use std::fmt;
 use std::sync::Arc;
 
@@ -11,6 +12,7 @@ pub enum Kind {
     //
     Const,
     Header,
+    HeaderUnion,
     Typedef,
     Control,
     Struct,
@@ -64,6 +66,7 @@ pub enum Kind {
     Comma,
     Colon,
     Underscore,
+    AtSign,
 
     //
     // preprocessor
@@ -90,6 +93,8 @@ pub enum Kind {
     Tilde,
     Shl,
     Pipe,
+    SaturatingPlus,
+    SaturatingMinus,
     Carat,
     GreaterThanEquals,
     LessThanEquals,
@@ -139,6 +144,7 @@ impl fmt::Display for Kind {
             //
             Kind::Const => write!(f, "keyword const"),
             Kind::Header => write!(f, "keyword header"),
+            Kind::HeaderUnion => write!(f, "keyword header_union"),
             Kind::Typedef => write!(f, "keyword typedef"),
             Kind::Control => write!(f, "keyword control"),
             Kind::Struct => write!(f, "keyword struct"),
@@ -192,6 +198,7 @@ impl fmt::Display for Kind {
             Kind::Comma => write!(f, ","),
             Kind::Colon => write!(f, ":"),
             Kind::Underscore => write!(f, "_"),
+            Kind::AtSign => write!(f, "@"),
 
             //
             // preprocessor
@@ -218,6 +225,8 @@ impl fmt::Display for Kind {
             Kind::Tilde => write!(f, "operator ~"),
             Kind::Shl => write!(f, "operator <<"),
             Kind::Pipe => write!(f, "operator |"),
+            Kind::SaturatingPlus => write!(f, "operator |+|"),
+            Kind::SaturatingMinus => write!(f, "operator |-|"),
             Kind::Carat => write!(f, "operator ^"),
             Kind::GreaterThanEquals => write!(f, "operator >="),
             Kind::LessThanEquals => write!(f, "operator <="),
@@ -255,6 +264,20 @@ pub struct Token {
     pub file: Arc<String>,
 }
 
+/// Tokens serialize as their kind and source location. The kind is rendered
+/// through its `Display` form so the output does not depend on the layout of
+/// the `Kind` enum.
+impl Serialize for Token {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let mut st = s.serialize_struct("Token", 4)?;
+        st.serialize_field("kind", &self.kind.to_string())?;
+        st.serialize_field("file", self.file.as_str())?;
+        st.serialize_field("line", &self.line)?;
+        st.serialize_field("col", &self.col)?;
+        st.end()
+    }
+}
+
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}:{}: {:?}", self.line, self.col, self.kind)
@@ -296,6 +319,18 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    /// Lex all remaining tokens up to, but not including, the end of file.
+    pub fn tokenize(&mut self) -> Result<Vec<Token>, TokenError> {
+        let mut tokens = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token.kind == Kind::Eof {
+                return Ok(tokens);
+            }
+            tokens.push(token);
+        }
+    }
+
     #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Result<Token, TokenError> {
         let token = self.do_next()?;
@@ -405,6 +440,14 @@ impl<'a> Lexer<'a> {
             return Ok(t);
         }
 
+        if let Some(t) = self.match_token("|+|", Kind::SaturatingPlus) {
+            return Ok(t);
+        }
+
+        if let Some(t) = self.match_token("|-|", Kind::SaturatingMinus) {
+            return Ok(t);
+        }
+
         if let Some(t) = self.match_token("|", Kind::Pipe) {
             return Ok(t);
         }
@@ -481,6 +524,10 @@ impl<'a> Lexer<'a> {
             return Ok(t);
         }
 
+        if let Some(t) = self.match_token("@", Kind::AtSign) {
+            return Ok(t);
+        }
+
         if let Some(t) = self.match_token(";", Kind::Semicolon) {
             return Ok(t);
         }
@@ -517,6 +564,10 @@ impl<'a> Lexer<'a> {
             return Ok(t);
         }
 
+        if let Some(t) = self.match_token("error", Kind::Error) {
+            return Ok(t);
+        }
+
         if let Some(t) = self.match_token("varbit", Kind::Varbit) {
             return Ok(t);
         }
@@ -533,6 +584,10 @@ impl<'a> Lexer<'a> {
             return Ok(t);
         }
 
+        if let Some(t) = self.match_token("header_union", Kind::HeaderUnion) {
+            return Ok(t);
+        }
+
         if let Some(t) = self.match_token("header", Kind::Header) {
             return Ok(t);
         }
@@ -606,6 +661,10 @@ impl<'a> Lexer<'a> {
             return Ok(t);
         }
 
+        if let Some(t) = self.match_string_literal()? {
+            return Ok(t);
+        }
+
         if let Some(t) = self.match_integer() {
             return Ok(t);
         }
@@ -625,6 +684,44 @@ impl<'a> Lexer<'a> {
         })
     }
 
+    /// A double quoted string, which may contain `\"` and `\\` escapes and
+    /// must end on the line it starts on.
+    fn match_string_literal(&mut self) -> Result<Option<Token>, TokenError> {
+        let cursor = self.cursor;
+        if !cursor.starts_with('"') {
+            return Ok(None);
+        }
+        let mut value = String::new();
+        let mut chars = cursor.char_indices().skip(1);
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    let token = Token {
+                        kind: Kind::StringLiteral(value),
+                        col: self.col,
+                        line: self.line,
+                        file: self.file.clone(),
+                    };
+                    self.col += i + 1;
+                    self.cursor = &cursor[i + 1..];
+                    return Ok(Some(token));
+                }
+                '\\' => match chars.next() {
+                    Some((_, c)) => value.push(c),
+                    None => break,
+                },
+                c => value.push(c),
+            }
+        }
+        Err(TokenError {
+            line: self.line,
+            col: self.col,
+            source: self.lines[self.line].into(),
+            file: self.file.clone(),
+            len: cursor.len(),
+        })
+    }
+
     fn match_identifier(&mut self) -> Option<Token> {
         let tok = self.peek_token();
         let len = tok.len();
@@ -665,17 +762,15 @@ impl<'a> Lexer<'a> {
             Ok(n) => n,
             Err(_) => return None,
         };
-        let value = if tok[n + 1..].starts_with("0x") {
-            match u128::from_str_radix(&tok[n + 3..], 16) {
-                Ok(n) => n,
-                Err(_) => return None,
-            }
-        } else {
-            match tok[n + 1..].parse::<u128>() {
-                Ok(n) => n,
-                Err(_) => return None,
-            }
+        let (digits, radix) = split_radix(&tok[n + 1..])?;
+        let value = match u128::from_str_radix(&digits, radix) {
+            Ok(n) => n,
+            Err(_) => return None,
         };
+        // the value must fit in the width
+        if bits < 128 && value >> bits != 0 {
+            return None;
+        }
         let token = Token {
             kind: ctor(bits, value),
             col: self.col,
@@ -697,16 +792,10 @@ impl<'a> Lexer<'a> {
             Ok(n) => n,
             Err(_) => return None,
         };
-        let value = if tok[n + 1..].starts_with("0x") {
-            match i128::from_str_radix(&tok[n + 3..], 16) {
-                Ok(n) => n,
-                Err(_) => return None,
-            }
-        } else {
-            match tok[n + 1..].parse::<i128>() {
-                Ok(n) => n,
-                Err(_) => return None,
-            }
+        let (digits, radix) = split_radix(&tok[n + 1..])?;
+        let value = match i128::from_str_radix(&digits, radix) {
+            Ok(n) => n,
+            Err(_) => return None,
         };
         let token = Token {
             kind: ctor(bits, value),
@@ -768,23 +857,8 @@ impl<'a> Lexer<'a> {
             }
         }
 
-        let value = if let Some(tok) = tok.strip_prefix("0x") {
-            let chars = tok.chars();
-            for c in chars {
-                if !c.is_ascii_hexdigit() {
-                    return None;
-                }
-            }
-            i128::from_str_radix(tok, 16).expect("parse hex int")
-        } else {
-            let chars = tok.chars();
-            for c in chars {
-                if !c.is_ascii_digit() {
-                    return None;
-                }
-            }
-            tok.parse::<i128>().expect("parse int")
-        };
+        let (digits, radix) = split_radix(tok)?;
+        let value = i128::from_str_radix(&digits, radix).ok()?;
         let token = Token {
             kind: Kind::IntLiteral(value),
             col: self.col,
@@ -957,11 +1031,15 @@ impl<'a> Lexer<'a> {
             Some('.') => return &self.cursor[..1],
             Some(':') => return &self.cursor[..1],
             Some('*') => return &self.cursor[..1],
-            Some('|') => return &self.cursor[..1],
+            Some('|') => match (chars.next(), chars.next()) {
+                (Some('+' | '-'), Some('|')) => return &self.cursor[..3],
+                _ => return &self.cursor[..1],
+            },
             Some('~') => return &self.cursor[..1],
             Some('^') => return &self.cursor[..1],
             Some('\\') => return &self.cursor[..1],
             Some('/') => return &self.cursor[..1],
+            Some('@') => return &self.cursor[..1],
             Some('!') => match chars.next() {
                 Some('=') => return &self.cursor[..2],
                 _ => return &self.cursor[..1],
@@ -1072,6 +1150,36 @@ impl<'a> Lexer<'a> {
         if c == '/' {
             return true;
         }
+        if c == '@' {
+            return true;
+        }
         false
     }
 }
+
+/// The digits of a numeric literal and their radix, given by a `0x` prefix for
+/// hexadecimal, `0b` for binary and none for decimal. `None` if the literal
+/// has digits that are not of its radix.
+fn split_radix(literal: &str) -> Option<(String, u32)> {
+    let (digits, radix) = match literal.get(..2) {
+        Some("0x" | "0X") => (&literal[2..], 16),
+        Some("0b" | "0B") => (&literal[2..], 2),
+        _ => (literal, 10),
+    };
+    if digits.is_empty()
+        || !digits.chars().all(|c| c.is_digit(radix) || c == '_')
+    {
+        return None;
+    }
+    Some((strip_separators(digits)?, radix))
+}
+
+/// The digits of a numeric literal without the underscores that may separate
+/// them, e.g. `DEADBEEF` for `DEAD_BEEF`. `None` if an underscore starts or
+/// ends the digits.
+fn strip_separators(digits: &str) -> Option<String> {
+    if digits.starts_with('_') || digits.ends_with('_') {
+        return None;
+    }
+    Some(digits.replace('_', ""))
+}
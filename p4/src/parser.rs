@@ -1,22 +1,28 @@
 // Copyright 2022 Oxide Computer Company
 
 use crate::ast::{
-    self, Action, ActionParameter, ActionRef, BinOp, Call, ConstTableEntry,
-    Constant, Control, ControlParameter, Direction, ElseIfBlock, Expression,
-    ExpressionKind, Extern, ExternMethod, Header, HeaderMember, IfBlock,
-    KeySetElement, KeySetElementValue, Lvalue, MatchKind, Package,
-    PackageInstance, PackageParameter, Select, SelectElement, State, Statement,
-    StatementBlock, Struct, StructMember, Table, Transition, Type, Typedef,
-    Variable, AST,
+    self, Action, ActionParameter, ActionRef, Annotation, BinOp,
+    BlockStatement, Call, ConstTableEntry, Constant, Control, ControlParameter,
+    Direction, ElseIfBlock, Expression, ExpressionKind, Extern, ExternMethod,
+    Header, HeaderMember, HeaderUnion, IfBlock, KeySetElement,
+    KeySetElementValue, Lvalue, MatchKind, Package, PackageInstance,
+    PackageParameter, Select, SelectElement, State, Statement, StatementBlock,
+    Struct, StructMember, Table, Transition, Type, Typedef, Variable, AST,
 };
 use crate::error::{Error, ParserError};
 use crate::lexer::{self, Kind, Lexer, Token};
 use colored::Colorize;
 
+/// The default limit on how deeply expressions and statement blocks may nest
+/// within each other. See [`Parser::set_max_depth`].
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
 /// This is a recurisve descent parser for the P4 language.
 pub struct Parser<'a> {
     lexer: Lexer<'a>,
     backlog: Vec<Token>,
+    depth: usize,
+    max_depth: usize,
 }
 
 impl<'a> Parser<'a> {
@@ -24,9 +30,53 @@ impl<'a> Parser<'a> {
         Parser {
             lexer,
             backlog: Vec::new(),
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
         }
     }
 
+    /// Create a parser over tokens that were already lexed from `lexer`, as
+    /// returned by `Lexer::tokenize`.
+    pub fn with_tokens(lexer: Lexer<'a>, mut tokens: Vec<Token>) -> Self {
+        tokens.reverse();
+        Parser {
+            lexer,
+            backlog: tokens,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    /// Set how deeply expressions and statement blocks may nest within each
+    /// other. The parser recurses for each level of nesting, so this bounds
+    /// the stack used on deeply nested input. Input that nests deeper is
+    /// rejected with a parser error.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    // Enter a nested expression or statement block starting at `token`.
+    // Every successful call must be paired with a call to `leave`.
+    fn enter(&mut self, token: &Token) -> Result<(), Error> {
+        if self.depth >= self.max_depth {
+            return Err(ParserError {
+                at: token.clone(),
+                message: format!(
+                    "Nesting exceeds the maximum depth of {}.",
+                    self.max_depth,
+                ),
+                source: self.lexer.lines[token.line].into(),
+            }
+            .into());
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
     pub fn run(&mut self, ast: &mut AST) -> Result<(), Error> {
         let mut gp = GlobalParser::new(self);
         gp.run(ast)?;
@@ -113,6 +163,9 @@ impl<'a> Parser<'a> {
                 None => first_token = Some(tk),
             }
             name = name + &ident;
+            if let Some(index) = self.parse_optional_stack_size()? {
+                name += &format!("[{}]", index);
+            }
             let token = self.next_token()?;
             match token.kind {
                 lexer::Kind::Dot => name += ".",
@@ -147,8 +200,16 @@ impl<'a> Parser<'a> {
                     Type::Int(self.parse_optional_width_parameter()?)
                 }
 
+                lexer::Kind::Identifier(name) if name == "tuple" => {
+                    let elements = self.parse_tuple_element_types()?;
+                    Type::List(elements.into_iter().map(Box::new).collect())
+                }
+
                 lexer::Kind::Identifier(name) => {
-                    Type::UserDefined(name.clone())
+                    match self.parse_optional_stack_size()? {
+                        Some(size) => Type::HeaderStack(name.clone(), size),
+                        None => Type::UserDefined(name.clone()),
+                    }
                 }
 
                 _ => {
@@ -169,6 +230,64 @@ impl<'a> Parser<'a> {
         ))
     }
 
+    /// Consume a constant bracketed integer such as the `[2]` in a header stack
+    /// type `vlan_h[2]` or index `vlan[0]`. Anything else, including a bit
+    /// slice `[7:0]`, is left in place.
+    fn parse_optional_stack_size(&mut self) -> Result<Option<usize>, Error> {
+        let open = self.next_token()?;
+        if open.kind != lexer::Kind::SquareOpen {
+            self.backlog.push(open);
+            return Ok(None);
+        }
+        let value = self.next_token()?;
+        let n = match value.kind {
+            lexer::Kind::IntLiteral(n) => n,
+            _ => {
+                self.backlog.push(value);
+                self.backlog.push(open);
+                return Ok(None);
+            }
+        };
+        let close = self.next_token()?;
+        if close.kind != lexer::Kind::SquareClose {
+            self.backlog.push(close);
+            self.backlog.push(value);
+            self.backlog.push(open);
+            return Ok(None);
+        }
+        Ok(Some(n as usize))
+    }
+
+    /// Parse the element types of a tuple type such as `tuple<bit<16>, bool>`.
+    fn parse_tuple_element_types(&mut self) -> Result<Vec<Type>, Error> {
+        self.expect_token(lexer::Kind::AngleOpen)?;
+
+        let mut result = Vec::new();
+        loop {
+            let (ty, _) = self.parse_type()?;
+            result.push(ty);
+
+            let token = self.next_token()?;
+            match token.kind {
+                lexer::Kind::AngleClose => break,
+                lexer::Kind::Comma => continue,
+                _ => {
+                    return Err(ParserError {
+                        at: token.clone(),
+                        message: format!(
+                            "Found {} expected: tuple element type",
+                            token.kind,
+                        ),
+                        source: self.lexer.lines[token.line].into(),
+                    }
+                    .into())
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
     fn parse_optional_width_parameter(&mut self) -> Result<usize, Error> {
         let token = self.next_token()?;
         match &token.kind {
@@ -202,6 +321,63 @@ impl<'a> Parser<'a> {
         Ok(*width as usize)
     }
 
+    /// Consume any annotations at the current position. The body of an
+    /// annotation is kept as an unparsed token sequence.
+    pub fn parse_annotations(&mut self) -> Result<Vec<Annotation>, Error> {
+        let mut result = Vec::new();
+        loop {
+            let token = self.next_token()?;
+            if token.kind != lexer::Kind::AtSign {
+                self.backlog.push(token);
+                break;
+            }
+            let (name, _) = self.parse_identifier("annotation name")?;
+            let mut annotation = Annotation {
+                name,
+                body: Vec::new(),
+                token,
+            };
+
+            let token = self.next_token()?;
+            if token.kind != lexer::Kind::ParenOpen {
+                self.backlog.push(token);
+                result.push(annotation);
+                continue;
+            }
+
+            // collect everything up to the matching close paren
+            let mut depth = 1;
+            loop {
+                let token = self.next_token()?;
+                match token.kind {
+                    lexer::Kind::ParenOpen => depth += 1,
+                    lexer::Kind::ParenClose => {
+                        depth -= 1;
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                    lexer::Kind::Eof => {
+                        return Err(ParserError {
+                            at: token.clone(),
+                            message: format!(
+                                "Found {} expected: end of annotation body",
+                                token.kind,
+                            ),
+                            source: self.lexer.lines[annotation.token.line]
+                                .into(),
+                        }
+                        .into())
+                    }
+                    _ => {}
+                }
+                annotation.body.push(token);
+            }
+            result.push(annotation);
+        }
+        Ok(result)
+    }
+
     pub fn parse_direction(&mut self) -> Result<(Direction, Token), Error> {
         let token = self.next_token()?;
         match token.kind {
@@ -255,6 +431,14 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Whether the next token is an identifier, without consuming it.
+    fn identifier_ahead(&mut self) -> Result<bool, Error> {
+        let token = self.next_token()?;
+        let ahead = matches!(token.kind, lexer::Kind::Identifier(_));
+        self.backlog.push(token);
+        Ok(ahead)
+    }
+
     pub fn parse_constant(&mut self) -> Result<Constant, Error> {
         let (ty, _) = self.parse_type()?;
         let (name, _) = self.parse_identifier("constant name")?;
@@ -265,6 +449,7 @@ impl<'a> Parser<'a> {
             ty,
             name,
             initializer,
+            annotations: Vec::new(),
         })
     }
 
@@ -447,6 +632,8 @@ impl<'a> Parser<'a> {
             lexer::Kind::Mask => Ok(Some(BinOp::Mask)),
             lexer::Kind::And => Ok(Some(BinOp::BitAnd)),
             lexer::Kind::Pipe => Ok(Some(BinOp::BitOr)),
+            lexer::Kind::SaturatingPlus => Ok(Some(BinOp::SaturatingAdd)),
+            lexer::Kind::SaturatingMinus => Ok(Some(BinOp::SaturatingSubtract)),
             lexer::Kind::Carat => Ok(Some(BinOp::Xor)),
 
             // TODO other binops
@@ -458,6 +645,15 @@ impl<'a> Parser<'a> {
     }
 
     pub fn parse_statement_block(&mut self) -> Result<StatementBlock, Error> {
+        let token = self.next_token()?;
+        self.backlog.push(token.clone());
+        self.enter(&token)?;
+        let result = self.parse_statement_block_inner();
+        self.leave();
+        result
+    }
+
+    fn parse_statement_block_inner(&mut self) -> Result<StatementBlock, Error> {
         let mut result = StatementBlock::default();
 
         self.expect_token(lexer::Kind::CurlyOpen)?;
@@ -479,6 +675,18 @@ impl<'a> Parser<'a> {
                     let var = self.parse_variable()?;
                     result.statements.push(Statement::Variable(var));
                 }
+                lexer::Kind::Identifier(ref name) if name == "tuple" => {
+                    self.backlog.push(token);
+                    let var = self.parse_variable()?;
+                    result.statements.push(Statement::Variable(var));
+                }
+
+                // a variable of a user defined type, e.g. `forward_t fwd`
+                lexer::Kind::Identifier(_) if self.identifier_ahead()? => {
+                    self.backlog.push(token);
+                    let var = self.parse_variable()?;
+                    result.statements.push(Statement::Variable(var));
+                }
 
                 // constant declaration / initialization
                 lexer::Kind::Const => {
@@ -489,7 +697,8 @@ impl<'a> Parser<'a> {
 
                 lexer::Kind::Identifier(_)
                 | lexer::Kind::If
-                | lexer::Kind::Return => {
+                | lexer::Kind::Return
+                | lexer::Kind::ParenOpen => {
                     // push the identifier token into the backlog and run the
                     // statement parser
                     self.backlog.push(token);
@@ -503,6 +712,17 @@ impl<'a> Parser<'a> {
                         .push(Statement::Transition(self.parse_transition()?));
                 }
 
+                // nested block, optionally annotated e.g. `@atomic { ... }`
+                lexer::Kind::AtSign | lexer::Kind::CurlyOpen => {
+                    self.backlog.push(token);
+                    let annotations = self.parse_annotations()?;
+                    let block = self.parse_statement_block()?;
+                    result.statements.push(Statement::Block(BlockStatement {
+                        annotations,
+                        block,
+                    }));
+                }
+
                 _ => {
                     return Err(ParserError {
                         at: token.clone(),
@@ -663,15 +883,29 @@ impl<'a, 'b> GlobalParser<'a, 'b> {
         match token.kind {
             lexer::Kind::Const => self.handle_const_decl(ast)?,
             lexer::Kind::Header => self.handle_header_decl(ast)?,
+            lexer::Kind::HeaderUnion => self.handle_header_union_decl(ast)?,
             lexer::Kind::Struct => self.handle_struct_decl(ast)?,
             lexer::Kind::Typedef => self.handle_typedef(ast)?,
             lexer::Kind::Control => self.handle_control(ast)?,
             lexer::Kind::Parser => self.handle_parser(ast, token)?,
             lexer::Kind::Package => self.handle_package(ast)?,
             lexer::Kind::Extern => self.handle_extern(ast)?,
+            lexer::Kind::Error => self.handle_error_decl(ast)?,
             lexer::Kind::Identifier(typ) => {
                 self.handle_package_instance(typ, ast)?
             }
+            lexer::Kind::AtSign => {
+                // Annotations are kept on the declarations that use them and
+                // ignored on the rest.
+                self.parser.backlog.push(token);
+                let annotations = self.parser.parse_annotations()?;
+                let token = self.parser.next_token()?;
+                let parser = token.kind == lexer::Kind::Parser;
+                self.handle_token(token, ast)?;
+                if let (true, Some(p)) = (parser, ast.parsers.last_mut()) {
+                    p.annotations = annotations;
+                }
+            }
             _ => {}
         }
         Ok(())
@@ -692,11 +926,33 @@ impl<'a, 'b> GlobalParser<'a, 'b> {
             ty,
             name,
             initializer,
+            annotations: Vec::new(),
         });
 
         Ok(())
     }
 
+    pub fn handle_error_decl(&mut self, ast: &mut AST) -> Result<(), Error> {
+        self.parser.expect_token(lexer::Kind::CurlyOpen)?;
+
+        // iterate over error members, error declarations are additive so
+        // members from multiple declarations accumulate in the AST.
+        loop {
+            let token = self.parser.next_token()?;
+            match token.kind {
+                lexer::Kind::CurlyClose => break,
+                lexer::Kind::Comma => continue,
+                _ => self.parser.backlog.push(token),
+            }
+            let (name, _) = self.parser.parse_identifier("error name")?;
+            if !ast.is_error(&name) {
+                ast.errors.push(name);
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn handle_header_decl(&mut self, ast: &mut AST) -> Result<(), Error> {
         // the first token of a header must be an identifier
         let (name, _) = self.parser.parse_identifier("header name")?;
@@ -760,6 +1016,7 @@ impl<'a, 'b> GlobalParser<'a, 'b> {
             self.parser.backlog.push(token);
 
             // parse a struct member
+            let annotations = self.parser.parse_annotations()?;
             let (ty, tyt) = self.parser.parse_type()?;
             let (name, _) =
                 self.parser.parse_identifier("struct member name")?;
@@ -769,6 +1026,7 @@ impl<'a, 'b> GlobalParser<'a, 'b> {
                 ty,
                 name,
                 token: tyt,
+                annotations,
             });
         }
 
@@ -777,6 +1035,50 @@ impl<'a, 'b> GlobalParser<'a, 'b> {
         Ok(())
     }
 
+    pub fn handle_header_union_decl(
+        &mut self,
+        ast: &mut AST,
+    ) -> Result<(), Error> {
+        // the first token of a header union must be an identifier
+        let (name, _) = self.parser.parse_identifier("header union name")?;
+
+        // next the header union body starts with an open curly brace
+        self.parser.expect_token(lexer::Kind::CurlyOpen)?;
+
+        let mut union = HeaderUnion::new(name);
+
+        // iterate over header union members
+        loop {
+            let token = self.parser.next_token()?;
+
+            // check if we've reached the end of the header union body
+            if token.kind == lexer::Kind::CurlyClose {
+                break;
+            }
+
+            // if the token was not a closing curly bracket push it into the
+            // backlog and carry on.
+            self.parser.backlog.push(token);
+
+            // parse a header union member
+            let (ty, tyt) = self.parser.parse_type()?;
+            let (name, _) =
+                self.parser.parse_identifier("header union member name")?;
+            self.parser.expect_token(lexer::Kind::Semicolon)?;
+
+            union.members.push(StructMember {
+                ty,
+                name,
+                token: tyt,
+                annotations: Vec::new(),
+            });
+        }
+
+        ast.header_unions.push(union);
+
+        Ok(())
+    }
+
     pub fn handle_typedef(&mut self, ast: &mut AST) -> Result<(), Error> {
         // first token must be a type
         let (ty, _) = self.parser.parse_type()?;
@@ -1007,6 +1309,7 @@ impl<'a, 'b> ControlParser<'a, 'b> {
         //
 
         self.parse_body(&mut control)?;
+        order_named_arguments(&mut control);
 
         Ok(control)
     }
@@ -1021,6 +1324,45 @@ impl<'a, 'b> ControlParser<'a, 'b> {
             match token.kind {
                 lexer::Kind::CurlyClose => break,
                 lexer::Kind::Action => self.parse_action(control)?,
+                lexer::Kind::AtSign => {
+                    self.parser.backlog.push(token);
+                    let annotations = self.parser.parse_annotations()?;
+                    let token = self.parser.next_token()?;
+                    match token.kind {
+                        lexer::Kind::Action => {
+                            self.parse_action(control)?;
+                            if let Some(action) = control.actions.last_mut() {
+                                action.annotations = annotations;
+                            }
+                        }
+                        lexer::Kind::Const => {
+                            let mut c = self.parser.parse_constant()?;
+                            c.annotations = annotations;
+                            control.constants.push(c);
+                        }
+                        lexer::Kind::Table => {
+                            self.parse_table(control)?;
+                            if let Some(table) = control.tables.last_mut() {
+                                table.annotations = annotations;
+                            }
+                        }
+                        _ => {
+                            return Err(ParserError {
+                                at: token.clone(),
+                                message: format!(
+                                    "Found {} expected: {}, {} or {}",
+                                    token.kind.to_string().bright_blue(),
+                                    "action".bright_blue(),
+                                    "const".bright_blue(),
+                                    "table".bright_blue(),
+                                ),
+                                source: self.parser.lexer.lines[token.line]
+                                    .into(),
+                            }
+                            .into())
+                        }
+                    }
+                }
                 lexer::Kind::Table => self.parse_table(control)?,
                 lexer::Kind::Apply => self.parse_apply(control)?,
                 lexer::Kind::Const => {
@@ -1077,6 +1419,42 @@ impl<'a, 'b> ControlParser<'a, 'b> {
     }
 }
 
+/// Put the named arguments of the const entries of `control` in the order
+/// their actions declare parameters. Arguments whose names are not exactly
+/// the parameters of the action are left with their names for the checker to
+/// report.
+fn order_named_arguments(control: &mut Control) {
+    for table in &mut control.tables {
+        for entry in &mut table.const_entries {
+            let aref = &mut entry.action;
+            if aref.parameter_names.is_empty() {
+                continue;
+            }
+            let action =
+                match control.actions.iter().find(|a| a.name == aref.name) {
+                    Some(a) => a,
+                    None => continue,
+                };
+            if action.parameters.len() != aref.parameter_names.len() {
+                continue;
+            }
+            let ordered: Option<Vec<Box<Expression>>> = action
+                .parameters
+                .iter()
+                .map(|p| {
+                    let i =
+                        aref.parameter_names.iter().position(|n| *n == p.name);
+                    i.map(|i| aref.parameters[i].clone())
+                })
+                .collect();
+            if let Some(ordered) = ordered {
+                aref.parameters = ordered;
+                aref.parameter_names.clear();
+            }
+        }
+    }
+}
+
 pub struct ActionParser<'a, 'b> {
     parser: &'b mut Parser<'a>,
 }
@@ -1087,8 +1465,8 @@ impl<'a, 'b> ActionParser<'a, 'b> {
     }
 
     pub fn run(&mut self) -> Result<Action, Error> {
-        let (name, _) = self.parser.parse_identifier("action name")?;
-        let mut action = Action::new(name);
+        let (name, token) = self.parser.parse_identifier("action name")?;
+        let mut action = Action::new(name, token);
 
         self.parse_parameters(&mut action)?;
         //self.parse_body(&mut action)?;
@@ -1301,10 +1679,16 @@ impl<'a, 'b> TableParser<'a, 'b> {
             }
             self.parser.backlog.push(token);
 
+            let annotations = self.parser.parse_annotations()?;
             let (action_name, atk) =
                 self.parser.parse_identifier("action name")?;
             self.parser.expect_token(lexer::Kind::Semicolon)?;
 
+            if !annotations.is_empty() {
+                table
+                    .action_annotations
+                    .insert(action_name.clone(), annotations);
+            }
             table.actions.push(Lvalue {
                 name: action_name,
                 token: atk,
@@ -1345,11 +1729,16 @@ impl<'a, 'b> TableParser<'a, 'b> {
     }
 
     pub fn parse_entry(&mut self) -> Result<ConstTableEntry, Error> {
+        let annotations = self.parser.parse_annotations()?;
         let keyset = self.parser.parse_keyset()?;
         self.parser.expect_token(lexer::Kind::Colon)?;
         let action = self.parse_actionref()?;
         self.parser.expect_token(lexer::Kind::Semicolon)?;
-        Ok(ConstTableEntry { keyset, action })
+        Ok(ConstTableEntry {
+            keyset,
+            action,
+            annotations,
+        })
     }
 
     pub fn parse_actionref(&mut self) -> Result<ActionRef, Error> {
@@ -1366,7 +1755,28 @@ impl<'a, 'b> TableParser<'a, 'b> {
                     self.parser.backlog.push(token);
                 }
                 let mut args = Vec::new();
+                let mut names = Vec::new();
                 loop {
+                    // arguments are either all positional or all named, as
+                    // in `forward(port = 9w2)`
+                    let token = self.parser.next_token()?;
+                    match self.parse_argument_name(token.clone())? {
+                        Some(name) if names.len() == args.len() => {
+                            names.push(name)
+                        }
+                        None if names.is_empty() => {}
+                        _ => {
+                            return Err(ParserError {
+                                at: token.clone(),
+                                message: "Action arguments must be all \
+                                    positional or all named"
+                                    .into(),
+                                source: self.parser.lexer.lines[token.line]
+                                    .into(),
+                            }
+                            .into())
+                        }
+                    }
                     let mut ep = ExpressionParser::new(self.parser);
                     let expr = ep.run()?;
                     let token = self.parser.next_token()?;
@@ -1378,6 +1788,7 @@ impl<'a, 'b> TableParser<'a, 'b> {
                         lexer::Kind::ParenClose => {
                             args.push(expr);
                             actionref.parameters = args;
+                            actionref.parameter_names = names;
                             return Ok(actionref);
                         }
                         _ => {
@@ -1407,6 +1818,23 @@ impl<'a, 'b> TableParser<'a, 'b> {
             .into()),
         }
     }
+
+    /// If `token` starts a named action argument, consume the `=` after it
+    /// and return the name. Otherwise put `token` back.
+    fn parse_argument_name(
+        &mut self,
+        token: Token,
+    ) -> Result<Option<String>, Error> {
+        if let lexer::Kind::Identifier(name) = &token.kind {
+            let next = self.parser.next_token()?;
+            if next.kind == lexer::Kind::Equals {
+                return Ok(Some(name.clone()));
+            }
+            self.parser.backlog.push(next);
+        }
+        self.parser.backlog.push(token);
+        Ok(None)
+    }
 }
 
 pub struct StatementParser<'a, 'b> {
@@ -1435,6 +1863,11 @@ impl<'a, 'b> StatementParser<'a, 'b> {
                     return Ok(Statement::Return(Some(ep.run()?)));
                 }
             }
+            lexer::Kind::ParenOpen => {
+                let statement = self.parse_tuple_assignment()?;
+                self.parser.expect_token(lexer::Kind::Semicolon)?;
+                return Ok(statement);
+            }
             _ => {
                 self.parser.backlog.push(token);
             }
@@ -1478,6 +1911,36 @@ impl<'a, 'b> StatementParser<'a, 'b> {
         Ok(Statement::Assignment(lval, expression))
     }
 
+    /// Parse the lvalues of a tuple assignment such as `(a, b) = f(x)`,
+    /// following the opening parenthesis, and the expression assigned to
+    /// them.
+    pub fn parse_tuple_assignment(&mut self) -> Result<Statement, Error> {
+        let mut lvals = Vec::new();
+        loop {
+            lvals.push(self.parser.parse_lvalue("identifier")?);
+            let token = self.parser.next_token()?;
+            match token.kind {
+                lexer::Kind::Comma => continue,
+                lexer::Kind::ParenClose => break,
+                _ => {
+                    return Err(ParserError {
+                        at: token.clone(),
+                        message: format!(
+                            "Found {} expected ',' or ')'",
+                            token.kind,
+                        ),
+                        source: self.parser.lexer.lines[token.line].into(),
+                    }
+                    .into())
+                }
+            }
+        }
+        self.parser.expect_token(lexer::Kind::Equals)?;
+        let mut ep = ExpressionParser::new(self.parser);
+        let expression = ep.run()?;
+        Ok(Statement::TupleAssignment(lvals, expression))
+    }
+
     pub fn parse_call(&mut self, lval: Lvalue) -> Result<Statement, Error> {
         let args = self.parser.parse_expr_parameters()?;
         Ok(Statement::Call(Call { lval, args }))
@@ -1556,6 +2019,36 @@ impl<'a, 'b> ExpressionParser<'a, 'b> {
 
     pub fn run(&mut self) -> Result<Box<Expression>, Error> {
         let token = self.parser.next_token()?;
+        self.parser.enter(&token)?;
+        let result = self.run_nested(token);
+        self.parser.leave();
+        result
+    }
+
+    // Operands of a chain of binary operators are parsed in a loop rather than
+    // by recursion, so only sub-expressions that are nested in the source,
+    // such as a parenthesized expression, count towards the maximum depth.
+    // The chain groups to the right as `a op (b op c)`.
+    fn run_nested(&mut self, token: Token) -> Result<Box<Expression>, Error> {
+        let mut operands = vec![(token.clone(), self.parse_operand(token)?)];
+        let mut ops = Vec::new();
+        while let Some(op) = self.parser.try_parse_binop()? {
+            let token = self.parser.next_token()?;
+            ops.push(op);
+            operands.push((token.clone(), self.parse_operand(token)?));
+        }
+        let (_, mut xpr) = operands.pop().unwrap();
+        while let Some((token, lhs)) = operands.pop() {
+            let op = ops.pop().unwrap();
+            xpr = Expression::new(token, ExpressionKind::Binary(lhs, op, xpr));
+        }
+        Ok(xpr)
+    }
+
+    fn parse_operand(
+        &mut self,
+        token: Token,
+    ) -> Result<Box<Expression>, Error> {
         let lhs = match token.kind {
             lexer::Kind::TrueLiteral => {
                 Expression::new(token.clone(), ExpressionKind::BoolLit(true))
@@ -1623,6 +2116,28 @@ impl<'a, 'b> ExpressionParser<'a, 'b> {
                     Expression::new(this_token, ExpressionKind::Lvalue(lval))
                 }
             }
+            // members of the error namespace e.g., error.NoMatch
+            lexer::Kind::Error => {
+                self.parser.expect_token(lexer::Kind::Dot)?;
+                let (name, _) = self.parser.parse_identifier("error name")?;
+                Expression::new(
+                    token.clone(),
+                    ExpressionKind::Lvalue(Lvalue {
+                        name: format!("error.{}", name),
+                        token: token.clone(),
+                    }),
+                )
+            }
+            // parenthesized expression
+            lexer::Kind::ParenOpen => {
+                let mut xp = ExpressionParser::new(self.parser);
+                let xpr = xp.run()?;
+                self.parser.expect_token(lexer::Kind::ParenClose)?;
+                xpr
+            }
+            lexer::Kind::CurlyOpen if self.struct_literal_ahead()? => {
+                self.parse_struct_literal(token.clone())?
+            }
             lexer::Kind::CurlyOpen => {
                 let mut elements = Vec::new();
                 loop {
@@ -1653,16 +2168,58 @@ impl<'a, 'b> ExpressionParser<'a, 'b> {
             }
         };
 
-        // check for binary operator
-        match self.parser.try_parse_binop()? {
-            Some(op) => {
-                // recurse to rhs
-                let mut ep = ExpressionParser::new(self.parser);
-                let rhs = ep.run()?;
-                Ok(Expression::new(token, ExpressionKind::Binary(lhs, op, rhs)))
+        Ok(lhs)
+    }
+
+    /// Whether the tokens after an opening brace start a struct literal,
+    /// that is a member name followed by `=`, rather than a list.
+    fn struct_literal_ahead(&mut self) -> Result<bool, Error> {
+        let first = self.parser.next_token()?;
+        let second = self.parser.next_token()?;
+        let ahead = matches!(first.kind, lexer::Kind::Identifier(_))
+            && second.kind == lexer::Kind::Equals;
+        self.parser.backlog.push(second);
+        self.parser.backlog.push(first);
+        Ok(ahead)
+    }
+
+    /// Parse the members of a struct literal following its opening brace.
+    fn parse_struct_literal(
+        &mut self,
+        token: Token,
+    ) -> Result<Box<Expression>, Error> {
+        let mut members = Vec::new();
+        loop {
+            let (name, _) = self.parser.parse_identifier("member name")?;
+            self.parser.expect_token(lexer::Kind::Equals)?;
+            let mut xp = ExpressionParser::new(self.parser);
+            members.push((name, xp.run()?));
+
+            let token = self.parser.next_token()?;
+            match token.kind {
+                lexer::Kind::Comma => {
+                    // allow a trailing comma
+                    let next = self.parser.next_token()?;
+                    if next.kind == lexer::Kind::CurlyClose {
+                        break;
+                    }
+                    self.parser.backlog.push(next);
+                }
+                lexer::Kind::CurlyClose => break,
+                _ => {
+                    return Err(ParserError {
+                        at: token.clone(),
+                        message: format!(
+                            "Found {} expected ',' or '}}'.",
+                            token.kind,
+                        ),
+                        source: self.parser.lexer.lines[token.line].into(),
+                    }
+                    .into())
+                }
             }
-            None => Ok(lhs),
         }
+        Ok(Expression::new(token, ExpressionKind::StructLit(members)))
     }
 }
 
@@ -2,6 +2,7 @@
 
 use crate::error::PreprocessorError;
 use std::fmt::Write;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 #[derive(Clone, Debug)]
@@ -18,7 +19,68 @@ pub struct PreprocessorResult {
 
 #[derive(Debug, Default)]
 pub struct PreprocessorElements {
-    pub includes: Vec<String>,
+    pub includes: Vec<Include>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Include {
+    pub path: String,
+    pub kind: IncludeKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IncludeKind {
+    /// `#include <file>`, found on the include search path.
+    System,
+    /// `#include "file"`, found next to the including file.
+    Local,
+}
+
+/// Standard library files, by name, that are built into the compiler. A
+/// system include that is not found on the include search path is looked up
+/// here.
+pub const BUNDLED: &[(&str, &str)] = &[
+    ("core.p4", include_str!("../lib/core.p4")),
+    ("softnpu.p4", include_str!("../lib/softnpu.p4")),
+];
+
+impl Include {
+    /// Find the file this include refers to. Local includes are looked up in
+    /// the directory of `including`, system includes in each directory of
+    /// `search` in order. Absolute paths are used as they are.
+    pub fn resolve(
+        &self,
+        including: &Path,
+        search: &[PathBuf],
+    ) -> Option<PathBuf> {
+        let path = Path::new(&self.path);
+        if path.is_absolute() {
+            return path.exists().then(|| path.into());
+        }
+        match self.kind {
+            IncludeKind::Local => {
+                let parent = including.parent().unwrap_or(Path::new(""));
+                let joined = parent.join(path);
+                joined.exists().then_some(joined)
+            }
+            IncludeKind::System => search
+                .iter()
+                .map(|dir| dir.join(path))
+                .find(|joined| joined.exists()),
+        }
+    }
+
+    /// The source of the bundled standard library file this include names,
+    /// if it is a system include of one.
+    pub fn bundled(&self) -> Option<&'static str> {
+        if self.kind != IncludeKind::System {
+            return None;
+        }
+        BUNDLED
+            .iter()
+            .find(|(name, _)| *name == self.path)
+            .map(|(_, source)| *source)
+    }
 }
 
 pub fn run(
@@ -86,6 +148,16 @@ pub fn run(
             continue;
         }
 
+        //
+        // skip pragmas, none of them are meaningful to us. an empty line is
+        // left in place so line numbers are preserved
+        //
+
+        if line.trim_start().starts_with("#pragma") {
+            new_lines.push("");
+            continue;
+        }
+
         //
         // if we are here, this is not a line to be pre-processed
         //
@@ -115,9 +187,9 @@ fn process_include(
     result: &mut PreprocessorResult,
     filename: &Arc<String>,
 ) -> Result<(), PreprocessorError> {
-    let (begin, end) = if let Some(begin) = line.find('<') {
+    let (begin, end, kind) = if let Some(begin) = line.find('<') {
         match line[begin..].find('>') {
-            Some(end) => (begin + 1, begin + end),
+            Some(end) => (begin + 1, begin + end, IncludeKind::System),
             None => {
                 return Err(PreprocessorError {
                     line: i,
@@ -128,8 +200,8 @@ fn process_include(
             }
         }
     } else if let Some(begin) = line.find('"') {
-        match line[begin..].find('"') {
-            Some(end) => (begin + 1, begin + end),
+        match line[begin + 1..].find('"') {
+            Some(end) => (begin + 1, begin + 1 + end, IncludeKind::Local),
             None => {
                 return Err(PreprocessorError {
                     line: i,
@@ -163,7 +235,10 @@ fn process_include(
             }
         }
     }
-    result.elements.includes.push(line[begin..end].into());
+    result.elements.includes.push(Include {
+        path: line[begin..end].into(),
+        kind,
+    });
 
     Ok(())
 }
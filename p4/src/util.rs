@@ -1,6 +1,6 @@
 // Copyright 2022 Oxide Computer Company
 
-use crate::ast::{Lvalue, NameInfo, Type, AST};
+use crate::ast::{Header, Lvalue, NameInfo, Type, AST};
 use std::collections::HashMap;
 
 pub fn resolve_lvalue(
@@ -8,7 +8,8 @@ pub fn resolve_lvalue(
     ast: &AST,
     names: &HashMap<String, NameInfo>,
 ) -> Result<NameInfo, String> {
-    let root = match names.get(lval.root()) {
+    let (root_name, index) = Lvalue::split_index(lval.root());
+    let root = match names.get(root_name) {
         Some(name_info) => name_info,
         None => return Err(format!("{} not found", lval.root())),
     };
@@ -23,9 +24,48 @@ pub fn resolve_lvalue(
         Type::HeaderMethod => root.clone(),
         Type::Table => root.clone(),
         Type::Void => root.clone(),
-        Type::List(_) => root.clone(),
+        Type::List(elements) => match index {
+            // an element of a tuple
+            Some(i) if lval.degree() == 1 => match elements.get(i) {
+                Some(ty) => NameInfo {
+                    ty: ty.as_ref().clone(),
+                    decl: root.decl.clone(),
+                },
+                None => {
+                    return Err(format!(
+                        "index {} out of range for {}",
+                        i, root.ty,
+                    ))
+                }
+            },
+            _ => root.clone(),
+        },
         Type::State => root.clone(),
         Type::Action => root.clone(),
+        Type::HeaderStack(name, _) => match index {
+            // an element of the stack is a header
+            Some(_) if lval.degree() == 1 => NameInfo {
+                ty: Type::UserDefined(name.clone()),
+                decl: root.decl.clone(),
+            },
+            Some(_) => match ast.get_header(name) {
+                Some(parent) => {
+                    resolve_lvalue(&lval.pop_left(), ast, &parent.names())?
+                }
+                None => {
+                    return Err(format!("Header '{}' does not exist", name))
+                }
+            },
+            None if lval.degree() == 1 => root.clone(),
+            // the next element to extract into is a header
+            None if lval.pop_left().name == "next" => NameInfo {
+                ty: Type::UserDefined(name.clone()),
+                decl: root.decl.clone(),
+            },
+            None => {
+                resolve_lvalue(&lval.pop_left(), ast, &Header::stack_names())?
+            }
+        },
         Type::UserDefined(name) => {
             if lval.degree() == 1 {
                 root.clone()
@@ -33,6 +73,8 @@ pub fn resolve_lvalue(
                 resolve_lvalue(&lval.pop_left(), ast, &parent.names())?
             } else if let Some(parent) = ast.get_header(name) {
                 resolve_lvalue(&lval.pop_left(), ast, &parent.names())?
+            } else if let Some(parent) = ast.get_header_union(name) {
+                resolve_lvalue(&lval.pop_left(), ast, &parent.names())?
             } else if let Some(parent) = ast.get_extern(name) {
                 resolve_lvalue(&lval.pop_left(), ast, &parent.names())?
             } else {
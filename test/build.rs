@@ -8,6 +8,35 @@ fn main() {
         "src/p4/router.p4",
         "src/p4/sidecar-lite.p4",
         "src/p4/decap.p4",
+        "src/p4/verify.p4",
+        "src/p4/header_union.p4",
+        "src/p4/atomic.p4",
+        "src/p4/header_stack.p4",
+        "src/p4/user_extern.p4",
+        "src/p4/meter.p4",
+        "src/p4/header_size.p4",
+        "src/p4/port9.p4",
+        "src/p4/assert.p4",
+        "src/p4/ternary.p4",
+        "src/p4/overflow.p4",
+        "src/p4/migrate_v1.p4",
+        "src/p4/migrate_v2.p4",
+        "src/p4/flag_key.p4",
+        "src/p4/wide_key.p4",
+        "src/p4/deparse.p4",
+        "src/p4/select_slice.p4",
+        "src/p4/tuple_extern.p4",
+        "src/p4/pragma.p4",
+        "src/p4/field_keys.p4",
+        "src/p4/ipv6_ext.p4",
+        "src/p4/out_extern.p4",
+        "src/p4/mpls.p4",
+        "src/p4/keyword_fields.p4",
+        "src/p4/ttl_checksum.p4",
+        "src/p4/clone.p4",
+        "src/p4/endian.p4",
+        "src/p4/const_select.p4",
+        "src/p4/dirty_field.p4",
         "src/p4/router.p4",
     ];
     for x in src {
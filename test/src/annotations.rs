@@ -0,0 +1,36 @@
+use p4::ast::AST;
+use p4::check::{self, Diagnostics, Level};
+use p4::{lexer, parser};
+use std::sync::Arc;
+
+fn check_file(filename: &str) -> Diagnostics {
+    let path = format!("{}/src/p4/{}", env!("CARGO_MANIFEST_DIR"), filename);
+    let contents = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = contents.lines().collect();
+
+    let lxr = lexer::Lexer::new(lines, Arc::new(path));
+    let mut psr = parser::Parser::new(lxr);
+    let mut ast = AST::default();
+    psr.run(&mut ast).unwrap();
+
+    let (_, diags) = check::all(&ast);
+    diags
+}
+
+#[test]
+fn defaultonly_action_in_const_entry() {
+    let diags = check_file("defaultonly.p4");
+    let errors = diags.errors();
+    assert_eq!(errors.len(), 1, "{:#?}", errors);
+    assert_eq!(errors[0].level, Level::Error);
+    assert!(errors[0].message.contains("@defaultonly"));
+    assert_eq!(errors[0].token.line, 26);
+}
+
+#[test]
+fn tableonly_action_as_default() {
+    let diags = check_file("tableonly.p4");
+    let errors = diags.errors();
+    assert_eq!(errors.len(), 1, "{:#?}", errors);
+    assert!(errors[0].message.contains("@tableonly"));
+}
@@ -0,0 +1,27 @@
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(p4 = "test/src/p4/assert.p4", pipeline_name = "assert");
+
+fn frame(ether_type: u16) -> [u8; 14] {
+    let mut frame = [0u8; 14];
+    frame[12..14].copy_from_slice(&ether_type.to_be_bytes());
+    frame
+}
+
+#[test]
+fn assertion_holds() {
+    let mut pipeline = main_pipeline::new(2);
+    let frame = frame(0x86dd);
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+}
+
+#[test]
+#[should_panic(expected = "P4 assertion failed at")]
+fn assertion_fires() {
+    let mut pipeline = main_pipeline::new(2);
+    let frame = frame(0x0800);
+    let mut pkt = packet_in::new(&frame);
+    pipeline.process_packet(0, &mut pkt);
+}
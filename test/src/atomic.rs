@@ -0,0 +1,31 @@
+use crate::frontend::parse_test_program;
+use p4::check::{self, Level};
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(p4 = "test/src/p4/atomic.p4", pipeline_name = "atomic");
+
+#[test]
+fn atomic_block_compiles_with_warning() {
+    let ast = parse_test_program("atomic.p4");
+
+    let (_, diags) = check::all(&ast);
+    assert!(diags.errors().is_empty(), "{:#?}", diags.errors());
+    let warnings = diags.warnings();
+    assert_eq!(warnings.len(), 1, "{:#?}", warnings);
+    assert_eq!(warnings[0].level, Level::Warning);
+    assert!(warnings[0].message.contains("@atomic"));
+    assert_eq!(warnings[0].token.line, 37);
+}
+
+#[test]
+fn atomic_block_executes() {
+    let mut pipeline = main_pipeline::new(2);
+
+    let mut frame = vec![0u8; 14];
+    frame[0..6].copy_from_slice(&[1, 2, 3, 4, 5, 6]);
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].1, 1);
+    assert_eq!(&out[0].0.header_data[6..12], &[1, 2, 3, 4, 5, 6]);
+}
@@ -0,0 +1,39 @@
+use p4::lexer::{Kind, Lexer};
+use std::sync::Arc;
+
+fn lex(source: &str) -> Result<Vec<Kind>, p4::error::TokenError> {
+    let mut lxr = Lexer::new(vec![source], Arc::new("literals.p4".into()));
+    let mut kinds = Vec::new();
+    loop {
+        let token = lxr.next()?;
+        if token.kind == Kind::Eof {
+            break;
+        }
+        kinds.push(token.kind);
+    }
+    Ok(kinds)
+}
+
+#[test]
+fn hex_and_binary_bit_literals() {
+    assert_eq!(
+        lex("8w0xFF 4w0b1010 16w0x1234 8w0B1111_0000 0b101 4s0b0111").unwrap(),
+        vec![
+            Kind::BitLiteral(8, 0xff),
+            Kind::BitLiteral(4, 0b1010),
+            Kind::BitLiteral(16, 0x1234),
+            Kind::BitLiteral(8, 0xf0),
+            Kind::IntLiteral(5),
+            Kind::SignedLiteral(4, 7),
+        ],
+    );
+}
+
+#[test]
+fn bit_literal_must_fit_width() {
+    assert!(lex("8w0x1FF").is_err());
+    assert!(lex("4w0b10000").is_err());
+    assert!(lex("2w4").is_err());
+    assert!(lex("4w0b1012").is_err());
+    assert!(lex("128w0xffffffffffffffffffffffffffffffff").is_ok());
+}
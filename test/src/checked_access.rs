@@ -0,0 +1,42 @@
+use p4rs::packet_in;
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/checked_access.p4",
+    pipeline_name = "checked_access",
+    checked_header_access = true,
+);
+
+fn frame(ether_type: u16, hop_limit: u8) -> Vec<u8> {
+    let mut data = vec![0u8; 12];
+    data.extend_from_slice(&ether_type.to_be_bytes());
+    let mut ipv6 = [0u8; 40];
+    ipv6[0] = 0x60;
+    ipv6[7] = hop_limit;
+    data.extend_from_slice(&ipv6);
+    data
+}
+
+#[test]
+fn read_of_valid_header() {
+    let mut pipeline = main_pipeline::new(2);
+
+    let data = frame(0x86dd, 64);
+    let mut pkt = packet_in::new(&data);
+    assert_eq!(pipeline.process_packet_headers(0, &mut pkt).len(), 1);
+
+    let data = frame(0x86dd, 0);
+    let mut pkt = packet_in::new(&data);
+    assert!(pipeline.process_packet_headers(0, &mut pkt).is_empty());
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "read of hdr.ipv6.hop_limit from an invalid header")]
+fn read_of_invalid_header() {
+    let mut pipeline = main_pipeline::new(2);
+
+    // Not IPv6, so the ipv6 header is never extracted.
+    let data = frame(0x0800, 64);
+    let mut pkt = packet_in::new(&data);
+    pipeline.process_packet_headers(0, &mut pkt);
+}
@@ -0,0 +1,56 @@
+use p4rs::packet_in;
+
+p4_macro::use_p4!(p4 = "test/src/p4/clone.p4", pipeline_name = "clone");
+
+fn probe(timestamp: u64, tag: u16) -> Vec<u8> {
+    let mut data = timestamp.to_be_bytes().to_vec();
+    data.extend_from_slice(&tag.to_be_bytes());
+    data.extend_from_slice(&[0u8; 8]);
+    data
+}
+
+#[test]
+fn clone_preserves_field_list() {
+    let pipeline = main_pipeline::new(4);
+    let data = probe(0x1122334455667788, 47);
+    let mut pkt = packet_in::new(&data);
+    let (mut parsed, mut ingress_metadata) =
+        pipeline.run_parser(0, &mut pkt).unwrap();
+    let egress_metadata =
+        pipeline.run_ingress(&mut parsed, &mut ingress_metadata);
+    assert_eq!(ingress_metadata.tag.load_le::<u16>(), 47);
+
+    // The timestamp is in field list 1 and is carried over to the clone, the
+    // tag is not and starts out as it does for a new packet.
+    let (port, cloned, metadata) = pipeline
+        .run_clone(0, &data, &ingress_metadata, &egress_metadata)
+        .unwrap();
+    let (_, fresh) =
+        pipeline.run_parser(0, &mut packet_in::new(&data)).unwrap();
+    assert_eq!(port, 2);
+    assert_eq!(metadata.timestamp.load_le::<u64>(), 0x1122334455667788);
+    assert_eq!(metadata.tag, fresh.tag);
+    assert_ne!(metadata.tag, ingress_metadata.tag);
+
+    // The clone is the packet as it arrived, not as ingress left it.
+    assert_eq!(cloned.probe.tag.load_le::<u16>(), 47);
+}
+
+#[test]
+fn clone_is_sent_out_of_clone_port() {
+    let mut pipeline = main_pipeline::new(4);
+    let data = probe(1000, 47);
+    let mut pkt = packet_in::new(&data);
+    let out = pipeline.process_packet_headers(0, &mut pkt);
+    assert_eq!(out.len(), 2);
+
+    let (original, port) = &out[0];
+    assert_eq!(*port, 1);
+    assert_eq!(original.probe.tag.load_le::<u16>(), 0);
+    assert_eq!(original.probe.seen_timestamp.load_le::<u64>(), 1000);
+
+    let (clone, port) = &out[1];
+    assert_eq!(*port, 2);
+    assert_eq!(clone.probe.tag.load_le::<u16>(), 47);
+    assert_eq!(clone.probe.seen_timestamp.load_le::<u64>(), 1000);
+}
@@ -0,0 +1,63 @@
+use p4rs::{packet_in, Pipeline};
+use std::net::Ipv6Addr;
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/composite_key.p4",
+    pipeline_name = "composite_key"
+);
+
+fn add_route(
+    pipeline: &mut main_pipeline,
+    vid: u16,
+    prefix: &str,
+    len: u8,
+    port: u16,
+) {
+    let prefix: Ipv6Addr = prefix.parse().unwrap();
+    let mut keyset_data = vid.to_le_bytes().to_vec();
+    keyset_data.extend_from_slice(&prefix.octets());
+    keyset_data.push(len);
+    pipeline
+        .add_table_entry(
+            "ingress.router",
+            "forward",
+            &keyset_data,
+            &port.to_le_bytes(),
+            0,
+        )
+        .unwrap();
+}
+
+fn egress_port(
+    pipeline: &mut main_pipeline,
+    vid: u16,
+    dst: &str,
+) -> Option<u16> {
+    let dst: Ipv6Addr = dst.parse().unwrap();
+    let mut frame = vid.to_be_bytes().to_vec();
+    frame.extend_from_slice(&dst.octets());
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet_headers(0, &mut pkt);
+    out.first().map(|(_, port)| *port)
+}
+
+#[test]
+fn exact_and_lpm_keys_must_both_match() {
+    let mut pipeline = main_pipeline::new(4);
+    add_route(&mut pipeline, 47, "fd00:1000::", 24, 1);
+    add_route(&mut pipeline, 47, "fd00:1000:2000::", 48, 2);
+    add_route(&mut pipeline, 48, "fd00:1000::", 24, 3);
+
+    // The longest prefix among the entries for the vlan wins.
+    assert_eq!(egress_port(&mut pipeline, 47, "fd00:1000::1"), Some(1));
+    assert_eq!(egress_port(&mut pipeline, 47, "fd00:1000:2000::1"), Some(2));
+
+    // A longer prefix for another vlan does not take part.
+    assert_eq!(egress_port(&mut pipeline, 48, "fd00:1000:2000::1"), Some(3));
+
+    // The vlan matches but the destination is outside every prefix.
+    assert_eq!(egress_port(&mut pipeline, 47, "fd00:2000::1"), None);
+
+    // The destination is in a prefix but the vlan has no entries.
+    assert_eq!(egress_port(&mut pipeline, 49, "fd00:1000::1"), None);
+}
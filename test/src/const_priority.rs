@@ -0,0 +1,30 @@
+use p4rs::packet_in;
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/const_priority.p4",
+    pipeline_name = "const_priority"
+);
+
+/// Run a frame with the given tag flags through the pipeline and return the
+/// resulting tag result.
+fn run(flags: u8) -> u8 {
+    let mut pipeline = main_pipeline::new(2);
+    let mut frame = [0u8; 16];
+    frame[14] = flags;
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet_headers(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    out[0].0.tag.result.load_le()
+}
+
+#[test]
+fn priority_annotation_wins() {
+    assert_eq!(run(0x01), 1);
+    assert_eq!(run(0x03), 2);
+}
+
+#[test]
+fn declaration_order_breaks_overlap() {
+    assert_eq!(run(0x0c), 3);
+    assert_eq!(run(0x08), 0);
+}
@@ -0,0 +1,53 @@
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/const_select.p4",
+    pipeline_name = "const_select",
+);
+
+fn frame(ether_type: u16) -> Vec<u8> {
+    let mut data = vec![0u8; 14 + 40];
+    data[12..14].copy_from_slice(&ether_type.to_be_bytes());
+    data
+}
+
+#[test]
+fn select_on_named_constants() {
+    let mut pipeline = main_pipeline::new(4);
+
+    // a constant initialized with a bit literal
+    let data = frame(0x86dd);
+    let out = pipeline.process_packet(0, &mut packet_in::new(&data));
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].1, 1);
+
+    // a constant initialized from another constant
+    let data = frame(0x8100);
+    let out = pipeline.process_packet(0, &mut packet_in::new(&data));
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].1, 2);
+
+    // neither constant matches
+    let data = frame(0x0800);
+    let mut pkt = packet_in::new(&data);
+    assert!(pipeline.run_parser(0, &mut pkt).is_none());
+}
+
+#[test]
+fn keyset_constants_are_folded() {
+    let ast = crate::frontend::parse_test_program("const_select.p4");
+    let (hlir, diags) = p4::check::all(&ast);
+    assert!(diags.errors().is_empty(), "{:#?}", diags.errors());
+
+    let mut values: Vec<_> = hlir
+        .constant_values
+        .iter()
+        .map(|(lval, value)| (lval.name.clone(), format!("{:?}", value.kind)))
+        .collect();
+    values.sort();
+    assert_eq!(values.len(), 2);
+    assert_eq!(values[0].0, "ETHERTYPE_DOT1Q");
+    assert_eq!(values[0].1, "IntegerLit(33024)");
+    assert_eq!(values[1].0, "ETHERTYPE_IPV6");
+    assert_eq!(values[1].1, "BitLit(16, 34525)");
+}
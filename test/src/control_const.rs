@@ -0,0 +1,20 @@
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/control_const.p4",
+    pipeline_name = "control_const"
+);
+
+#[test]
+fn action_reads_control_constants() {
+    let mut pipeline = main_pipeline::new(4);
+
+    let mut buf = vec![0u8; 12];
+    buf.extend_from_slice(&[0x86, 0xdd]);
+
+    let mut pkt = packet_in::new(&buf);
+    let out = pipeline.process_packet(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].1, 3);
+    assert_eq!(out[0].0.header_data[12..14], [0x09, 0x01]);
+}
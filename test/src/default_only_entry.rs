@@ -0,0 +1,40 @@
+use p4rs::error::PipelineError;
+use p4rs::Pipeline;
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/default_only_entry.p4",
+    pipeline_name = "default_only_entry"
+);
+
+#[test]
+fn default_only_action_entry_rejected() {
+    let mut pipeline = main_pipeline::new(2);
+    let dst = [0x1d; 6];
+    let port = 1u16.to_le_bytes();
+
+    let default_only = PipelineError::DefaultOnlyAction {
+        table: "ingress.fwd".into(),
+        action: "drop".into(),
+    };
+    assert_eq!(
+        pipeline.add_ingress_fwd_entry("drop", &dst, &[], 0),
+        Err(default_only.clone()),
+    );
+    assert_eq!(
+        pipeline.add_table_entry("ingress.fwd", "drop", &dst, &[], 0),
+        Err(default_only),
+    );
+    assert_eq!(
+        pipeline.add_ingress_fwd_entry("bounce", &dst, &port, 0),
+        Err(PipelineError::UnknownAction {
+            table: "ingress.fwd".into(),
+            action: "bounce".into(),
+        }),
+    );
+    assert!(pipeline.get_ingress_fwd_entries().is_empty());
+
+    pipeline
+        .add_ingress_fwd_entry("forward", &dst, &port, 0)
+        .unwrap();
+    assert_eq!(pipeline.get_ingress_fwd_entries().len(), 1);
+}
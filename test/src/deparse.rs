@@ -0,0 +1,52 @@
+use p4rs::{packet_in, packet_out, Header, Pipeline};
+
+p4_macro::use_p4!(p4 = "test/src/p4/deparse.p4", pipeline_name = "deparse");
+
+#[test]
+fn emit_in_deparser_order() {
+    let mut pipeline = main_pipeline::new(2);
+
+    let mut buf = vec![0x1d, 0x1d, 0x1d, 0x1d, 0x1d, 0x1d];
+    buf.extend_from_slice(&[0xa0, 0xa1, 0xa2, 0xa3, 0xa4, 0xa5]);
+    buf.extend_from_slice(&[0x86, 0xdd]);
+    buf.extend_from_slice(b"muffins");
+
+    let mut pkt = packet_in::new(&buf);
+    let out = pipeline.process_packet(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].1, 1);
+
+    // The VLAN tag added by ingress is emitted before the ethernet header it
+    // follows in the headers struct.
+    let mut expected = vec![0x81, 0x00, 0x00, 0x2f];
+    expected.extend_from_slice(&buf[..14]);
+    assert_eq!(out[0].0.header_data, expected);
+    assert_eq!(out[0].0.payload_data, b"muffins");
+}
+
+#[test]
+fn emitted_length_is_header_width() {
+    let pipeline = main_pipeline::new(2);
+
+    let mut buf = vec![0x1d; 12];
+    buf.extend_from_slice(&[0x86, 0xdd]);
+
+    let mut pkt = packet_in::new(&buf);
+    let (mut parsed, mut ingress) = pipeline.run_parser(0, &mut pkt).unwrap();
+    assert_eq!(parsed.ethernet.width_bits(), 112);
+    assert_eq!(parsed.vlan.width_bits(), 0);
+
+    // ingress adds a VLAN tag
+    pipeline.run_ingress(&mut parsed, &mut ingress);
+    assert_eq!(parsed.vlan.width_bits(), 32);
+
+    let mut out = packet_out {
+        header_data: Vec::new(),
+        payload_data: &[],
+    };
+    pipeline.run_deparser(&parsed, &mut out);
+    assert_eq!(
+        out.header_data.len() * 8,
+        parsed.ethernet.width_bits() + parsed.vlan.width_bits(),
+    );
+}
@@ -0,0 +1,71 @@
+use p4rs::Pipeline;
+
+p4_macro::use_p4!(p4 = "test/src/p4/router.p4", pipeline_name = "describe");
+
+#[test]
+fn router_description() {
+    let pipeline = main_pipeline::new(2);
+    let description = pipeline.describe();
+
+    let json = serde_json::to_string(&description).unwrap();
+    let v: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+    let router = v["tables"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|t| t["table_id"] == "ingress.router.router")
+        .expect("routing table");
+    let keys = router["schema"]["keys"].as_array().unwrap();
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0]["name"], "hdr.ipv6.dst");
+    assert_eq!(keys[0]["match_kind"], "lpm");
+    assert_eq!(keys[0]["width"], 16);
+
+    let states: Vec<_> = v["parser"]["states"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| {
+            let transitions: Vec<_> = s["transitions"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|t| t.as_str().unwrap().to_owned())
+                .collect();
+            (s["name"].as_str().unwrap().to_owned(), transitions)
+        })
+        .collect();
+    assert_eq!(
+        states[0],
+        (
+            "start".into(),
+            vec!["ipv6".into(), "sidecar".into(), "reject".into()]
+        ),
+    );
+    assert_eq!(states[2], ("ipv6".into(), vec!["accept".into()]));
+
+    let ipv6 = description.get_header("ipv6_h").unwrap();
+    let dst = ipv6.fields.iter().find(|f| f.name == "dst").unwrap();
+    assert_eq!((dst.offset, dst.width), (192, 128));
+}
+
+#[test]
+fn nested_table_location() {
+    let pipeline = main_pipeline::new(2);
+
+    let location = pipeline.table_location("ingress.router.router").unwrap();
+    assert_eq!(location.table, "router");
+    let chain: Vec<_> = location
+        .controls
+        .iter()
+        .map(|c| (c.instance.as_str(), c.control.as_str()))
+        .collect();
+    assert_eq!(chain, vec![("ingress", "ingress"), ("router", "router")]);
+
+    let location = pipeline.table_location("ingress.local.tbl").unwrap();
+    assert_eq!(location.table, "tbl");
+    assert_eq!(location.controls.last().unwrap().control, "local");
+
+    assert!(pipeline.table_location("ingress.router").is_none());
+}
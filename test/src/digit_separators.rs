@@ -0,0 +1,32 @@
+use p4::lexer::{Kind, Lexer};
+use std::sync::Arc;
+
+fn lex(source: &str) -> Vec<Kind> {
+    let mut lxr = Lexer::new(vec![source], Arc::new("literals.p4".into()));
+    let mut kinds = Vec::new();
+    loop {
+        let token = lxr.next().unwrap();
+        if token.kind == Kind::Eof {
+            break;
+        }
+        kinds.push(token.kind);
+    }
+    kinds
+}
+
+#[test]
+fn underscores_separate_digits() {
+    assert_eq!(
+        lex("0xDEAD_BEEF 10_000 32w0xFFFF_0000 16w1_024 8s1_0"),
+        vec![
+            Kind::IntLiteral(0xdeadbeef),
+            Kind::IntLiteral(10000),
+            Kind::BitLiteral(32, 0xffff0000),
+            Kind::BitLiteral(16, 1024),
+            Kind::SignedLiteral(8, 10),
+        ],
+    );
+
+    // an underscore cannot start a literal, that is an identifier
+    assert_eq!(lex("_1"), vec![Kind::Identifier("_1".into())]);
+}
@@ -0,0 +1,33 @@
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/dirty_field.p4",
+    pipeline_name = "dirty_field",
+    optimize = true,
+);
+
+fn process(pipeline: &mut main_pipeline, frame: &[u8]) -> Vec<u8> {
+    let mut pkt = packet_in::new(frame);
+    let out = pipeline.process_packet(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    let (out, _) = &out[0];
+    let mut data = out.header_data.to_vec();
+    data.extend_from_slice(out.payload_data);
+    data
+}
+
+#[test]
+fn header_field_named_dirty() {
+    let mut pipeline = main_pipeline::new(2);
+    let mut frame = [0u8; 20];
+
+    // An untouched header is passed through as it arrived.
+    assert_eq!(process(&mut pipeline, &frame), frame);
+
+    // Writing the P4 field is still noticed and serialized.
+    frame[15] = 1;
+    let out = process(&mut pipeline, &frame);
+    assert_eq!(out[14], 7);
+    assert_eq!(out[15], 1);
+    assert_eq!(out[16..], frame[16..]);
+}
@@ -12,6 +12,8 @@ fn pipeline_create() -> Result<(), anyhow::Error> {
     let mut pkt: packet_in = packet_in {
         data: &data,
         index: 0,
+        headroom: 0,
+        loops: 0,
     };
 
     // the goal is simply not to explode
@@ -56,6 +58,8 @@ fn dynamic_load() -> Result<(), anyhow::Error> {
     let mut pkt: packet_in = packet_in {
         data: &data,
         index: 0,
+        headroom: 0,
+        loops: 0,
     };
 
     // the goal is simply not to explode
@@ -0,0 +1,44 @@
+use p4rs::{packet_in, DropStats, Pipeline};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/drop_stats.p4",
+    pipeline_name = "drop_stats"
+);
+
+fn frame(dst: u8, src: u8, ether_type: u16) -> Vec<u8> {
+    let mut frame = vec![0, 0, 0, 0, 0, dst, 0, 0, 0, 0, 0, src];
+    frame.extend_from_slice(&ether_type.to_be_bytes());
+    frame
+}
+
+#[test]
+fn drops_counted_by_reason() {
+    let mut pipeline = main_pipeline::new(2);
+    assert_eq!(pipeline.drop_stats(), DropStats::default());
+
+    let frames = [
+        frame(9, 0, 0x0800), // not ipv6, the parser rejects it
+        frame(1, 0, 0x86dd), // dropped by ingress
+        frame(1, 0, 0x86dd),
+        frame(2, 0, 0x86dd), // sent to a port beyond the radix
+        frame(9, 3, 0x86dd), // dropped by egress
+        frame(9, 0, 0x86dd), // forwarded
+    ];
+    let mut forwarded = 0;
+    for f in &frames {
+        let mut pkt = packet_in::new(f);
+        forwarded += pipeline.process_packet(0, &mut pkt).len();
+    }
+    assert_eq!(forwarded, 1);
+
+    assert_eq!(
+        pipeline.drop_stats(),
+        DropStats {
+            parser: 1,
+            ingress: 2,
+            egress: 1,
+            no_port: 1,
+            unregistered_extern: 0,
+        },
+    );
+}
@@ -49,7 +49,7 @@ fn dynamic_router2() -> Result<(), anyhow::Error> {
         &buf,
         &1u16.to_le_bytes(),
         0,
-    );
+    )?;
 
     let prefix: Ipv6Addr = "fd00:2000::".parse().unwrap();
     let mut buf = prefix.octets().to_vec();
@@ -60,7 +60,7 @@ fn dynamic_router2() -> Result<(), anyhow::Error> {
         &buf,
         &2u16.to_le_bytes(),
         0,
-    );
+    )?;
 
     let prefix: Ipv6Addr = "fd00:3000::".parse().unwrap();
     let mut buf = prefix.octets().to_vec();
@@ -71,7 +71,7 @@ fn dynamic_router2() -> Result<(), anyhow::Error> {
         &buf,
         &3u16.to_le_bytes(),
         0,
-    );
+    )?;
 
     //
     // run program
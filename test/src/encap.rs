@@ -0,0 +1,26 @@
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(p4 = "test/src/p4/encap.p4", pipeline_name = "encap");
+
+#[test]
+fn encapsulated_output_keeps_payload() {
+    let mut pipeline = main_pipeline::new(2);
+
+    let mut frame = vec![0x1d; 6];
+    frame.extend_from_slice(&[0xa0, 0xa1, 0xa2, 0xa3, 0xa4, 0xa5]);
+    frame.extend_from_slice(&[0x86, 0xdd]);
+    frame.extend_from_slice(b"muffins");
+
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    let (out, port) = &out[0];
+    assert_eq!(*port, 1);
+
+    // outer ethernet + tunnel + the original frame
+    assert_eq!(out.header_data.len() + out.payload_data.len(), 14 + 4 + 21);
+    assert_eq!(&out.header_data[12..14], &[0x88, 0xb5]);
+    assert_eq!(&out.header_data[14..18], &[0x65, 0x58, 0x00, 99]);
+    assert_eq!(&out.header_data[18..], &frame[..14]);
+    assert_eq!(out.payload_data, b"muffins");
+}
@@ -0,0 +1,82 @@
+use p4rs::endian::{load, Endian};
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(p4 = "test/src/p4/endian.p4", pipeline_name = "endian");
+
+#[test]
+fn metadata_is_host_order() {
+    let pipeline = main_pipeline::new(4);
+    let data = [0, 0, 0, 3];
+    let mut pkt = packet_in::new(&data);
+    let (_, ingress_metadata) = pipeline.run_parser(0x0102, &mut pkt).unwrap();
+
+    // Metadata is written in the in-memory layout as is.
+    assert_eq!(ingress_metadata.port.as_raw_slice(), [0x02, 0x01]);
+    assert_eq!(load(&ingress_metadata.port), 0x0102);
+}
+
+#[test]
+fn header_fields_are_network_order() {
+    let pipeline = main_pipeline::new(4);
+    let data = [0, 0, 0x01, 0x02];
+    let mut pkt = packet_in::new(&data);
+    let (parsed, _) = pipeline.run_parser(0, &mut pkt).unwrap();
+
+    // Header fields are read from the packet big endian and converted to the
+    // in-memory layout.
+    assert_eq!(parsed.ports.dst_port.as_raw_slice(), [0x02, 0x01]);
+    assert_eq!(load(&parsed.ports.dst_port), 0x0102);
+    assert_eq!(
+        Endian::Network.extract(&data, 16, 32),
+        parsed.ports.dst_port
+    );
+}
+
+#[test]
+fn metadata_to_header_round_trip() {
+    let mut pipeline = main_pipeline::new(8);
+    let data = [0, 0, 0, 3];
+    let mut pkt = packet_in::new(&data);
+    let out = pipeline.process_packet(5, &mut pkt);
+
+    // The destination port is read in network order to pick the egress port,
+    // and the ingress port is written back into the packet in network order.
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].1, 3);
+    assert_eq!(out[0].0.header_data, [0, 5, 0, 3]);
+}
+
+#[test]
+fn port_round_trip_full_range() {
+    let mut pipeline = main_pipeline::new(u16::MAX);
+
+    let ports = (0..=u16::MAX)
+        .step_by(257)
+        .chain([1, 0xff, 0x100, 0x7fff, 0x8000, 0xfffe, 0xffff]);
+    for port in ports {
+        // The port is copied into a header and back out to egress. Reflect
+        // each port back to itself by using it as the destination.
+        let [hi, lo] = port.to_be_bytes();
+        let data = [0, 0, hi, lo];
+
+        let mut pkt = packet_in::new(&data);
+        let (mut parsed, mut ingress_metadata) =
+            pipeline.run_parser(port, &mut pkt).unwrap();
+        assert_eq!(load(&ingress_metadata.port), u128::from(port));
+        let egress_metadata =
+            pipeline.run_ingress(&mut parsed, &mut ingress_metadata);
+        assert_eq!(load(&egress_metadata.port), u128::from(port));
+
+        // Ports at or beyond the radix are dropped, so the last port can only
+        // be checked up to the traffic manager.
+        let mut pkt = packet_in::new(&data);
+        let out = pipeline.process_packet(port, &mut pkt);
+        if port == u16::MAX {
+            assert!(out.is_empty());
+            continue;
+        }
+        assert_eq!(out.len(), 1, "port {}", port);
+        assert_eq!(out[0].1, port);
+        assert_eq!(out[0].0.header_data, [hi, lo, hi, lo], "port {}", port);
+    }
+}
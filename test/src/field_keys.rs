@@ -0,0 +1,118 @@
+use p4rs::error::PipelineError;
+use p4rs::{packet_in, Pipeline};
+use std::net::Ipv4Addr;
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/field_keys.p4",
+    pipeline_name = "field_keys"
+);
+
+fn egress_port(
+    pipeline: &mut main_pipeline,
+    dst: Ipv4Addr,
+    vid: u16,
+) -> Option<u16> {
+    let mut frame = dst.octets().to_vec();
+    frame.extend_from_slice(&vid.to_be_bytes());
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet_headers(0, &mut pkt);
+    out.first().map(|(_, port)| *port)
+}
+
+#[test]
+fn keys_by_field_name() {
+    let mut pipeline = main_pipeline::new(4);
+
+    // Keys and parameters are given in the reverse of their declared order.
+    let dst = u32::from(Ipv4Addr::new(10, 0, 0, 1)).to_le_bytes();
+    let vid = 47u16.to_le_bytes();
+    let port = 3u16.to_le_bytes();
+    pipeline
+        .add_table_entry_by_fields(
+            "ingress.by_tag",
+            "forward",
+            &[("hdr.tag.vid", &vid), ("hdr.tag.dst", &dst)],
+            &[("port", &port), ("vid", &vid)],
+            0,
+        )
+        .unwrap();
+
+    assert_eq!(
+        egress_port(&mut pipeline, Ipv4Addr::new(10, 0, 0, 1), 47),
+        Some(3)
+    );
+    assert_eq!(
+        egress_port(&mut pipeline, Ipv4Addr::new(10, 0, 0, 1), 48),
+        None
+    );
+
+    // The entry is the same one positional insertion would have added.
+    let mut keyset_data = dst.to_vec();
+    keyset_data.extend_from_slice(&vid);
+    let entries = pipeline.get_table_entries("ingress.by_tag").unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].keyset_data, keyset_data);
+}
+
+#[test]
+fn missing_and_unknown_fields() {
+    let mut pipeline = main_pipeline::new(4);
+    let dst = [0u8; 4];
+    let vid = [0u8; 2];
+
+    assert_eq!(
+        pipeline.add_table_entry_by_fields(
+            "ingress.by_tag",
+            "drop",
+            &[("hdr.tag.dst", &dst)],
+            &[],
+            0,
+        ),
+        Err(PipelineError::MissingField {
+            table: "ingress.by_tag".into(),
+            field: "hdr.tag.vid".into(),
+        })
+    );
+    assert_eq!(
+        pipeline.add_table_entry_by_fields(
+            "ingress.by_tag",
+            "drop",
+            &[("hdr.tag.dst", &dst), ("hdr.tag.vid", &vid)],
+            &[("port", &vid)],
+            0,
+        ),
+        Err(PipelineError::UnknownField {
+            table: "ingress.by_tag".into(),
+            field: "port".into(),
+        })
+    );
+}
+
+#[test]
+fn field_of_wrong_width() {
+    let mut pipeline = main_pipeline::new(4);
+    let dst = [0u8; 4];
+    let vid = [0u8; 2];
+
+    // A 4 byte vid and a 2 byte dst have the same total length as the keys
+    // of the table, but each field must match its own width.
+    assert_eq!(
+        pipeline.add_table_entry_by_fields(
+            "ingress.by_tag",
+            "drop",
+            &[("hdr.tag.dst", &vid), ("hdr.tag.vid", &dst)],
+            &[],
+            0,
+        ),
+        Err(PipelineError::FieldLengthMismatch {
+            table: "ingress.by_tag".into(),
+            field: "hdr.tag.dst".into(),
+            expected: 4,
+            found: 2,
+        })
+    );
+    assert!(pipeline
+        .get_table_entries("ingress.by_tag")
+        .unwrap()
+        .is_empty());
+}
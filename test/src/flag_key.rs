@@ -0,0 +1,45 @@
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(p4 = "test/src/p4/flag_key.p4", pipeline_name = "flag_key");
+
+fn egress_port(pipeline: &mut main_pipeline, frame: &[u8]) -> Option<u16> {
+    let mut pkt = packet_in::new(frame);
+    let out = pipeline.process_packet_headers(0, &mut pkt);
+    out.first().map(|(_, port)| *port)
+}
+
+#[test]
+fn single_bit_table_key() {
+    let mut pipeline = main_pipeline::new(8);
+
+    // The neighboring fields are all ones so a match that considers more than
+    // the flag bit fails.
+    let clear = [0b1110_1111u8];
+    let set = [0b1111_1111u8];
+
+    // Const entries match on both flag values.
+    assert_eq!(egress_port(&mut pipeline, &clear), Some(3));
+    assert_eq!(egress_port(&mut pipeline, &set), Some(4));
+
+    // Entries added at runtime override the const table for both values.
+    pipeline
+        .add_table_entry(
+            "ingress.by_flag",
+            "forward",
+            &[0],
+            &1u16.to_le_bytes(),
+            0,
+        )
+        .unwrap();
+    pipeline
+        .add_table_entry(
+            "ingress.by_flag",
+            "forward",
+            &[1],
+            &2u16.to_le_bytes(),
+            0,
+        )
+        .unwrap();
+    assert_eq!(egress_port(&mut pipeline, &clear), Some(1));
+    assert_eq!(egress_port(&mut pipeline, &set), Some(2));
+}
@@ -0,0 +1,29 @@
+use p4::ast::AST;
+use p4::{lexer, parser, preprocessor};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Parse a P4 file and everything it includes into `ast`. Local includes are
+/// resolved relative to the including file, system includes against `search`.
+pub fn parse_file(path: &Path, search: &[PathBuf], ast: &mut AST) {
+    let contents = std::fs::read_to_string(path).unwrap();
+    let filename = Arc::new(path.to_str().unwrap().to_string());
+    let ppr = preprocessor::run(&contents, filename.clone()).unwrap();
+    for included in &ppr.elements.includes {
+        let included = included.resolve(path, search).unwrap();
+        parse_file(&included, search, ast);
+    }
+    let lines: Vec<&str> = ppr.lines.iter().map(|x| x.as_str()).collect();
+    let lxr = lexer::Lexer::new(lines, filename);
+    let mut psr = parser::Parser::new(lxr);
+    psr.run(ast).unwrap();
+}
+
+/// Parse a P4 file from the `src/p4` directory of this crate.
+pub fn parse_test_program(name: &str) -> AST {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/p4");
+    let path = dir.join(name);
+    let mut ast = AST::default();
+    parse_file(&path, &[dir], &mut ast);
+    ast
+}
@@ -0,0 +1,37 @@
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/header_copy.p4",
+    pipeline_name = "header_copy"
+);
+
+#[test]
+fn whole_header_assignment() {
+    let mut pipeline = main_pipeline::new(2);
+
+    let mut frame = vec![0x1d; 6];
+    frame.extend_from_slice(&[0xa0, 0xa1, 0xa2, 0xa3, 0xa4, 0xa5]);
+    frame.extend_from_slice(&[0x86, 0xdd]);
+    frame.extend_from_slice(b"muffins");
+
+    let mut pkt = packet_in::new(&frame);
+    let (parsed, _) = pipeline.run_parser(0, &mut pkt).unwrap();
+    let inner = &parsed.inner;
+    assert!(inner.isValid());
+
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet_headers(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    let hdr = &out[0].0;
+    assert!(hdr.outer.isValid());
+    assert_eq!(hdr.outer.dst_addr, inner.dst_addr);
+    assert_eq!(hdr.outer.src_addr, inner.src_addr);
+    assert_eq!(hdr.outer.ether_type, inner.ether_type);
+    assert!(!hdr.inner.isValid());
+
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].0.header_data, &frame[..14]);
+    assert_eq!(out[0].0.payload_data, b"muffins");
+}
@@ -0,0 +1,20 @@
+use p4rs::packet_in;
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/header_size.p4",
+    pipeline_name = "header_size",
+);
+
+#[test]
+fn header_size_queries() {
+    let mut pipeline = main_pipeline::new(2);
+
+    let frame = [0u8; 36];
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet_headers(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+
+    // ipv4_t is 160 bits, ethernet_t and ipv4_t together are 34 bytes.
+    assert_eq!(out[0].0.result.ipv4_bits.load_le::<u8>(), 1);
+    assert_eq!(out[0].0.result.total_bytes.load_le::<u8>(), 1);
+}
@@ -0,0 +1,50 @@
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/header_stack.p4",
+    pipeline_name = "header_stack",
+);
+
+#[test]
+fn push_front_moves_tag_down() {
+    let mut pipeline = main_pipeline::new(2);
+
+    // ethernet frame carrying a single 802.1q tag
+    let mut frame = vec![0u8; 18];
+    frame[12..14].copy_from_slice(&0x8100u16.to_be_bytes());
+    frame[14..16].copy_from_slice(&0x2007u16.to_be_bytes());
+    frame[16..18].copy_from_slice(&0x0800u16.to_be_bytes());
+
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].1, 1);
+
+    // the new tag is on top and the original tag is now second
+    let data = &out[0].0.header_data;
+    assert_eq!(data.len(), 14 + 4 + 4);
+    assert_eq!(&data[18..22], &frame[14..18]);
+}
+
+#[test]
+fn push_and_pop_full_stack() {
+    let mut headers = headers_t::default();
+    headers.vlans[0].setValid();
+    headers.vlans[0].vid = {
+        let mut x = bitvec![mut u8, Msb0; 0; 12];
+        x.store_le(1u16);
+        x
+    };
+    headers.vlans[1].setValid();
+
+    // pushing onto a full stack discards the bottom element
+    p4rs::header_stack_push_front(&mut headers.vlans, 1);
+    assert!(!headers.vlans[0].isValid());
+    assert!(headers.vlans[1].isValid());
+    assert_eq!(headers.vlans[1].vid.load_le::<u16>(), 1);
+
+    // popping more elements than the stack holds empties it
+    p4rs::header_stack_pop_front(&mut headers.vlans, 3);
+    assert!(!headers.vlans[0].isValid());
+    assert!(!headers.vlans[1].isValid());
+}
@@ -0,0 +1,56 @@
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/header_union.p4",
+    pipeline_name = "header_union",
+);
+
+/// An ethernet frame of the given ethertype followed by `len` zeroed bytes
+/// with the leading IP version nibble set.
+fn ip_frame(ether_type: u16, version: u8, len: usize) -> Vec<u8> {
+    let mut frame = vec![0u8; 14 + len];
+    frame[12..14].copy_from_slice(&ether_type.to_be_bytes());
+    frame[14] = version << 4;
+    frame
+}
+
+#[test]
+fn header_union_single_valid_member() {
+    let mut hdr = headers_t::default();
+    let mut ingress = ingress_metadata_t::default();
+    assert!(!hdr.ip.isValid());
+
+    let frame = ip_frame(0x0800, 4, 20);
+    let mut pkt = packet_in::new(&frame);
+    assert!(parse_start(&mut pkt, &mut hdr, &mut ingress));
+    assert!(hdr.ip.isValid());
+    assert!(hdr.ip.v4.isValid());
+    assert!(!hdr.ip.v6.isValid());
+
+    // extracting into the other member of the union invalidates the first
+    let frame = ip_frame(0x86dd, 6, 40);
+    let mut pkt = packet_in::new(&frame);
+    assert!(parse_start(&mut pkt, &mut hdr, &mut ingress));
+    assert!(hdr.ip.isValid());
+    assert!(!hdr.ip.v4.isValid());
+    assert!(hdr.ip.v6.isValid());
+}
+
+#[test]
+fn header_union_deparse() {
+    let mut pipeline = main_pipeline::new(4);
+
+    let frame = ip_frame(0x0800, 4, 20);
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].1, 1);
+    assert_eq!(out[0].0.header_data.len(), 14 + 20);
+
+    let frame = ip_frame(0x86dd, 6, 40);
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].1, 2);
+    assert_eq!(out[0].0.header_data.len(), 14 + 40);
+}
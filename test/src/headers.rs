@@ -100,3 +100,19 @@ fn ipv6_header_read_write() {
     assert_eq!(traffic_class, BigUint::from(127u8));
     */
 }
+
+#[test]
+fn ipv6_header_short_buffer() {
+    // src ends at bit 192, so a 20 byte buffer is short by 32 bits
+    let data = [0u8; 20];
+    let mut v6 = ipv6_t::new();
+    let err = v6.set(&data).unwrap_err();
+    assert_eq!(err.header, Some("ipv6_t"));
+    assert_eq!(err.field, Some("src"));
+    assert_eq!(err.expected, 192);
+    assert_eq!(err.actual, 160);
+    assert_eq!(
+        err.to_string(),
+        "ipv6_t.src: slice of 160 bits not big enough for 192 bits",
+    );
+}
@@ -0,0 +1,25 @@
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(p4 = "test/src/p4/overflow.p4", pipeline_name = "headroom");
+
+#[test]
+fn extract_after_headroom() {
+    let mut pipeline = main_pipeline::new(2);
+
+    let mut buf = vec![0xeeu8; 16];
+    buf.extend_from_slice(&[200, 100, 0, 0, 0, 0, 0, 0, 0]);
+    buf.extend_from_slice(b"muffins");
+
+    let mut pkt = packet_in::with_offset(&buf, 16);
+    let out = pipeline.process_packet_headers(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].0.ops.a.load_le::<u8>(), 200);
+    assert_eq!(out[0].0.ops.b.load_le::<u8>(), 100);
+    assert_eq!(out[0].0.ops.sum.load_le::<u8>(), 44);
+
+    let mut pkt = packet_in::with_offset(&buf, 16);
+    let out = pipeline.process_packet(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].0.header_data[..2], [200, 100]);
+    assert_eq!(out[0].0.payload_data, b"muffins");
+}
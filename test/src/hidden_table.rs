@@ -0,0 +1,35 @@
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/hidden_table.p4",
+    pipeline_name = "hidden_table"
+);
+
+#[test]
+fn hidden_tables_not_listed() {
+    let mut pipeline = main_pipeline::new(2);
+
+    assert_eq!(pipeline.get_table_ids(), vec!["ingress.fwd"]);
+    let described: Vec<String> = pipeline
+        .describe()
+        .tables
+        .into_iter()
+        .map(|t| t.table_id)
+        .collect();
+    assert_eq!(described, vec!["ingress.fwd"]);
+
+    let dst = [0x1d; 6];
+    pipeline
+        .add_table_entry("ingress.fwd", "forward", &dst, &1u16.to_le_bytes(), 0)
+        .unwrap();
+
+    // the hidden table still applies its entries
+    let mut frame = dst.to_vec();
+    frame.extend_from_slice(&[0xa0; 6]);
+    frame.extend_from_slice(&[0x09, 0x01]);
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].1, 1);
+    assert_eq!(&out[0].0.header_data[12..14], &[0x86, 0xdd]);
+}
@@ -0,0 +1,30 @@
+use crate::frontend::parse_file;
+use p4::ast::AST;
+use p4::check;
+use std::path::Path;
+
+#[test]
+fn basic_router_hlir_json() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../p4/examples/codegen/router.p4");
+    let mut ast = AST::default();
+    parse_file(&path, &[path.parent().unwrap().into()], &mut ast);
+    let (hlir, diags) = check::all(&ast);
+    assert!(diags.errors().is_empty());
+
+    let json = serde_json::to_string(&hlir).unwrap();
+    let v: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+    let decls = v["lvalue_decls"].as_array().unwrap();
+    let dst = decls
+        .iter()
+        .find(|d| d["lvalue"]["name"] == "hdr.ipv6.dst")
+        .expect("hdr.ipv6.dst in lvalue decls");
+    assert_eq!(dst["info"]["ty"], "bit<128>");
+    assert_eq!(dst["info"]["decl"], "HeaderMember");
+    assert!(dst["lvalue"]["token"]["line"].is_u64());
+
+    // Serialization is stable across runs of the checker.
+    let (again, _) = check::all(&ast);
+    assert_eq!(json, serde_json::to_string(&again).unwrap());
+}
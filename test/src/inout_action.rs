@@ -0,0 +1,28 @@
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/inout_action.p4",
+    pipeline_name = "inout_action"
+);
+
+#[test]
+fn inout_action_parameters_write_back() {
+    let mut pipeline = main_pipeline::new(2);
+
+    let mut frame = vec![0x1d; 6];
+    frame.extend_from_slice(&[0xa0, 0xa1, 0xa2, 0xa3, 0xa4, 0xa5]);
+    frame.extend_from_slice(&[0x86, 0xdd]);
+    frame.extend_from_slice(b"muffins");
+
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet(0, &mut pkt);
+
+    // bump(port) took the local from 0 to 1 and port_of wrote it out
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].1, 1);
+
+    // bump(hdr.ethernet.ether_type) is observed by the deparser
+    let header = &out[0].0.header_data;
+    assert_eq!(&header[12..14], &[0x86, 0xde]);
+    assert_eq!(out[0].0.payload_data, b"muffins");
+}
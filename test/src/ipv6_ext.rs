@@ -0,0 +1,68 @@
+use p4rs::{packet_in, PARSER_LOOP_LIMIT};
+
+p4_macro::use_p4!(p4 = "test/src/p4/ipv6_ext.p4", pipeline_name = "ipv6_ext");
+
+const HOP_BY_HOP: u8 = 0;
+const UDP: u8 = 17;
+
+fn ipv6(next_hdr: u8) -> Vec<u8> {
+    let mut data = vec![0u8; 40];
+    data[0] = 0x60;
+    data[6] = next_hdr;
+    data[7] = 64;
+    data
+}
+
+// An extension header of (1 + hdr_ext_len) * 8 bytes.
+fn extension(next_hdr: u8, hdr_ext_len: u8) -> Vec<u8> {
+    let mut data = vec![0u8; (1 + hdr_ext_len as usize) * 8];
+    data[0] = next_hdr;
+    data[1] = hdr_ext_len;
+    data
+}
+
+fn udp() -> Vec<u8> {
+    [4747u16, 1701, 8, 0]
+        .iter()
+        .flat_map(|x| x.to_be_bytes())
+        .collect()
+}
+
+#[test]
+fn hop_by_hop_then_udp() {
+    let pipeline = main_pipeline::new(2);
+    for hdr_ext_len in [0, 1] {
+        let mut frame = ipv6(HOP_BY_HOP);
+        frame.extend(extension(UDP, hdr_ext_len));
+        frame.extend(udp());
+
+        let mut pkt = packet_in::new(&frame);
+        let (parsed, _) = pipeline.run_parser(0, &mut pkt).unwrap();
+        assert!(parsed.ext.is_valid());
+        assert!(parsed.udp.is_valid());
+        assert_eq!(parsed.udp.src_port.load_le::<u16>(), 4747);
+        assert_eq!(parsed.udp.dst_port.load_le::<u16>(), 1701);
+        assert_eq!(pkt.index, frame.len() * 8);
+    }
+}
+
+#[test]
+fn extension_chain_is_bounded() {
+    let pipeline = main_pipeline::new(2);
+
+    let mut frame = ipv6(HOP_BY_HOP);
+    for _ in 0..PARSER_LOOP_LIMIT {
+        frame.extend(extension(HOP_BY_HOP, 0));
+    }
+    frame.extend(extension(UDP, 0));
+    frame.extend(udp());
+    let mut pkt = packet_in::new(&frame);
+    assert!(pipeline.run_parser(0, &mut pkt).is_none());
+
+    let mut frame = ipv6(HOP_BY_HOP);
+    frame.extend(extension(HOP_BY_HOP, 0));
+    frame.extend(extension(UDP, 0));
+    frame.extend(udp());
+    let mut pkt = packet_in::new(&frame);
+    assert!(pipeline.run_parser(0, &mut pkt).is_some());
+}
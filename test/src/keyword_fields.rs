@@ -0,0 +1,52 @@
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/keyword_fields.p4",
+    pipeline_name = "keyword_fields",
+);
+
+#[test]
+fn rust_keyword_names() {
+    let mut pipeline = main_pipeline::new(2);
+    let frame = [1u8, 0, 0, 47];
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet_headers(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+
+    // P4 names that are Rust keywords are suffixed with an underscore, and
+    // type becomes typ.
+    let kw = &out[0].0.kw;
+    assert_eq!(kw.typ.load_le::<u8>(), 1);
+    assert_eq!(kw.move_.load_le::<u8>(), 1);
+    assert_eq!(kw.fn_.load_le::<u8>(), 47);
+    assert_eq!(kw.self_.load_le::<u8>(), 47);
+}
+
+#[test]
+fn table_ids_use_p4_names() {
+    let mut pipeline = main_pipeline::new(4);
+
+    // The control instance `loop` and table `match` are sanitized in the
+    // generated code but are addressed by their P4 names at runtime.
+    assert_eq!(pipeline.get_table_ids(), vec!["ingress.loop.match"]);
+    let schema = pipeline.get_table_schema("ingress.loop.match").unwrap();
+    assert_eq!(schema.keys[0].name, "hdr.kw.type");
+    let forward = schema.get_action("forward").unwrap();
+    assert_eq!(forward.parameters[0].name, "ref");
+
+    let port = 3u16.to_le_bytes();
+    pipeline
+        .add_table_entry_by_fields(
+            "ingress.loop.match",
+            "forward",
+            &[("hdr.kw.type", &[2])],
+            &[("ref", &port)],
+            0,
+        )
+        .unwrap();
+
+    let frame = [2u8, 0, 0, 0];
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet_headers(0, &mut pkt);
+    assert_eq!(out[0].1, 3);
+}
@@ -1,32 +1,167 @@
 #![allow(clippy::too_many_arguments)]
 
+#[cfg(test)]
+mod annotations;
+#[cfg(test)]
+mod assert;
+#[cfg(test)]
+mod atomic;
 #[cfg(test)]
 mod basic_router;
 #[cfg(test)]
+mod batch;
+#[cfg(test)]
+mod bit_literals;
+#[cfg(test)]
+mod checked_access;
+#[cfg(test)]
+mod clone;
+#[cfg(test)]
+mod composite_key;
+#[cfg(test)]
+mod const_priority;
+#[cfg(test)]
+mod const_select;
+#[cfg(test)]
+mod control_const;
+#[cfg(test)]
 mod controller_multiple_instantiation;
 #[cfg(test)]
 mod decap;
 #[cfg(test)]
+mod default_only_entry;
+#[cfg(test)]
+mod deparse;
+#[cfg(test)]
+mod describe;
+#[cfg(test)]
+mod digit_separators;
+#[cfg(test)]
+mod dirty_field;
+#[cfg(test)]
 mod disag_router;
 #[cfg(test)]
 mod dload;
 #[cfg(test)]
+mod drop_stats;
+#[cfg(test)]
 mod dynamic_router;
 #[cfg(test)]
+mod encap;
+#[cfg(test)]
+mod endian;
+#[cfg(test)]
+mod field_keys;
+#[cfg(test)]
+mod flag_key;
+#[cfg(test)]
+mod header_copy;
+#[cfg(test)]
+mod header_size;
+#[cfg(test)]
+mod header_stack;
+#[cfg(test)]
+mod header_union;
+#[cfg(test)]
 mod headers;
 #[cfg(test)]
+mod headroom;
+#[cfg(test)]
+mod hidden_table;
+#[cfg(test)]
+mod hlir_json;
+#[cfg(test)]
 mod hub;
 #[cfg(test)]
+mod inout_action;
+#[cfg(test)]
 mod ipv6;
 #[cfg(test)]
+mod ipv6_ext;
+#[cfg(test)]
+mod keyword_fields;
+#[cfg(test)]
+mod lint;
+#[cfg(test)]
 mod mac_rewrite;
 #[cfg(test)]
+mod metadata_default;
+#[cfg(test)]
+mod meter;
+#[cfg(test)]
+mod migrate;
+#[cfg(test)]
+mod mpls;
+#[cfg(test)]
+mod named_args;
+#[cfg(test)]
+mod nesting;
+#[cfg(test)]
+mod out_extern;
+#[cfg(test)]
+mod overflow;
+#[cfg(test)]
+mod packet_length;
+#[cfg(test)]
+mod packet_outputs;
+#[cfg(test)]
+mod parser_error;
+#[cfg(test)]
+mod parser_local;
+#[cfg(test)]
+mod parser_reject;
+#[cfg(test)]
+mod pipeline_stages;
+#[cfg(test)]
+mod port_width;
+#[cfg(test)]
+mod pragma;
+#[cfg(test)]
+mod radix;
+#[cfg(test)]
 mod range;
 #[cfg(test)]
+mod saturating;
+#[cfg(test)]
+mod select_slice;
+#[cfg(test)]
+mod sideband;
+#[cfg(test)]
+mod skip_header;
+#[cfg(test)]
+mod standalone;
+#[cfg(test)]
+mod string_literals;
+#[cfg(test)]
+mod struct_literal;
+#[cfg(test)]
+mod table_actions;
+#[cfg(test)]
+mod table_change;
+#[cfg(test)]
+mod table_entry;
+#[cfg(test)]
+mod table_ids;
+#[cfg(test)]
 mod table_in_egress_and_ingress;
 #[cfg(test)]
+mod table_key;
+#[cfg(test)]
+mod ternary;
+#[cfg(test)]
+mod ttl_checksum;
+#[cfg(test)]
+mod tuple_extern;
+#[cfg(test)]
+mod user_extern;
+#[cfg(test)]
+mod verify;
+#[cfg(test)]
 mod vlan;
+#[cfg(test)]
+mod wide_key;
 
 pub mod data;
+pub mod frontend;
 pub mod packet;
 pub mod softnpu;
@@ -0,0 +1,34 @@
+use crate::frontend::parse_test_program;
+use p4::check;
+
+#[test]
+fn dead_assignments() {
+    let ast = parse_test_program("dead_assign.p4");
+    let (_, diags) = check::all(&ast);
+    assert!(diags.warnings().is_empty(), "{:#?}", diags.warnings());
+
+    let lints = check::lint(&ast);
+    let warnings = lints.warnings();
+    assert_eq!(warnings.len(), 2, "{:#?}", warnings);
+    assert!(warnings[0].message.contains("port"));
+    assert_eq!(warnings[0].token.line, 51);
+    assert!(warnings[1].message.contains("egress.nexthop_v4"));
+    assert_eq!(warnings[1].token.line, 62);
+}
+
+#[test]
+fn no_warn_suppresses_category() {
+    let ast = parse_test_program("no_warn.p4");
+    let (_, diags) = check::all(&ast);
+    assert!(diags.errors().is_empty(), "{:#?}", diags.errors());
+
+    // trace is unused but allowed to be, and its dead assignment still warns.
+    // rewrite allows its dead assignment but not being unused.
+    let lints = check::lint(&ast);
+    let warnings = lints.warnings();
+    assert_eq!(warnings.len(), 2, "{:#?}", warnings);
+    assert_eq!(warnings[0].category, Some("dead-assignment"));
+    assert_eq!(warnings[0].token.line, 48);
+    assert_eq!(warnings[1].category, Some("unused"));
+    assert_eq!(warnings[1].message, "action rewrite is never used");
+}
@@ -0,0 +1,26 @@
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/metadata_default.p4",
+    pipeline_name = "metadata_default"
+);
+
+#[test]
+fn metadata_defaults_set_at_construction() {
+    let mut pipeline = main_pipeline::new(4);
+
+    let mut frame = vec![0x1d; 6];
+    frame.extend_from_slice(&[0xa0, 0xa1, 0xa2, 0xa3, 0xa4, 0xa5]);
+    frame.extend_from_slice(&[0x86, 0xdd]);
+
+    let mut pkt = packet_in::new(&frame);
+    let (_, ingress) = pipeline.run_parser(0, &mut pkt).unwrap();
+    assert_eq!(p4rs::endian::load(&ingress.nat_id), 7);
+    assert_eq!(p4rs::endian::load(&ingress.port), 0);
+
+    // the first packet through the pipeline goes to the default CPU port
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].1, 3);
+}
@@ -0,0 +1,70 @@
+use p4rs::externs::{MeterConfig, TimeSource};
+use p4rs::{packet_in, Pipeline};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+p4_macro::use_p4!(p4 = "test/src/p4/meter.p4", pipeline_name = "meter");
+
+#[derive(Clone, Default)]
+struct MockClock(Arc<AtomicU64>);
+
+impl MockClock {
+    fn advance(&self, d: Duration) {
+        self.0.fetch_add(d.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+impl TimeSource for MockClock {
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.0.load(Ordering::Relaxed))
+    }
+}
+
+/// Send a packet through the pipeline and return the color it was marked
+/// with, or `None` if it was dropped for being red.
+fn send(pipeline: &mut main_pipeline) -> Option<u8> {
+    let frame = [0u8; 15];
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet_headers(0, &mut pkt);
+    out.first().map(|(hdr, _)| hdr.mark.color.load_le::<u8>())
+}
+
+#[test]
+fn packets_exceeding_rate_are_red() {
+    let clock = MockClock::default();
+    let mut pipeline = main_pipeline::new(2);
+    pipeline.set_time_source(Box::new(clock.clone()));
+    pipeline.configure_meter(
+        "ingress.rate_limit",
+        0,
+        MeterConfig {
+            rate: 10,
+            burst: 2,
+            excess_burst: 1,
+        },
+    );
+
+    // two green packets within the committed burst, one yellow packet within
+    // the excess burst, everything after that is red.
+    assert_eq!(send(&mut pipeline), Some(0));
+    assert_eq!(send(&mut pipeline), Some(0));
+    assert_eq!(send(&mut pipeline), Some(1));
+    assert_eq!(send(&mut pipeline), None);
+    assert_eq!(send(&mut pipeline), None);
+
+    // at 10 packets per second a token is available after 100ms.
+    clock.advance(Duration::from_millis(50));
+    assert_eq!(send(&mut pipeline), None);
+    clock.advance(Duration::from_millis(50));
+    assert_eq!(send(&mut pipeline), Some(0));
+    assert_eq!(send(&mut pipeline), None);
+}
+
+#[test]
+fn unconfigured_meter_is_green() {
+    let mut pipeline = main_pipeline::new(2);
+    for _ in 0..100 {
+        assert_eq!(send(&mut pipeline), Some(0));
+    }
+}
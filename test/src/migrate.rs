@@ -0,0 +1,63 @@
+use p4rs::snapshot::DropReason;
+use p4rs::Pipeline;
+
+mod v1 {
+    p4_macro::use_p4!(
+        p4 = "test/src/p4/migrate_v1.p4",
+        pipeline_name = "migrate_v1"
+    );
+}
+
+mod v2 {
+    p4_macro::use_p4!(
+        p4 = "test/src/p4/migrate_v2.p4",
+        pipeline_name = "migrate_v2"
+    );
+}
+
+#[test]
+fn migrate_preserves_compatible_tables() {
+    let mut old = v1::main_pipeline::new(2);
+
+    let mac = [0x11u8, 0x22, 0x33, 0x44, 0x55, 0x66];
+    old.add_table_entry("ingress.fwd", "forward", &mac, &1u16.to_le_bytes(), 0)
+        .unwrap();
+    let mac = [0x11u8, 0x22, 0x33, 0x44, 0x55, 0x77];
+    old.add_table_entry("ingress.fwd", "drop", &mac, &[], 0)
+        .unwrap();
+    old.add_table_entry(
+        "ingress.retype",
+        "set_type",
+        &0x0800u16.to_le_bytes(),
+        &0x86ddu16.to_le_bytes(),
+        0,
+    )
+    .unwrap();
+
+    let snapshot = old.snapshot();
+
+    let mut new = v2::main_pipeline::new(2);
+    let report = new.migrate_from(&snapshot);
+
+    // The forwarding table is unchanged, so its entries carry over.
+    assert_eq!(report.restored("ingress.fwd"), 2);
+    assert!(report.dropped("ingress.fwd").is_empty());
+    let mut before = old.get_table_entries("ingress.fwd").unwrap();
+    let mut after = new.get_table_entries("ingress.fwd").unwrap();
+    before.sort_by(|a, b| a.keyset_data.cmp(&b.keyset_data));
+    after.sort_by(|a, b| a.keyset_data.cmp(&b.keyset_data));
+    assert_eq!(before.len(), after.len());
+    for (b, a) in before.iter().zip(&after) {
+        assert_eq!(b.to_string(), a.to_string());
+    }
+
+    // The retype table changed its key, so its entry is dropped.
+    assert_eq!(report.restored("ingress.retype"), 0);
+    let dropped = report.dropped("ingress.retype");
+    assert_eq!(dropped.len(), 1);
+    assert_eq!(dropped[0].reason, DropReason::KeyMismatch);
+    assert!(new.get_table_entries("ingress.retype").unwrap().is_empty());
+
+    // The acl table is new and starts out empty.
+    assert!(new.get_table_entries("ingress.acl").unwrap().is_empty());
+}
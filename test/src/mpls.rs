@@ -0,0 +1,54 @@
+use p4rs::packet_in;
+
+p4_macro::use_p4!(p4 = "test/src/p4/mpls.p4", pipeline_name = "mpls");
+
+fn frame(ether_type: u16, labels: &[u32]) -> Vec<u8> {
+    let mut data = vec![0u8; 12];
+    data.extend_from_slice(&ether_type.to_be_bytes());
+    for (i, label) in labels.iter().enumerate() {
+        let bos = (i + 1 == labels.len()) as u32;
+        let entry = (label << 12) | (bos << 8) | (64 + i as u32);
+        data.extend_from_slice(&entry.to_be_bytes());
+    }
+    data
+}
+
+#[test]
+fn extract_into_stack_elements() {
+    let pipeline = main_pipeline::new(2);
+    let data = frame(0x8847, &[100, 200, 300]);
+    let mut pkt = packet_in::new(&data);
+    let (parsed, _) = pipeline.run_parser(0, &mut pkt).unwrap();
+
+    for (i, mpls) in parsed.mpls.iter().enumerate() {
+        assert!(mpls.is_valid());
+        assert_eq!(mpls.ttl.load_le::<u8>(), 64 + i as u8);
+        assert_eq!(mpls.bos[0], i == 2);
+    }
+    assert_eq!(pkt.index, data.len() * 8);
+}
+
+#[test]
+fn extract_into_full_stack_rejects() {
+    let pipeline = main_pipeline::new(2);
+    let data = frame(0x8848, &[100, 200, 300, 400]);
+    let mut pkt = packet_in::new(&data);
+    assert!(pipeline.run_parser(0, &mut pkt).is_none());
+}
+
+#[test]
+fn extract_past_end_of_packet_rejects() {
+    // The parser extracts three labels, but the frame only carries two.
+    let pipeline = main_pipeline::new(2);
+    let data = frame(0x8847, &[100, 200]);
+    let mut pkt = packet_in::new(&data);
+    assert!(pipeline.run_parser(0, &mut pkt).is_none());
+    assert!(pkt.index <= data.len() * 8);
+    assert_eq!(pkt.remaining_bits(), 0);
+
+    // A frame too short for even the ethernet header.
+    let mut pkt = packet_in::new(&data[..6]);
+    assert!(pipeline.run_parser(0, &mut pkt).is_none());
+    assert_eq!(pkt.index, 0);
+    assert_eq!(pkt.remaining_bits(), 48);
+}
@@ -0,0 +1,39 @@
+use crate::frontend::parse_test_program;
+use p4::check;
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/named_args.p4",
+    pipeline_name = "named_args"
+);
+
+#[test]
+fn named_action_arguments() {
+    let mut pipeline = main_pipeline::new(2);
+
+    // one entry gives its arguments by position, the other by name
+    for ether_type in [[0x08, 0x00], [0x08, 0x06]] {
+        let mut frame = vec![0x1d; 12];
+        frame.extend_from_slice(&ether_type);
+        let mut pkt = packet_in::new(&frame);
+        let out = pipeline.process_packet(0, &mut pkt);
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].1, 1);
+        assert_eq!(&out[0].0.header_data[12..14], &[0x86, 0xdd]);
+    }
+}
+
+#[test]
+fn bad_named_action_arguments() {
+    let ast = parse_test_program("bad_named_args.p4");
+    let (_, diags) = check::all(&ast);
+    let errors = diags.errors();
+    let messages: Vec<&str> =
+        errors.iter().map(|e| e.message.as_str()).collect();
+    assert_eq!(errors.len(), 4, "{:#?}", errors);
+    assert!(errors.iter().all(|e| e.code == "E0036"));
+    assert!(messages[0].contains("more than once"));
+    assert!(messages[1].contains("missing"));
+    assert!(messages[2].contains("no parameter"));
+    assert!(messages[3].contains("missing"));
+}
@@ -0,0 +1,88 @@
+use p4::ast::AST;
+use p4::error::Error;
+use p4::{lexer, parser};
+use std::sync::Arc;
+
+// A control whose apply block assigns an expression nested in `depth` levels
+// of parentheses.
+fn nested_parens(depth: usize) -> String {
+    format!(
+        "control ingress() {{ apply {{ bit<8> x = {}8w1{}; }} }}",
+        "(".repeat(depth),
+        ")".repeat(depth),
+    )
+}
+
+// A control whose apply block assigns a flat chain of `terms` additions.
+fn flat_chain(terms: usize) -> String {
+    format!(
+        "control ingress() {{ apply {{ bit<8> x = {}; }} }}",
+        vec!["8w1"; terms].join(" + "),
+    )
+}
+
+// A control whose apply block holds `depth` levels of nested blocks.
+fn nested_blocks(depth: usize) -> String {
+    format!(
+        "control ingress() {{ apply {{ {}{} }} }}",
+        "{ ".repeat(depth),
+        "} ".repeat(depth),
+    )
+}
+
+fn parse(source: &str, max_depth: Option<usize>) -> Result<AST, Error> {
+    let lxr = lexer::Lexer::new(vec![source], Arc::new("nesting.p4".into()));
+    let mut psr = parser::Parser::new(lxr);
+    if let Some(max_depth) = max_depth {
+        psr.set_max_depth(max_depth);
+    }
+    let mut ast = AST::default();
+    psr.run(&mut ast)?;
+    Ok(ast)
+}
+
+fn assert_too_deep(result: Result<AST, Error>, max_depth: usize) {
+    match result {
+        Err(Error::Parser(e)) => assert_eq!(
+            e.message,
+            format!("Nesting exceeds the maximum depth of {}.", max_depth),
+        ),
+        Err(e) => panic!("expected a parser error, found {}", e),
+        Ok(_) => panic!("expected a parser error"),
+    }
+}
+
+#[test]
+fn nesting_within_limit_parses() {
+    let ast = parse(&nested_parens(64), None).unwrap();
+    assert!(ast.get_control("ingress").is_some());
+    parse(&nested_blocks(64), None).unwrap();
+}
+
+#[test]
+fn long_flat_chain_parses() {
+    // Operands of a flat chain are not nested, however many there are.
+    parse(&flat_chain(1000), None).unwrap();
+}
+
+#[test]
+fn nested_parens_beyond_limit() {
+    assert_too_deep(
+        parse(&nested_parens(100_000), None),
+        parser::DEFAULT_MAX_DEPTH,
+    );
+}
+
+#[test]
+fn nested_blocks_beyond_limit() {
+    assert_too_deep(
+        parse(&nested_blocks(100_000), None),
+        parser::DEFAULT_MAX_DEPTH,
+    );
+}
+
+#[test]
+fn configured_max_depth() {
+    assert_too_deep(parse(&nested_parens(16), Some(8)), 8);
+    parse(&nested_parens(4), Some(8)).unwrap();
+}
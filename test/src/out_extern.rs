@@ -0,0 +1,54 @@
+use p4rs::externs::ExternMethod;
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/out_extern.p4",
+    pipeline_name = "out_extern",
+);
+
+struct MockHasher;
+
+impl ExternMethod for MockHasher {
+    fn call(&self, method: &str, _: &[&BitVec<u8, Msb0>]) -> BitVec<u8, Msb0> {
+        panic!("{} should be called through call_mut", method);
+    }
+
+    fn call_mut(
+        &self,
+        method: &str,
+        args: &mut [&mut BitVec<u8, Msb0>],
+    ) -> BitVec<u8, Msb0> {
+        match method {
+            "hash" => {
+                let data: u32 = args[1].load_le();
+                args[0].store_le((data >> 16) as u16 ^ data as u16);
+                // writes to in arguments are not seen by the program
+                args[1].store_le(0u32);
+            }
+            "bump" => {
+                let counter: u16 = args[0].load_le();
+                args[0].store_le(counter + 1);
+            }
+            x => panic!("unexpected method {}", x),
+        }
+        BitVec::new()
+    }
+}
+
+#[test]
+fn extern_writes_out_parameters() {
+    let mut pipeline = main_pipeline::new(2);
+    pipeline.register_extern("Hasher", Box::new(MockHasher));
+
+    let mut frame = 0x1234_5678u32.to_be_bytes().to_vec();
+    frame.extend_from_slice(&0u16.to_be_bytes());
+    frame.extend_from_slice(&41u16.to_be_bytes());
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet_headers(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+
+    let flow = &out[0].0.flow;
+    assert_eq!(flow.hash.load_le::<u16>(), 0x1234 ^ 0x5678);
+    assert_eq!(flow.counter.load_le::<u16>(), 42);
+    assert_eq!(flow.data.load_le::<u32>(), 0x1234_5678);
+}
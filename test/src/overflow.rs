@@ -0,0 +1,39 @@
+use crate::frontend::parse_test_program;
+use p4::check;
+use p4rs::packet_in;
+
+p4_macro::use_p4!(p4 = "test/src/p4/overflow.p4", pipeline_name = "overflow");
+
+#[test]
+fn bit_arithmetic_wraps() {
+    let mut pipeline = main_pipeline::new(2);
+
+    let frame = [200u8, 100, 0, 0, 0x01, 0x80, 0x01, 0x90, 0];
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet_headers(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    let ops = &out[0].0.ops;
+
+    assert_eq!(ops.sum.load_le::<u8>(), 44);
+    assert_eq!(ops.diff.load_le::<u8>(), 156);
+
+    let wide_a = ops.wide_a.load_le::<u16>();
+    let wide_b = ops.wide_b.load_le::<u16>();
+    assert_eq!(
+        ops.narrow.load_le::<u8>(),
+        wide_a.wrapping_add(wide_b) as u8
+    );
+}
+
+#[test]
+fn truncating_arithmetic_warns() {
+    let ast = parse_test_program("overflow.p4");
+    let (_, diags) = check::all(&ast);
+    assert!(diags.errors().is_empty(), "{:#?}", diags.errors());
+    let warnings = diags.warnings();
+    assert_eq!(warnings.len(), 1, "{:#?}", warnings);
+    assert_eq!(
+        warnings[0].message,
+        "Implicit truncation of bit<16> arithmetic result to bit<8>"
+    );
+}
@@ -0,0 +1,25 @@
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/packet_length.p4",
+    pipeline_name = "packet_length"
+);
+
+#[test]
+fn short_packets_dropped() {
+    let mut pipeline = main_pipeline::new(2);
+
+    let frame = [0u8; 40];
+    let mut pkt = packet_in::new(&frame);
+    let (_, ingress) = pipeline.run_parser(0, &mut pkt).unwrap();
+    assert_eq!(p4rs::endian::load(&ingress.packet_length), 40);
+
+    let mut pkt = packet_in::new(&frame);
+    assert!(pipeline.process_packet(0, &mut pkt).is_empty());
+
+    let frame = [0u8; 100];
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].1, 1);
+}
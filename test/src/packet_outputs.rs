@@ -0,0 +1,47 @@
+use p4rs::{packet_in, Pipeline};
+
+mod broadcast {
+    p4_macro::use_p4!(
+        p4 = "test/src/p4/hub.p4",
+        pipeline_name = "outputs_broadcast"
+    );
+}
+
+mod marker {
+    p4_macro::use_p4!(
+        p4 = "test/src/p4/const_priority.p4",
+        pipeline_name = "outputs_marker",
+        optimize = true,
+    );
+}
+
+#[test]
+fn broadcast_outputs_spill() {
+    let mut pipeline = broadcast::main_pipeline::new(4);
+    let frame = [0u8; 64];
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet_outputs(0, &mut pkt);
+    assert!(out.spilled());
+    let ports: Vec<u16> = out.into_iter().map(|(_, port)| port).collect();
+    assert_eq!(ports, vec![1, 2, 3]);
+}
+
+#[test]
+fn optimized_rewrite_serializes() {
+    let mut pipeline = marker::main_pipeline::new(2);
+    let mut frame = [0u8; 20];
+    frame[15] = 0xee;
+
+    // A miss leaves the headers as they arrived.
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet(0, &mut pkt);
+    assert_eq!(out[0].0.header_data, &frame[..16]);
+
+    // A hit writes the result field of the tag.
+    frame[14] = 0x01;
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet(0, &mut pkt);
+    assert_eq!(out[0].0.header_data[..15], frame[..15]);
+    assert_eq!(out[0].0.header_data[15], 1);
+    assert_eq!(out[0].0.payload_data, &frame[16..]);
+}
@@ -0,0 +1,50 @@
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/parser_error.p4",
+    pipeline_name = "parser_error"
+);
+
+/// An ethernet frame carrying `len` bytes of an IPv4 header.
+fn ipv4_frame(len: usize) -> Vec<u8> {
+    let mut frame = vec![0u8; 14 + len];
+    frame[12..14].copy_from_slice(&0x0800u16.to_be_bytes());
+    frame
+}
+
+#[test]
+fn short_packet_sets_parser_error() {
+    let pipeline = main_pipeline::new(4);
+
+    let frame = ipv4_frame(20);
+    let mut pkt = packet_in::new(&frame);
+    let (_, ingress) = pipeline.run_parser(0, &mut pkt).unwrap();
+    assert_eq!(ingress.parser_error, error::NoError);
+
+    let frame = ipv4_frame(4);
+    let mut pkt = packet_in::new(&frame);
+    let (headers, ingress) = pipeline.run_parser(0, &mut pkt).unwrap();
+    assert_eq!(ingress.parser_error, error::PacketTooShort);
+    assert!(headers.ethernet.isValid());
+    assert!(!headers.ipv4.isValid());
+}
+
+#[test]
+fn parser_error_reaches_ingress() {
+    let mut pipeline = main_pipeline::new(4);
+
+    let frame = ipv4_frame(20);
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].1, 1);
+
+    // The truncated packet is punted to the CPU port whole.
+    let frame = ipv4_frame(4);
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].1, 3);
+    assert_eq!(out[0].0.header_data, &frame[..14]);
+    assert_eq!(out[0].0.payload_data, &frame[14..]);
+}
@@ -0,0 +1,9 @@
+use crate::frontend::parse_test_program;
+use p4::check;
+
+#[test]
+fn parser_state_locals_are_in_scope() {
+    let ast = parse_test_program("parser_local.p4");
+    let (_, diags) = check::all(&ast);
+    assert!(diags.errors().is_empty(), "{:#?}", diags.errors());
+}
@@ -0,0 +1,33 @@
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/parser_reject.p4",
+    pipeline_name = "parser_reject"
+);
+
+fn frame(ether_type: u16) -> Vec<u8> {
+    let mut frame = vec![0x1d; 12];
+    frame.extend_from_slice(&ether_type.to_be_bytes());
+    frame
+}
+
+#[test]
+fn transition_to_terminal_states() {
+    let mut pipeline = main_pipeline::new(2);
+
+    // accepted
+    let f = frame(0x86dd);
+    let mut pkt = packet_in::new(&f);
+    assert!(pipeline.run_parser(0, &mut pkt).is_some());
+    let mut pkt = packet_in::new(&f);
+    assert_eq!(pipeline.process_packet(0, &mut pkt).len(), 1);
+
+    // rejected by a select element and by a state that transitions to reject
+    for f in [frame(0x0800), frame(0x0806)] {
+        let mut pkt = packet_in::new(&f);
+        assert!(pipeline.run_parser(0, &mut pkt).is_none());
+        let mut pkt = packet_in::new(&f);
+        assert!(pipeline.process_packet(0, &mut pkt).is_empty());
+    }
+    assert_eq!(pipeline.drop_stats().parser, 4);
+}
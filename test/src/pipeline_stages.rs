@@ -0,0 +1,38 @@
+use p4rs::packet_in;
+
+p4_macro::use_p4!(p4 = "test/src/p4/hub.p4", pipeline_name = "stages");
+
+#[test]
+fn ingress_egress_spec() {
+    let pipeline = main_pipeline::new(4);
+
+    let frame = [0u8; 14];
+    let mut pkt = packet_in::new(&frame);
+    let (mut parsed, mut ingress) = pipeline.run_parser(0, &mut pkt).unwrap();
+    assert_eq!(ingress.port.load_le::<u16>(), 0);
+
+    // the const entry for port 0 forwards to port 1 and sets broadcast
+    let egress = pipeline.run_ingress(&mut parsed, &mut ingress);
+    assert!(egress.broadcast);
+    assert!(!egress.drop);
+    assert_eq!(egress.port.load_le::<u16>(), 1);
+
+    // broadcast replicates to every port but the ingress port
+    assert_eq!(pipeline.run_traffic_manager(0, &egress), vec![1, 2, 3]);
+}
+
+#[test]
+fn traffic_manager_unicast_and_drop() {
+    let pipeline = main_pipeline::new(4);
+
+    // no port set by ingress
+    let mut egress = egress_metadata_t::default();
+    assert!(pipeline.run_traffic_manager(0, &egress).is_empty());
+
+    egress.port = bitvec![u8, Msb0; 0; 16];
+    egress.port.store_le(2u16);
+    assert_eq!(pipeline.run_traffic_manager(0, &egress), vec![2]);
+
+    egress.drop = true;
+    assert!(pipeline.run_traffic_manager(0, &egress).is_empty());
+}
@@ -0,0 +1,25 @@
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(p4 = "test/src/p4/port9.p4", pipeline_name = "port9");
+
+#[test]
+fn nine_bit_ports_round_trip() {
+    let mut pipeline = main_pipeline::new(512);
+
+    for port in [0u16, 1, 255, 256, 510, 511] {
+        let frame = [0u8; 15];
+        let mut pkt = packet_in::new(&frame);
+        let out = pipeline.process_packet(port, &mut pkt);
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].1, port);
+
+        let last = if port == 511 { 1 } else { 0 };
+        assert_eq!(out[0].0.header_data[14], last, "port {}", port);
+    }
+}
+
+#[test]
+fn radix_accessor() {
+    let pipeline: Box<dyn Pipeline> = Box::new(main_pipeline::new(8));
+    assert_eq!(pipeline.radix(), 8);
+}
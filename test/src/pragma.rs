@@ -0,0 +1,44 @@
+use crate::softnpu::{RxFrame, SoftNpu, TxFrame};
+use crate::{expect_frames, muffins};
+use p4::preprocessor;
+use std::sync::Arc;
+
+p4_macro::use_p4!(p4 = "test/src/p4/pragma.p4", pipeline_name = "pragma");
+
+#[test]
+fn pragmas_are_skipped() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/src/p4/pragma.p4");
+    let contents = std::fs::read_to_string(path).unwrap();
+    let ppr = preprocessor::run(&contents, Arc::new(path.to_string())).unwrap();
+
+    // pragma lines are blanked rather than removed so the lines that follow
+    // keep their original line numbers
+    assert_eq!(ppr.lines.len(), contents.lines().count());
+    for (i, line) in contents.lines().enumerate() {
+        if line.trim_start().starts_with("#pragma") {
+            assert_eq!(ppr.lines[i], "");
+        } else if !line.starts_with("#include") {
+            assert_eq!(ppr.lines[i], line);
+        }
+    }
+}
+
+#[test]
+fn pragma_program_forwards() -> Result<(), anyhow::Error> {
+    let mut npu = SoftNpu::new(2, main_pipeline::new(2), false);
+    let phy1 = npu.phy(0);
+    let phy2 = npu.phy(1);
+
+    npu.run();
+
+    let et = 0;
+    let msg = muffins!();
+
+    phy1.send(&[TxFrame::new(phy2.mac, et, msg.0)])?;
+    expect_frames!(phy2, &[RxFrame::new(phy1.mac, et, msg.0)]);
+
+    phy2.send(&[TxFrame::new(phy1.mac, et, msg.1)])?;
+    expect_frames!(phy1, &[RxFrame::new(phy2.mac, et, msg.1)]);
+
+    Ok(())
+}
@@ -0,0 +1,35 @@
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(p4 = "test/src/p4/hub.p4", pipeline_name = "radix");
+
+#[test]
+fn single_port_broadcast() {
+    let mut pipeline = main_pipeline::new(1);
+
+    // the const entry for port 0 broadcasts, and there is no other port to
+    // send the packet to
+    let frame = [0u8; 14];
+    let mut pkt = packet_in::new(&frame);
+    assert!(pipeline.process_packet(0, &mut pkt).is_empty());
+}
+
+#[test]
+#[should_panic(expected = "radix")]
+fn zero_radix() {
+    main_pipeline::new(0);
+}
+
+#[test]
+fn unicast_beyond_radix() {
+    let pipeline = main_pipeline::new(2);
+
+    let mut egress = egress_metadata_t {
+        port: bitvec![u8, Msb0; 0; 16],
+        ..Default::default()
+    };
+    egress.port.store_le(1u16);
+    assert_eq!(pipeline.run_traffic_manager(0, &egress), vec![1]);
+
+    egress.port.store_le(2u16);
+    assert!(pipeline.run_traffic_manager(0, &egress).is_empty());
+}
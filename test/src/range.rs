@@ -27,7 +27,7 @@ fn range() -> Result<(), anyhow::Error> {
         &buf,
         &0u16.to_le_bytes(),
         0,
-    );
+    )?;
 
     let begin = v4_range_key("6.0.0.0".parse().unwrap());
     let end = v4_range_key("8.0.0.0".parse().unwrap());
@@ -39,7 +39,7 @@ fn range() -> Result<(), anyhow::Error> {
         &buf,
         &1u16.to_le_bytes(),
         0,
-    );
+    )?;
 
     let begin = v4_range_key("10.0.0.0".parse().unwrap());
     let end = v4_range_key("12.0.0.0".parse().unwrap());
@@ -51,7 +51,7 @@ fn range() -> Result<(), anyhow::Error> {
         &buf,
         &2u16.to_le_bytes(),
         0,
-    );
+    )?;
 
     let begin = v4_range_key("14.0.0.0".parse().unwrap());
     let end = v4_range_key("16.0.0.0".parse().unwrap());
@@ -63,7 +63,7 @@ fn range() -> Result<(), anyhow::Error> {
         &buf,
         &3u16.to_le_bytes(),
         0,
-    );
+    )?;
 
     //
     // run program
@@ -0,0 +1,37 @@
+use p4rs::packet_in;
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/saturating.p4",
+    pipeline_name = "saturating",
+);
+
+#[test]
+fn saturating_arithmetic_clamps() {
+    let mut pipeline = main_pipeline::new(2);
+
+    // wide is within 1000 of its maximum in either byte order.
+    let frame = [250u8, 4, 0xff, 0xfe];
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet_headers(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    let counters = &out[0].0.counters;
+
+    assert_eq!(counters.up.load_le::<u8>(), 255);
+    assert_eq!(counters.down.load_le::<u8>(), 0);
+    assert_eq!(counters.wide.load_le::<u16>(), u16::MAX);
+}
+
+#[test]
+fn saturating_arithmetic_in_range() {
+    let mut pipeline = main_pipeline::new(2);
+
+    let frame = [100u8, 40, 0, 0];
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet_headers(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    let counters = &out[0].0.counters;
+
+    assert_eq!(counters.up.load_le::<u8>(), 110);
+    assert_eq!(counters.down.load_le::<u8>(), 30);
+    assert_eq!(counters.wide.load_le::<u16>(), 1000);
+}
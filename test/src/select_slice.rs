@@ -0,0 +1,33 @@
+use p4rs::{packet_in, Header};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/select_slice.p4",
+    pipeline_name = "select_slice",
+);
+
+// Returns whether the low and high headers were extracted.
+fn parse(pipeline: &mut main_pipeline, ether_type: u16) -> (bool, bool) {
+    let mut frame = [0u8; 15];
+    frame[12..14].copy_from_slice(&ether_type.to_be_bytes());
+    frame[14] = 0x47;
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet_headers(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    let hdr = &out[0].0;
+    (hdr.low.is_valid(), hdr.high.is_valid())
+}
+
+#[test]
+fn select_on_low_nibble() {
+    let mut pipeline = main_pipeline::new(2);
+
+    assert_eq!(parse(&mut pipeline, 0x0801), (true, false));
+    assert_eq!(parse(&mut pipeline, 0x86de), (false, true));
+    assert_eq!(parse(&mut pipeline, 0x1e01), (true, false));
+    assert_eq!(parse(&mut pipeline, 0x0800), (false, false));
+    assert_eq!(parse(&mut pipeline, 0x0810), (false, false));
+
+    // matches the mask on bit 2 of the nibble
+    assert_eq!(parse(&mut pipeline, 0x080c), (true, false));
+    assert_eq!(parse(&mut pipeline, 0x0803), (false, false));
+}
@@ -0,0 +1,26 @@
+use p4rs::packet_in;
+
+p4_macro::use_p4!(p4 = "test/src/p4/sideband.p4", pipeline_name = "sideband");
+
+fn queue_for(timestamp: u64) -> u8 {
+    let mut pipeline = main_pipeline::new(2);
+
+    let mut metadata = ingress_metadata_t {
+        timestamp: bitvec![u8, Msb0; 0; 64],
+        ..Default::default()
+    };
+    metadata.timestamp.store_le(timestamp);
+
+    let frame = [0u8; 14];
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet_with_metadata(0, &mut pkt, metadata);
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].1, 1);
+    out[0].2.queue_id.load_le()
+}
+
+#[test]
+fn timestamp_in_queue_id_out() {
+    assert_eq!(queue_for(1701), 7);
+    assert_eq!(queue_for(47), 1);
+}
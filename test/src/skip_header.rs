@@ -0,0 +1,38 @@
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/skip_header.p4",
+    pipeline_name = "skip_header"
+);
+
+fn frame(ether_type: u16) -> Vec<u8> {
+    let mut frame = vec![0x1d; 12];
+    frame.extend_from_slice(&ether_type.to_be_bytes());
+    frame.extend_from_slice(&[0x45; 20]);
+    frame
+}
+
+#[test]
+fn skipped_header_is_invalid() {
+    let mut pipeline = main_pipeline::new(3);
+
+    let ipv4 = frame(0x0800);
+    let mut pkt = packet_in::new(&ipv4);
+    let (parsed, _) = pipeline.run_parser(0, &mut pkt).unwrap();
+    assert!(parsed.ipv4.isValid());
+
+    let ipv6 = frame(0x86dd);
+    let mut pkt = packet_in::new(&ipv6);
+    let (parsed, _) = pipeline.run_parser(0, &mut pkt).unwrap();
+    assert!(parsed.ethernet.isValid());
+    assert!(!parsed.ipv4.isValid());
+
+    // An IPv4 packet ahead of the IPv6 one leaves nothing valid behind for
+    // it, ingress takes the invalid branch.
+    for (f, port) in [(&ipv4, 1), (&ipv6, 2), (&ipv4, 1), (&ipv6, 2)] {
+        let mut pkt = packet_in::new(f);
+        let out = pipeline.process_packet(0, &mut pkt);
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].1, port);
+    }
+}
@@ -147,8 +147,9 @@ impl<P: p4rs::Pipeline + 'static> SoftNpu<P> {
                     let mut pkt = packet_in::new(content);
 
                     let port = i as u16;
-                    let output = pipeline.process_packet(port, &mut pkt);
-                    for (out_pkt, out_port) in &output {
+                    let output =
+                        pipeline.process_packet_outputs(port, &mut pkt);
+                    for (out_pkt, out_port) in output.iter() {
                         let out_port = *out_port as usize;
                         //
                         // get frame for packet
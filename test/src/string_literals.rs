@@ -0,0 +1,48 @@
+use p4::lexer::{Kind, Lexer};
+use std::sync::Arc;
+
+fn lex(source: &str) -> Result<Vec<Kind>, p4::error::TokenError> {
+    let mut lxr = Lexer::new(vec![source], Arc::new("strings.p4".into()));
+    let mut kinds = Vec::new();
+    loop {
+        let token = lxr.next()?;
+        if token.kind == Kind::Eof {
+            break;
+        }
+        kinds.push(token.kind);
+    }
+    Ok(kinds)
+}
+
+#[test]
+fn annotation_string_argument() {
+    assert_eq!(
+        lex(r#"@noWarn("unused")"#).unwrap(),
+        vec![
+            Kind::AtSign,
+            Kind::Identifier("noWarn".into()),
+            Kind::ParenOpen,
+            Kind::StringLiteral("unused".into()),
+            Kind::ParenClose,
+        ],
+    );
+}
+
+#[test]
+fn string_literal_contents() {
+    assert_eq!(
+        lex(r#""" "two words" "a \"quote\"" "back\\slash""#).unwrap(),
+        vec![
+            Kind::StringLiteral("".into()),
+            Kind::StringLiteral("two words".into()),
+            Kind::StringLiteral(r#"a "quote""#.into()),
+            Kind::StringLiteral(r"back\slash".into()),
+        ],
+    );
+}
+
+#[test]
+fn unterminated_string_literal() {
+    assert!(lex(r#""unused"#).is_err());
+    assert!(lex(r#""unused\""#).is_err());
+}
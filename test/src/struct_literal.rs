@@ -0,0 +1,42 @@
+use crate::frontend::parse_test_program;
+use p4::check;
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/struct_literal.p4",
+    pipeline_name = "struct_literal"
+);
+
+#[test]
+fn struct_literals() {
+    let mut pipeline = main_pipeline::new(3);
+
+    for (ether_type, port, rewritten) in [
+        ([0x08, 0x00], 0, [0x08, 0x00]),
+        ([0x86, 0xdd], 1, [0x86, 0xdd]),
+        ([0x08, 0x06], 2, [0x08, 0x00]),
+    ] {
+        let mut frame = vec![0x1d; 12];
+        frame.extend_from_slice(&ether_type);
+        let mut pkt = packet_in::new(&frame);
+        let out = pipeline.process_packet(0, &mut pkt);
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].1, port);
+        assert_eq!(&out[0].0.header_data[12..14], &rewritten);
+    }
+}
+
+#[test]
+fn bad_struct_literals() {
+    let ast = parse_test_program("bad_struct_literal.p4");
+    let (_, diags) = check::all(&ast);
+    let errors = diags.errors();
+    let messages: Vec<&str> =
+        errors.iter().map(|e| e.message.as_str()).collect();
+    assert_eq!(errors.len(), 4, "{:#?}", errors);
+    assert!(errors.iter().all(|e| e.code == "E0037"));
+    assert!(messages[0].contains("member rewrite of type bool"));
+    assert!(messages[1].contains("initialized twice"));
+    assert!(messages[2].contains("no member ttl"));
+    assert!(messages[3].contains("ether_type is not initialized"));
+}
@@ -0,0 +1,19 @@
+use p4rs::Pipeline;
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/dynamic_router.p4",
+    pipeline_name = "table_actions",
+);
+
+#[test]
+fn routing_table_actions() {
+    let pipeline = main_pipeline::new(2);
+
+    let actions = pipeline.get_table_actions("ingress.router.router").unwrap();
+    assert_eq!(actions, vec!["drop", "forward"]);
+
+    let actions = pipeline.get_table_actions("ingress.local.tbl").unwrap();
+    assert_eq!(actions, vec!["local", "nonlocal"]);
+
+    assert!(pipeline.get_table_actions("ingress.nope").is_none());
+}
@@ -0,0 +1,77 @@
+use p4rs::{Pipeline, TableChange};
+use std::net::Ipv6Addr;
+use std::sync::{Arc, Mutex};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/dynamic_router.p4",
+    pipeline_name = "table_change",
+);
+
+const ROUTER: &str = "ingress.router.router";
+
+fn route_key() -> Vec<u8> {
+    let prefix: Ipv6Addr = "fd00:1000::".parse().unwrap();
+    let mut buf = prefix.octets().to_vec();
+    buf.push(24); // prefix length
+    buf
+}
+
+#[test]
+fn add_and_remove_notify() {
+    let mut pipeline = main_pipeline::new(2);
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let log = events.clone();
+    pipeline.on_table_change(Box::new(move |table, change| {
+        log.lock().unwrap().push((table.to_owned(), change));
+    }));
+
+    let key = route_key();
+    pipeline
+        .add_table_entry(ROUTER, "forward", &key, &47u16.to_le_bytes(), 0)
+        .unwrap();
+    pipeline.remove_table_entry(ROUTER, &key).unwrap();
+
+    // Failed modifications do not change the table and are not reported.
+    assert!(pipeline.remove_table_entry("ingress.nope", &key).is_err());
+
+    let events = events.lock().unwrap();
+    assert_eq!(
+        *events,
+        vec![
+            (
+                ROUTER.to_owned(),
+                TableChange::Add {
+                    action_id: "forward".into(),
+                    keyset_data: key.clone(),
+                    parameter_data: 47u16.to_le_bytes().to_vec(),
+                    priority: 0,
+                },
+            ),
+            (ROUTER.to_owned(), TableChange::Remove { keyset_data: key }),
+        ]
+    );
+}
+
+#[test]
+fn clear_table_notifies() {
+    let mut pipeline = main_pipeline::new(2);
+
+    let key = route_key();
+    pipeline
+        .add_table_entry(ROUTER, "forward", &key, &47u16.to_le_bytes(), 0)
+        .unwrap();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let log = events.clone();
+    pipeline.on_table_change(Box::new(move |table, change| {
+        log.lock().unwrap().push((table.to_owned(), change));
+    }));
+
+    pipeline.clear_table(ROUTER).unwrap();
+    assert!(pipeline.get_table_entries(ROUTER).unwrap().is_empty());
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![(ROUTER.to_owned(), TableChange::Clear)]
+    );
+}
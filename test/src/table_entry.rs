@@ -0,0 +1,377 @@
+use p4rs::error::{PipelineError, PrefixError};
+use p4rs::{FieldLayout, Pipeline};
+use std::net::Ipv6Addr;
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/dynamic_router.p4",
+    pipeline_name = "table_entry",
+);
+
+#[test]
+fn lpm_table_entry_layout() {
+    let mut pipeline = main_pipeline::new(2);
+
+    let prefix: Ipv6Addr = "fd00:1000::".parse().unwrap();
+    let mut buf = prefix.octets().to_vec();
+    buf.push(24); // prefix length
+
+    pipeline
+        .add_table_entry(
+            "ingress.router.router",
+            "forward",
+            &buf,
+            &47u16.to_le_bytes(),
+            0,
+        )
+        .unwrap();
+
+    let entries = pipeline.get_table_entries("ingress.router.router").unwrap();
+    assert_eq!(entries.len(), 1);
+    let entry = &entries[0];
+
+    assert_eq!(
+        entry.keyset_layout,
+        vec![FieldLayout {
+            name: "hdr.ipv6.dst".into(),
+            match_kind: Some("lpm".into()),
+            width: 17,
+        }]
+    );
+    assert_eq!(
+        entry.parameter_layout,
+        vec![FieldLayout {
+            name: "port".into(),
+            match_kind: None,
+            width: 2,
+        }]
+    );
+    assert_eq!(
+        entry.to_string(),
+        "hdr.ipv6.dst=fd00:1000::/24 => forward(port=0x002f)",
+    );
+}
+
+#[test]
+fn table_entry_errors() {
+    let mut pipeline = main_pipeline::new(2);
+
+    let key = [0u8; 17];
+    assert_eq!(
+        pipeline.add_table_entry("ingress.nope", "forward", &key, &[], 0),
+        Err(PipelineError::UnknownTable("ingress.nope".into())),
+    );
+    assert_eq!(
+        pipeline.remove_table_entry("ingress.nope", &key),
+        Err(PipelineError::UnknownTable("ingress.nope".into())),
+    );
+    assert_eq!(
+        pipeline.add_table_entry("ingress.router.router", "nope", &key, &[], 0),
+        Err(PipelineError::UnknownAction {
+            table: "ingress.router.router".into(),
+            action: "nope".into(),
+        }),
+    );
+    assert_eq!(
+        pipeline
+            .get_table_entries("ingress.router.router")
+            .unwrap()
+            .len(),
+        0
+    );
+}
+
+#[test]
+fn undersized_keyset() {
+    let mut pipeline = main_pipeline::new(2);
+
+    // An IPv4 prefix for a table keyed on an IPv6 address.
+    let key = [10, 0, 0, 0, 8];
+    assert_eq!(
+        pipeline.add_table_entry(
+            "ingress.router.router",
+            "forward",
+            &key,
+            &47u16.to_le_bytes(),
+            0,
+        ),
+        Err(PipelineError::KeyLengthMismatch {
+            table: "ingress.router.router".into(),
+            expected: 17,
+            found: 5,
+        }),
+    );
+    assert_eq!(
+        pipeline.remove_table_entry("ingress.router.router", &key[..4]),
+        Err(PipelineError::KeyLengthMismatch {
+            table: "ingress.router.router".into(),
+            expected: 17,
+            found: 4,
+        }),
+    );
+}
+
+#[test]
+fn invalid_prefix() {
+    let mut pipeline = main_pipeline::new(2);
+
+    let prefix: Ipv6Addr = "fd00:1000::".parse().unwrap();
+    let mut key = prefix.octets().to_vec();
+    key.push(129); // longer than the address
+    let err = Err(PipelineError::InvalidPrefix {
+        table: "ingress.router.router".into(),
+        error: PrefixError::Length { len: 129, max: 128 },
+    });
+    let port = 47u16.to_le_bytes();
+    assert_eq!(
+        pipeline.validate_table_entry(
+            "ingress.router.router",
+            "forward",
+            &key,
+            &port,
+        ),
+        err,
+    );
+    assert_eq!(
+        pipeline.add_table_entry(
+            "ingress.router.router",
+            "forward",
+            &key,
+            &port,
+            0,
+        ),
+        err,
+    );
+    assert_eq!(
+        pipeline.remove_table_entry("ingress.router.router", &key),
+        err,
+    );
+
+    // Bits set beyond the prefix length.
+    key[16] = 16;
+    assert_eq!(
+        pipeline.add_table_entry(
+            "ingress.router.router",
+            "forward",
+            &key,
+            &port,
+            0,
+        ),
+        Err(PipelineError::InvalidPrefix {
+            table: "ingress.router.router".into(),
+            error: PrefixError::HostBits {
+                addr: prefix.into(),
+                len: 16,
+            },
+        }),
+    );
+}
+
+#[test]
+fn remove_entries_matching_predicate() {
+    let mut pipeline = main_pipeline::new(4);
+
+    // Four routes, alternating between ports 1 and 2.
+    for i in 0..4u16 {
+        let prefix = Ipv6Addr::new(0xfd00, 0x1000 + i, 0, 0, 0, 0, 0, 0);
+        let mut buf = prefix.octets().to_vec();
+        buf.push(32); // prefix length
+        let port = 1 + i % 2;
+        pipeline
+            .add_table_entry(
+                "ingress.router.router",
+                "forward",
+                &buf,
+                &port.to_le_bytes(),
+                0,
+            )
+            .unwrap();
+    }
+
+    // Port 2 went down, remove every route through it.
+    let via_2 = 2u16.to_le_bytes();
+    let removed = pipeline
+        .remove_table_entries_matching("ingress.router.router", &|e| {
+            e.parameter_data == via_2
+        });
+    assert_eq!(removed, 2);
+
+    let entries = pipeline.get_table_entries("ingress.router.router").unwrap();
+    assert_eq!(entries.len(), 2);
+    for e in &entries {
+        assert_eq!(e.parameter_data, 1u16.to_le_bytes());
+    }
+
+    assert_eq!(
+        pipeline.remove_table_entries_matching("ingress.nope", &|_| true),
+        0
+    );
+}
+
+#[test]
+fn snapshot_diff() {
+    fn route(pipeline: &mut main_pipeline, prefix: &str, port: u16) {
+        let prefix: Ipv6Addr = prefix.parse().unwrap();
+        let mut key = prefix.octets().to_vec();
+        key.push(24);
+        pipeline
+            .add_table_entry(
+                "ingress.router.router",
+                "forward",
+                &key,
+                &port.to_le_bytes(),
+                0,
+            )
+            .unwrap();
+    }
+
+    let mut current = main_pipeline::new(2);
+    route(&mut current, "fd00:1000::", 1);
+    route(&mut current, "fd00:2000::", 1);
+
+    let mut desired = main_pipeline::new(2);
+    route(&mut desired, "fd00:2000::", 1);
+    route(&mut desired, "fd00:3000::", 0);
+
+    let diff = current.snapshot().diff(&desired.snapshot());
+    assert_eq!(diff.tables.len(), 1);
+    let table = diff.get_table("ingress.router.router").unwrap();
+    assert_eq!(table.added.len(), 1);
+    assert_eq!(
+        table.added[0].to_string(),
+        "hdr.ipv6.dst=fd00:3000::/24 => forward(port=0x0000)",
+    );
+    assert_eq!(table.removed.len(), 1);
+    assert_eq!(
+        table.removed[0].to_string(),
+        "hdr.ipv6.dst=fd00:1000::/24 => forward(port=0x0001)",
+    );
+    assert!(table.changed.is_empty());
+
+    assert!(desired.snapshot().diff(&desired.snapshot()).is_empty());
+}
+
+#[test]
+fn validate_without_adding() {
+    let mut pipeline = main_pipeline::new(2);
+    let changes = std::sync::Arc::new(std::sync::Mutex::new(0));
+    let count = changes.clone();
+    pipeline.on_table_change(Box::new(move |_, _| {
+        *count.lock().unwrap() += 1;
+    }));
+
+    let prefix: Ipv6Addr = "fd00:1000::".parse().unwrap();
+    let mut key = prefix.octets().to_vec();
+    key.push(24);
+
+    // forward takes a 16 bit port
+    assert_eq!(
+        pipeline.validate_table_entry(
+            "ingress.router.router",
+            "forward",
+            &key,
+            &[47],
+        ),
+        Err(PipelineError::ParameterLengthMismatch {
+            table: "ingress.router.router".into(),
+            action: "forward".into(),
+            expected: 2,
+            found: 1,
+        }),
+    );
+    assert_eq!(
+        pipeline.validate_table_entry(
+            "ingress.router.router",
+            "forward",
+            &key,
+            &47u16.to_le_bytes(),
+        ),
+        Ok(()),
+    );
+
+    // validating leaves the table alone
+    let entries = pipeline.get_table_entries("ingress.router.router").unwrap();
+    assert!(entries.is_empty());
+    assert_eq!(*changes.lock().unwrap(), 0);
+
+    // adding fails the same way
+    assert!(matches!(
+        pipeline.add_table_entry(
+            "ingress.router.router",
+            "forward",
+            &key,
+            &[47],
+            0,
+        ),
+        Err(PipelineError::ParameterLengthMismatch { .. }),
+    ));
+}
+
+#[test]
+fn const_entries_are_immutable() {
+    let mut pipeline = main_pipeline::new(2);
+
+    let addr: Ipv6Addr = "fd00::1".parse().unwrap();
+    pipeline
+        .add_table_entry("ingress.local.tbl", "local", &addr.octets(), &[], 0)
+        .unwrap();
+
+    let entries = pipeline.get_table_entries("ingress.local.tbl").unwrap();
+    assert_eq!(entries.len(), 4);
+    let (added, consts): (Vec<_>, Vec<_>) =
+        entries.into_iter().partition(|e| !e.immutable);
+    assert_eq!(consts.len(), 3);
+    assert_eq!(added.len(), 1);
+    assert_eq!(added[0].action_id, "local");
+
+    // clearing the table leaves only the const entries
+    pipeline.clear_table("ingress.local.tbl").unwrap();
+    let entries = pipeline.get_table_entries("ingress.local.tbl").unwrap();
+    assert_eq!(entries.len(), 3);
+    assert!(entries.iter().all(|e| e.immutable));
+}
+
+#[test]
+fn epoch_advances_on_table_changes() {
+    let mut pipeline = main_pipeline::new(2);
+    let snapshot = pipeline.snapshot();
+    assert_eq!(snapshot.epoch, pipeline.epoch());
+
+    let prefix: Ipv6Addr = "fd00:1000::".parse().unwrap();
+    let mut key = prefix.octets().to_vec();
+    key.push(24);
+
+    // validating an entry leaves the tables alone
+    pipeline
+        .validate_table_entry(
+            "ingress.router.router",
+            "forward",
+            &key,
+            &47u16.to_le_bytes(),
+        )
+        .unwrap();
+    assert_eq!(pipeline.epoch(), snapshot.epoch);
+
+    pipeline
+        .add_table_entry(
+            "ingress.router.router",
+            "forward",
+            &key,
+            &47u16.to_le_bytes(),
+            0,
+        )
+        .unwrap();
+    let added = pipeline.epoch();
+    assert!(added > snapshot.epoch);
+
+    pipeline
+        .remove_table_entry("ingress.router.router", &key)
+        .unwrap();
+    let removed = pipeline.epoch();
+    assert!(removed > added);
+
+    // removing an entry that is not there changes nothing
+    pipeline
+        .remove_table_entry("ingress.router.router", &key)
+        .unwrap();
+    assert_eq!(pipeline.epoch(), removed);
+}
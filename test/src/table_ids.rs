@@ -0,0 +1,41 @@
+use p4rs::Pipeline;
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/controller_multiple_instantiation.p4",
+    pipeline_name = "table_ids",
+);
+
+#[test]
+fn nested_table_ids_round_trip() {
+    let mut pipeline = main_pipeline::new(2);
+
+    let mut ids: Vec<String> = pipeline
+        .get_table_ids()
+        .iter()
+        .map(|x| x.to_string())
+        .collect();
+    ids.sort();
+    assert_eq!(
+        ids,
+        vec![
+            "ingress.pizza.resolver.arp",
+            "ingress.pizza.taco.arp",
+            "ingress.taco.resolver.arp",
+        ]
+    );
+
+    for id in &ids {
+        assert_eq!(pipeline.get_table_entries(id).unwrap().len(), 0);
+        pipeline
+            .add_table_entry(id, "resolve", &[1, 0, 0, 0], &[], 0)
+            .unwrap();
+        assert_eq!(
+            pipeline.get_table_entries(id).unwrap().len(),
+            1,
+            "table {} did not accept entry",
+            id,
+        );
+        pipeline.remove_table_entry(id, &[1, 0, 0, 0]).unwrap();
+        assert_eq!(pipeline.get_table_entries(id).unwrap().len(), 0);
+    }
+}
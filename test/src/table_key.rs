@@ -0,0 +1,37 @@
+use crate::frontend::parse_test_program;
+use p4::check;
+
+#[test]
+fn bool_table_key() {
+    let ast = parse_test_program("bool_key.p4");
+    let (_, diags) = check::all(&ast);
+    let errors = diags.errors();
+    assert_eq!(errors.len(), 1, "{:#?}", errors);
+    assert!(errors[0].message.contains("egress.drop"));
+    assert!(errors[0].message.contains("bool"));
+    assert_eq!(errors[0].token.line, 42);
+    assert_eq!(errors[0].code, "E0003");
+}
+
+#[test]
+fn multiple_lpm_keys() {
+    let ast = parse_test_program("two_lpm_keys.p4");
+    let (_, diags) = check::all(&ast);
+    let errors = diags.errors();
+    assert_eq!(errors.len(), 1, "{:#?}", errors);
+    assert_eq!(errors[0].code, "E0001");
+    assert!(errors[0].message.contains("by_addr"));
+    assert_eq!(errors[0].token.line, 42);
+}
+
+#[test]
+fn default_action_not_listed() {
+    let ast = parse_test_program("unlisted_default.p4");
+    let (_, diags) = check::all(&ast);
+    let errors = diags.errors();
+    assert_eq!(errors.len(), 1, "{:#?}", errors);
+    assert_eq!(errors[0].code, "E0035");
+    assert!(errors[0].message.contains("drop"));
+    assert!(errors[0].message.contains("by_addr"));
+    assert_eq!(errors[0].token.line, 43);
+}
@@ -0,0 +1,65 @@
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(p4 = "test/src/p4/ternary.p4", pipeline_name = "ternary");
+
+/// Run a frame with the given ethertype and tag flags through the pipeline
+/// and return the resulting tag class and result.
+fn run(ether_type: u16, flags: u8) -> (u8, u8) {
+    run_on(&mut main_pipeline::new(2), ether_type, flags)
+}
+
+fn run_on(
+    pipeline: &mut main_pipeline,
+    ether_type: u16,
+    flags: u8,
+) -> (u8, u8) {
+    let mut frame = [0u8; 17];
+    frame[12..14].copy_from_slice(&ether_type.to_be_bytes());
+    frame[14] = flags;
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet_headers(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    let tag = &out[0].0.tag;
+    (tag.class.load_le(), tag.result.load_le())
+}
+
+#[test]
+fn const_entry_non_prefix_mask() {
+    // 0xf8f6 & 0x0f0f == 0x0806
+    assert_eq!(run(0xf8f6, 0), (7, 2));
+    assert_eq!(run(0x0806, 0), (7, 2));
+    // differs from 0x0806 in a masked bit
+    assert_eq!(run(0x0807, 0), (0, 0));
+    assert_eq!(run(0x0906, 0), (0, 0));
+}
+
+#[test]
+fn const_entry_masked_tuple_keyset() {
+    // 0x93 & 0x81 == 0x81
+    assert_eq!(run(0, 0x93), (0, 1));
+    assert_eq!(run(0, 0x13), (0, 0));
+}
+
+#[test]
+fn runtime_entry_priority() {
+    // Both entries match flags 0x01 with class 0, whichever order they are
+    // added in the one with the higher priority wins.
+    let by_flags = ([1, 0x01, 0, 0], [10], 1);
+    let by_class = ([0, 0, 1, 0], [20], 5);
+    for order in [[by_flags, by_class], [by_class, by_flags]] {
+        let mut pipeline = main_pipeline::new(2);
+        for (keyset, params, priority) in order {
+            pipeline
+                .add_table_entry(
+                    "ingress.marker",
+                    "mark",
+                    &keyset,
+                    &params,
+                    priority,
+                )
+                .unwrap();
+        }
+        assert_eq!(run_on(&mut pipeline, 0, 0x01), (0, 20));
+        assert_eq!(run_on(&mut pipeline, 0, 0x03), (0, 20));
+    }
+}
@@ -0,0 +1,76 @@
+use p4rs::{checksum::Csum, packet_in, Pipeline};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/ttl_checksum.p4",
+    pipeline_name = "ttl_checksum",
+);
+
+/// The ones' complement sum of the 16 bit words of an IPv4 header.
+fn sum(header: &[u8]) -> u16 {
+    let mut csum = Csum::default();
+    for w in header.chunks(2) {
+        csum.add(w[0], w[1]);
+    }
+    !csum.result()
+}
+
+/// An IPv4 header with a valid checksum.
+fn ipv4(ttl: u8, identification: u16) -> [u8; 20] {
+    let mut h = [0u8; 20];
+    h[0] = 0x45;
+    h[2..4].copy_from_slice(&20u16.to_be_bytes());
+    h[4..6].copy_from_slice(&identification.to_be_bytes());
+    h[8] = ttl;
+    h[9] = 17;
+    h[12..16].copy_from_slice(&[10, 0, 0, 1]);
+    h[16..20].copy_from_slice(&[10, 0, 0, 2]);
+    let checksum = !sum(&h);
+    h[10..12].copy_from_slice(&checksum.to_be_bytes());
+    h
+}
+
+fn forward(pipeline: &mut main_pipeline, h: &[u8; 20]) -> Vec<u8> {
+    let mut pkt = packet_in::new(h);
+    let out = pipeline.process_packet(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    out[0].0.header_data.clone()
+}
+
+#[test]
+fn ttl_decrement_updates_checksum() {
+    let mut pipeline = main_pipeline::new(2);
+    for ttl in [1u8, 2, 64, 255] {
+        for identification in (0..=u16::MAX).step_by(251) {
+            let h = ipv4(ttl, identification);
+            let out = forward(&mut pipeline, &h);
+            assert_eq!(out.len(), 20);
+            assert_eq!(out[8], ttl - 1);
+            assert_eq!(
+                sum(&out),
+                0xffff,
+                "ttl {} identification {:#x}",
+                ttl,
+                identification,
+            );
+        }
+    }
+}
+
+#[test]
+fn ttl_decrement_checksum_wraps() {
+    let mut pipeline = main_pipeline::new(2);
+
+    // Pick a header whose checksum is at least 0xff00. Adding 0x0100 for the
+    // lower TTL then carries out of the top of the checksum and has to be
+    // wrapped around into the bottom.
+    let h = (0..=u16::MAX)
+        .map(|identification| ipv4(64, identification))
+        .find(|h| h[10] == 0xff)
+        .unwrap();
+    let out = forward(&mut pipeline, &h);
+    assert_eq!(sum(&out), 0xffff);
+
+    let before = u16::from_be_bytes([h[10], h[11]]);
+    let after = u16::from_be_bytes([out[10], out[11]]);
+    assert_eq!(after, before.wrapping_add(0x0100) + 1);
+}
@@ -0,0 +1,75 @@
+use crate::frontend::parse_test_program;
+use p4::check;
+use p4rs::externs::ExternMethod;
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/tuple_extern.p4",
+    pipeline_name = "tuple_extern",
+);
+
+/// Returns the successor of its argument and whether the argument is even.
+struct MockDigest;
+
+impl ExternMethod for MockDigest {
+    fn call(
+        &self,
+        method: &str,
+        _args: &[&BitVec<u8, Msb0>],
+    ) -> BitVec<u8, Msb0> {
+        panic!("unexpected call to {}", method);
+    }
+
+    fn call_tuple(
+        &self,
+        method: &str,
+        args: &[&BitVec<u8, Msb0>],
+    ) -> Vec<BitVec<u8, Msb0>> {
+        assert_eq!(method, "compute");
+        let x: u16 = args[0].load_le();
+        let mut next = bitvec![u8, Msb0; 0; 16];
+        next.store_le(x.wrapping_add(1));
+        let even = bitvec![u8, Msb0; (x % 2 == 0) as u8; 1];
+        vec![next, even]
+    }
+}
+
+fn compute(pipeline: &mut main_pipeline, ether_type: u16) -> [u16; 4] {
+    let mut frame = [0u8; 20];
+    frame[12..14].copy_from_slice(&ether_type.to_be_bytes());
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet_headers(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    let result = &out[0].0.result;
+    [
+        result.value.load_le(),
+        result.even.load_le(),
+        result.next.load_le(),
+        result.next_even.load_le(),
+    ]
+}
+
+#[test]
+fn extern_returns_tuple() {
+    let mut pipeline = main_pipeline::new(2);
+    pipeline.register_extern("Digest", Box::new(MockDigest));
+
+    assert_eq!(compute(&mut pipeline, 0x0800), [0x0801, 1, 0x0802, 0]);
+    assert_eq!(compute(&mut pipeline, 0x86dd), [0x86de, 0, 0x86df, 1]);
+}
+
+#[test]
+fn bad_tuple_assignments() {
+    let ast = parse_test_program("bad_tuple_assignment.p4");
+    let (_, diags) = check::all(&ast);
+    let errors = diags.errors();
+    let messages: Vec<&str> =
+        errors.iter().map(|e| e.message.as_str()).collect();
+    assert_eq!(errors.len(), 3, "{:#?}", errors);
+    assert_eq!(errors[0].code, "E0010");
+    assert!(messages[0].contains("Cannot assign bool to bit<16>"));
+    assert_eq!(errors[1].code, "E0038");
+    assert!(messages[1].contains("tuple of 2 elements to 3 lvalues"));
+    assert_eq!(errors[2].code, "E0038");
+    assert!(messages[2].contains("Cannot destructure bit<16>"));
+}
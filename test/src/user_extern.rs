@@ -0,0 +1,51 @@
+use p4rs::externs::ExternMethod;
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/user_extern.p4",
+    pipeline_name = "user_extern",
+);
+
+struct MockTimestamp(u32);
+
+impl ExternMethod for MockTimestamp {
+    fn call(
+        &self,
+        method: &str,
+        args: &[&BitVec<u8, Msb0>],
+    ) -> BitVec<u8, Msb0> {
+        assert_eq!(method, "now");
+        assert!(args.is_empty());
+        let mut x = bitvec![u8, Msb0; 0; 32];
+        x.store_le(self.0);
+        x
+    }
+}
+
+#[test]
+fn registered_extern_is_called() {
+    let mut pipeline = main_pipeline::new(2);
+    pipeline.register_extern("Timestamp", Box::new(MockTimestamp(0x1701d)));
+
+    let frame = [0u8; 18];
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet_headers(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].0.ts.value.load_le::<u32>(), 0x1701d);
+}
+
+#[test]
+fn unregistered_extern_drops() {
+    let mut pipeline = main_pipeline::new(2);
+    assert_eq!(pipeline.missing_externs(), vec!["Timestamp"]);
+
+    let frame = [0u8; 18];
+    let mut pkt = packet_in::new(&frame);
+    assert!(pipeline.process_packet(0, &mut pkt).is_empty());
+    assert_eq!(pipeline.drop_stats().unregistered_extern, 1);
+
+    pipeline.register_extern("Timestamp", Box::new(MockTimestamp(1)));
+    assert!(pipeline.missing_externs().is_empty());
+    let mut pkt = packet_in::new(&frame);
+    assert_eq!(pipeline.process_packet(0, &mut pkt).len(), 1);
+}
@@ -0,0 +1,44 @@
+use p4rs::{packet_in, Pipeline};
+
+p4_macro::use_p4!(p4 = "test/src/p4/verify.p4", pipeline_name = "verify");
+
+/// An ethernet frame carrying an IPv4 header with the given version.
+fn ipv4_frame(version: u8) -> Vec<u8> {
+    let mut frame = vec![0u8; 34];
+    frame[12..14].copy_from_slice(&0x0800u16.to_be_bytes());
+    frame[14] = (version << 4) | 5;
+    frame
+}
+
+#[test]
+fn verify_sets_parser_error() {
+    let frame = ipv4_frame(4);
+    let mut pkt = packet_in::new(&frame);
+    let mut headers = headers_t::default();
+    let mut ingress = ingress_metadata_t::default();
+    assert!(parse_start(&mut pkt, &mut headers, &mut ingress));
+    assert_eq!(ingress.parser_error, error::NoError);
+
+    let frame = ipv4_frame(6);
+    let mut pkt = packet_in::new(&frame);
+    let mut headers = headers_t::default();
+    let mut ingress = ingress_metadata_t::default();
+    assert!(!parse_start(&mut pkt, &mut headers, &mut ingress));
+    assert_eq!(ingress.parser_error, error::ParserInvalidArgument);
+}
+
+#[test]
+fn verify_rejects_malformed_packet() {
+    let mut pipeline = main_pipeline::new(2);
+
+    let frame = ipv4_frame(4);
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet(0, &mut pkt);
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].1, 1);
+
+    let frame = ipv4_frame(6);
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet(0, &mut pkt);
+    assert!(out.is_empty());
+}
@@ -0,0 +1,51 @@
+use p4rs::{packet_in, Pipeline};
+use std::net::Ipv4Addr;
+
+p4_macro::use_p4!(p4 = "test/src/p4/wide_key.p4", pipeline_name = "wide_key");
+
+fn egress_port(pipeline: &mut main_pipeline, dst: Ipv4Addr) -> Option<u16> {
+    let frame = dst.octets();
+    let mut pkt = packet_in::new(&frame);
+    let out = pipeline.process_packet_headers(0, &mut pkt);
+    out.first().map(|(_, port)| *port)
+}
+
+fn add_route(pipeline: &mut main_pipeline, dst: Ipv4Addr, port: u16) {
+    // Keys are little endian, header fields are in network order.
+    let key = u32::from(dst).to_le_bytes();
+    pipeline
+        .add_table_entry(
+            "ingress.by_dst",
+            "forward",
+            &key,
+            &port.to_le_bytes(),
+            0,
+        )
+        .unwrap();
+}
+
+#[test]
+fn exact_key_compares_full_width() {
+    let mut pipeline = main_pipeline::new(4);
+
+    // These keys share their first three bytes.
+    add_route(&mut pipeline, Ipv4Addr::new(10, 0, 0, 1), 1);
+    add_route(&mut pipeline, Ipv4Addr::new(10, 0, 0, 2), 2);
+    // And these their last three.
+    add_route(&mut pipeline, Ipv4Addr::new(11, 0, 0, 1), 3);
+
+    assert_eq!(
+        egress_port(&mut pipeline, Ipv4Addr::new(10, 0, 0, 1)),
+        Some(1)
+    );
+    assert_eq!(
+        egress_port(&mut pipeline, Ipv4Addr::new(10, 0, 0, 2)),
+        Some(2)
+    );
+    assert_eq!(
+        egress_port(&mut pipeline, Ipv4Addr::new(11, 0, 0, 1)),
+        Some(3)
+    );
+    assert_eq!(egress_port(&mut pipeline, Ipv4Addr::new(10, 0, 0, 3)), None);
+    assert_eq!(egress_port(&mut pipeline, Ipv4Addr::new(12, 0, 0, 1)), None);
+}
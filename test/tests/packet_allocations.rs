@@ -0,0 +1,152 @@
+//! Allocation counts of packet processing. These tests replace the global
+//! allocator, so they run in their own test binary rather than alongside the
+//! tests in the library.
+
+#![allow(clippy::too_many_arguments)]
+
+use p4rs::{packet_in, Pipeline};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::net::Ipv4Addr;
+
+mod unicast {
+    p4_macro::use_p4!(
+        p4 = "test/src/p4/wide_key.p4",
+        pipeline_name = "allocations_unicast"
+    );
+}
+
+mod broadcast {
+    p4_macro::use_p4!(
+        p4 = "test/src/p4/hub.p4",
+        pipeline_name = "allocations_broadcast"
+    );
+}
+
+mod passthrough {
+    p4_macro::use_p4!(
+        p4 = "test/src/p4/hub.p4",
+        pipeline_name = "allocations_passthrough",
+        optimize = true,
+    );
+}
+
+// Counts the heap allocations made by the current thread, so tests running
+// concurrently do not disturb each other's counts.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.with(|n| n.get());
+    let result = f();
+    (result, ALLOCATIONS.with(|n| n.get()) - before)
+}
+
+#[test]
+fn unicast_outputs_are_inline() {
+    let mut pipeline = unicast::main_pipeline::new(4);
+    let dst = Ipv4Addr::new(10, 0, 0, 1);
+    pipeline
+        .add_table_entry(
+            "ingress.by_dst",
+            "forward",
+            &u32::from(dst).to_le_bytes(),
+            &2u16.to_le_bytes(),
+            0,
+        )
+        .unwrap();
+    let frame = dst.octets();
+
+    let mut pkt = packet_in::new(&frame);
+    let (out, vec_allocations) =
+        allocations(|| pipeline.process_packet(0, &mut pkt));
+    assert_eq!(out.len(), 1);
+
+    let mut pkt = packet_in::new(&frame);
+    let (out, inline_allocations) =
+        allocations(|| pipeline.process_packet_outputs(0, &mut pkt));
+    assert_eq!(out.len(), 1);
+    assert!(!out.spilled());
+    assert_eq!(out.iter().next().unwrap().1, 2);
+
+    // The only difference is the allocation of the output vector.
+    assert_eq!(inline_allocations + 1, vec_allocations);
+}
+
+/// Count the allocations made processing a broadcast packet once the pipeline
+/// has warmed up, handing each output back to the pipeline once it is done.
+fn broadcast_allocations(arena: bool) -> usize {
+    let mut pipeline = broadcast::main_pipeline::new(4);
+    pipeline.set_arena(arena);
+    let frame = [0u8; 64];
+    let run = |pipeline: &mut broadcast::main_pipeline| {
+        let mut pkt = packet_in::new(&frame);
+        let out = pipeline.process_packet_outputs(0, &mut pkt);
+        assert_eq!(out.iter().count(), 3);
+        for (out, _) in out {
+            pipeline.recycle(out);
+        }
+    };
+    run(&mut pipeline);
+    allocations(|| run(&mut pipeline)).1
+}
+
+#[test]
+fn broadcast_arena_reuses_header_buffers() {
+    let with_arena = broadcast_allocations(true);
+    let without_arena = broadcast_allocations(false);
+    // Each of the three outputs takes its header buffer from the arena.
+    assert!(
+        with_arena + 3 <= without_arena,
+        "{} allocations with the arena, {} without",
+        with_arena,
+        without_arena,
+    );
+}
+
+#[test]
+fn optimized_passthrough_skips_serialization() {
+    let frame: Vec<u8> = (0..64).collect();
+    let per_packet = |pipeline: &mut dyn Pipeline| {
+        pipeline.set_arena(true);
+        let mut run = || {
+            let mut pkt = packet_in::new(&frame);
+            let out = pipeline.process_packet_outputs(0, &mut pkt);
+            assert_eq!(out.iter().count(), 3);
+            for (out, _) in out {
+                assert_eq!(out.header_data, &frame[..14]);
+                assert_eq!(out.payload_data, &frame[14..]);
+                pipeline.recycle(out);
+            }
+        };
+        run();
+        allocations(run).1
+    };
+    let optimized = per_packet(&mut passthrough::main_pipeline::new(4));
+    let serialized = per_packet(&mut broadcast::main_pipeline::new(4));
+    // Serializing the headers of each of the three outputs allocates at least
+    // one bitvec.
+    assert!(
+        optimized + 3 <= serialized,
+        "{} allocations optimized, {} serializing",
+        optimized,
+        serialized,
+    );
+}
@@ -0,0 +1,89 @@
+// Copyright 2022 Oxide Computer Company
+
+//! Code generation backends. Each target the compiler generates code for is
+//! a [`CodegenBackend`], so adding a target is a matter of implementing the
+//! trait and returning it from [`Target::backend`].
+
+use anyhow::{anyhow, Result};
+use p4::{ast::AST, hlir::Hlir};
+
+use crate::Target;
+
+/// Settings shared by all backends.
+pub struct BackendSettings {
+    /// Name of the pipeline being generated.
+    pub pipeline_name: String,
+
+    /// Check that a header is valid each time one of its fields is read.
+    pub checked_header_access: bool,
+
+    /// Controls to generate standalone code for, so they can be applied and
+    /// tested without a pipeline.
+    pub standalone_controls: Vec<String>,
+
+    /// Reuse the original header bytes of packets whose headers are not
+    /// modified.
+    pub optimize: bool,
+}
+
+pub trait CodegenBackend {
+    /// Rewrite the AST into the form this backend generates code from, such
+    /// as renaming identifiers that are reserved in the target language. This
+    /// runs before the AST is checked, as the rewrite may change the names
+    /// the checker resolves.
+    fn prepare(&mut self, _ast: &mut AST) {}
+
+    /// Generate code for a checked program and write it to the file `out`.
+    fn emit(
+        &self,
+        ast: &AST,
+        hlir: &Hlir,
+        out: &str,
+        settings: &BackendSettings,
+    ) -> Result<()>;
+}
+
+/// Generates a Rust crate implementing the `p4rs::Pipeline` trait.
+#[derive(Default)]
+pub struct RustBackend {
+    names: p4_rust::SanitizedNames,
+}
+
+impl CodegenBackend for RustBackend {
+    fn prepare(&mut self, ast: &mut AST) {
+        self.names = p4_rust::sanitize(ast);
+    }
+
+    fn emit(
+        &self,
+        ast: &AST,
+        hlir: &Hlir,
+        out: &str,
+        settings: &BackendSettings,
+    ) -> Result<()> {
+        p4_rust::emit(
+            ast,
+            hlir,
+            out,
+            p4_rust::Settings {
+                pipeline_name: settings.pipeline_name.clone(),
+                names: self.names.clone(),
+                checked_header_access: settings.checked_header_access,
+                standalone_controls: settings.standalone_controls.clone(),
+                optimize: settings.optimize,
+            },
+        )?;
+        Ok(())
+    }
+}
+
+impl Target {
+    /// The backend that generates code for this target.
+    pub fn backend(&self) -> Result<Box<dyn CodegenBackend>> {
+        match self {
+            Target::Rust => Ok(Box::<RustBackend>::default()),
+            Target::RedHawk => Err(anyhow!("no code generator for RedHawk")),
+            Target::Docs => Err(anyhow!("no code generator for Docs")),
+        }
+    }
+}
@@ -14,36 +14,52 @@ fn main() {
 
 fn run() -> Result<()> {
     let opts = x4c::Opts::parse();
+    let mut times = x4c::PassTimes::default();
+    let result = compile(&opts, &mut times);
+    if opts.time_passes {
+        eprint!("{}", times.report());
+    }
+    result
+}
+
+fn compile(opts: &x4c::Opts, times: &mut x4c::PassTimes) -> Result<()> {
     let filename = Arc::new(opts.filename.clone());
     let mut ast = AST::default();
-    x4c::process_file(filename, &mut ast, &opts)?;
+    x4c::process_file(filename, &mut ast, opts, times)?;
+
+    match opts.stop_after {
+        Some(x4c::Phase::Lex) => return Ok(()),
+        Some(x4c::Phase::Parse) => {
+            println!("{:#?}", ast);
+            return Ok(());
+        }
+        Some(x4c::Phase::Check) => return Ok(()),
+        None => {}
+    }
 
     if opts.check {
         return Ok(());
     }
 
-    match opts.target {
-        x4c::Target::Rust => {
-            // NOTE: it's important to sanitize *before* generating hlir as the
-            // sanitization process can change lvalue names.
-            p4_rust::sanitize(&mut ast);
-            let (hlir, _) = p4::check::all(&ast);
-            p4_rust::emit(
-                &ast,
-                &hlir,
-                &opts.out,
-                p4_rust::Settings {
-                    pipeline_name: "main".to_owned(),
-                },
-            )?;
-        }
-        x4c::Target::RedHawk => {
-            todo!("RedHawk code generator");
-        }
-        x4c::Target::Docs => {
-            todo!("Docs code generator");
-        }
-    }
+    let mut backend = opts.target.backend()?;
+    let out = opts.out_path(&ast)?;
+    times.time("emit", || {
+        // NOTE: it's important to prepare *before* generating hlir as the
+        // preparation can change lvalue names.
+        backend.prepare(&mut ast);
+        let (hlir, _) = p4::check::all(&ast);
+        backend.emit(
+            &ast,
+            &hlir,
+            &out,
+            &x4c::backend::BackendSettings {
+                pipeline_name: "main".to_owned(),
+                checked_header_access: opts.checked_header_access,
+                standalone_controls: opts.standalone_controls.clone(),
+                optimize: opts.optimize,
+            },
+        )
+    })?;
 
     Ok(())
 }
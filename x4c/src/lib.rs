@@ -2,13 +2,17 @@
 
 use anyhow::{anyhow, Result};
 use clap::Parser;
+use colored::Colorize;
 use p4::check::Diagnostics;
 use p4::{
     ast::AST, check, error, error::SemanticError, lexer, parser, preprocessor,
 };
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub mod backend;
 
 #[derive(Parser)]
 #[clap(version = "0.1")]
@@ -29,9 +33,20 @@ pub struct Opts {
     #[clap(long)]
     pub show_hlir: bool,
 
+    /// Write high-level intermediate representation info as JSON to a file.
+    #[clap(long)]
+    pub emit_hlir: Option<String>,
+
     /// File to compile.
     pub filename: String,
 
+    /// Add a directory to search for `#include <...>` files. Directories are
+    /// searched in the order given, followed by the directory of the file
+    /// being compiled. The bundled core.p4 and softnpu.p4 are used when they
+    /// are not found on the search path.
+    #[clap(short = 'I', long = "include")]
+    pub include_dirs: Vec<String>,
+
     /// What target to generate code for.
     #[clap(arg_enum, default_value_t = Target::Rust)]
     pub target: Target,
@@ -43,6 +58,53 @@ pub struct Opts {
     /// Filename to write generated code to.
     #[clap(short, long, default_value = "out.rs")]
     pub out: String,
+
+    /// Directory to write generated code to, in a file named for the package
+    /// instance of the program, e.g. `main_pipeline.rs`. Takes the place of
+    /// `--out`.
+    #[clap(long)]
+    pub out_dir: Option<String>,
+
+    /// Report the time spent in each compiler phase on stderr.
+    #[clap(long)]
+    pub time_passes: bool,
+
+    /// Also warn about code that is legal but likely a mistake, such as
+    /// assignments that are overwritten before they are read.
+    #[clap(long)]
+    pub lint: bool,
+
+    /// Stop after a compiler phase and print what it produced: the lexical
+    /// tokens, the abstract syntax tree or the high-level intermediate
+    /// representation. Later phases are skipped, so stopping after lexing or
+    /// parsing succeeds even for programs that do not yet check.
+    #[clap(long, arg_enum)]
+    pub stop_after: Option<Phase>,
+
+    /// Generate code that checks a header is valid each time one of its
+    /// fields is read. Debug builds of the generated code panic on reads of
+    /// fields of invalid headers.
+    #[clap(long)]
+    pub checked_header_access: bool,
+
+    /// Generate a `<control>_control` struct for the named control that holds
+    /// its tables and applies it directly, for testing a control without a
+    /// pipeline. May be given more than once.
+    #[clap(long = "standalone-control")]
+    pub standalone_controls: Vec<String>,
+
+    /// Emit the original header bytes of packets whose headers were not
+    /// modified by the pipeline, rather than serializing them again.
+    #[clap(long)]
+    pub optimize: bool,
+}
+
+/// A compiler phase to stop after.
+#[derive(clap::ArgEnum, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Lex,
+    Parse,
+    Check,
 }
 
 #[derive(clap::ArgEnum, Clone)]
@@ -52,62 +114,178 @@ pub enum Target {
     Docs,
 }
 
+/// Time spent in each compiler phase, summed over all files processed.
+#[derive(Default)]
+pub struct PassTimes {
+    passes: Vec<(&'static str, Duration)>,
+}
+
+impl Opts {
+    /// The directories searched for `#include <...>` files.
+    pub fn include_path(&self) -> Vec<PathBuf> {
+        let mut path: Vec<PathBuf> =
+            self.include_dirs.iter().map(PathBuf::from).collect();
+        if let Some(parent) = Path::new(&self.filename).parent() {
+            path.push(parent.into());
+        }
+        path
+    }
+
+    /// The file to write generated code for `ast` to, see `--out` and
+    /// `--out-dir`. The output directory is created if it does not exist.
+    pub fn out_path(&self, ast: &AST) -> Result<String> {
+        let dir = match &self.out_dir {
+            Some(dir) => dir,
+            None => return Ok(self.out.clone()),
+        };
+        let inst = ast.package_instance.as_ref().ok_or_else(|| {
+            anyhow!("--out-dir requires a package instance to name the output")
+        })?;
+        fs::create_dir_all(dir)?;
+        let path = Path::new(dir).join(format!("{}_pipeline.rs", inst.name));
+        Ok(path.to_string_lossy().into_owned())
+    }
+}
+
+impl PassTimes {
+    /// Run `f`, adding the time it takes to the phase called `name`.
+    pub fn time<T>(&mut self, name: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        let elapsed = start.elapsed();
+        match self.passes.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, total)) => *total += elapsed,
+            None => self.passes.push((name, elapsed)),
+        }
+        result
+    }
+
+    /// A table of phases in the order they first ran, followed by the total.
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        let mut total = Duration::ZERO;
+        for (name, elapsed) in &self.passes {
+            out += &format!("{:<12}{:>12.3?}\n", name, elapsed);
+            total += *elapsed;
+        }
+        out += &format!("{:<12}{:>12.3?}\n", "total", total);
+        out
+    }
+}
+
 pub fn process_file(
     filename: Arc<String>,
     ast: &mut AST,
     opts: &Opts,
+    times: &mut PassTimes,
 ) -> Result<()> {
     let contents = fs::read_to_string(&*filename)
         .map_err(|e| anyhow!("read input: {}: {}", &*filename, e))?;
+    process_source(filename, &contents, ast, opts, times)
+}
 
-    let ppr = preprocessor::run(&contents, filename.clone())?;
+/// Compile `contents` as though it were read from `filename`.
+pub fn process_source(
+    filename: Arc<String>,
+    contents: &str,
+    ast: &mut AST,
+    opts: &Opts,
+    times: &mut PassTimes,
+) -> Result<()> {
+    let ppr = times.time("preprocess", || {
+        preprocessor::run(contents, filename.clone())
+    })?;
     if opts.show_pre {
         println!("{:#?}", ppr.elements);
     }
 
+    let search = opts.include_path();
     for included in &ppr.elements.includes {
-        let path = Path::new(included);
-        if !path.is_absolute() {
-            let parent = Path::new(&*filename).parent().unwrap();
-            let joined = parent.join(included);
+        if let Some(path) = included.resolve(Path::new(&*filename), &search) {
             process_file(
-                Arc::new(joined.to_str().unwrap().to_string()),
+                Arc::new(path.to_str().unwrap().to_string()),
                 ast,
                 opts,
+                times,
             )?
+        } else if let Some(source) = included.bundled() {
+            let name = Arc::new(format!("<{}>", included.path));
+            process_source(name, source, ast, opts, times)?
         } else {
-            process_file(Arc::new(included.clone()), ast, opts)?
+            return Err(anyhow!(
+                "include not found: {}: {}",
+                &*filename,
+                included.path
+            ));
         }
     }
 
     let lines: Vec<&str> = ppr.lines.iter().map(|x| x.as_str()).collect();
 
     let mut lxr = lexer::Lexer::new(lines.clone(), filename);
-    lxr.show_tokens = opts.show_tokens;
+    lxr.show_tokens = opts.show_tokens || opts.stop_after == Some(Phase::Lex);
+
+    if opts.stop_after == Some(Phase::Lex) {
+        times.time("lex", || lxr.tokenize())?;
+        return Ok(());
+    }
 
-    let mut psr = parser::Parser::new(lxr);
-    psr.run(ast)?;
+    // Lexing normally happens on demand as the parser asks for tokens. To time
+    // it on its own, lex everything up front.
+    let mut psr = if opts.time_passes {
+        let tokens = times.time("lex", || lxr.tokenize())?;
+        parser::Parser::with_tokens(lxr, tokens)
+    } else {
+        parser::Parser::new(lxr)
+    };
+    times.time("parse", || psr.run(ast))?;
     if opts.show_ast {
         println!("{:#?}", ast);
     }
 
-    let (hlir, diags) = check::all(ast);
+    if opts.stop_after == Some(Phase::Parse) {
+        return Ok(());
+    }
+
+    let (hlir, diags) = times.time("check", || check::all(ast));
     check(&lines, &diags)?;
 
-    if opts.show_hlir {
+    if opts.lint {
+        let lints = times.time("lint", || check::lint(ast));
+        check(&lines, &lints)?;
+    }
+
+    if opts.show_hlir || opts.stop_after == Some(Phase::Check) {
         println!("{:#?}", hlir);
     }
 
+    if let Some(path) = &opts.emit_hlir {
+        let json = serde_json::to_string_pretty(&hlir)?;
+        fs::write(path, json)
+            .map_err(|e| anyhow!("write hlir: {}: {}", path, e))?;
+    }
+
     Ok(())
 }
 
 fn check(lines: &[&str], diagnostics: &Diagnostics) -> Result<()> {
+    for w in diagnostics.warnings() {
+        eprintln!(
+            "{}: {} [{}:{}] {}",
+            format!("warning[{}]", w.code).yellow(),
+            w.message,
+            w.token.line + 1,
+            w.token.col + 1,
+            *w.token.file,
+        );
+    }
     let errors = diagnostics.errors();
     if !errors.is_empty() {
         let mut err = Vec::new();
         for e in errors {
             err.push(SemanticError {
                 at: e.token.clone(),
+                code: e.code,
                 message: e.message.clone(),
                 source: lines[e.token.line].into(),
             });
@@ -0,0 +1,74 @@
+use anyhow::Result;
+use clap::Parser;
+use p4::{ast::AST, hlir::Hlir};
+use std::fs;
+use std::sync::Arc;
+use x4c::backend::{BackendSettings, CodegenBackend};
+
+/// A backend that lists the controls of a program, one per line.
+struct ControlList;
+
+impl CodegenBackend for ControlList {
+    fn emit(
+        &self,
+        ast: &AST,
+        _hlir: &Hlir,
+        out: &str,
+        settings: &BackendSettings,
+    ) -> Result<()> {
+        let mut listing = format!("{}\n", settings.pipeline_name);
+        for control in &ast.controls {
+            listing += &format!("{}\n", control.name);
+        }
+        fs::write(out, listing)?;
+        Ok(())
+    }
+}
+
+#[test]
+fn backends_share_an_interface() {
+    let root = concat!(env!("CARGO_MANIFEST_DIR"), "/..");
+    let filename = format!("{}/test/src/p4/hub.p4", root);
+    let opts = x4c::Opts::parse_from(["x4c", filename.as_str()]);
+    let settings = BackendSettings {
+        pipeline_name: "hub".to_owned(),
+        checked_header_access: false,
+        standalone_controls: Vec::new(),
+        optimize: false,
+    };
+
+    let dir = std::env::temp_dir().join("x4c_backend");
+    fs::create_dir_all(&dir).unwrap();
+    let backends: Vec<(&str, Box<dyn CodegenBackend>)> = vec![
+        ("hub.rs", opts.target.backend().unwrap()),
+        ("hub.txt", Box::new(ControlList)),
+    ];
+    for (name, mut backend) in backends {
+        let mut ast = AST::default();
+        let mut times = x4c::PassTimes::default();
+        x4c::process_file(
+            Arc::new(filename.clone()),
+            &mut ast,
+            &opts,
+            &mut times,
+        )
+        .unwrap();
+        backend.prepare(&mut ast);
+        let (hlir, _) = p4::check::all(&ast);
+        let out = dir.join(name);
+        backend
+            .emit(&ast, &hlir, out.to_str().unwrap(), &settings)
+            .unwrap();
+    }
+
+    let rust = fs::read_to_string(dir.join("hub.rs")).unwrap();
+    assert!(rust.contains("impl p4rs::Pipeline for main_pipeline"));
+    let listing = fs::read_to_string(dir.join("hub.txt")).unwrap();
+    assert_eq!(listing, "hub\ningress\negress\n");
+}
+
+#[test]
+fn unimplemented_target_is_an_error() {
+    let opts = x4c::Opts::parse_from(["x4c", "p.p4", "docs"]);
+    assert!(opts.target.backend().is_err());
+}
@@ -0,0 +1,78 @@
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn system_and_local_includes_resolve() {
+    let dir = std::env::temp_dir().join("x4c_include");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("inc")).unwrap();
+    fs::create_dir_all(dir.join("src")).unwrap();
+
+    fs::write(dir.join("inc/defs.p4"), "header h_t { bit<8> a; }\n").unwrap();
+    fs::write(dir.join("src/local.p4"), "struct s_t { h_t h; }\n").unwrap();
+    fs::write(
+        dir.join("src/main.p4"),
+        "#include <defs.p4>\n#include \"local.p4\"\n",
+    )
+    .unwrap();
+
+    let x4c = |include: bool| {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_x4c"));
+        if include {
+            cmd.arg("-I").arg(dir.join("inc"));
+        }
+        cmd.arg("--check")
+            .arg(dir.join("src/main.p4"))
+            .output()
+            .unwrap()
+    };
+
+    // defs.p4 is only on the include path, not next to main.p4.
+    let output = x4c(false);
+    assert!(!output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("include not found"), "{}", stdout);
+
+    let output = x4c(true);
+    assert!(output.status.success(), "{:?}", output);
+}
+
+#[test]
+fn bundled_core_resolves() {
+    let dir = std::env::temp_dir().join("x4c_include_bundled");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    // There is no core.p4 next to the program, so the bundled one is used.
+    let src = dir.join("main.p4");
+    fs::write(
+        &src,
+        "#include <core.p4>
+
+header ethernet_t {
+    bit<48> dst;
+    bit<48> src;
+    bit<16> ether_type;
+}
+
+struct headers_t {
+    ethernet_t ethernet;
+}
+
+parser parse(packet_in pkt, out headers_t hdr) {
+    state start {
+        pkt.extract(hdr.ethernet);
+        transition accept;
+    }
+}
+",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_x4c"))
+        .arg("--check")
+        .arg(&src)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+}
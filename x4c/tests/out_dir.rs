@@ -0,0 +1,25 @@
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn out_dir_names_file_for_instance() {
+    let root = concat!(env!("CARGO_MANIFEST_DIR"), "/..");
+    let dir = std::env::temp_dir().join("x4c_out_dir");
+    let _ = fs::remove_dir_all(&dir);
+    let output = Command::new(env!("CARGO_BIN_EXE_x4c"))
+        .current_dir(root)
+        .arg("--out-dir")
+        .arg(&dir)
+        .arg("test/src/p4/hub.p4")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+
+    let files: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .collect();
+    assert_eq!(files, ["main_pipeline.rs"]);
+    let rust = fs::read_to_string(dir.join("main_pipeline.rs")).unwrap();
+    assert!(rust.contains("impl p4rs::Pipeline for main_pipeline"));
+}
@@ -0,0 +1,49 @@
+use std::process::Command;
+
+// Parses, but does not check: `nope` is never declared.
+const PROGRAM: &str = "
+control ingress(inout bit<8> x) {
+    apply {
+        x = nope;
+    }
+}
+";
+
+fn x4c(stop_after: Option<&str>) -> std::process::Output {
+    let dir = std::env::temp_dir();
+    let src = dir.join(format!("x4c_stop_after_{:?}.p4", stop_after));
+    std::fs::write(&src, PROGRAM).unwrap();
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_x4c"));
+    if let Some(phase) = stop_after {
+        cmd.arg("--stop-after").arg(phase);
+    }
+    cmd.arg("-o")
+        .arg(dir.join("x4c_stop_after_out.rs"))
+        .arg(&src)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn stop_after_parse_skips_checking() {
+    let output = x4c(None);
+    assert!(!output.status.success(), "{:?}", output);
+
+    let output = x4c(Some("parse"));
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("ingress"), "{}", stdout);
+
+    let output = x4c(Some("lex"));
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("nope"), "{}", stdout);
+}
+
+#[test]
+fn stop_after_check_reports_errors() {
+    let output = x4c(Some("check"));
+    assert!(!output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("nope"), "{}", stdout);
+}
@@ -0,0 +1,26 @@
+use std::process::Command;
+
+#[test]
+fn time_passes_reports_all_phases() {
+    let root = concat!(env!("CARGO_MANIFEST_DIR"), "/..");
+    let out = std::env::temp_dir().join("x4c_time_passes_out.rs");
+    let output = Command::new(env!("CARGO_BIN_EXE_x4c"))
+        .current_dir(root)
+        .arg("--time-passes")
+        .arg("-o")
+        .arg(&out)
+        .arg("test/src/p4/sidecar-lite.p4")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    for phase in ["preprocess", "lex", "parse", "check", "emit", "total"] {
+        assert!(
+            stderr.lines().any(|l| l.starts_with(phase)),
+            "{} missing from:\n{}",
+            phase,
+            stderr,
+        );
+    }
+}